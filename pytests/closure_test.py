@@ -0,0 +1,85 @@
+def make_counter():
+    count = 0
+    def increment():
+        nonlocal count
+        count = count + 1
+        return count
+    return increment
+
+counter = make_counter()
+counter()
+counter()
+assert_eq(3, counter())
+assert_eq(1, make_counter()())
+
+def adder(n):
+    def add(x):
+        return x + n
+    return add
+
+assert_eq(7, adder(3)(4))
+assert_eq(11, adder(10)(1))
+
+def outer():
+    x = "outer"
+    def middle():
+        def inner():
+            return x
+        return inner
+    return middle()()
+
+assert_eq("outer", outer())
+
+total = 10
+def read_total():
+    return total
+
+def set_total(value):
+    global total
+    total = value
+
+assert_eq(10, read_total())
+set_total(5)
+assert_eq(5, total)
+assert_eq(5, read_total())
+
+def class_in_function():
+    captured = 7
+    class Holder:
+        value = captured
+        def get(self):
+            return captured
+    return Holder
+
+Holder = class_in_function()
+assert_eq(7, Holder.value)
+assert_eq(7, Holder().get())
+
+def decorator(function):
+    calls = []
+    def wrapper(*args):
+        calls.append(args)
+        return function(*args)
+    wrapper.calls = calls
+    return wrapper
+
+def double(x):
+    return x * 2
+
+double = decorator(double)
+assert_eq(8, double(4))
+assert_eq(1, len(double.calls))
+assert_eq((4,), double.calls[0])
+
+def reads_before_assignment():
+    def inner():
+        return late
+    try:
+        inner()
+        return "not raised"
+    except NameError as e:
+        message = str(e)
+    late = 1
+    return message + "; then " + str(inner())
+
+assert_eq("free variable 'late' referenced before assignment in enclosing scope; then 1", reads_before_assignment())
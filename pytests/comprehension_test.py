@@ -0,0 +1,52 @@
+numbers = [1, 2, 3, 4, 5, 6]
+
+assert_eq([2, 4, 6, 8, 10, 12], [n * 2 for n in numbers])
+assert_eq([1, 9, 25], [n * n for n in numbers if n % 2 == 1])
+assert_eq([4, 6], [n for n in numbers if n > 2 if n % 2 == 0])
+assert_eq([0, 0, 3, 4, 5, 6], [n if n > 2 else 0 for n in numbers])
+assert_eq([], [n for n in numbers if n > 10])
+
+pairs = [(x, y) for x in [1, 2] for y in ["a", "b"]]
+assert_eq(4, len(pairs))
+assert_eq(2, pairs[2][0])
+assert_eq("b", pairs[3][1])
+
+triangle = [[j for j in range(i)] for i in range(4)]
+assert_eq([0, 1, 2], triangle[3])
+assert_eq(0, len(triangle[0]))
+
+squares = {n: n * n for n in numbers if n < 4}
+assert_eq({1: 1, 2: 4, 3: 9}, squares)
+
+ages = {"alice": 30, "bob": 25}
+assert_eq(["alice"], [name for name, age in ages.items() if age > 26])
+assert_eq({30: "alice", 25: "bob"}, {age: name for name, age in ages.items()})
+
+remainders = {n % 3 for n in numbers}
+assert_eq({0, 1, 2}, remainders)
+assert_eq(3, len(remainders))
+
+def total_of(values):
+    total = 0
+    for v in values:
+        total = total + v
+    return total
+
+assert_eq(21, total_of(n for n in numbers))
+assert_eq(12, total_of((n for n in numbers if n % 2 == 0)))
+assert_eq([2, 3], list(n + 1 for n in [1, 2]))
+
+n = "unchanged"
+offset = 100
+assert_eq([101, 102], [n + offset for n in [1, 2]])
+assert_eq("unchanged", n)
+
+def scaled(values, factor):
+    return [v * factor for v in values]
+
+assert_eq([3, 6], scaled([1, 2], 3))
+
+def adders():
+    return [lambda x, k=k: x + k for k in range(3)]
+
+assert_eq(12, adders()[2](10))
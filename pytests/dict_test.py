@@ -0,0 +1,77 @@
+ages = {"alice": 30, "bob": 25}
+assert_eq(30, ages["alice"])
+assert_eq(2, len(ages))
+
+ages["carol"] = 41
+ages["bob"] = 26
+assert_eq(3, len(ages))
+assert_eq(26, ages["bob"])
+
+config = {
+    "debug": True,
+    "level": 3,
+}
+assert_eq(3, config["level"])
+
+assert_eq(True, "alice" in ages)
+assert_eq(True, "dave" not in ages)
+assert_eq(None, ages.get("dave"))
+assert_eq(0, ages.get("dave", 0))
+
+assert_eq(41, ages.pop("carol"))
+assert_eq(2, len(ages))
+
+ages.update({"erin": 19})
+assert_eq(19, ages["erin"])
+
+order = ""
+for name in ages:
+    order = order + name + ","
+assert_eq("alice,bob,erin,", order)
+
+assert_eq("bob", ages.keys()[1])
+assert_eq(19, ages.values()[2])
+assert_eq(30, ages.items()[0][1])
+
+numbers = {1: "one", 2.0: "two"}
+assert_eq("one", numbers[1.0])
+assert_eq("two", numbers[2])
+
+class Point:
+    def __init__(self, x, y):
+        self.x = x
+        self.y = y
+    def __hash__(self):
+        return self.x * 31 + self.y
+    def __eq__(self, other):
+        if self.x == other.x:
+            if self.y == other.y:
+                return True
+        return False
+
+names = {Point(1, 2): "a"}
+assert_eq("a", names[Point(1, 2)])
+
+def missing_key(ages):
+    try:
+        ages["zed"]
+        return "not raised"
+    except KeyError as e:
+        return "caught"
+
+assert_eq("caught", missing_key(ages))
+
+items = [1, 2, 3]
+items[1] = 5
+assert_eq(5, items[1])
+assert_eq(True, 3 in items)
+
+nested = {}
+nested["k"] = nested
+assert_eq("{'k': {...}}", str(nested))
+looped = [1]
+looped.append(looped)
+assert_eq("[1, [...]]", str(looped))
+nested["l"] = [nested, looped]
+assert_eq("{'k': {...}, 'l': [{...}, [1, [...]]]}", str(nested))
+assert_eq("[[1, [...]], [1, [...]]]", str([looped, looped]))
@@ -0,0 +1,46 @@
+import gc
+
+class Node:
+    def __init__(self, value):
+        self.value = value
+        self.next = None
+        self.prev = None
+
+def make_ring(size):
+    first = Node(0)
+    last = first
+    for i in range(1, size):
+        node = Node(i)
+        node.prev = last
+        last.next = node
+        last = node
+    last.next = first
+    first.prev = last
+
+gc.collect()
+make_ring(10)
+freed = gc.collect()
+assert_eq(True, freed >= 10)
+freed = gc.collect()
+assert_eq(0, freed)
+
+kept = Node(1)
+kept.next = kept
+gc.collect()
+assert_eq(1, kept.next.value)
+
+gc.disable()
+assert_eq(False, gc.isenabled())
+before = gc.get_count()[0]
+for i in range(1000):
+    make_ring(2)
+assert_eq(True, gc.get_count()[0] > before)
+freed = gc.collect()
+assert_eq(True, freed >= 2000)
+gc.enable()
+assert_eq(True, gc.isenabled())
+
+for i in range(5000):
+    make_ring(2)
+assert_eq(True, gc.get_count()[0] < 10000)
+assert_eq(3, len(gc.get_count()))
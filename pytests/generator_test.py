@@ -0,0 +1,89 @@
+def countdown(n):
+    while n > 0:
+        yield n
+        n = n - 1
+    return "liftoff"
+
+assert_eq([3, 2, 1], list(countdown(3)))
+
+counter = countdown(2)
+assert_eq(2, counter.__next__())
+assert_eq(1, counter.__next__())
+returned = "nothing"
+try:
+    counter.__next__()
+except StopIteration as e:
+    returned = e.value
+assert_eq("liftoff", returned)
+
+def accumulate():
+    total = 0
+    while True:
+        value = yield total
+        total = total + value
+
+running = accumulate()
+assert_eq(0, running.__next__())
+assert_eq(5, running.send(5))
+assert_eq(12, running.send(7))
+
+def inner():
+    yield 1
+    yield 2
+    return 3
+
+def outer():
+    result = yield from inner()
+    yield result
+    yield from [4, 5]
+
+assert_eq([1, 2, 3, 4, 5], list(outer()))
+
+def guarded():
+    try:
+        yield 1
+        yield 2
+    except ValueError as e:
+        yield str(e)
+
+g = guarded()
+assert_eq(1, g.__next__())
+assert_eq("boom", g.throw(ValueError("boom")))
+
+def delegating():
+    yield from guarded()
+
+d = delegating()
+assert_eq(1, d.__next__())
+assert_eq("deep", d.throw(ValueError("deep")))
+
+cleaned = []
+def with_cleanup():
+    try:
+        yield 1
+        yield 2
+    finally:
+        cleaned.append(True)
+
+c = with_cleanup()
+c.__next__()
+c.close()
+assert_eq(1, len(cleaned))
+finished = False
+try:
+    c.__next__()
+except StopIteration:
+    finished = True
+assert_eq(True, finished)
+
+evaluated = []
+def record(x):
+    evaluated.append(x)
+    return x * 10
+
+lazy = (record(x) for x in [1, 2, 3])
+assert_eq(0, len(evaluated))
+assert_eq(10, lazy.__next__())
+assert_eq(1, len(evaluated))
+
+assert_eq([0, 2, 4], list(map(lambda x: x * 2, filter(lambda x: x < 3, range(5)))))
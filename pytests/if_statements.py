@@ -5,4 +5,17 @@ if x == 0:
 else:
     y = 3
 
-assert_eq(3, y)
\ No newline at end of file
+assert_eq(3, y)
+
+items = [1, 2]
+if items:
+    y = 4
+assert_eq(4, y)
+if items:
+    y = 5
+assert_eq([1, 2], items)
+if []:
+    y = 6
+if None:
+    y = 7
+assert_eq(5, y)
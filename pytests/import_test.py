@@ -0,0 +1,44 @@
+import importable
+import importable.shapes
+import importable.shapes as shapes
+from importable.shapes import Square, double as twice
+from importable import shapes as same_shapes
+
+assert_eq("hello", importable.greeting)
+assert_eq(16, Square(4).area())
+assert_eq(6, twice(3))
+assert_eq(1, shapes.make())
+assert_eq(2, same_shapes.make())
+assert_eq(2, importable.shapes.created)
+assert_eq("importable.shapes", shapes.__name__)
+assert_eq("__main__", __name__)
+
+def import_inside_function():
+    import importable.shapes as local_shapes
+    return local_shapes.double(5)
+
+assert_eq(10, import_inside_function())
+
+message = ""
+try:
+    import does_not_exist
+except ModuleNotFoundError as e:
+    message = str(e)
+assert_eq("No module named 'does_not_exist'", message)
+
+message = ""
+try:
+    from importable import missing
+except ImportError as e:
+    message = str(e)
+assert_eq("cannot import name 'missing' from 'importable'", message)
+
+import dis
+from dis import dis as disassemble
+assert_eq("dis", dis.__name__)
+message = ""
+try:
+    disassemble(42)
+except TypeError as e:
+    message = str(e)
+assert_eq("don't know how to disassemble int objects", message)
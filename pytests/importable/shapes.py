@@ -0,0 +1,22 @@
+class Square:
+    def __init__(self, side):
+        self.side = side
+
+    def area(self):
+        return self.side * self.side
+
+def helper(x):
+    return x
+
+def double(x):
+    return helper(x) * 2
+
+created = 0
+
+def make():
+    global created
+    created = created + 1
+    return created
+
+if __name__ == "__main__":
+    panic("an imported module is not __main__")
@@ -0,0 +1,82 @@
+class Animal:
+    kind = "animal"
+
+    def __init__(self, name):
+        self.name = name
+
+    def speak(self):
+        return self.name + " makes a sound"
+
+    def describe(self):
+        return self.name + " is a " + self.kind
+
+class Dog(Animal):
+    kind = "dog"
+
+    def __init__(self, name, breed):
+        super().__init__(name)
+        self.breed = breed
+
+    def speak(self):
+        return self.name + " barks"
+
+rex = Dog("Rex", "lab")
+assert_eq("Rex barks", rex.speak())
+assert_eq("Rex is a dog", rex.describe())
+assert_eq("lab", rex.breed)
+assert_eq("Rex makes a sound", Animal.speak(rex))
+assert_eq("animal", Animal.kind)
+
+assert_eq(True, isinstance(rex, Animal))
+assert_eq(True, isinstance(rex, (int, Dog)))
+assert_eq(False, isinstance(3, Animal))
+assert_eq(True, isinstance(3, object))
+assert_eq(True, issubclass(Dog, Animal))
+assert_eq(False, issubclass(Animal, Dog))
+
+class A:
+    def who(self):
+        return "A"
+
+class B(A):
+    def who(self):
+        return "B" + super().who()
+
+class C(A):
+    def who(self):
+        return "C" + super().who()
+
+class D(B, C):
+    def who(self):
+        return "D" + super().who()
+
+assert_eq("DBCA", D().who())
+assert_eq((D, B, C, A, object), D.__mro__)
+
+class InvalidCode(ValueError):
+    def __init__(self, code):
+        super().__init__("invalid code " + str(code))
+        self.code = code
+
+caught = None
+try:
+    raise InvalidCode(42)
+except ValueError as e:
+    caught = e
+assert_eq("invalid code 42", str(caught))
+assert_eq(42, caught.code)
+assert_eq(True, isinstance(caught, Exception))
+
+class P:
+    pass
+
+class Q(P):
+    pass
+
+message = None
+try:
+    class R(P, Q):
+        pass
+except TypeError as e:
+    message = str(e)
+assert_eq("Cannot create a consistent method resolution order (MRO) for bases P, Q", message)
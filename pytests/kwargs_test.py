@@ -0,0 +1,80 @@
+def greet(greeting, name="world", punctuation="!"):
+    return greeting + ", " + name + punctuation
+
+assert_eq("hello, world!", greet("hello"))
+assert_eq("hello, bob!", greet("hello", "bob"))
+assert_eq("hello, world?", greet("hello", punctuation="?"))
+assert_eq("hi, ann.", greet(punctuation=".", name="ann", greeting="hi"))
+
+def collect(first, *rest):
+    return rest
+
+assert_eq((), collect(1))
+assert_eq((2, 3), collect(1, 2, 3))
+assert_eq((2, 3, 4), collect(1, *[2, 3], 4))
+
+def options(**kw):
+    return kw
+
+assert_eq({}, options())
+assert_eq({"a": 1, "b": 2}, options(a=1, b=2))
+assert_eq({"a": 1, "b": 2}, options(**{"a": 1}, b=2))
+
+def keyword_only(a, *, scale=10, offset):
+    return a * scale + offset
+
+assert_eq(15, keyword_only(1, offset=5))
+assert_eq(7, keyword_only(1, scale=2, offset=5))
+
+def everything(a, b=2, *args, c, d=4, **kw):
+    return (a, b, args, c, d, kw)
+
+assert_eq((1, 2, (), 3, 4, {}), everything(1, c=3))
+assert_eq((1, 5, (6, 7), 3, 4, {"e": 8}), everything(1, 5, 6, 7, c=3, e=8))
+
+args = (1, 2)
+kwargs = {"c": 3, "d": 9}
+assert_eq((1, 2, (), 3, 9, {}), everything(*args, **kwargs))
+
+assert_eq({"a": 1, "b": 2}, dict(a=1, b=2))
+
+def type_error_message(f):
+    try:
+        f()
+        return "not raised"
+    except TypeError as e:
+        return str(e)
+
+def too_many():
+    greet("a", "b", "c", "d")
+
+def missing():
+    keyword_only(1)
+
+def unexpected():
+    greet("a", colour="red")
+
+def repeated():
+    greet("a", greeting="b")
+
+assert_eq("greet() takes from 1 to 3 positional arguments but 4 were given", type_error_message(too_many))
+assert_eq("keyword_only() missing 1 required keyword-only argument: 'offset'", type_error_message(missing))
+assert_eq("greet() got an unexpected keyword argument 'colour'", type_error_message(unexpected))
+assert_eq("greet() got multiple values for argument 'greeting'", type_error_message(repeated))
+
+class Point:
+    def __init__(self, x=0, y=0):
+        self.x = x
+        self.y = y
+
+p = Point(y=3)
+assert_eq(0, p.x)
+assert_eq(3, p.y)
+
+items = [1]
+settings = {}
+assert_eq("len() takes no keyword arguments", type_error_message(lambda: len(items, x=2)))
+assert_eq("append() takes no keyword arguments", type_error_message(lambda: items.append(2, where=3)))
+assert_eq("get() takes no keyword arguments", type_error_message(lambda: settings.get("x", default=3)))
+assert_eq("ValueError() takes no keyword arguments", type_error_message(lambda: ValueError("a", x=1)))
+assert_eq(1, len(items))
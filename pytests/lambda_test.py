@@ -0,0 +1,49 @@
+add = lambda x, y=2: x + y
+assert_eq(3, add(1))
+assert_eq(6, add(1, 5))
+assert_eq(9, add(4, y=5))
+
+constant = lambda: 42
+assert_eq(42, constant())
+
+def make_multiplier(factor):
+    return lambda x: x * factor
+
+triple = make_multiplier(3)
+assert_eq(12, triple(4))
+
+doubled = []
+for n in map(lambda n: n * 2, [1, 2, 3]):
+    doubled.append(n)
+assert_eq(3, len(doubled))
+assert_eq(2, doubled[0])
+assert_eq(6, doubled[2])
+
+evens = []
+for n in filter(lambda n: n % 2 == 0, [1, 2, 3, 4, 5, 6]):
+    evens.append(n)
+assert_eq(3, len(evens))
+assert_eq(4, evens[1])
+
+x = 3
+assert_eq("big", "big" if x > 2 else "small")
+assert_eq("small", "big" if x > 5 else "small")
+
+def classify(n):
+    return "negative" if n < 0 else "zero" if n == 0 else "positive"
+
+assert_eq("negative", classify(-1))
+assert_eq("zero", classify(0))
+assert_eq("positive", classify(7))
+
+assert_eq(10, add(x if x > 5 else 8))
+
+sign = lambda n: -1 if n < 0 else 1
+assert_eq(-1, sign(-5))
+assert_eq(1, sign(5))
+
+maximum = lambda a, b: a if a > b else b
+assert_eq(9, maximum(2, 9))
+
+assert_eq(3, (lambda a: a)(3))
+assert_eq(8, (lambda: (lambda b: b * 2))()(4))
@@ -0,0 +1,22 @@
+pairs = []
+for x in [1, 2, 3]:
+    for y in [10, 20]:
+        pairs.append(x + y)
+assert_eq([11, 21, 12, 22, 13, 23], pairs)
+
+found = []
+for x in [1, 2, 3]:
+    for y in [1, 2, 3]:
+        if y > x:
+            break
+        found.append(y)
+assert_eq([1, 1, 2, 1, 2, 3], found)
+
+def first_over(values, limit):
+    for v in values:
+        if v > limit:
+            return v
+    return None
+
+assert_eq(7, first_over([1, 7, 9], 5))
+assert_eq(None, first_over([1, 2], 5))
@@ -0,0 +1,20 @@
+x = [1, [2, 3]]
+assert_eq(1, (x)[0])
+assert_eq(3, ((x)[1])[1])
+assert_eq(3, (1).__add__(2))
+assert_eq(9, (1 + 2) * 3)
+
+def pair():
+    return [4, 5]
+
+assert_eq(5, (pair())[1])
+assert_eq(2, len((1, 2)))
+
+class Box:
+    size = 7
+
+assert_eq(7, (Box()).size)
+assert_eq(7, (Box).size)
+
+(x)[0] = 10
+assert_eq(10, x[0])
@@ -1,2 +1,5 @@
 x = None
-print(x)
\ No newline at end of file
+print(x)
+assert_eq(None, print("sep and end", end=None))
+assert_eq(None, print("default", "sep", sep=None))
+assert_eq(None, print("both", "defaults", sep=None, end=None))
@@ -0,0 +1,71 @@
+def raises(f, exception_type):
+    try:
+        f()
+    except exception_type as e:
+        return str(e)
+    return "not raised"
+
+assert_eq("unsupported operand type(s) for +: 'str' and 'int'", raises(lambda: "a" + 1, TypeError))
+assert_eq("unsupported operand type(s) for +: 'list' and 'int'", raises(lambda: [1] + 1, TypeError))
+assert_eq("unsupported operand type(s) for +: 'int' and 'str'", raises(lambda: 1 + "a", TypeError))
+assert_eq("unsupported operand type(s) for -: 'int' and 'str'", raises(lambda: print(5 - "a"), TypeError))
+assert_eq("'<' not supported between instances of 'int' and 'str'", raises(lambda: 1 < "a", TypeError))
+assert_eq(False, 1 == "a")
+assert_eq(True, "a" != 1)
+assert_eq("list indices must be integers, not str", raises(lambda: [1]["a"], TypeError))
+assert_eq("list indices must be integers, not float", raises(lambda: [1, 2][1.0], TypeError))
+assert_eq("list indices must be integers, not NoneType", raises(lambda: [1][None], TypeError))
+assert_eq("'int' object is not iterable", raises(lambda: list(5), TypeError))
+assert_eq("object of type 'int' has no len()", raises(lambda: len(5), TypeError))
+assert_eq("integer division or modulo by zero", raises(lambda: 1 % 0, ZeroDivisionError))
+assert_eq("integer overflow", raises(lambda: 170141183460469231731687303715884105727 + 1, OverflowError))
+assert_eq("descriptor 'upper' for 'str' objects doesn't apply to a 'int' object", raises(lambda: str.upper(1), TypeError))
+assert_eq("A", str.upper("a"))
+assert_eq("unsupported operand type(s) for +: 'int' and 'NoneType'", raises(lambda: 1 + None, TypeError))
+assert_eq("unsupported operand type(s) for *: 'float' and 'list'", raises(lambda: 1.5 * [1], TypeError))
+assert_eq("'<' not supported between instances of 'int' and 'tuple'", raises(lambda: 1 < (1,), TypeError))
+assert_eq("unsupported operand type(s) for +: 'list' and 'NoneType'", raises(lambda: [1] + None, TypeError))
+assert_eq("None", raises(lambda: {}.pop(None), KeyError))
+assert_eq("<object object>", str(object()))
+
+def set_item():
+    items = [1]
+    items["a"] = 2
+
+assert_eq("list indices must be integers, not str", raises(set_item, TypeError))
+
+class Money:
+    def __init__(self, amount):
+        self.amount = amount
+    def __add__(self, other):
+        if isinstance(other, Money):
+            return Money(self.amount + other.amount)
+        return NotImplemented
+    def __radd__(self, other):
+        return Money(self.amount + other)
+
+total = Money(2) + Money(3)
+assert_eq(5, total.amount)
+total = 1 + Money(5)
+assert_eq(6, total.amount)
+assert_eq("unsupported operand type(s) for +: 'Money' and 'str'", raises(lambda: Money(1) + "a", TypeError))
+
+def recurse(n):
+    return recurse(n + 1)
+
+assert_eq("maximum recursion depth exceeded", raises(lambda: recurse(0), RecursionError))
+assert_eq(True, issubclass(RecursionError, RuntimeError))
+
+class NotAnIterator:
+    def __iter__(self):
+        return 5
+
+def loop_over(iterable):
+    for x in iterable:
+        pass
+
+assert_eq("iter() returned non-iterator of type 'int'", raises(lambda: loop_over(NotAnIterator()), TypeError))
+assert_eq("iter() returned non-iterator of type 'int'", raises(lambda: list(NotAnIterator()), TypeError))
+assert_eq([], list())
+assert_eq("end must be None or a string, not object", raises(lambda: print("x", end=object()), TypeError))
+assert_eq("sep must be None or a string, not int", raises(lambda: print("x", sep=1), TypeError))
@@ -0,0 +1,22 @@
+primes = {2, 3, 5, 7}
+assert_eq(4, len(primes))
+assert_eq(True, 3 in primes)
+assert_eq(False, 4 in primes)
+
+primes.add(11)
+primes.add(2)
+assert_eq(5, len(primes))
+
+primes.remove(11)
+primes.discard(13)
+assert_eq({7, 5, 3, 2}, primes)
+
+assert_eq(3, len(set([1, 1, 2, 3, 3])))
+assert_eq(0, len(set()))
+assert_eq("set()", str(set()))
+assert_eq("{1, 2}", str({1, 2}))
+
+total = 0
+for p in primes:
+    total = total + p
+assert_eq(17, total)
@@ -0,0 +1,27 @@
+import sys
+
+code = None
+try:
+    sys.exit(3)
+except SystemExit as e:
+    code = e.code
+assert_eq(3, code)
+
+message = ""
+try:
+    raise SystemExit("bye")
+except SystemExit as e:
+    message = str(e)
+assert_eq("bye", message)
+
+caught_by_exception = False
+try:
+    try:
+        sys.exit()
+    except Exception:
+        caught_by_exception = True
+except SystemExit as e:
+    assert_eq(None, e.code)
+assert_eq(False, caught_by_exception)
+assert_eq(True, issubclass(SystemExit, BaseException))
+assert_eq(1, len(sys.argv))
@@ -0,0 +1,167 @@
+def index_out_of_range():
+    items = [1, 2, 3]
+    try:
+        items[10]
+        return "not raised"
+    except IndexError:
+        return "caught"
+
+assert_eq("caught", index_out_of_range())
+
+def bound_exception():
+    try:
+        raise ValueError("bad value")
+    except TypeError as e:
+        return "wrong handler"
+    except ValueError as e:
+        return str(e)
+
+assert_eq("bad value", bound_exception())
+
+def catch_by_supertype():
+    try:
+        raise KeyError("k")
+    except Exception as e:
+        return "caught by Exception"
+
+assert_eq("caught by Exception", catch_by_supertype())
+
+def else_runs_without_exception():
+    result = 0
+    try:
+        result = 1
+    except Exception:
+        result = 2
+    else:
+        result = result + 10
+    return result
+
+assert_eq(11, else_runs_without_exception())
+
+class Counter:
+    def __init__(self):
+        self.value = 0
+
+counter = Counter()
+
+def finally_runs_on_return(counter):
+    try:
+        return 1
+    finally:
+        counter.value = counter.value + 1
+
+assert_eq(1, finally_runs_on_return(counter))
+assert_eq(1, counter.value)
+
+def finally_runs_on_break():
+    runs = 0
+    i = 0
+    while i < 10:
+        try:
+            if i == 3:
+                break
+        finally:
+            runs = runs + 1
+        i = i + 1
+    return runs
+
+assert_eq(4, finally_runs_on_break())
+
+def raises_deep():
+    raise RuntimeError("deep")
+
+def calls_raises_deep():
+    raises_deep()
+    return "not raised"
+
+def propagates_through_calls():
+    try:
+        calls_raises_deep()
+    except RuntimeError as e:
+        return str(e)
+    return "not caught"
+
+assert_eq("deep", propagates_through_calls())
+
+def reraises_to_outer_try(counter):
+    try:
+        try:
+            raise ValueError("inner")
+        finally:
+            counter.value = counter.value + 1
+    except ValueError as e:
+        return str(e)
+
+assert_eq("inner", reraises_to_outer_try(counter))
+assert_eq(2, counter.value)
+
+def unmatched_handler_propagates():
+    try:
+        try:
+            raise TypeError("unmatched")
+        except ValueError:
+            return "wrong handler"
+    except TypeError as e:
+        return str(e)
+
+assert_eq("unmatched", unmatched_handler_propagates())
+
+def catches_any_of(exception):
+    try:
+        raise exception
+    except (TypeError, (KeyError, ValueError)) as e:
+        return str(e)
+    except RuntimeError:
+        return "runtime"
+
+assert_eq("key", catches_any_of(KeyError("key")))
+assert_eq("value", catches_any_of(ValueError("value")))
+assert_eq("runtime", catches_any_of(RuntimeError("x")))
+
+def reraises(counter):
+    try:
+        raise ValueError("again")
+    except ValueError:
+        counter.value = counter.value + 1
+        raise
+
+def catches_reraised(counter):
+    try:
+        reraises(counter)
+    except ValueError as e:
+        return str(e)
+
+assert_eq("again", catches_reraised(counter))
+assert_eq(3, counter.value)
+
+def reraises_outer_after_inner():
+    try:
+        raise KeyError("outer")
+    except KeyError:
+        try:
+            raise ValueError("inner")
+        except ValueError:
+            pass
+        if True:
+            raise
+
+def catches_outer():
+    try:
+        reraises_outer_after_inner()
+    except KeyError as e:
+        return str(e)
+    except ValueError:
+        return "inner"
+
+assert_eq("outer", catches_outer())
+
+def raise_class():
+    raise KeyError
+
+def raised_class_is_instantiated():
+    try:
+        raise_class()
+    except KeyError as e:
+        return isinstance(e, KeyError) and e.__traceback__ != None
+
+assert_eq(True, raised_class_is_instantiated())
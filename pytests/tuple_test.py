@@ -0,0 +1,93 @@
+point = (1, 2)
+assert_eq(2, len(point))
+assert_eq(1, point[0])
+assert_eq(2, point[1])
+
+single = (5,)
+assert_eq(1, len(single))
+assert_eq(0, len(()))
+assert_eq("(5,)", str(single))
+assert_eq("(1, 'a')", str((1, "a")))
+
+bare = 3, 4
+assert_eq((3, 4), bare)
+assert_eq(True, (1, 2) != (2, 1))
+assert_eq((1, 2, 3), (1, 2) + (3,))
+
+a = 1
+b = 2
+a, b = b, a
+assert_eq(2, a)
+assert_eq(1, b)
+
+x, (y, z) = 1, (2, 3)
+assert_eq(3, z)
+
+def divmod_like(n, d):
+    return n / d, n % d
+
+q, r = divmod_like(7, 2)
+assert_eq(1, r)
+
+pairs = [(1, "one"), (2, "two")]
+total = 0
+names = ""
+for number, name in pairs:
+    total = total + number
+    names = names + name
+assert_eq(3, total)
+assert_eq("onetwo", names)
+
+ages = {"alice": 30}
+for key, value in ages.items():
+    assert_eq("alice", key)
+    assert_eq(30, value)
+
+grid = {(0, 1): "x"}
+assert_eq("x", grid[(0, 1)])
+
+assert_eq(True, 2 in (1, 2))
+assert_eq((1, 2), tuple([1, 2]))
+
+class Holder:
+    def __init__(self):
+        self.first = 0
+        self.second = 0
+
+holder = Holder()
+holder.first, holder.second = 10, 20
+assert_eq(20, holder.second)
+
+items = [0, 0]
+items[0], items[1] = "a", "b"
+assert_eq("b", items[1])
+
+def unpack_too_many():
+    try:
+        first, second = 1, 2, 3
+        return "not raised"
+    except ValueError as e:
+        return str(e)
+
+assert_eq("too many values to unpack (expected 2)", unpack_too_many())
+
+def assign_to_tuple():
+    t = (1, 2)
+    try:
+        t[0] = 5
+        return "not raised"
+    except TypeError as e:
+        return str(e)
+
+assert_eq("'tuple' object does not support item assignment", assign_to_tuple())
+
+def index_error(index):
+    try:
+        return (1, 2)[index]
+    except TypeError as e:
+        return str(e)
+
+assert_eq("tuple indices must be integers, not str", index_error("a"))
+assert_eq("tuple indices must be integers, not float", index_error(1.5))
+assert_eq("tuple indices must be integers, not NoneType", index_error(None))
+assert_eq(2, index_error(1))
@@ -1,4 +1,5 @@
 use crate::commons::float::Float;
+use crate::commons::errors::LexError;
 
 #[derive(Eq, PartialEq, Debug, Copy, Clone)]
 pub enum Operator {
@@ -15,6 +16,8 @@ pub enum Operator {
     And,
     Or,
     Xor,
+    In,
+    NotIn,
 
     Greater,
     GreaterEquals,
@@ -36,6 +39,8 @@ pub enum Token {
     None,
     Comma,
     Colon,
+    //`**` only appears in `**kwargs` parameters and arguments, there is no power operator yet
+    DoubleStar,
     ClassDef,
     IfKeyword,
     ForKeyword,
@@ -44,17 +49,82 @@ pub enum Token {
     InKeyword,
     WhileKeyword,
     BreakKeyword,
+    PassKeyword,
+    GlobalKeyword,
+    NonlocalKeyword,
     ElifKeyword,
     ElseKeyword,
     DefKeyword,
+    TryKeyword,
+    ExceptKeyword,
+    FinallyKeyword,
+    AsKeyword,
+    LambdaKeyword,
+    YieldKeyword,
+    FromKeyword,
+    ImportKeyword,
     OpenParen,
     CloseParen,
     OpenArrayBracket,
     CloseArrayBracket,
+    OpenBrace,
+    CloseBrace,
     MemberAccessor,
     Indentation,
 }
 
+//Position of a token in the source. Both line and column start at 1.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default, Hash, PartialOrd, Ord)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+//Tokens built by hand (mostly in tests) have no known position
+impl From<Token> for SpannedToken {
+    fn from(token: Token) -> Self {
+        SpannedToken { token, span: Span::default() }
+    }
+}
+
+//Words that are not identifiers, and the tokens they become. The REPL also completes and highlights them.
+pub const KEYWORDS: &[(&str, Token)] = &[
+    ("None", Token::None),
+    ("not", Token::Operator(Operator::Not)),
+    ("True", Token::True),
+    ("False", Token::False),
+    ("and", Token::Operator(Operator::And)),
+    ("or", Token::Operator(Operator::Or)),
+    ("if", Token::IfKeyword),
+    ("elif", Token::ElifKeyword),
+    ("else", Token::ElseKeyword),
+    ("for", Token::ForKeyword),
+    ("def", Token::DefKeyword),
+    ("raise", Token::RaiseKeyword),
+    ("return", Token::ReturnKeyword),
+    ("in", Token::InKeyword),
+    ("while", Token::WhileKeyword),
+    ("break", Token::BreakKeyword),
+    ("pass", Token::PassKeyword),
+    ("global", Token::GlobalKeyword),
+    ("nonlocal", Token::NonlocalKeyword),
+    ("class", Token::ClassDef),
+    ("try", Token::TryKeyword),
+    ("except", Token::ExceptKeyword),
+    ("finally", Token::FinallyKeyword),
+    ("as", Token::AsKeyword),
+    ("lambda", Token::LambdaKeyword),
+    ("yield", Token::YieldKeyword),
+    ("from", Token::FromKeyword),
+    ("import", Token::ImportKeyword),
+];
+
 #[derive(Debug)]
 enum PartialToken {
     UndefinedOrWhitespace,
@@ -66,6 +136,8 @@ enum PartialToken {
     Comma,
     OpenArrayBracket,
     CloseArrayBracket,
+    OpenBrace,
+    CloseBrace,
     MemberAccessor,
     Colon,
 }
@@ -76,25 +148,9 @@ impl PartialToken {
             Self::UndefinedOrWhitespace => {
                 panic!("Unexpected undefined token. This is a tokenizer bug.")
             }
-            Self::Identifier(s) => match s.as_str() {
-                "None" => Token::None,
-                "not" => Token::Operator(Operator::Not),
-                "True" => Token::True,
-                "False" => Token::False,
-                "and" => Token::Operator(Operator::And),
-                "or" => Token::Operator(Operator::Or),
-                "if" => Token::IfKeyword,
-                "elif" => Token::ElifKeyword,
-                "else" => Token::ElseKeyword,
-                "for" => Token::ForKeyword,
-                "def" => Token::DefKeyword,
-                "raise" => Token::RaiseKeyword,
-                "return" => Token::ReturnKeyword,
-                "in" => Token::InKeyword,
-                "while" => Token::WhileKeyword,
-                "break" => Token::BreakKeyword,
-                "class" => Token::ClassDef,
-                _ => Token::Identifier(s),
+            Self::Identifier(s) => match KEYWORDS.iter().find(|(keyword, _)| *keyword == s) {
+                Some((_, token)) => token.clone(),
+                None => Token::Identifier(s),
             },
             Self::Comma => Token::Comma,
             Self::Colon => Token::Colon,
@@ -102,6 +158,8 @@ impl PartialToken {
             Self::MemberAccessor => Token::MemberAccessor,
             Self::OpenArrayBracket => Token::OpenArrayBracket,
             Self::CloseArrayBracket => Token::CloseArrayBracket,
+            Self::OpenBrace => Token::OpenBrace,
+            Self::CloseBrace => Token::CloseBrace,
             Self::LiteralFloat(s) => {
                 if s.contains('.') || s.contains('e') {
                     match s.parse::<f64>() {
@@ -119,6 +177,7 @@ impl PartialToken {
             Self::Operator(s) => match s.as_str() {
                 "+" => Token::Operator(Operator::Plus),
                 "-" => Token::Operator(Operator::Minus),
+                "**" => Token::DoubleStar,
                 "*" => Token::Operator(Operator::Multiply),
                 "%" => Token::Operator(Operator::Mod),
                 "/" => Token::Operator(Operator::Divide),
@@ -144,21 +203,58 @@ pub struct Tokenizer {
     index: usize,
     chars: Vec<char>,
     cur_partial_token: PartialToken,
-    final_result: Vec<Token>,
+    //where the pending partial token started
+    cur_token_start: usize,
+    //char index where each line begins, used to compute spans
+    line_starts: Vec<usize>,
+    //newlines and indentation are ignored inside (), [] and {}, like in CPython
+    bracket_depth: usize,
+    final_result: Vec<SpannedToken>,
     eater_buf: String,
 }
 
 impl Tokenizer {
     pub fn new(source: &str) -> Tokenizer {
+        let chars: Vec<char> = source.chars().collect();
+        let mut line_starts = vec![0];
+        for (index, c) in chars.iter().enumerate() {
+            if *c == '\n' {
+                line_starts.push(index + 1);
+            }
+        }
         Tokenizer {
             index: 0,
-            chars: source.chars().collect(),
+            chars,
             cur_partial_token: PartialToken::UndefinedOrWhitespace,
+            cur_token_start: 0,
+            line_starts,
+            bracket_depth: 0,
             final_result: vec![],
             eater_buf: String::new(),
         }
     }
 
+    fn span_at(&self, index: usize) -> Span {
+        //index of the line that contains the char, line_starts[0] is always 0
+        let line = match self.line_starts.binary_search(&index) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+        Span {
+            line: line + 1,
+            column: index - self.line_starts[line] + 1,
+        }
+    }
+
+    fn error(&self, message: String) -> LexError {
+        LexError::at(message, self.span_at(self.index))
+    }
+
+    fn push_token(&mut self, token: Token, start: usize) {
+        let span = self.span_at(start);
+        self.final_result.push(SpannedToken { token, span });
+    }
+
     fn reset_eater_buffer(&mut self) {
         self.eater_buf = String::new();
     }
@@ -171,12 +267,17 @@ impl Tokenizer {
         self.index = self.index + offset;
     }
 
+    //At the end of the input this is '\0', which no token starts with
     fn cur(&self) -> char {
-        self.cur_offset(0)
+        self.cur_offset(0).unwrap_or('\0')
     }
 
-    fn cur_offset(&self, offset: isize) -> char {
-        self.chars[(self.index as isize + offset) as usize]
+    fn cur_offset(&self, offset: isize) -> Option<char> {
+        let index = self.index as isize + offset;
+        if index < 0 {
+            return None;
+        }
+        self.chars.get(index as usize).copied()
     }
 
     fn can_go(&self) -> bool {
@@ -195,7 +296,7 @@ impl Tokenizer {
 
     fn eat_identifier(&mut self) -> bool {
         let first_char_is_valid_identifier =
-            self.can_go() && self.cur().is_ascii_alphabetic() || self.cur() == '_';
+            self.can_go() && (self.cur().is_ascii_alphabetic() || self.cur() == '_');
 
         if first_char_is_valid_identifier {
             self.eater_buf.push(self.cur());
@@ -222,16 +323,18 @@ impl Tokenizer {
         }
     }
 
-    fn eat_string_literal(&mut self) -> bool {
+    fn eat_string_literal(&mut self) -> Result<(), LexError> {
+        let start = self.index;
         let stop = self.cur();
-        if stop != '\'' && stop != '"' {
-            return false;
-        }
         self.next();
         let mut is_escaping = false;
         let mut finished = false;
         while self.can_go() {
             let cur = self.cur();
+            //strings end on their line, an unterminated one is reported where it starts
+            if cur == '\n' {
+                break;
+            }
             if cur == '\\' && !is_escaping {
                 is_escaping = true;
                 self.next();
@@ -245,7 +348,7 @@ impl Tokenizer {
                 } else if cur == '\\' {
                     self.eater_buf.push('\\');
                 } else {
-                    panic!("cannot escape char {}", cur);
+                    return Err(self.error(format!("cannot escape char {}", cur)));
                 }
                 is_escaping = false;
                 self.next();
@@ -262,7 +365,10 @@ impl Tokenizer {
             self.eater_buf.push(cur);
             self.next();
         }
-        return finished;
+        if !finished {
+            return Err(LexError::at("EOL while scanning string literal", self.span_at(start)));
+        }
+        return Ok(());
     }
 
     fn commit_current_token(&mut self) {
@@ -273,7 +379,7 @@ impl Tokenizer {
                     &mut self.cur_partial_token,
                     PartialToken::UndefinedOrWhitespace,
                 );
-                self.final_result.push(cur_token.to_token());
+                self.push_token(cur_token.to_token(), self.cur_token_start);
             }
         };
     }
@@ -286,7 +392,7 @@ impl Tokenizer {
         let mut matched_chars = 0;
         let chars: Vec<char> = query.chars().collect();
         for i in 0..query.len() {
-            if self.cur_offset(i as isize) != chars[i] {
+            if self.cur_offset(i as isize) != Some(chars[i]) {
                 return (false, 0);
             }
             matched_chars = matched_chars + 1
@@ -305,13 +411,14 @@ impl Tokenizer {
         return None;
     }
 
-    pub fn tokenize(mut self) -> Result<Vec<Token>, String> {
+    pub fn tokenize(mut self) -> Result<Vec<SpannedToken>, LexError> {
         let operators = &[
-            "+", "-", "*", "%", "/", "<<", ">>", "<=", ">=", ">", "<", "!=", "==", "=", "^", "(",
+            "+", "-", "**", "*", "%", "/", "<<", ">>", "<=", ">=", ">", "<", "!=", "==", "=", "^", "(",
             ")",
         ];
         while self.can_go() {
             self.commit_current_token();
+            self.cur_token_start = self.index;
             if self.cur().is_numeric() {
                 self.reset_eater_buffer();
                 self.eat_numbers();
@@ -320,7 +427,17 @@ impl Tokenizer {
                 self.eat_char('e');
                 self.eat_char('-');
                 self.eat_numbers();
-                self.cur_partial_token = PartialToken::LiteralFloat(self.clone_buf());
+                let number = self.clone_buf();
+                //like `1e` at the end of the input, or an integer that doesn't fit
+                let valid = if number.contains('.') || number.contains('e') {
+                    number.parse::<f64>().is_ok()
+                } else {
+                    number.parse::<i128>().is_ok()
+                };
+                if !valid {
+                    return Err(LexError::at(format!("invalid number literal {}", number), self.span_at(self.cur_token_start)));
+                }
+                self.cur_partial_token = PartialToken::LiteralFloat(number);
                 self.reset_eater_buffer();
             } else if self.cur() == ',' {
                 self.cur_partial_token = PartialToken::Comma;
@@ -333,36 +450,55 @@ impl Tokenizer {
             } else if self.cur() == '[' {
                 self.cur_partial_token = PartialToken::OpenArrayBracket;
                 self.commit_current_token();
+                self.bracket_depth += 1;
                 self.next();
             } else if self.cur() == ']' {
                 self.cur_partial_token = PartialToken::CloseArrayBracket;
                 self.commit_current_token();
+                self.bracket_depth = self.bracket_depth.saturating_sub(1);
+                self.next();
+            } else if self.cur() == '{' {
+                self.cur_partial_token = PartialToken::OpenBrace;
+                self.commit_current_token();
+                self.bracket_depth += 1;
+                self.next();
+            } else if self.cur() == '}' {
+                self.cur_partial_token = PartialToken::CloseBrace;
+                self.commit_current_token();
+                self.bracket_depth = self.bracket_depth.saturating_sub(1);
                 self.next();
             } else if self.cur() == '.' {
                 self.cur_partial_token = PartialToken::MemberAccessor;
                 self.commit_current_token();
                 self.next();
+            } else if self.cur() == '\n' && self.bracket_depth > 0 {
+                self.next();
             } else if self.cur() == '\n' {
                 self.cur_partial_token = PartialToken::NewLine;
                 self.commit_current_token();
                 self.next();
-            } else if self.index > 0 && self.cur_offset(-1) == '\n' && self.cur() == ' ' {
+            } else if self.bracket_depth == 0 && self.cur_offset(-1) == Some('\n') && self.cur() == ' ' {
                 let mut current_spaces = 0;
                 while self.can_go() && self.cur() == ' ' {
                     current_spaces = current_spaces + 1;
                     self.next();
                 }
                 if current_spaces % 4 != 0 {
-                    panic!("Indentation must be a multiple of 4");
+                    return Err(self.error("Indentation must be a multiple of 4".into()));
                 }
                 let indents = current_spaces / 4;
-                for _i in 0..indents {
-                    self.final_result.push(Token::Indentation);
+                for i in 0..indents {
+                    self.push_token(Token::Indentation, self.index - current_spaces + i * 4);
                 }
             } else if self.cur().is_whitespace() {
                 //if it's whitespace and there's a pending token, add it
                 self.next();
             } else if let Some(s) = self.match_first_and_advance(operators) {
+                match s {
+                    "(" => self.bracket_depth += 1,
+                    ")" => self.bracket_depth = self.bracket_depth.saturating_sub(1),
+                    _ => {}
+                }
                 self.cur_partial_token = PartialToken::Operator(String::from(s));
                 self.commit_current_token();
             } else if self.cur().is_ascii_alphabetic() || self.cur() == '_' {
@@ -370,13 +506,13 @@ impl Tokenizer {
                 self.cur_partial_token = PartialToken::Identifier(self.clone_buf());
                 self.reset_eater_buffer();
             } else if self.cur() == '\'' || self.cur() == '"' {
-                self.eat_string_literal();
+                self.eat_string_literal()?;
                 self.cur_partial_token = PartialToken::String(self.clone_buf());
                 self.commit_current_token();
                 self.reset_eater_buffer();
                 self.next();
             } else {
-                return Err(format!("Unrecognized token {}", self.cur()));
+                return Err(self.error(format!("Unrecognized token {}", self.cur())));
             }
         }
         self.commit_current_token();
@@ -384,47 +520,53 @@ impl Tokenizer {
     }
 }
 
-pub fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+pub fn tokenize(source: &str) -> Result<Vec<SpannedToken>, LexError> {
     Tokenizer::new(source).tokenize()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    //Most tests only care about the tokens, spans are checked separately
+    fn tokenize(source: &str) -> Result<Vec<Token>, LexError> {
+        Ok(super::tokenize(source)?.into_iter().map(|t| t.token).collect())
+    }
+
     #[test]
-    fn tokenizer_simple_number() -> Result<(), String> {
+    fn tokenizer_simple_number() -> Result<(), LexError> {
         let result = tokenize("2")?;
         assert_eq!(result, [Token::LiteralInteger(2)]);
         Ok(())
     }
     #[test]
-    fn tokenizer_bigger_number() -> Result<(), String> {
+    fn tokenizer_bigger_number() -> Result<(), LexError> {
         let result = tokenize("22")?;
         assert_eq!(result, [Token::LiteralInteger(22)]);
         Ok(())
     }
     #[test]
-    fn tokenizer_decimal_number() -> Result<(), String> {
+    fn tokenizer_decimal_number() -> Result<(), LexError> {
         let result = tokenize("22.321")?;
         assert_eq!(result, [Token::LiteralFloat(Float(22.321))]);
         Ok(())
     }
 
     #[test]
-    fn tokenizer_decimal_exponent_number() -> Result<(), String> {
+    fn tokenizer_decimal_exponent_number() -> Result<(), LexError> {
         let result = tokenize("22.22e2")?;
         assert_eq!(result, [Token::LiteralFloat(Float(22.22e2))]);
         Ok(())
     }
     #[test]
-    fn tokenizer_operator() -> Result<(), String> {
+    fn tokenizer_operator() -> Result<(), LexError> {
         let result = tokenize("+")?;
         assert_eq!(result, [Token::Operator(Operator::Plus)]);
         Ok(())
     }
 
     #[test]
-    fn tokenizer_number_space_operator() -> Result<(), String> {
+    fn tokenizer_number_space_operator() -> Result<(), LexError> {
         let result = tokenize("6 +")?;
         assert_eq!(
             result,
@@ -434,7 +576,7 @@ mod tests {
     }
 
     #[test]
-    fn tokenizer_number_space_operator_space_operator() -> Result<(), String> {
+    fn tokenizer_number_space_operator_space_operator() -> Result<(), LexError> {
         let result = tokenize("6 + +")?;
         assert_eq!(
             result,
@@ -448,7 +590,7 @@ mod tests {
     }
 
     #[test]
-    fn tokenizer_not_equals() -> Result<(), String> {
+    fn tokenizer_not_equals() -> Result<(), LexError> {
         let result = tokenize("10 != 12")?;
         assert_eq!(
             result,
@@ -471,7 +613,7 @@ mod tests {
     }
 
     #[test]
-    fn tokenizer_many_operators() -> Result<(), String> {
+    fn tokenizer_many_operators() -> Result<(), LexError> {
         let result = tokenize("10 + - / * << >> != == -12")?;
         assert_eq!(
             result,
@@ -493,7 +635,7 @@ mod tests {
     }
 
     #[test]
-    fn tokenizer_number_space_operator_space_number() -> Result<(), String> {
+    fn tokenizer_number_space_operator_space_number() -> Result<(), LexError> {
         let result = tokenize("6 + 6")?;
         assert_eq!(
             result,
@@ -507,7 +649,7 @@ mod tests {
     }
 
     #[test]
-    fn tokenizer_number_space_operator_lots_of_space_number() -> Result<(), String> {
+    fn tokenizer_number_space_operator_lots_of_space_number() -> Result<(), LexError> {
         let result = tokenize("6         +                                6.2312e99")?;
         assert_eq!(
             result,
@@ -521,7 +663,7 @@ mod tests {
     }
 
     #[test]
-    fn tokenizer_number_operator_number() -> Result<(), String> {
+    fn tokenizer_number_operator_number() -> Result<(), LexError> {
         let result = tokenize("6+6")?;
         assert_eq!(
             result,
@@ -535,7 +677,7 @@ mod tests {
     }
 
     #[test]
-    fn tokenizer_space_corner_cases() -> Result<(), String> {
+    fn tokenizer_space_corner_cases() -> Result<(), LexError> {
         let result = tokenize("   6         +             6.2312e99   ")?;
         assert_eq!(
             result,
@@ -549,21 +691,21 @@ mod tests {
     }
 
     #[test]
-    fn tokenier_openparen() -> Result<(), String> {
+    fn tokenier_openparen() -> Result<(), LexError> {
         let result = tokenize("(")?;
         assert_eq!(result, [Token::OpenParen]);
         Ok(())
     }
 
     #[test]
-    fn tokenier_closeparen() -> Result<(), String> {
+    fn tokenier_closeparen() -> Result<(), LexError> {
         let result = tokenize(")")?;
         assert_eq!(result, [Token::CloseParen]);
         Ok(())
     }
 
     #[test]
-    fn tokenier_opencloseparen() -> Result<(), String> {
+    fn tokenier_opencloseparen() -> Result<(), LexError> {
         let result = tokenize("()")?;
         assert_eq!(
             result,
@@ -576,7 +718,7 @@ mod tests {
     }
 
     #[test]
-    fn tokenier_opencloseparen_with_expr() -> Result<(), String> {
+    fn tokenier_opencloseparen_with_expr() -> Result<(), LexError> {
         let result = tokenize("(1 + 2) * 3")?;
         assert_eq!(
             result,
@@ -594,14 +736,14 @@ mod tests {
     }
 
     #[test]
-    fn tokenizer_identifier() -> Result<(), String> {
+    fn tokenizer_identifier() -> Result<(), LexError> {
         let result = tokenize("some_identifier")?;
         assert_eq!(result, [Token::Identifier(String::from("some_identifier"))]);
         Ok(())
     }
 
     #[test]
-    fn tokenizer_function_call() -> Result<(), String> {
+    fn tokenizer_function_call() -> Result<(), LexError> {
         let result = tokenize("some_identifier(1)")?;
         assert_eq!(
             result,
@@ -616,7 +758,7 @@ mod tests {
     }
 
     #[test]
-    fn assign_operator() -> Result<(), String> {
+    fn assign_operator() -> Result<(), LexError> {
         let result = tokenize("x = 1")?;
         assert_eq!(
             result,
@@ -630,14 +772,14 @@ mod tests {
     }
 
     #[test]
-    fn none() -> Result<(), String> {
+    fn none() -> Result<(), LexError> {
         let result = tokenize("None")?;
         assert_eq!(result, [Token::None]);
         Ok(())
     }
 
     #[test]
-    fn boolean_tokens() -> Result<(), String> {
+    fn boolean_tokens() -> Result<(), LexError> {
         let result = tokenize("not True and False or ^")?;
         assert_eq!(
             result,
@@ -654,35 +796,35 @@ mod tests {
     }
 
     #[test]
-    fn string_literal() -> Result<(), String> {
+    fn string_literal() -> Result<(), LexError> {
         let result = tokenize("'abc'")?;
         assert_eq!(result, [Token::LiteralString(String::from("abc"))]);
         Ok(())
     }
 
     #[test]
-    fn string_literal_doublequotes() -> Result<(), String> {
+    fn string_literal_doublequotes() -> Result<(), LexError> {
         let result = tokenize("\"abc\"")?;
         assert_eq!(result, [Token::LiteralString(String::from("abc"))]);
         Ok(())
     }
 
     #[test]
-    fn string_literal_escapedouble() -> Result<(), String> {
+    fn string_literal_escapedouble() -> Result<(), LexError> {
         let result = tokenize("\"a\\\"b\\\"c\"")?;
         assert_eq!(result, [Token::LiteralString(String::from("a\"b\"c"))]);
         Ok(())
     }
 
     #[test]
-    fn string_literal_escapesingle() -> Result<(), String> {
+    fn string_literal_escapesingle() -> Result<(), LexError> {
         let result = tokenize("\'a\\'b\\'c\'")?;
         assert_eq!(result, [Token::LiteralString(String::from("a'b'c"))]);
         Ok(())
     }
 
     #[test]
-    fn tokenize_if() -> Result<(), String> {
+    fn tokenize_if() -> Result<(), LexError> {
         let result = tokenize(
             "if x == 0:
     x = x + 1",
@@ -708,7 +850,7 @@ mod tests {
     }
 
     #[test]
-    fn method_call() -> Result<(), String> {
+    fn method_call() -> Result<(), LexError> {
         let result = tokenize("obj.method")?;
         assert_eq!(
             result,
@@ -722,7 +864,7 @@ mod tests {
     }
 
     #[test]
-    fn method_call2() -> Result<(), String> {
+    fn method_call2() -> Result<(), LexError> {
         let result = tokenize("obj . method")?;
         assert_eq!(
             result,
@@ -736,7 +878,7 @@ mod tests {
     }
 
     #[test]
-    fn for_list() -> Result<(), String> {
+    fn for_list() -> Result<(), LexError> {
         let result = tokenize("for item in ls:")?;
         assert_eq!(
             result,
@@ -752,7 +894,7 @@ mod tests {
     }
 
     #[test]
-    fn def_function() -> Result<(), String> {
+    fn def_function() -> Result<(), LexError> {
         let result = tokenize("def function(x):")?;
         assert_eq!(
             result,
@@ -769,7 +911,7 @@ mod tests {
     }
 
     #[test]
-    fn return_keyword() -> Result<(), String> {
+    fn return_keyword() -> Result<(), LexError> {
         let result = tokenize("return")?;
         assert_eq!(
             result,
@@ -781,7 +923,7 @@ mod tests {
     }
 
     #[test]
-    fn raise_exception_expr() -> Result<(), String> {
+    fn raise_exception_expr() -> Result<(), LexError> {
         let result = tokenize("raise SomeError")?;
         assert_eq!(
             result,
@@ -794,7 +936,7 @@ mod tests {
     }
 
     #[test]
-    fn array_access() -> Result<(), String> {
+    fn array_access() -> Result<(), LexError> {
         let result = tokenize("array[0]")?;
         assert_eq!(
             result,
@@ -810,7 +952,7 @@ mod tests {
 
 
     #[test]
-    fn class_def() -> Result<(), String> {
+    fn class_def() -> Result<(), LexError> {
         let result = tokenize("class Test:")?;
         assert_eq!(
             result,
@@ -823,4 +965,107 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn try_except_as() -> Result<(), LexError> {
+        let result = tokenize("try:\nexcept IndexError as e:\nfinally:")?;
+        assert_eq!(
+            result,
+            [
+                Token::TryKeyword,
+                Token::Colon,
+                Token::NewLine,
+                Token::ExceptKeyword,
+                Token::Identifier("IndexError".into()),
+                Token::AsKeyword,
+                Token::Identifier("e".into()),
+                Token::Colon,
+                Token::NewLine,
+                Token::FinallyKeyword,
+                Token::Colon
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn string_literal_unknown_escape_is_error() {
+        let result = tokenize("'a\\qb'");
+        assert_eq!(result.unwrap_err().message, "cannot escape char q");
+    }
+
+    #[test]
+    fn string_literal_unterminated_is_error() {
+        assert!(tokenize("'abc").is_err());
+    }
+
+    #[test]
+    fn newlines_inside_braces_are_ignored() -> Result<(), LexError> {
+        let result = tokenize("x = {\n    1: 2,\n}\ny")?;
+        assert_eq!(
+            result,
+            [
+                Token::Identifier("x".into()),
+                Token::Assign,
+                Token::OpenBrace,
+                Token::LiteralInteger(1),
+                Token::Colon,
+                Token::LiteralInteger(2),
+                Token::Comma,
+                Token::CloseBrace,
+                Token::NewLine,
+                Token::Identifier("y".into()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn double_star_is_one_token() -> Result<(), LexError> {
+        let result = tokenize("f(*a, **b)")?;
+        assert_eq!(
+            result,
+            [
+                Token::Identifier("f".into()),
+                Token::OpenParen,
+                Token::Operator(Operator::Multiply),
+                Token::Identifier("a".into()),
+                Token::Comma,
+                Token::DoubleStar,
+                Token::Identifier("b".into()),
+                Token::CloseParen,
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn tokens_carry_line_and_column() {
+        let result = super::tokenize("x = 1\ny = x + 2").unwrap();
+        let spans: Vec<(Token, Span)> = result.into_iter().map(|t| (t.token, t.span)).collect();
+        assert_eq!(spans[0], (Token::Identifier("x".into()), Span { line: 1, column: 1 }));
+        assert_eq!(spans[2], (Token::LiteralInteger(1), Span { line: 1, column: 5 }));
+        assert_eq!(spans[7], (Token::Operator(Operator::Plus), Span { line: 2, column: 7 }));
+    }
+
+    #[test]
+    fn lex_error_points_at_the_string() {
+        let result = super::tokenize("x = 1\ny = 'abc");
+        assert_eq!(result.unwrap_err().span, Some(Span { line: 2, column: 5 }));
+    }
+
+    #[test]
+    fn truncated_input_is_not_a_crash() {
+        for source in ["x =", "x *", "a <", "f(", "l[", "obj.", "_"] {
+            assert!(super::tokenize(source).is_ok(), "{}", source);
+        }
+        for source in ["1e", "x = 2e-", "10000000000000000000000000000000000000000"] {
+            assert_eq!(super::tokenize(source).unwrap_err().span.map(|span| span.column), Some(source.find(|c: char| c.is_numeric()).unwrap() + 1));
+        }
+    }
+
+    #[test]
+    fn string_literal_ends_at_the_end_of_the_line() {
+        let result = super::tokenize("x = 'abc\ny = 'def'\n");
+        assert_eq!(result.unwrap_err().span, Some(Span { line: 1, column: 5 }));
+    }
 }
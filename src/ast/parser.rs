@@ -1,4 +1,5 @@
 use crate::ast::lexer::*;
+use crate::commons::errors::ParseError;
 use crate::commons::float::*;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -8,26 +9,106 @@ pub enum Expr {
     StringValue(String),
     BooleanValue(bool),
     None,
-    FunctionCall(Box<Expr>, Vec<Expr>),
-    IndexAccess(Box<Expr>, Box<Expr>),
+    FunctionCall(Box<Expr>, Vec<Expr>, ExprSpan),
+    IndexAccess(Box<Expr>, Box<Expr>, ExprSpan),
     Variable(String),
     BinaryOperation(Box<Expr>, Operator, Box<Expr>),
     Parenthesized(Box<Expr>),
     UnaryExpression(Operator, Box<Expr>),
     MemberAccess(Box<Expr>, String),
     Array(Vec<Expr>),
+    Tuple(Vec<Expr>),
+    Dict(Vec<(Expr, Expr)>),
+    Set(Vec<Expr>),
+    //only valid as function call arguments: `name=value`, `*iterable` and `**mapping`
+    KeywordArgument(String, Box<Expr>),
+    Starred(Box<Expr>),
+    DoubleStarred(Box<Expr>),
+    Lambda(Vec<FunctionParameter>, Box<Expr>, ExprSpan),
+    //`body if condition else orelse`, kept in source order
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    ListComprehension(Box<Expr>, Vec<ComprehensionClause>),
+    SetComprehension(Box<Expr>, Vec<ComprehensionClause>),
+    //key, value
+    DictComprehension(Box<Expr>, Box<Expr>, Vec<ComprehensionClause>),
+    GeneratorExpression(Box<Expr>, Vec<ComprehensionClause>),
+    //`yield` and `yield value`
+    Yield(Option<Box<Expr>>),
+    YieldFrom(Box<Expr>),
+}
+
+//`for target in iterable if condition if ...` inside a comprehension
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComprehensionClause {
+    pub target: Expr,
+    pub iterable: Expr,
+    pub conditions: Vec<Expr>,
+}
+
+//`name as alias` in an import statement. In `import a.b`, the name is the whole dotted path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedName {
+    pub name: String,
+    pub alias: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ASTIfStatement {
     pub expression: Expr,
-    pub statements: Vec<AST>,
+    pub statements: Vec<ASTNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ASTExceptHandler {
+    pub exception_type: Option<Expr>,
+    pub bound_name: Option<String>,
+    pub statements: Vec<ASTNode>,
+}
+
+//Where an expression that can fail on its own line starts, for calls at the open paren and
+//for subscripts at the open bracket. Not compared either, like the span of statements.
+#[derive(Debug, Clone, Copy, Default, Eq)]
+pub struct ExprSpan(pub Span);
+
+impl PartialEq for ExprSpan {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+//A statement and the position where it starts in the source.
+//The span is not compared, so the same code parsed at different positions is still equal.
+#[derive(Debug, Clone, Eq)]
+pub struct ASTNode {
+    pub ast: AST,
+    pub span: Span,
+}
+
+impl PartialEq for ASTNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.ast == other.ast
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum FunctionParameter {
     Simple(String),
-    DefaultValue(String, Expr)
+    DefaultValue(String, Expr),
+    //`*args`, or a bare `*` that only makes the following parameters keyword-only
+    VarArgs(Option<String>),
+    KwArgs(String),
+}
+
+impl FunctionParameter {
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            FunctionParameter::Simple(name)
+            | FunctionParameter::DefaultValue(name, _)
+            | FunctionParameter::VarArgs(Some(name))
+            | FunctionParameter::KwArgs(name) => Some(name),
+            FunctionParameter::VarArgs(None) => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -37,32 +118,63 @@ pub enum AST {
         path: Vec<String>,
         expression: Expr,
     },
+    //a, b = expression. Targets can be names, attributes, indexings or nested tuples.
+    UnpackAssign {
+        targets: Vec<Expr>,
+        expression: Expr,
+    },
+    //target[index] = expression
+    AssignIndex {
+        target: Expr,
+        index: Expr,
+        expression: Expr,
+    },
     IfStatement {
         true_branch: ASTIfStatement,
         elifs: Vec<ASTIfStatement>,
-        final_else: Option<Vec<AST>>,
+        final_else: Option<Vec<ASTNode>>,
     },
     WhileStatement {
         expression: Expr,
-        body: Vec<AST>,
+        body: Vec<ASTNode>,
     },
     ForStatement {
-        item_name: String,
+        //a name, or a tuple of names for `for k, v in ...`
+        target: Expr,
         list_expression: Expr,
-        body: Vec<AST>,
+        body: Vec<ASTNode>,
     },
     ClassDeclaration {
         class_name: String,
-        body: Vec<AST>,
+        bases: Vec<Expr>,
+        body: Vec<ASTNode>,
     },
     DeclareFunction {
         function_name: String,
         parameters: Vec<FunctionParameter>,
-        body: Vec<AST>,
+        body: Vec<ASTNode>,
+    },
+    TryStatement {
+        body: Vec<ASTNode>,
+        handlers: Vec<ASTExceptHandler>,
+        else_body: Option<Vec<ASTNode>>,
+        finally_body: Option<Vec<ASTNode>>,
     },
     Break,
+    Pass,
+    Global(Vec<String>),
+    Nonlocal(Vec<String>),
+    Import(Vec<ImportedName>),
+    //`from module import names`
+    ImportFrom {
+        module: String,
+        names: Vec<ImportedName>,
+    },
     Return(Option<Expr>),
-    Raise(Expr),
+    //None for a bare `raise`, which re-raises the exception being handled
+    Raise(Option<Expr>),
+    //A bare `raise` inside an except block, pointed by the compiler at the variable holding its exception
+    Reraise(String),
 }
 
 impl Expr {
@@ -103,13 +215,20 @@ fn clean_parens(expr: Expr) -> Expr {
             let right_clean = Box::new(clean_parens(*right));
             Expr::BinaryOperation(left_clean, op, right_clean)
         }
+        //`(f)(x)`, `(x)[0]` and `(1).real`: the arguments and indices were cleaned when they were parsed
+        Expr::FunctionCall(callee, args, span) => Expr::FunctionCall(Box::new(clean_parens(*callee)), args, span),
+        Expr::IndexAccess(target, index, span) => Expr::IndexAccess(Box::new(clean_parens(*target)), index, span),
+        Expr::MemberAccess(target, name) => Expr::MemberAccess(Box::new(clean_parens(*target)), name),
         _ => expr,
     }
 }
 
+static END_OF_INPUT: Token = Token::NewLine;
+
 pub struct Parser {
     parsing_state: Vec<ParsingState>,
     tokens: Vec<Token>,
+    spans: Vec<Span>,
 }
 
 struct ParsingState {
@@ -119,13 +238,14 @@ struct ParsingState {
     current_indent: usize,
 }
 
-#[derive(Debug, Clone)]
-pub enum ParsingError {
-    ExprError(String),
-}
-
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Parser {
+    pub fn new(spanned_tokens: Vec<SpannedToken>) -> Parser {
+        let mut tokens = vec![];
+        let mut spans = vec![];
+        for spanned in spanned_tokens {
+            tokens.push(spanned.token);
+            spans.push(spanned.span);
+        }
         Parser {
             parsing_state: vec![ParsingState {
                 index: 0,
@@ -134,9 +254,23 @@ impl Parser {
                 current_indent: 0,
             }],
             tokens: tokens,
+            spans,
         }
     }
 
+    //Span of the current token. At the end of the input, the span of the last token is used.
+    fn cur_span(&self) -> Span {
+        let index = self.parsing_state.last().unwrap().index;
+        match self.spans.get(index) {
+            Some(span) => *span,
+            None => self.spans.last().copied().unwrap_or_default(),
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        ParseError::at(message, self.cur_span())
+    }
+
     fn new_stack(&mut self) {
         let cur_indent = self.parsing_state.last().unwrap().current_indent;
         self.parsing_state.push(ParsingState {
@@ -191,8 +325,10 @@ impl Parser {
         self.cur_offset_opt(0)
     }
 
+    //Past the end of the input this is a newline, which ends any statement, so truncated code
+    //like `x =` is a syntax error at the end instead of a crash
     fn cur_offset(&self, offset: isize) -> &Token {
-        return self.cur_offset_opt(offset).unwrap();
+        return self.cur_offset_opt(offset).unwrap_or(&END_OF_INPUT);
     }
 
     fn cur_offset_opt(&self, offset: isize) -> Option<&Token> {
@@ -253,55 +389,55 @@ impl Parser {
         return &mut self.parsing_state.last_mut().unwrap().operator_stack;
     }
 
-    pub fn parse_assign(&mut self) -> Option<AST> {
+    pub fn parse_assign(&mut self) -> Result<Option<AST>, ParseError> {
         let mut path = vec![];
-        while let Token::Identifier(id) = self.cur().clone() {
+        while let Some(Token::Identifier(id)) = self.cur_opt().cloned() {
             path.push(id.clone());
             if self.is_last() {
-                return None;
+                return Ok(None);
             } else {
                 self.next()
             }
-            if let Token::MemberAccessor = self.cur() {
+            if let Some(Token::MemberAccessor) = self.cur_opt() {
                 self.next();
             }
         }
         if !self.can_go() {
-            return None;
+            return Ok(None);
         }
-        if let Token::Assign = self.cur() {
+        if let Some(Token::Assign) = self.cur_opt() {
             self.next();
-            let expr = self.parse_expr().expect("Expected expression after assign");
-            Some(AST::Assign {
+            let expr = self.expect_expr_or_tuple("Expected expression after assign")?;
+            Ok(Some(AST::Assign {
                 path: path,
-                expression: expr.resulting_expr,
-            })
+                expression: expr,
+            }))
         } else {
-            None
+            Ok(None)
         }
     }
 
-    pub fn parse_if_statement(&mut self) -> Option<AST> {
-        if let Token::IfKeyword = self.cur().clone() {
+    pub fn parse_if_statement(&mut self) -> Result<Option<AST>, ParseError> {
+        if let Some(Token::IfKeyword) = self.cur_opt().cloned() {
             self.next();
             if !self.can_go() {
-                None
+                Ok(None)
             } else {
-                let expr = self.parse_expr().expect("Expected expr").resulting_expr;
-                if let Token::Colon = self.cur() {
+                let expr = self.expect_expr("Expected expr")?.resulting_expr;
+                if let Some(Token::Colon) = self.cur_opt() {
                     self.next();
                 } else {
-                    panic!("Expected colon after if expr");
+                    return Err(self.error("Expected colon after if expr"));
                 }
 
-                if let Token::NewLine = self.cur() {
+                if let Some(Token::NewLine) = self.cur_opt() {
                     self.next();
                 } else {
-                    panic!("Expected newline after colon");
+                    return Err(self.error("Expected newline after colon"));
                 }
 
                 self.increment_expected_indent();
-                let ast = self.parse_ast().unwrap();
+                let ast = self.parse_ast()?;
                 let mut if_statement = AST::IfStatement {
                     true_branch: ASTIfStatement {
                         expression: expr,
@@ -318,22 +454,22 @@ impl Parser {
                 let identation_else = self.skip_whitespace_newline();
 
                 if self.can_go() && identation_else == cur_identation {
-                    if let Token::ElseKeyword = self.cur() {
+                    if let Some(Token::ElseKeyword) = self.cur_opt() {
                         self.next();
-                        if let Token::Colon = self.cur() {
+                        if let Some(Token::Colon) = self.cur_opt() {
                             self.next();
                         } else {
-                            panic!("Expected colon after if expr");
+                            return Err(self.error("Expected colon after if expr"));
                         }
 
-                        if let Token::NewLine = self.cur() {
+                        if let Some(Token::NewLine) = self.cur_opt() {
                             self.next();
                         } else {
-                            panic!("Expected newline after colon");
+                            return Err(self.error("Expected newline after colon"));
                         }
 
                         self.increment_expected_indent();
-                        let ast = self.parse_ast().unwrap();
+                        let ast = self.parse_ast()?;
                         if_statement = match if_statement {
                             AST::IfStatement {
                                 true_branch,
@@ -353,165 +489,340 @@ impl Parser {
                 } else {
                     self.pop_stack();
                 }
-                return Some(if_statement);
+                return Ok(Some(if_statement));
             }
         } else {
-            None
+            Ok(None)
         }
     }
 
-    pub fn parse_classdef(&mut self) -> Option<AST> {
-        if let Token::ClassDef = self.cur().clone() {
+    pub fn parse_classdef(&mut self) -> Result<Option<AST>, ParseError> {
+        if let Some(Token::ClassDef) = self.cur_opt().cloned() {
             self.next();
             if !self.can_go() {
-                None
+                Ok(None)
             } else {
-                if let Token::Identifier(name) = self.cur().clone() {
+                if let Some(Token::Identifier(name)) = self.cur_opt().cloned() {
                     self.next();
-                    if let Token::Colon = self.cur() {
+                    let bases = self.parse_class_bases()?;
+                    if let Some(Token::Colon) = self.cur_opt() {
                         self.next();
                     } else {
-                        panic!("Expected colon after class decl identifier");
+                        return Err(self.error("Expected colon after class decl identifier"));
                     }
-                    if let Token::NewLine = self.cur() {
+                    if let Some(Token::NewLine) = self.cur_opt() {
                         self.next();
                     } else {
-                        panic!("Expected newline after colon");
+                        return Err(self.error("Expected newline after colon"));
                     }
                     self.increment_expected_indent();
-                    let ast = self.parse_ast().unwrap();
+                    let ast = self.parse_ast()?;
                     let def_classdecl = AST::ClassDeclaration {
                         class_name: name.clone(),
+                        bases,
                         body: ast,
                     };
                     self.decrement_expected_indent();
 
-                    return Some(def_classdecl);
+                    return Ok(Some(def_classdecl));
                 } else {
-                    panic!("Unexpected token: expected identifier, got something else")
+                    return Err(self.error("Unexpected token: expected identifier, got something else"))
                 }
             }
         } else {
-            None
+            Ok(None)
+        }
+    }
+
+    //the optional `(Base1, Base2)` after the class name
+    fn parse_class_bases(&mut self) -> Result<Vec<Expr>, ParseError> {
+        if let Some(Token::OpenParen) = self.cur_opt() {
+            self.next();
+        } else {
+            return Ok(vec![]);
+        }
+        let mut bases = vec![];
+        if let Some(Token::CloseParen) = self.cur_opt() {
+            self.next();
+            return Ok(bases);
+        }
+        self.new_stack();
+        let parsed = self.parse_comma_sep_list_expr()?;
+        let popped = self.pop_stack();
+        self.set_cur(&popped);
+        bases.extend(parsed.resulting_expr_list);
+        if let Some(Token::CloseParen) = self.cur_opt() {
+            self.next();
+        } else {
+            return Err(self.error("Expected close paren after class bases"));
         }
+        return Ok(bases);
     }
 
-    pub fn parse_while_statement(&mut self) -> Option<AST> {
-        if let Token::WhileKeyword = self.cur().clone() {
+    pub fn parse_while_statement(&mut self) -> Result<Option<AST>, ParseError> {
+        if let Some(Token::WhileKeyword) = self.cur_opt().cloned() {
             self.next();
             if !self.can_go() {
-                None
+                Ok(None)
             } else {
-                let expr = self.parse_expr().expect("Expected expr").resulting_expr;
-                if let Token::Colon = self.cur() {
+                let expr = self.expect_expr("Expected expr")?.resulting_expr;
+                if let Some(Token::Colon) = self.cur_opt() {
                     self.next();
                 } else {
-                    panic!("Expected colon after while expr");
+                    return Err(self.error("Expected colon after while expr"));
                 }
 
-                if let Token::NewLine = self.cur() {
+                if let Some(Token::NewLine) = self.cur_opt() {
                     self.next();
                 } else {
-                    panic!("Expected newline after colon");
+                    return Err(self.error("Expected newline after colon"));
                 }
 
                 self.increment_expected_indent();
-                let ast = self.parse_ast().unwrap();
+                let ast = self.parse_ast()?;
                 let while_statement = AST::WhileStatement {
                     expression: expr,
                     body: ast,
                 };
                 self.decrement_expected_indent();
 
-                return Some(while_statement);
+                return Ok(Some(while_statement));
             }
         } else {
-            None
+            Ok(None)
         }
     }
 
-    pub fn parse_for_statement(&mut self) -> Option<AST> {
-        if let Token::ForKeyword = self.cur().clone() {
+    pub fn parse_for_statement(&mut self) -> Result<Option<AST>, ParseError> {
+        if let Some(Token::ForKeyword) = self.cur_opt().cloned() {
             self.next();
             if !self.can_go() {
-                None
+                Ok(None)
             } else {
-                let variable_name: String;
-                if let Token::Identifier(name) = self.cur() {
-                    variable_name = name.clone();
-                    self.next();
-                } else {
-                    panic!("Expected identifier after for keyword")
-                }
+                let target = self.parse_for_target()?;
 
-                if let Token::InKeyword = self.cur() {
+                if let Some(Token::InKeyword) = self.cur_opt() {
                     self.next();
                 } else {
-                    panic!("Expected in keyword after identifier in for keyword")
+                    return Err(self.error("Expected in keyword after identifier in for keyword"))
                 }
 
                 let expr = self
-                    .parse_expr()
-                    .expect("Expected expr after in keyword in for expression")
+                    .expect_expr("Expected expr after in keyword in for expression")?
                     .resulting_expr;
-                if let Token::Colon = self.cur() {
+                if let Some(Token::Colon) = self.cur_opt() {
                     self.next();
                 } else {
-                    panic!("Expected colon after for statement");
+                    return Err(self.error("Expected colon after for statement"));
                 }
 
-                if let Token::NewLine = self.cur() {
+                if let Some(Token::NewLine) = self.cur_opt() {
                     self.next();
                 } else {
-                    panic!("Expected newline after colon");
+                    return Err(self.error("Expected newline after colon"));
                 }
 
                 self.increment_expected_indent();
-                let ast = self.parse_ast().unwrap();
+                let ast = self.parse_ast()?;
 
                 let for_statement = AST::ForStatement {
-                    item_name: variable_name,
+                    target,
                     list_expression: expr,
                     body: ast,
                 };
                 self.decrement_expected_indent();
 
-                return Some(for_statement);
+                return Ok(Some(for_statement));
             }
         } else {
-            None
+            Ok(None)
+        }
+    }
+
+    //The names after `for`. This can't go through parse_expr because `in` would be parsed as an operator.
+    fn parse_for_target(&mut self) -> Result<Expr, ParseError> {
+        let mut targets = vec![];
+        loop {
+            match self.cur_opt().cloned() {
+                Some(Token::Identifier(name)) => {
+                    self.next();
+                    targets.push(Expr::Variable(name));
+                }
+                Some(Token::OpenParen) => {
+                    self.next();
+                    targets.push(self.parse_for_target()?);
+                    if let Some(Token::CloseParen) = self.cur_opt() {
+                        self.next();
+                    } else {
+                        return Err(self.error("Expected close paren in for target"));
+                    }
+                }
+                _ => return Err(self.error("Expected identifier after for keyword")),
+            }
+            if let Some(Token::Comma) = self.cur_opt() {
+                self.next();
+                if let Some(Token::Identifier(_)) | Some(Token::OpenParen) = self.cur_opt() {
+                    continue;
+                }
+                //trailing comma still makes a tuple: `for x, in pairs`
+                return Ok(Expr::Tuple(targets));
+            }
+            break;
         }
+        if targets.len() == 1 {
+            return Ok(targets.pop().unwrap());
+        }
+        return Ok(Expr::Tuple(targets));
     }
 
-    pub fn parse_def_statement(&mut self) -> Option<AST> {
-        if let Token::DefKeyword = self.cur().clone() {
+    pub fn parse_def_statement(&mut self) -> Result<Option<AST>, ParseError> {
+        if let Some(Token::DefKeyword) = self.cur_opt().cloned() {
             self.next();
             if !self.can_go() {
-                None
+                Ok(None)
             } else {
                 let function_name: String;
-                if let Token::Identifier(name) = self.cur() {
+                if let Some(Token::Identifier(name)) = self.cur_opt() {
                     function_name = name.clone();
                     self.next();
                 } else {
-                    panic!("Expected function identifier")
+                    return Err(self.error("Expected function identifier"))
                 }
 
-                if let Token::OpenParen = self.cur() {
+                if let Some(Token::OpenParen) = self.cur_opt() {
                     self.next();
                 } else {
-                    panic!("Expected open paren function name")
+                    return Err(self.error("Expected open paren function name"))
                 }
-                let mut params: Vec<FunctionParameter> = vec![];
+                let params = self.parse_parameters(&Token::CloseParen)?;
 
-                while let Token::Identifier(name) = self.cur() {
-                    let param_name = name.clone();
-                    
+                if let Some(Token::CloseParen) = self.cur_opt() {
                     self.next();
-                    if let Token::Comma = self.cur() {
-                        params.push(FunctionParameter::Simple(param_name));
-                        self.next();
-                    } else if let Token::Assign = self.cur() {
+                } else {
+                    return Err(self.error("Expected close paren after parameters in function declaration"))
+                }
+
+                if let Some(Token::Colon) = self.cur_opt() {
+                    self.next();
+                } else {
+                    return Err(self.error("Expected colon paren after parameters in function declaration"))
+                }
+
+                self.increment_expected_indent();
+                let ast = self.parse_ast()?;
+
+                let for_statement = AST::DeclareFunction {
+                    function_name: function_name,
+                    parameters: params,
+                    body: ast,
+                };
+                self.decrement_expected_indent();
+
+                return Ok(Some(for_statement));
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    //The names after `global` or `nonlocal`, separated by commas
+    fn parse_declared_names(&mut self, statement: &str) -> Result<Vec<String>, ParseError> {
+        let mut names = vec![];
+        loop {
+            let name = self.parse_parameter_name(&format!("Expected name in {} statement", statement))?;
+            names.push(name);
+            if let Some(Token::Comma) = self.cur_opt() {
+                self.next();
+            } else {
+                return Ok(names);
+            }
+        }
+    }
+
+    //`a.b.c`, the name of a module
+    fn parse_dotted_name(&mut self, message: &str) -> Result<String, ParseError> {
+        let mut parts = vec![self.parse_parameter_name(message)?];
+        while let Some(Token::MemberAccessor) = self.cur_opt() {
+            self.next();
+            parts.push(self.parse_parameter_name(message)?);
+        }
+        Ok(parts.join("."))
+    }
+
+    //The names after `import`, each one optionally followed by `as alias`.
+    //Only `import a.b` takes dotted names, `from a import b` imports a single name.
+    fn parse_imported_names(&mut self, dotted: bool) -> Result<Vec<ImportedName>, ParseError> {
+        let mut names = vec![];
+        loop {
+            let name = if dotted {
+                self.parse_dotted_name("Expected module name in import statement")?
+            } else {
+                self.parse_parameter_name("Expected name in import statement")?
+            };
+            let alias = match self.cur_opt() {
+                Some(Token::AsKeyword) => {
+                    self.next();
+                    Some(self.parse_parameter_name("Expected name after 'as'")?)
+                }
+                _ => None,
+            };
+            names.push(ImportedName { name, alias });
+            if let Some(Token::Comma) = self.cur_opt() {
+                self.next();
+            } else {
+                return Ok(names);
+            }
+        }
+    }
+
+    //`from a.b import c, d as e`, the names can be in parentheses
+    fn parse_from_import(&mut self) -> Result<AST, ParseError> {
+        let module = self.parse_dotted_name("Expected module name after 'from'")?;
+        if let Some(Token::ImportKeyword) = self.cur_opt() {
+            self.next();
+        } else {
+            return Err(self.error("Expected 'import' after module name"));
+        }
+        let names = if let Some(Token::OpenParen) = self.cur_opt() {
+            self.next();
+            let names = self.parse_imported_names(false)?;
+            if let Some(Token::CloseParen) = self.cur_opt() {
+                self.next();
+            } else {
+                return Err(self.error("Expected ')' after imported names"));
+            }
+            names
+        } else {
+            self.parse_imported_names(false)?
+        };
+        Ok(AST::ImportFrom { module, names })
+    }
+
+    fn parse_parameter_name(&mut self, message: &str) -> Result<String, ParseError> {
+        if let Some(Token::Identifier(name)) = self.cur_opt().cloned() {
+            self.next();
+            Ok(name)
+        } else {
+            Err(self.error(message))
+        }
+    }
+
+    //The parameter list of a def or lambda, up to the closing token (which is not consumed).
+    //Checks the same ordering rules as CPython: defaults last among the positional parameters,
+    //a single `*`, and nothing after `**kwargs`
+    fn parse_parameters(&mut self, closing: &Token) -> Result<Vec<FunctionParameter>, ParseError> {
+        let mut params: Vec<FunctionParameter> = vec![];
+        let mut seen_default = false;
+        let mut seen_star = false;
+        let mut seen_kwargs = false;
+        loop {
+            if seen_kwargs && self.cur_opt() != Some(closing) {
+                return Err(self.error("arguments cannot follow var-keyword argument"));
+            }
+            let param = match self.cur_opt().cloned() {
+                Some(Token::Identifier(param_name)) => {
+                    self.next();
+                    if let Some(Token::Assign) = self.cur_opt() {
                         self.next();
 
                         //this is potentially a default value expression
@@ -519,45 +830,208 @@ impl Parser {
 
                         match expr {
                             Ok(expr) => {
-                                params.push(FunctionParameter::DefaultValue(param_name, expr.resulting_expr));
+                                seen_default = seen_default || !seen_star;
+                                FunctionParameter::DefaultValue(param_name, expr.resulting_expr)
                             },
                             Err(e) => {
-                                panic!("Error parsing default value expr: {:?}", e);
+                                return Err(self.error(format!("Error parsing default value expr: {}", e.message)));
                             }
                         }
-
                     } else {
-                        params.push(FunctionParameter::Simple(param_name));
-                        break;
+                        //keyword-only parameters can have defaults in any order
+                        if seen_default && !seen_star {
+                            return Err(self.error("non-default argument follows default argument"));
+                        }
+                        FunctionParameter::Simple(param_name)
                     }
                 }
-
-                if let Token::CloseParen = self.cur() {
+                Some(Token::Operator(Operator::Multiply)) => {
+                    if seen_star {
+                        return Err(self.error("* argument may appear only once"));
+                    }
+                    seen_star = true;
                     self.next();
-                } else {
-                    panic!("Expected close paren after parameters in function declaration")
+                    if let Some(Token::Identifier(_)) = self.cur_opt() {
+                        FunctionParameter::VarArgs(Some(self.parse_parameter_name("Expected parameter name after *")?))
+                    } else {
+                        FunctionParameter::VarArgs(None)
+                    }
+                }
+                Some(Token::DoubleStar) => {
+                    seen_kwargs = true;
+                    self.next();
+                    FunctionParameter::KwArgs(self.parse_parameter_name("Expected parameter name after **")?)
+                }
+                _ => break,
+            };
+            if let Some(name) = param.name() {
+                if params.iter().any(|other| other.name() == Some(name)) {
+                    return Err(self.error(format!("duplicate argument '{}' in function definition", name)));
                 }
+            }
+            params.push(param);
+
+            if let Some(Token::Comma) = self.cur_opt() {
+                self.next();
+            } else {
+                break;
+            }
+        }
+
+        //a bare `*` needs at least one keyword-only parameter after it
+        let bare_star_last = matches!(
+            params.as_slice(),
+            [.., FunctionParameter::VarArgs(None)] | [.., FunctionParameter::VarArgs(None), FunctionParameter::KwArgs(_)]
+        );
+        if bare_star_last {
+            return Err(self.error("named arguments must follow bare *"));
+        }
+        Ok(params)
+    }
+
+    //Parses the colon, newline and the indented block that follows a compound statement header
+    fn parse_block_after_header(&mut self, statement_name: &str) -> Result<Vec<ASTNode>, ParseError> {
+        if let Some(Token::Colon) = self.cur_opt() {
+            self.next();
+        } else {
+            return Err(self.error(format!("Expected colon after {}", statement_name)));
+        }
+
+        if let Some(Token::NewLine) = self.cur_opt() {
+            self.next();
+        } else {
+            return Err(self.error("Expected newline after colon"));
+        }
+
+        self.increment_expected_indent();
+        let ast = self.parse_ast()?;
+        self.decrement_expected_indent();
+        return Ok(ast);
+    }
+
+    //Looks ahead for a clause keyword (except, else, finally) at the current indentation level.
+    //If it's found, the parser is moved right after the keyword, otherwise nothing is consumed.
+    fn lookahead_clause(&mut self, keyword: Token) -> bool {
+        let cur_identation = self.get_expected_indent();
+        self.new_stack();
+        let identation = self.skip_whitespace_newline();
+        if self.is_not_end() && identation == cur_identation && *self.cur() == keyword {
+            self.next();
+            let popped = self.pop_stack();
+            self.set_cur(&popped);
+            return true;
+        } else {
+            self.pop_stack();
+            return false;
+        }
+    }
+
+    pub fn parse_try_statement(&mut self) -> Result<Option<AST>, ParseError> {
+        if let Some(Token::TryKeyword) = self.cur_opt().cloned() {
+            self.next();
+            let body = self.parse_block_after_header("try")?;
+            let mut handlers = vec![];
+
+            while self.lookahead_clause(Token::ExceptKeyword) {
+                let exception_type = if let Some(Token::Colon) = self.cur_opt() {
+                    None
+                } else {
+                    Some(self.expect_expr("Expected exception type after except")?.resulting_expr)
+                };
 
-                if let Token::Colon = self.cur() {
+                let bound_name = if let Some(Token::AsKeyword) = self.cur_opt() {
                     self.next();
+                    if let Some(Token::Identifier(name)) = self.cur_opt().cloned() {
+                        self.next();
+                        Some(name)
+                    } else {
+                        return Err(self.error("Expected identifier after as keyword in except clause"))
+                    }
                 } else {
-                    panic!("Expected colon paren after parameters in function declaration")
+                    None
+                };
+
+                if bound_name.is_some() && exception_type.is_none() {
+                    return Err(self.error("Except clause binds a name but has no exception type"));
                 }
 
-                self.increment_expected_indent();
-                let ast = self.parse_ast().unwrap();
+                let statements = self.parse_block_after_header("except")?;
+                handlers.push(ASTExceptHandler {
+                    exception_type,
+                    bound_name,
+                    statements,
+                });
+            }
 
-                let for_statement = AST::DeclareFunction {
-                    function_name: function_name,
-                    parameters: params,
-                    body: ast,
-                };
-                self.decrement_expected_indent();
+            let else_body = if !handlers.is_empty() && self.lookahead_clause(Token::ElseKeyword) {
+                Some(self.parse_block_after_header("else")?)
+            } else {
+                None
+            };
 
-                return Some(for_statement);
+            let finally_body = if self.lookahead_clause(Token::FinallyKeyword) {
+                Some(self.parse_block_after_header("finally")?)
+            } else {
+                None
+            };
+
+            if handlers.is_empty() && finally_body.is_none() {
+                return Err(self.error("Expected except or finally block after try block"));
+            }
+
+            return Ok(Some(AST::TryStatement {
+                body,
+                handlers,
+                else_body,
+                finally_body,
+            }));
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn expect_expr(&mut self, message: &str) -> Result<ParseExpressionResult, ParseError> {
+        match self.parse_expr() {
+            Ok(result) => Ok(result),
+            Err(e) => Err(ParseError { message: format!("{}: {}", message, e.message), span: e.span }),
+        }
+    }
+
+    //expr, expr, ... without brackets, like in `return a, b` or `x = 1, 2`.
+    //A single expression without a comma is returned as-is.
+    fn parse_expr_or_tuple(&mut self) -> Result<Expr, ParseError> {
+        let first = self.parse_expr()?.resulting_expr;
+        if self.cur_opt() != Some(&Token::Comma) {
+            return Ok(first);
+        }
+        let mut items = vec![first];
+        while let Some(Token::Comma) = self.cur_opt() {
+            self.next();
+            //trailing comma
+            if !self.can_go() {
+                break;
             }
+            if let Token::CloseParen | Token::Assign | Token::Colon = self.cur() {
+                break;
+            }
+            items.push(self.parse_expr()?.resulting_expr);
+        }
+        return Ok(Expr::Tuple(items));
+    }
+
+    fn expect_expr_or_tuple(&mut self, message: &str) -> Result<Expr, ParseError> {
+        match self.parse_expr_or_tuple() {
+            Ok(result) => Ok(result),
+            Err(e) => Err(ParseError { message: format!("{}: {}", message, e.message), span: e.span }),
+        }
+    }
+
+    //Every statement must be followed by a newline or the end of the file
+    fn expect_end_of_statement(&self, message: &str) -> Result<(), ParseError> {
+        if !self.is_not_end() || self.cur_is_newline() {
+            Ok(())
         } else {
-            None
+            Err(self.error(format!("{}, found {:?}", message, self.cur())))
         }
     }
 
@@ -580,7 +1054,7 @@ impl Parser {
         return identation_level;
     }
 
-    pub fn parse_ast(&mut self) -> Result<Vec<AST>, ParsingError> {
+    pub fn parse_ast(&mut self) -> Result<Vec<ASTNode>, ParseError> {
         let mut results = vec![];
 
         loop {
@@ -600,20 +1074,18 @@ impl Parser {
                 return Ok(results);
             }
 
+            let statement_span = self.cur_span();
             let mut parsed_successfully = false;
 
             if !parsed_successfully {
                 self.new_stack();
-                if let Some(assign_ast) = self.parse_classdef() {
-                    results.push(assign_ast);
+                if let Some(assign_ast) = self.parse_classdef()? {
+                    results.push(ASTNode { ast: assign_ast, span: statement_span });
                     parsed_successfully = true;
                     let popped = self.pop_stack();
                     //correct indentation found: commit
                     self.set_cur(&popped);
-                    assert!(
-                        !self.is_not_end() || self.cur_is_newline(),
-                        "Newline or EOF expected after assign"
-                    );
+                    self.expect_end_of_statement("Newline or EOF expected after assign")?;
                 } else {
                     self.pop_stack();
                 }
@@ -621,16 +1093,13 @@ impl Parser {
 
             if !parsed_successfully {
                 self.new_stack();
-                if let Some(assign_ast) = self.parse_assign() {
-                    results.push(assign_ast);
+                if let Some(assign_ast) = self.parse_assign()? {
+                    results.push(ASTNode { ast: assign_ast, span: statement_span });
                     parsed_successfully = true;
                     let popped = self.pop_stack();
                     //correct indentation found: commit
                     self.set_cur(&popped);
-                    assert!(
-                        !self.is_not_end() || self.cur_is_newline(),
-                        "Newline or EOF expected after assign"
-                    );
+                    self.expect_end_of_statement("Newline or EOF expected after assign")?;
                 } else {
                     self.pop_stack();
                 }
@@ -638,18 +1107,15 @@ impl Parser {
 
             if !parsed_successfully {
                 self.new_stack();
-                let expr = self.parse_if_statement();
+                let expr = self.parse_if_statement()?;
                 match expr {
                     Some(ast_if) => {
-                        results.push(ast_if);
+                        results.push(ASTNode { ast: ast_if, span: statement_span });
                         parsed_successfully = true;
                         let popped = self.pop_stack();
                         //correct indentation found: commit
                         self.set_cur(&popped);
-                        assert!(
-                            !self.is_not_end() || self.cur_is_newline(),
-                            "Newline or EOF expected after if block"
-                        );
+                        self.expect_end_of_statement("Newline or EOF expected after if block")?;
                     }
                     None => {
                         parsed_successfully = false;
@@ -660,18 +1126,15 @@ impl Parser {
 
             if !parsed_successfully {
                 self.new_stack();
-                let expr = self.parse_while_statement();
+                let expr = self.parse_while_statement()?;
                 match expr {
                     Some(ast_if) => {
-                        results.push(ast_if);
+                        results.push(ASTNode { ast: ast_if, span: statement_span });
                         parsed_successfully = true;
                         let popped = self.pop_stack();
                         //correct indentation found: commit
                         self.set_cur(&popped);
-                        assert!(
-                            !self.is_not_end() || self.cur_is_newline(),
-                            "Newline or EOF expected after if block"
-                        );
+                        self.expect_end_of_statement("Newline or EOF expected after if block")?;
                     }
                     None => {
                         parsed_successfully = false;
@@ -682,18 +1145,15 @@ impl Parser {
 
             if !parsed_successfully {
                 self.new_stack();
-                let expr = self.parse_for_statement();
+                let expr = self.parse_for_statement()?;
                 match expr {
                     Some(ast_for) => {
-                        results.push(ast_for);
+                        results.push(ASTNode { ast: ast_for, span: statement_span });
                         parsed_successfully = true;
                         let popped = self.pop_stack();
                         //correct indentation found: commit
                         self.set_cur(&popped);
-                        assert!(
-                            !self.is_not_end() || self.cur_is_newline(),
-                            "Newline or EOF expected after for block"
-                        );
+                        self.expect_end_of_statement("Newline or EOF expected after for block")?;
                     }
                     None => {
                         parsed_successfully = false;
@@ -704,18 +1164,15 @@ impl Parser {
 
             if !parsed_successfully {
                 self.new_stack();
-                let expr = self.parse_def_statement();
+                let expr = self.parse_def_statement()?;
                 match expr {
                     Some(ast_for) => {
-                        results.push(ast_for);
+                        results.push(ASTNode { ast: ast_for, span: statement_span });
                         parsed_successfully = true;
                         let popped = self.pop_stack();
                         //correct indentation found: commit
                         self.set_cur(&popped);
-                        assert!(
-                            !self.is_not_end() || self.cur_is_newline(),
-                            "Newline or EOF expected after for block"
-                        );
+                        self.expect_end_of_statement("Newline or EOF expected after for block")?;
                     }
                     None => {
                         parsed_successfully = false;
@@ -726,22 +1183,17 @@ impl Parser {
 
             if !parsed_successfully {
                 self.new_stack();
-                let tok = self.cur();
-                match tok {
-                    Token::BreakKeyword => {
-                        results.push(AST::Break);
-                        self.next();
+                let expr = self.parse_try_statement()?;
+                match expr {
+                    Some(ast_try) => {
+                        results.push(ASTNode { ast: ast_try, span: statement_span });
                         parsed_successfully = true;
                         let popped = self.pop_stack();
                         //correct indentation found: commit
                         self.set_cur(&popped);
-                        assert!(
-                            !self.is_not_end() || self.cur_is_newline(),
-                            "Newline or EOF expected after if block, got {:?}",
-                            self.cur_opt()
-                        );
+                        self.expect_end_of_statement("Newline or EOF expected after try block")?;
                     }
-                    _ => {
+                    None => {
                         parsed_successfully = false;
                         self.pop_stack();
                     }
@@ -752,23 +1204,83 @@ impl Parser {
                 self.new_stack();
                 let tok = self.cur();
                 match tok {
-                    Token::ReturnKeyword => {
+                    Token::BreakKeyword => {
+                        results.push(ASTNode { ast: AST::Break, span: statement_span });
                         self.next();
-                        if self.can_go() {
-                            let expr = self.parse_expr()?;
-                            results.push(AST::Return(Some(expr.resulting_expr)));
-                        } else {
-                            results.push(AST::Return(None));
-                        }
                         parsed_successfully = true;
                         let popped = self.pop_stack();
                         //correct indentation found: commit
                         self.set_cur(&popped);
-                        assert!(
-                            !self.is_not_end() || self.cur_is_newline(),
-                            "Newline or EOF expected after if block, got {:?}",
-                            self.cur_opt()
-                        );
+                        self.expect_end_of_statement("Newline or EOF expected after if block")?;
+                    }
+                    Token::PassKeyword => {
+                        results.push(ASTNode { ast: AST::Pass, span: statement_span });
+                        self.next();
+                        parsed_successfully = true;
+                        let popped = self.pop_stack();
+                        self.set_cur(&popped);
+                        self.expect_end_of_statement("Newline or EOF expected after pass")?;
+                    }
+                    Token::GlobalKeyword => {
+                        self.next();
+                        let names = self.parse_declared_names("global")?;
+                        results.push(ASTNode { ast: AST::Global(names), span: statement_span });
+                        parsed_successfully = true;
+                        let popped = self.pop_stack();
+                        self.set_cur(&popped);
+                        self.expect_end_of_statement("Newline or EOF expected after global statement")?;
+                    }
+                    Token::NonlocalKeyword => {
+                        self.next();
+                        let names = self.parse_declared_names("nonlocal")?;
+                        results.push(ASTNode { ast: AST::Nonlocal(names), span: statement_span });
+                        parsed_successfully = true;
+                        let popped = self.pop_stack();
+                        self.set_cur(&popped);
+                        self.expect_end_of_statement("Newline or EOF expected after nonlocal statement")?;
+                    }
+                    Token::ImportKeyword => {
+                        self.next();
+                        let names = self.parse_imported_names(true)?;
+                        results.push(ASTNode { ast: AST::Import(names), span: statement_span });
+                        parsed_successfully = true;
+                        let popped = self.pop_stack();
+                        self.set_cur(&popped);
+                        self.expect_end_of_statement("Newline or EOF expected after import statement")?;
+                    }
+                    Token::FromKeyword => {
+                        self.next();
+                        let ast = self.parse_from_import()?;
+                        results.push(ASTNode { ast, span: statement_span });
+                        parsed_successfully = true;
+                        let popped = self.pop_stack();
+                        self.set_cur(&popped);
+                        self.expect_end_of_statement("Newline or EOF expected after import statement")?;
+                    }
+                    _ => {
+                        parsed_successfully = false;
+                        self.pop_stack();
+                    }
+                }
+            }
+
+            if !parsed_successfully {
+                self.new_stack();
+                let tok = self.cur();
+                match tok {
+                    Token::ReturnKeyword => {
+                        self.next();
+                        if self.can_go() {
+                            let expr = self.parse_expr_or_tuple()?;
+                            results.push(ASTNode { ast: AST::Return(Some(expr)), span: statement_span });
+                        } else {
+                            results.push(ASTNode { ast: AST::Return(None), span: statement_span });
+                        }
+                        parsed_successfully = true;
+                        let popped = self.pop_stack();
+                        //correct indentation found: commit
+                        self.set_cur(&popped);
+                        self.expect_end_of_statement("Newline or EOF expected after if block")?;
                     }
                     _ => {
                         parsed_successfully = false;
@@ -785,19 +1297,15 @@ impl Parser {
                         self.next();
                         if self.can_go() {
                             let expr = self.parse_expr()?;
-                            results.push(AST::Raise(expr.resulting_expr));
+                            results.push(ASTNode { ast: AST::Raise(Some(expr.resulting_expr)), span: statement_span });
                         } else {
-                            panic!("Must inform expression with raise keyword")
+                            results.push(ASTNode { ast: AST::Raise(None), span: statement_span });
                         }
                         parsed_successfully = true;
                         let popped = self.pop_stack();
                         //correct indentation found: commit
                         self.set_cur(&popped);
-                        assert!(
-                            !self.is_not_end() || self.cur_is_newline(),
-                            "Newline or EOF expected after if block, got {:?}",
-                            self.cur_opt()
-                        );
+                        self.expect_end_of_statement("Newline or EOF expected after if block")?;
                     }
                     _ => {
                         parsed_successfully = false;
@@ -808,30 +1316,42 @@ impl Parser {
 
             if !parsed_successfully {
                 self.new_stack();
-                let expr = self.parse_expr()?;
-                results.push(AST::StandaloneExpr(expr.resulting_expr));
+                let expr = self.parse_expr_or_tuple()?;
+                let ast = match (expr, self.cur_opt()) {
+                    (Expr::IndexAccess(target, index, _), Some(Token::Assign)) => {
+                        self.next();
+                        let expression = self.expect_expr_or_tuple("Expected expression after assign")?;
+                        AST::AssignIndex {
+                            target: *target,
+                            index: *index,
+                            expression,
+                        }
+                    }
+                    (Expr::Tuple(targets), Some(Token::Assign)) => {
+                        self.next();
+                        let expression = self.expect_expr_or_tuple("Expected expression after assign")?;
+                        AST::UnpackAssign { targets, expression }
+                    }
+                    (expr, _) => AST::StandaloneExpr(expr),
+                };
+                results.push(ASTNode { ast, span: statement_span });
                 let popped = self.pop_stack();
                 //correct indentation found: commit
                 self.set_cur(&popped);
                 parsed_successfully = true;
-                assert!(
-                    !self.is_not_end() || self.cur_is_newline(),
-                    "Newline or EOF expected after standalone expr, instead found {:?}",
-                    self.cur()
-                );
+                self.expect_end_of_statement("Newline or EOF expected after standalone expr")?;
             }
 
             if !parsed_successfully {
-                panic!("Could not parse code")
+                return Err(self.error("Could not parse code"))
             }
 
             if self.is_not_end() {
                 if !self.cur_is_newline() {
-                    panic!(
-                        "is not end but is also not newline, cur = {:?}, parsed = {:?}",
-                        self.cur(),
-                        results
-                    )
+                    return Err(self.error(format!(
+                        "Unexpected token {:?} after statement",
+                        self.cur()
+                    )));
                 } else {
                     continue;
                 }
@@ -850,9 +1370,9 @@ impl Parser {
     *
     */
 
-    fn index_access_helper(&mut self, expr_list_or_array: &Expr) -> Result<Expr, ParsingError> {
+    fn index_access_helper(&mut self, expr_list_or_array: &Expr, span: Span) -> Result<Expr, ParseError> {
         if let Token::CloseParen = self.cur() {
-            panic!("Invalid syntax: must inform index value");
+            return Err(self.error("Invalid syntax: must inform index value"));
         } else {
             self.new_stack();
             let list_of_exprs = self.parse_comma_sep_list_expr();
@@ -864,12 +1384,13 @@ impl Parser {
                     let popped = self.pop_stack();
                     let mut resulting_exprs = expressions.resulting_expr_list;
                     if resulting_exprs.len() > 1 {
-                        panic!("Invalid syntax: must inform only one index");
+                        return Err(self.error("Invalid syntax: must inform only one index"));
                     }
 
                     let fcall = Expr::IndexAccess(
                         Box::new(expr_list_or_array.clone()),
                         Box::new(resulting_exprs.pop().unwrap()),
+                        ExprSpan(span),
                     );
 
                     self.set_cur(&popped);
@@ -877,19 +1398,18 @@ impl Parser {
                     return Ok(fcall);
                 }
                 Err(e) => {
-                    eprintln!("Failed parsing exprssion: {:?}", e);
                     return Err(e);
                 }
             }
         }
     }
 
-    fn function_call_helper(&mut self, expr_callable: &Expr) -> Result<Expr, ParsingError> {
+    fn function_call_helper(&mut self, expr_callable: &Expr, span: Span) -> Result<Expr, ParseError> {
         if let Token::CloseParen = self.cur() {
-            return Ok(Expr::FunctionCall(Box::new(expr_callable.clone()), vec![]));
+            return Ok(Expr::FunctionCall(Box::new(expr_callable.clone()), vec![], ExprSpan(span)));
         } else {
             self.new_stack();
-            let list_of_exprs = self.parse_comma_sep_list_expr();
+            let list_of_exprs = self.parse_call_arguments();
 
             match list_of_exprs {
                 //try parse stuff
@@ -899,27 +1419,85 @@ impl Parser {
                     let resulting_exprs = expressions.resulting_expr_list;
 
                     let fcall =
-                        Expr::FunctionCall(Box::new(expr_callable.clone()), resulting_exprs);
+                        Expr::FunctionCall(Box::new(expr_callable.clone()), resulting_exprs, ExprSpan(span));
 
                     self.set_cur(&popped);
 
                     return Ok(fcall);
                 }
                 Err(e) => {
-                    eprintln!("Failed parsing exprssion: {:?}", e);
                     return Err(e);
                 }
             }
         }
     }
 
-    pub fn parse_expr(&mut self) -> Result<ParseExpressionResult, ParsingError> {
+    //A full expression: a lambda, or an operation optionally followed by `if condition else orelse`
+    pub fn parse_expr(&mut self) -> Result<ParseExpressionResult, ParseError> {
+        if let Some(Token::LambdaKeyword) = self.cur_opt() {
+            return self.parse_lambda();
+        }
+        if let Some(Token::YieldKeyword) = self.cur_opt() {
+            return self.parse_yield();
+        }
+        let body = self.parse_operation_expr()?;
+        if let Some(Token::IfKeyword) = self.cur_opt() {
+            self.next();
+            let condition = self.parse_operation_expr()?.resulting_expr;
+            if let Some(Token::ElseKeyword) = self.cur_opt() {
+                self.next();
+            } else {
+                return Err(self.error("expected 'else' after 'if' expression"));
+            }
+            //right associative: `a if x else b if y else c` is `a if x else (b if y else c)`
+            let orelse = self.expect_expr("Expected expression after 'else'")?.resulting_expr;
+            return Ok(ParseExpressionResult {
+                resulting_expr: Expr::Conditional(Box::new(body.resulting_expr), Box::new(condition), Box::new(orelse)),
+            });
+        }
+        Ok(body)
+    }
+
+    fn parse_lambda(&mut self) -> Result<ParseExpressionResult, ParseError> {
+        let span = self.cur_span();
+        self.next();
+        let params = self.parse_parameters(&Token::Colon)?;
+        if let Some(Token::Colon) = self.cur_opt() {
+            self.next();
+        } else {
+            return Err(self.error("Expected colon after lambda parameters"));
+        }
+        let body = self.expect_expr("Expected expression in lambda body")?.resulting_expr;
+        Ok(ParseExpressionResult {
+            resulting_expr: Expr::Lambda(params, Box::new(body), ExprSpan(span)),
+        })
+    }
+
+    fn parse_yield(&mut self) -> Result<ParseExpressionResult, ParseError> {
+        self.next();
+        let resulting_expr = match self.cur_opt() {
+            Some(Token::FromKeyword) => {
+                self.next();
+                let iterable = self.expect_expr("Expected expression after 'yield from'")?.resulting_expr;
+                Expr::YieldFrom(Box::new(iterable))
+            }
+            None | Some(Token::NewLine) | Some(Token::CloseParen) => Expr::Yield(None),
+            Some(_) => Expr::Yield(Some(Box::new(self.expect_expr_or_tuple("Expected expression after 'yield'")?))),
+        };
+        Ok(ParseExpressionResult { resulting_expr })
+    }
+
+    //Operators, operands, calls and literals, without the lambda and conditional forms
+    fn parse_operation_expr(&mut self) -> Result<ParseExpressionResult, ParseError> {
+        //the last token was an operator, so the expression can't end yet
+        let mut expects_operand = false;
         loop {
             if !self.can_go() {
                 break;
             }
             let mut was_operand = false;
             let mut not_part_of_expr = false;
+            let is_operator = matches!(self.cur(), Token::Operator(_) | Token::InKeyword);
             //if there is an open paren, we collect all the tokens for this open paren
             //and parse the sub-expression recursively
             {
@@ -940,7 +1518,7 @@ impl Parser {
                         // - `function_call()` and then `(` would work if the function returns another function
                         let mut could_be_fcall = true;
 
-                        if let Some(Token::Operator(_)) = prev_token {
+                        if let Some(Token::Operator(_)) | Some(Token::InKeyword) = prev_token {
                             could_be_fcall = false;
                         }
                         if let None = prev_token {
@@ -964,6 +1542,7 @@ impl Parser {
                             //like it's part of an expression, then maybe we should just understand that the expression has been finished.
                             //Let's ensure that we have no operators pending
                             if !self.operand_stack().is_empty() {
+                                let span = self.cur_span();
                                 self.next();
                                 let current_expr = self.operand_stack_mut().pop().unwrap();
 
@@ -972,7 +1551,7 @@ impl Parser {
                                 //because the parenthesis invokes a call over the result of the whole right-side expr.
                                 match current_expr {
                                     Expr::BinaryOperation(left, op, right) => {
-                                        let right_side_fcall = self.function_call_helper(&right)?;
+                                        let right_side_fcall = self.function_call_helper(&right, span)?;
                                         self.push_operand(Expr::BinaryOperation(
                                             left,
                                             op,
@@ -980,7 +1559,7 @@ impl Parser {
                                         ));
                                     }
                                     expr => {
-                                        let fcall = self.function_call_helper(&expr)?;
+                                        let fcall = self.function_call_helper(&expr, span)?;
                                         self.push_operand(fcall);
                                     }
                                 }
@@ -990,21 +1569,30 @@ impl Parser {
                         } else {
                             self.new_stack(); //new parsing stack/state
                             self.next();
-                            match self.parse_expr() {
+                            //`()` is the empty tuple, otherwise a comma inside the parens makes a tuple
+                            let parsed = if let Some(Token::CloseParen) = self.cur_opt() {
+                                Ok(Expr::Tuple(vec![]))
+                            } else {
+                                self.parse_expr_or_tuple()
+                            };
+                            match parsed {
                                 //try parse stuff
-                                Ok(expr_result) => {
+                                Ok(resulting_expr) => {
                                     //worked
                                     //commit the result
-                                    let resulting_expr = expr_result.resulting_expr;
-                                    let parenthesized =
-                                        Expr::Parenthesized(Box::new(resulting_expr));
+                                    let parenthesized = match resulting_expr {
+                                        Expr::Tuple(items) => Expr::Tuple(items),
+                                        expr if self.cur_opt() == Some(&Token::ForKeyword) => {
+                                            Expr::GeneratorExpression(Box::new(expr), self.parse_comprehension_clauses()?)
+                                        }
+                                        expr => Expr::Parenthesized(Box::new(expr)),
+                                    };
                                     let popped = self.pop_stack();
                                     self.push_operand(parenthesized);
                                     self.set_cur(&popped);
                                     was_operand = true;
                                 }
                                 Err(e) => {
-                                    eprintln!("Failed parsing exprssion: {:?}", e);
                                     return Err(e);
                                 }
                             }
@@ -1013,7 +1601,7 @@ impl Parser {
                     Token::OpenArrayBracket => {
                         let mut could_be_indexing = true;
 
-                        if let Some(Token::Operator(_)) = prev_token {
+                        if let Some(Token::Operator(_)) | Some(Token::InKeyword) = prev_token {
                             //in this case, it could be operators being applied to 2 lists, like a concat
                             could_be_indexing = false;
                         }
@@ -1032,6 +1620,7 @@ impl Parser {
                             //for now it doesn't
 
                             if !self.operand_stack().is_empty() {
+                                let span = self.cur_span();
                                 self.next();
                                 let current_expr = self.operand_stack_mut().pop().unwrap();
 
@@ -1043,7 +1632,7 @@ impl Parser {
                                 match current_expr {
                                     Expr::BinaryOperation(left, op, right) => {
                                         let right_side_index_access =
-                                            self.index_access_helper(&right)?;
+                                            self.index_access_helper(&right, span)?;
                                         self.push_operand(Expr::BinaryOperation(
                                             left,
                                             op,
@@ -1051,7 +1640,7 @@ impl Parser {
                                         ));
                                     }
                                     expr => {
-                                        let index_access = self.index_access_helper(&expr)?;
+                                        let index_access = self.index_access_helper(&expr, span)?;
                                         self.push_operand(index_access);
                                     }
                                 }
@@ -1062,7 +1651,10 @@ impl Parser {
                             self.new_stack(); //new parsing stack/state
                             self.next(); //move to the first token, out of the open array
                             if let Token::CloseArrayBracket = self.cur() {
+                                let popped = self.pop_stack();
                                 self.push_operand(Expr::Array(vec![]));
+                                self.set_cur(&popped);
+                                was_operand = true;
                             } else {
                                 let list_of_exprs = self.parse_comma_sep_list_expr();
                                 match list_of_exprs {
@@ -1070,19 +1662,38 @@ impl Parser {
                                     Ok(expressions) => {
                                         //worked
                                         //commit the result
+                                        let mut resulting_exprs = expressions.resulting_expr_list;
+                                        //the element of a comprehension is followed by `for` instead of a comma
+                                        let list = if let Some(Token::ForKeyword) = self.cur_opt() {
+                                            if resulting_exprs.len() > 1 {
+                                                return Err(self.error("did you forget parentheses around the comprehension target?"));
+                                            }
+                                            let element = resulting_exprs.pop().unwrap();
+                                            Expr::ListComprehension(Box::new(element), self.parse_comprehension_clauses()?)
+                                        } else {
+                                            Expr::Array(resulting_exprs)
+                                        };
                                         let popped = self.pop_stack();
-                                        let resulting_exprs = expressions.resulting_expr_list;
-                                        self.push_operand(Expr::Array(resulting_exprs));
+                                        self.push_operand(list);
                                         self.set_cur(&popped);
+                                        was_operand = true;
                                     }
                                     Err(e) => {
-                                        eprintln!("Failed parsing exprssion: {:?}", e);
                                         return Err(e);
                                     }
                                 }
                             }
                         }
                     }
+                    Token::OpenBrace => {
+                        self.new_stack(); //new parsing stack/state
+                        self.next(); //move to the first token, out of the open brace
+                        let display = self.parse_brace_display()?;
+                        let popped = self.pop_stack();
+                        self.push_operand(display);
+                        self.set_cur(&popped);
+                        was_operand = true;
+                    }
                     Token::Identifier(identifier_str) => {
                         self.push_operand(Expr::Variable(identifier_str.to_string()));
                         was_operand = true;
@@ -1094,13 +1705,12 @@ impl Parser {
                         let cur_token = self.cur();
                         if let Token::Identifier(name) = cur_token {
                             let cur_expr = popped.unwrap();
-                            let member_access_expr =
-                                Expr::MemberAccess(Box::new(cur_expr), name.to_string());
+                            let member_access_expr = member_access_on_last_operand(cur_expr, name);
                             self.push_operand(member_access_expr);
                             was_operand = true;
                         } else {
-                            return Err(ParsingError::ExprError(
-                                "Failed parsing member acessor".into(),
+                            return Err(self.error(
+                                "Failed parsing member acessor",
                             ));
                         }
                     }
@@ -1131,6 +1741,15 @@ impl Parser {
                     Token::CloseParen | Token::CloseArrayBracket => {
                         not_part_of_expr = true;
                     }
+                    Token::InKeyword => self.push_operator(Operator::In),
+                    //`not in` is a single operator when it comes after an operand
+                    Token::Operator(Operator::Not)
+                        if !self.operand_stack().is_empty()
+                            && self.cur_offset_opt(1) == Some(&Token::InKeyword) =>
+                    {
+                        self.next();
+                        self.push_operator(Operator::NotIn);
+                    }
                     Token::Operator(o) => self.push_operator(o),
                     _ => {
                         not_part_of_expr = true;
@@ -1140,6 +1759,7 @@ impl Parser {
             if not_part_of_expr {
                 break;
             } else {
+                expects_operand = is_operator;
                 self.next();
                 if self.can_go() {
                     if Token::MemberAccessor == self.cur().clone() {
@@ -1213,6 +1833,10 @@ impl Parser {
             }
         }
 
+        if expects_operand {
+            return Err(self.error("Invalid syntax: expected an expression after the operator"));
+        }
+
         //consume the remaining operators
         if self.operand_stack().len() == 1 {
             while self.operator_stack().len() > 0 {
@@ -1223,7 +1847,7 @@ impl Parser {
         }
 
         if !self.operator_stack().is_empty() {
-            return Err(ParsingError::ExprError(format!(
+            return Err(self.error(format!(
                 "Unparsed operators: {:?}, operands = {:?}",
                 self.operator_stack(),
                 self.operand_stack()
@@ -1231,14 +1855,14 @@ impl Parser {
         }
 
         if self.operand_stack().len() > 1 {
-            return Err(ParsingError::ExprError(format!(
+            return Err(self.error(format!(
                 "Unparsed operands: {:?}",
                 self.operand_stack()
             )));
         }
 
         if self.operand_stack().is_empty() {
-            return Err(ParsingError::ExprError(String::from(
+            return Err(self.error(String::from(
                 "Empty operand stack, didn't parse anything",
             )));
         }
@@ -1250,27 +1874,166 @@ impl Parser {
         })
     }
 
-    //expr, expr, ..., expr
-    fn parse_comma_sep_list_expr(&mut self) -> Result<ParseListExpressionResult, ParsingError> {
-        let mut expressions = vec![];
+    //What goes between braces: a dict, a set, or a dict or set comprehension.
+    //The closing brace is not consumed.
+    fn parse_brace_display(&mut self) -> Result<Expr, ParseError> {
+        if let Some(Token::CloseBrace) = self.cur_opt() {
+            return Ok(Expr::Dict(vec![]));
+        }
+        let first = self.expect_expr("Expected expression after open brace")?.resulting_expr;
+        match self.cur_opt() {
+            Some(Token::Colon) => {
+                self.next();
+                let value = self.expect_expr("Expected value in dict literal")?.resulting_expr;
+                if let Some(Token::ForKeyword) = self.cur_opt() {
+                    let clauses = self.parse_comprehension_clauses()?;
+                    return Ok(Expr::DictComprehension(Box::new(first), Box::new(value), clauses));
+                }
+                let mut entries = vec![(first, value)];
+                self.parse_dict_entries(&mut entries)?;
+                Ok(Expr::Dict(entries))
+            }
+            Some(Token::ForKeyword) => Ok(Expr::SetComprehension(Box::new(first), self.parse_comprehension_clauses()?)),
+            _ => {
+                let mut elements = vec![first];
+                while let Some(Token::Comma) = self.cur_opt() {
+                    self.next();
+                    if let Some(Token::CloseBrace) = self.cur_opt() {
+                        break;
+                    }
+                    elements.push(self.expect_expr("Expected expression in set literal")?.resulting_expr);
+                }
+                match self.cur_opt() {
+                    Some(Token::CloseBrace) => Ok(Expr::Set(elements)),
+                    _ => Err(self.error("Expected comma or closing brace in set literal")),
+                }
+            }
+        }
+    }
+
+    //, key: value, key: value, ... after the first entry, until the closing brace
+    fn parse_dict_entries(&mut self, entries: &mut Vec<(Expr, Expr)>) -> Result<(), ParseError> {
         loop {
-            let parse_result = self.parse_expr();
+            match self.cur_opt() {
+                Some(Token::Comma) => self.next(),
+                Some(Token::CloseBrace) => return Ok(()),
+                _ => return Err(self.error("Expected comma or closing brace in dict literal")),
+            }
+            match self.cur_opt() {
+                Some(Token::CloseBrace) => return Ok(()),
+                None => return Err(self.error("Unclosed brace in dict literal")),
+                _ => {}
+            }
+            let key = self.expect_expr("Expected key in dict literal")?.resulting_expr;
+            if let Some(Token::Colon) = self.cur_opt() {
+                self.next();
+            } else {
+                return Err(self.error("Expected colon after key in dict literal"));
+            }
+            let value = self.expect_expr("Expected value in dict literal")?.resulting_expr;
+            entries.push((key, value));
+        }
+    }
 
-            match parse_result {
-                Ok(r) => {
-                    expressions.push(r.resulting_expr);
+    //One or more `for target in iterable` clauses, each followed by any number of `if condition`.
+    //The iterable and the conditions are parsed without the conditional expression,
+    //otherwise the `if` of the next condition would be taken as part of it.
+    fn parse_comprehension_clauses(&mut self) -> Result<Vec<ComprehensionClause>, ParseError> {
+        let mut clauses = vec![];
+        while let Some(Token::ForKeyword) = self.cur_opt() {
+            self.next();
+            let target = self.parse_for_target()?;
+            if let Some(Token::InKeyword) = self.cur_opt() {
+                self.next();
+            } else {
+                return Err(self.error("Expected 'in' after comprehension target"));
+            }
+            let iterable = self.parse_operation_expr()?.resulting_expr;
+            let mut conditions = vec![];
+            while let Some(Token::IfKeyword) = self.cur_opt() {
+                self.next();
+                conditions.push(self.parse_operation_expr()?.resulting_expr);
+            }
+            clauses.push(ComprehensionClause { target, iterable, conditions });
+        }
+        Ok(clauses)
+    }
+
+    //expr, expr, ..., expr
+    //Like parse_comma_sep_list_expr, but also accepts `name=value`, `*iterable` and `**mapping`.
+    //Stops at the close paren, which is left for the caller.
+    fn parse_call_arguments(&mut self) -> Result<ParseListExpressionResult, ParseError> {
+        let mut expressions: Vec<Expr> = vec![];
+        loop {
+            if let Some(Token::CloseParen) = self.cur_opt() {
+                break;
+            }
+            let is_keyword = matches!(
+                (self.cur_opt(), self.cur_offset_opt(1)),
+                (Some(Token::Identifier(_)), Some(Token::Assign))
+            );
+            let argument = if is_keyword {
+                let name = match self.cur().clone() {
+                    Token::Identifier(name) => name,
+                    _ => unreachable!(),
+                };
+                self.next();
+                self.next();
+                if expressions.iter().any(|e| matches!(e, Expr::KeywordArgument(other, _) if *other == name)) {
+                    return Err(self.error(format!("keyword argument repeated: {}", name)));
                 }
-                Err(e) => {
-                    eprintln!("Error on parse: {:?}", e);
-                    break;
+                Expr::KeywordArgument(name, Box::new(self.expect_expr("Expected expression after keyword argument")?.resulting_expr))
+            } else if let Some(Token::Operator(Operator::Multiply)) = self.cur_opt() {
+                self.next();
+                if expressions.iter().any(|e| matches!(e, Expr::DoubleStarred(_))) {
+                    return Err(self.error("iterable argument unpacking follows keyword argument unpacking"));
                 }
+                Expr::Starred(Box::new(self.expect_expr("Expected expression after *")?.resulting_expr))
+            } else if let Some(Token::DoubleStar) = self.cur_opt() {
+                self.next();
+                Expr::DoubleStarred(Box::new(self.expect_expr("Expected expression after **")?.resulting_expr))
+            } else {
+                if expressions.iter().any(|e| matches!(e, Expr::DoubleStarred(_))) {
+                    return Err(self.error("positional argument follows keyword argument unpacking"));
+                }
+                if expressions.iter().any(|e| matches!(e, Expr::KeywordArgument(..))) {
+                    return Err(self.error("positional argument follows keyword argument"));
+                }
+                let argument = self.expect_expr("Expected expression in function call arguments")?.resulting_expr;
+                //`f(x for x in xs)`, the generator doesn't need its own parens when it's the only argument
+                if let Some(Token::ForKeyword) = self.cur_opt() {
+                    let generator = Expr::GeneratorExpression(Box::new(argument), self.parse_comprehension_clauses()?);
+                    if !expressions.is_empty() || self.cur_opt() != Some(&Token::CloseParen) {
+                        return Err(self.error("Generator expression must be parenthesized"));
+                    }
+                    generator
+                } else {
+                    argument
+                }
+            };
+            expressions.push(argument);
+
+            if let Some(Token::Comma) = self.cur_opt() {
+                self.next();
+            } else {
+                break;
             }
+        }
 
-            if self.can_go() {
-                if let Token::Comma = self.cur() {
-                    self.next();
-                    continue;
-                } else {
+        Ok(ParseListExpressionResult {
+            resulting_expr_list: expressions,
+        })
+    }
+
+    fn parse_comma_sep_list_expr(&mut self) -> Result<ParseListExpressionResult, ParseError> {
+        let mut expressions = vec![];
+        loop {
+            expressions.push(self.parse_expr()?.resulting_expr);
+
+            if let Some(Token::Comma) = self.cur_opt() {
+                self.next();
+                //a trailing comma ends the list
+                if !self.can_go() || matches!(self.cur(), Token::CloseParen | Token::CloseArrayBracket | Token::CloseBrace) {
                     break;
                 }
             } else {
@@ -1279,7 +2042,7 @@ impl Parser {
         }
 
         if expressions.is_empty() {
-            return Err(ParsingError::ExprError(String::from("While parsing list of expressions: no expression was found. Deal with edge cases before calling this expr.")));
+            return Err(self.error(String::from("While parsing list of expressions: no expression was found. Deal with edge cases before calling this expr.")));
         }
 
         Ok(ParseListExpressionResult {
@@ -1288,6 +2051,20 @@ impl Parser {
     }
 }
 
+//After a call like `"a" + f()` the operands may already be folded into a binary operation.
+//The member access binds tighter, so it goes to the rightmost operand: `"a" + (f().name)`
+fn member_access_on_last_operand(expr: Expr, name: &str) -> Expr {
+    match expr {
+        Expr::BinaryOperation(left, op, right) => {
+            Expr::BinaryOperation(left, op, Box::new(member_access_on_last_operand(*right, name)))
+        }
+        Expr::UnaryExpression(op, operand) => {
+            Expr::UnaryExpression(op, Box::new(member_access_on_last_operand(*operand, name)))
+        }
+        expr => Expr::MemberAccess(Box::new(expr), name.to_string()),
+    }
+}
+
 struct ParseListExpressionResult {
     //remaining_tokens: Vec<&'a Token>,
     resulting_expr_list: Vec<Expr>,
@@ -1298,9 +2075,30 @@ pub struct ParseExpressionResult {
     resulting_expr: Expr,
 }
 
-pub fn parse_ast(tokens: Vec<Token>) -> Vec<AST> {
+//The parser takes the end of the input as the closing bracket, so `x = [1,` is caught here
+fn check_brackets_closed(tokens: &[SpannedToken]) -> Result<(), ParseError> {
+    let mut open = vec![];
+    for spanned in tokens {
+        match spanned.token {
+            Token::OpenParen => open.push(("(", spanned.span)),
+            Token::OpenArrayBracket => open.push(("[", spanned.span)),
+            Token::OpenBrace => open.push(("{", spanned.span)),
+            Token::CloseParen | Token::CloseArrayBracket | Token::CloseBrace => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    match open.pop() {
+        Some((bracket, span)) => Err(ParseError::at(format!("'{}' was never closed", bracket), span)),
+        None => Ok(()),
+    }
+}
+
+pub fn parse_ast(tokens: Vec<SpannedToken>) -> Result<Vec<ASTNode>, ParseError> {
+    check_brackets_closed(&tokens)?;
     let mut parser = Parser::new(tokens);
-    return parser.parse_ast().unwrap();
+    return parser.parse_ast();
 }
 
 #[cfg(test)]
@@ -1308,11 +2106,17 @@ mod tests {
     use super::*;
 
     //Parses a single expression
-    fn parse(tokens: Vec<Token>) -> Expr {
-        let mut parser = Parser::new(tokens);
+    fn parse<T: Into<SpannedToken>>(tokens: Vec<T>) -> Expr {
+        let spanned = tokens.into_iter().map(|token| token.into()).collect();
+        let mut parser = Parser::new(spanned);
         return parser.parse_expr().unwrap().resulting_expr;
     }
 
+    //Statement with an unspecified position, spans are ignored when comparing nodes
+    fn node(ast: AST) -> ASTNode {
+        ASTNode { ast, span: Span::default() }
+    }
+
     #[test]
     fn multiline_code() {
         let tokens = tokenize(
@@ -1321,17 +2125,17 @@ x = 'abc' + 'cde'
 y = x + str(True)",
         )
         .unwrap();
-        let result = parse_ast(tokens);
+        let result = parse_ast(tokens).unwrap();
         let expected = vec![
-            AST::Assign {
+            node(AST::Assign {
                 path: vec![String::from("x")],
                 expression: Expr::BinaryOperation(
                     Box::new(Expr::StringValue(String::from("abc"))),
                     Operator::Plus,
                     Box::new(Expr::StringValue(String::from("cde"))),
                 ),
-            },
-            AST::Assign {
+            }),
+            node(AST::Assign {
                 path: vec![String::from("y")],
                 expression: Expr::BinaryOperation(
                     Box::new(Expr::Variable(String::from("x"))),
@@ -1339,9 +2143,10 @@ y = x + str(True)",
                     Box::new(Expr::FunctionCall(
                         Box::new(Expr::Variable(String::from("str"))),
                         vec![Expr::BooleanValue(true)],
+                        ExprSpan::default(),
                     )),
                 ),
-            },
+            }),
         ];
 
         assert_eq!(expected, result);
@@ -1358,17 +2163,17 @@ while True:
         )
         .unwrap();
 
-        let result = parse_ast(tokens);
-        let expected = vec![AST::WhileStatement {
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::WhileStatement {
             expression: Expr::BooleanValue(true),
             body: vec![
-                AST::Assign {
+                node(AST::Assign {
                     path: vec![String::from("x")],
                     expression: Expr::IntegerValue(1),
-                },
-                AST::Break,
+                }),
+                node(AST::Break),
             ],
-        }];
+        })];
         assert_eq!(expected, result);
     }
 
@@ -1383,14 +2188,14 @@ while True:
         )
         .unwrap();
 
-        let result = parse_ast(tokens);
-        let expected = vec![AST::WhileStatement {
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::WhileStatement {
             expression: Expr::BinaryOperation(
                 Box::new(Expr::Variable("x".to_string())),
                 Operator::Less,
                 Box::new(Expr::IntegerValue(1000000)),
             ),
-            body: vec![AST::IfStatement {
+            body: vec![node(AST::IfStatement {
                 true_branch: ASTIfStatement {
                     expression: Expr::BinaryOperation(
                         Box::new(Expr::BinaryOperation(
@@ -1401,12 +2206,12 @@ while True:
                         Operator::Equals,
                         Box::new(Expr::IntegerValue(0)),
                     ),
-                    statements: vec![AST::Break],
+                    statements: vec![node(AST::Break)],
                 },
                 elifs: vec![],
                 final_else: None,
-            }],
-        }];
+            })],
+        })];
         assert_eq!(expected, result);
     }
 
@@ -1425,51 +2230,53 @@ print(x)
         )
         .unwrap();
 
-        let result = parse_ast(tokens);
+        let result = parse_ast(tokens).unwrap();
         let expected = vec![
-            AST::IfStatement {
+            node(AST::IfStatement {
                 true_branch: ASTIfStatement {
                     expression: Expr::BinaryOperation(
                         Box::new(Expr::Variable(String::from("x"))),
                         Operator::Equals,
                         Box::new(Expr::IntegerValue(0)),
                     ),
-                    statements: vec![AST::Assign {
+                    statements: vec![node(AST::Assign {
                         path: vec![String::from("x")],
                         expression: Expr::BinaryOperation(
                             Box::new(Expr::Variable(String::from("x"))),
                             Operator::Plus,
                             Box::new(Expr::IntegerValue(1)),
                         ),
-                    }],
+                    })],
                 },
                 elifs: vec![],
                 final_else: Some(vec![
-                    AST::Assign {
+                    node(AST::Assign {
                         path: vec![String::from("x")],
                         expression: Expr::IntegerValue(999),
-                    },
-                    AST::IfStatement {
+                    }),
+                    node(AST::IfStatement {
                         true_branch: ASTIfStatement {
                             expression: Expr::BinaryOperation(
                                 Box::new(Expr::Variable(String::from("x"))),
                                 Operator::Equals,
                                 Box::new(Expr::IntegerValue(1)),
                             ),
-                            statements: vec![AST::StandaloneExpr(Expr::FunctionCall(
+                            statements: vec![node(AST::StandaloneExpr(Expr::FunctionCall(
                                 Box::new(Expr::Variable(String::from("print"))),
                                 vec![Expr::IntegerValue(2)],
-                            ))],
+                                ExprSpan::default(),
+                            )))],
                         },
                         elifs: vec![],
                         final_else: None,
-                    },
+                    }),
                 ]),
-            },
-            AST::StandaloneExpr(Expr::FunctionCall(
+            }),
+            node(AST::StandaloneExpr(Expr::FunctionCall(
                 Box::new(Expr::Variable(String::from("print"))),
                 vec![Expr::Variable(String::from("x"))],
-            )),
+                ExprSpan::default(),
+            ))),
         ];
         assert_eq!(expected, result);
     }
@@ -1499,9 +2306,9 @@ print(x)
 
         let tokens = tokenize(source_replaced.as_str()).unwrap();
 
-        let result = parse_ast(tokens);
+        let result = parse_ast(tokens).unwrap();
         let expected = vec![
-            AST::IfStatement {
+            node(AST::IfStatement {
                 true_branch: ASTIfStatement {
                     expression: Expr::BinaryOperation(
                         Box::new(Expr::Variable(String::from("x"))),
@@ -1509,38 +2316,40 @@ print(x)
                         Box::new(Expr::IntegerValue(0)),
                     ),
                     statements: vec![
-                        AST::Assign {
+                        node(AST::Assign {
                             path: vec![String::from("x")],
                             expression: Expr::BinaryOperation(
                                 Box::new(Expr::Variable(String::from("x"))),
                                 Operator::Plus,
                                 Box::new(Expr::IntegerValue(1)),
                             ),
-                        },
-                        AST::IfStatement {
+                        }),
+                        node(AST::IfStatement {
                             true_branch: ASTIfStatement {
                                 expression: Expr::BinaryOperation(
                                     Box::new(Expr::Variable(String::from("x"))),
                                     Operator::Equals,
                                     Box::new(Expr::IntegerValue(1)),
                                 ),
-                                statements: vec![AST::StandaloneExpr(Expr::FunctionCall(
+                                statements: vec![node(AST::StandaloneExpr(Expr::FunctionCall(
                                     Box::new(Expr::Variable(String::from("print"))),
                                     vec![Expr::IntegerValue(2)],
-                                ))],
+                                    ExprSpan::default(),
+                                )))],
                             },
                             elifs: vec![],
                             final_else: None,
-                        },
+                        }),
                     ],
                 },
                 elifs: vec![],
                 final_else: None,
-            },
-            AST::StandaloneExpr(Expr::FunctionCall(
+            }),
+            node(AST::StandaloneExpr(Expr::FunctionCall(
                 Box::new(Expr::Variable(String::from("print"))),
                 vec![Expr::Variable(String::from("x"))],
-            )),
+                ExprSpan::default(),
+            ))),
         ];
         assert_eq!(expected, result);
     }
@@ -1553,26 +2362,26 @@ if x == 0:
     x = x + 1",
         )
         .unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::IfStatement {
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::IfStatement {
             true_branch: ASTIfStatement {
                 expression: Expr::BinaryOperation(
                     Box::new(Expr::Variable(String::from("x"))),
                     Operator::Equals,
                     Box::new(Expr::IntegerValue(0)),
                 ),
-                statements: vec![AST::Assign {
+                statements: vec![node(AST::Assign {
                     path: vec![String::from("x")],
                     expression: Expr::BinaryOperation(
                         Box::new(Expr::Variable(String::from("x"))),
                         Operator::Plus,
                         Box::new(Expr::IntegerValue(1)),
                     ),
-                }],
+                })],
             },
             elifs: vec![],
             final_else: None,
-        }];
+        })];
         assert_eq!(expected, result);
     }
 
@@ -1584,31 +2393,32 @@ if x == 0:
 print(x)",
         )
         .unwrap();
-        let result = parse_ast(tokens);
+        let result = parse_ast(tokens).unwrap();
         let expected = vec![
-            AST::IfStatement {
+            node(AST::IfStatement {
                 true_branch: ASTIfStatement {
                     expression: Expr::BinaryOperation(
                         Box::new(Expr::Variable(String::from("x"))),
                         Operator::Equals,
                         Box::new(Expr::IntegerValue(0)),
                     ),
-                    statements: vec![AST::Assign {
+                    statements: vec![node(AST::Assign {
                         path: vec![String::from("x")],
                         expression: Expr::BinaryOperation(
                             Box::new(Expr::Variable(String::from("x"))),
                             Operator::Plus,
                             Box::new(Expr::IntegerValue(1)),
                         ),
-                    }],
+                    })],
                 },
                 elifs: vec![],
                 final_else: None,
-            },
-            AST::StandaloneExpr(Expr::FunctionCall(
+            }),
+            node(AST::StandaloneExpr(Expr::FunctionCall(
                 Box::new(Expr::Variable(String::from("print"))),
                 vec![Expr::Variable(String::from("x"))],
-            )),
+                ExprSpan::default(),
+            ))),
         ];
         assert_eq!(expected, result);
     }
@@ -1621,17 +2431,17 @@ y = x + str(True)
 print(y)",
         )
         .unwrap();
-        let result = parse_ast(tokens);
+        let result = parse_ast(tokens).unwrap();
         let expected = vec![
-            AST::Assign {
+            node(AST::Assign {
                 path: vec![String::from("x")],
                 expression: Expr::BinaryOperation(
                     Box::new(Expr::StringValue(String::from("abc"))),
                     Operator::Plus,
                     Box::new(Expr::StringValue(String::from("cde"))),
                 ),
-            },
-            AST::Assign {
+            }),
+            node(AST::Assign {
                 path: vec![String::from("y")],
                 expression: Expr::BinaryOperation(
                     Box::new(Expr::Variable(String::from("x"))),
@@ -1639,13 +2449,15 @@ print(y)",
                     Box::new(Expr::FunctionCall(
                         Box::new(Expr::Variable(String::from("str"))),
                         vec![Expr::BooleanValue(true)],
+                        ExprSpan::default(),
                     )),
                 ),
-            },
-            AST::StandaloneExpr(Expr::FunctionCall(
+            }),
+            node(AST::StandaloneExpr(Expr::FunctionCall(
                 Box::new(Expr::Variable(String::from("print"))),
                 vec![Expr::Variable(String::from("y"))],
-            )),
+                ExprSpan::default(),
+            ))),
         ];
 
         assert_eq!(expected, result);
@@ -2032,6 +2844,7 @@ print(y)",
         let expected = Expr::FunctionCall(
             Box::new(Expr::Variable(String::from("some_identifier"))),
             vec![],
+            ExprSpan::default(),
         );
 
         assert_eq!(expected, result);
@@ -2044,6 +2857,7 @@ print(y)",
         let expected = Expr::FunctionCall(
             Box::new(Expr::Variable(String::from("some_identifier"))),
             vec![Expr::IntegerValue(1)],
+            ExprSpan::default(),
         );
 
         assert_eq!(expected, result);
@@ -2060,6 +2874,7 @@ print(y)",
                 Expr::IntegerValue(2),
                 Expr::IntegerValue(3),
             ],
+            ExprSpan::default(),
         );
 
         assert_eq!(expected, result);
@@ -2076,6 +2891,7 @@ print(y)",
                 Operator::Multiply,
                 2.into(),
             )],
+            ExprSpan::default(),
         );
 
         assert_eq!(expected, result);
@@ -2092,6 +2908,7 @@ print(y)",
                 Expr::BinaryOperation(3.into(), Operator::Plus, 5.into()),
                 Expr::IntegerValue(88),
             ],
+            ExprSpan::default(),
         );
         assert_eq!(expected, result);
     }
@@ -2105,7 +2922,9 @@ print(y)",
             vec![Expr::FunctionCall(
                 Box::new(Expr::Variable(String::from("nested"))),
                 vec![],
+                ExprSpan::default(),
             )],
+            ExprSpan::default(),
         );
         assert_eq!(expected, result);
     }
@@ -2119,7 +2938,9 @@ print(y)",
             vec![Expr::FunctionCall(
                 Box::new(Expr::Variable(String::from("nested"))),
                 vec![Expr::IntegerValue(1)],
+                ExprSpan::default(),
             )],
+            ExprSpan::default(),
         );
         assert_eq!(expected, result);
     }
@@ -2133,7 +2954,9 @@ print(y)",
             vec![Expr::FunctionCall(
                 Box::new(Expr::Variable(String::from("nested"))),
                 vec![Expr::IntegerValue(1), Expr::IntegerValue(2)],
+                ExprSpan::default(),
             )],
+            ExprSpan::default(),
         );
         assert_eq!(expected, result);
     }
@@ -2150,7 +2973,9 @@ print(y)",
                     Expr::BinaryOperation(1.into(), Operator::Multiply, 2.into()),
                     Expr::BinaryOperation(2.into(), Operator::Divide, (3.4).into()),
                 ],
+                ExprSpan::default(),
             )],
+            ExprSpan::default(),
         );
         assert_eq!(expected, result);
     }
@@ -2165,9 +2990,11 @@ print(y)",
                 Expr::FunctionCall(
                     Box::new(Expr::Variable(String::from("nested"))),
                     vec![Expr::IntegerValue(1)],
+                    ExprSpan::default(),
                 ),
                 Expr::IntegerValue(1),
             ],
+            ExprSpan::default(),
         );
         assert_eq!(expected, result);
     }
@@ -2185,10 +3012,12 @@ print(y)",
                         Expr::BinaryOperation(1.into(), Operator::Multiply, 2.into()),
                         Expr::BinaryOperation(2.into(), Operator::Divide, (3.4).into()),
                     ],
+                    ExprSpan::default(),
                 ),
                 Expr::IntegerValue(3),
-                Expr::FunctionCall(Box::new(Expr::Variable(String::from("nested2"))), vec![]),
+                Expr::FunctionCall(Box::new(Expr::Variable(String::from("nested2"))), vec![], ExprSpan::default()),
             ],
+            ExprSpan::default(),
         );
         assert_eq!(expected, result);
     }
@@ -2200,6 +3029,7 @@ print(y)",
         let call = Expr::FunctionCall(
             Box::new(Expr::Variable(String::from("some_identifier"))),
             vec![Expr::IntegerValue(1)],
+            ExprSpan::default(),
         );
         let expected = Expr::BinaryOperation(Box::new(call), Operator::Multiply, 5.into());
         assert_eq!(expected, result);
@@ -2212,6 +3042,7 @@ print(y)",
         let call = Expr::FunctionCall(
             Box::new(Expr::Variable(String::from("some_identifier"))),
             vec![Expr::IntegerValue(1), Expr::IntegerValue(2)],
+            ExprSpan::default(),
         );
         let expected = Expr::BinaryOperation(Box::new(call), Operator::Multiply, 5.into());
         assert_eq!(expected, result);
@@ -2226,7 +3057,9 @@ print(y)",
             vec![Expr::FunctionCall(
                 Box::new(Expr::Variable(String::from("nested"))),
                 vec![],
+                ExprSpan::default(),
             )],
+            ExprSpan::default(),
         );
         let expected = Expr::BinaryOperation(Box::new(call), Operator::Multiply, 5.into());
         assert_eq!(expected, result);
@@ -2240,8 +3073,9 @@ print(y)",
             Box::new(Expr::Variable(String::from("some_identifier"))),
             vec![
                 Expr::IntegerValue(1),
-                Expr::FunctionCall(Box::new(Expr::Variable(String::from("nested"))), vec![]),
+                Expr::FunctionCall(Box::new(Expr::Variable(String::from("nested"))), vec![], ExprSpan::default()),
             ],
+            ExprSpan::default(),
         );
         let expected = Expr::BinaryOperation(Box::new(call), Operator::Multiply, 5.into());
         assert_eq!(expected, result);
@@ -2260,10 +3094,12 @@ print(y)",
                         Expr::BinaryOperation(1.into(), Operator::Multiply, 2.into()),
                         Expr::BinaryOperation(2.into(), Operator::Divide, (3.4).into()),
                     ],
+                    ExprSpan::default(),
                 ),
                 Expr::IntegerValue(3),
-                Expr::FunctionCall(Box::new(Expr::Variable(String::from("nested2"))), vec![]),
+                Expr::FunctionCall(Box::new(Expr::Variable(String::from("nested2"))), vec![], ExprSpan::default()),
             ],
+            ExprSpan::default(),
         );
         let expected = Expr::BinaryOperation(Box::new(call), Operator::Multiply, 5.into());
         assert_eq!(expected, result);
@@ -2282,10 +3118,12 @@ print(y)",
                         Expr::BinaryOperation(1.into(), Operator::Multiply, 2.into()),
                         Expr::BinaryOperation(2.into(), Operator::Divide, (3.4).into()),
                     ],
+                    ExprSpan::default(),
                 ),
                 Expr::IntegerValue(3),
-                Expr::FunctionCall(Box::new(Expr::Variable(String::from("nested2"))), vec![]),
+                Expr::FunctionCall(Box::new(Expr::Variable(String::from("nested2"))), vec![], ExprSpan::default()),
             ],
+            ExprSpan::default(),
         );
         let expected = Expr::BinaryOperation(5.into(), Operator::Multiply, Box::new(call));
         assert_eq!(expected, result);
@@ -2298,6 +3136,7 @@ print(y)",
         let expected = Expr::FunctionCall(
             Box::new(Expr::Variable(String::from("func"))),
             vec![Expr::IntegerValue(1)],
+            ExprSpan::default(),
         );
         assert_eq!(expected, result);
     }
@@ -2312,6 +3151,7 @@ print(y)",
             Box::new(Expr::FunctionCall(
                 Box::new(Expr::Variable(String::from("func"))),
                 vec![Expr::IntegerValue(1)],
+                ExprSpan::default(),
             )),
         );
         assert_eq!(expected, result);
@@ -2325,6 +3165,7 @@ print(y)",
             Box::new(Expr::FunctionCall(
                 Box::new(Expr::Variable(String::from("func"))),
                 vec![Expr::IntegerValue(1)],
+                ExprSpan::default(),
             )),
             Operator::Multiply,
             (2).into(),
@@ -2340,11 +3181,13 @@ print(y)",
             Box::new(Expr::FunctionCall(
                 Box::new(Expr::Variable(String::from("func"))),
                 vec![Expr::IntegerValue(1)],
+                ExprSpan::default(),
             )),
             Operator::Multiply,
             Box::new(Expr::FunctionCall(
                 Box::new(Expr::Variable(String::from("func"))),
                 vec![Expr::IntegerValue(2)],
+                ExprSpan::default(),
             )),
         );
         assert_eq!(expected, result);
@@ -2452,6 +3295,7 @@ print(y)",
                         Box::new(Expr::UnaryExpression(Operator::Minus, 1.into())),
                     )),
                 )],
+                ExprSpan::default(),
             )),
         );
         assert_eq!(expected, result);
@@ -2515,46 +3359,46 @@ print(y)",
     #[test]
     fn test_assign() {
         let tokens = tokenize("x = 1").unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::Assign {
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::Assign {
             path: vec![String::from("x")],
             expression: Expr::IntegerValue(1),
-        }];
+        })];
         assert_eq!(expected, result);
     }
 
     #[test]
     fn test_parse_ast_first_token_is_identifier() {
         let tokens = tokenize("x * 1").unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::StandaloneExpr(Expr::BinaryOperation(
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::StandaloneExpr(Expr::BinaryOperation(
             Box::new(Expr::Variable(String::from("x"))),
             Operator::Multiply,
             1.into(),
-        ))];
+        )))];
         assert_eq!(expected, result);
     }
 
     #[test]
     fn test_parse_assign_expr() {
         let tokens = tokenize("x = x * 1").unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::Assign {
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::Assign {
             path: vec![String::from("x")],
             expression: Expr::BinaryOperation(
                 Box::new(Expr::Variable(String::from("x"))),
                 Operator::Multiply,
                 1.into(),
             ),
-        }];
+        })];
         assert_eq!(expected, result);
     }
 
     #[test]
     fn test_parse_just_id_ast() {
         let tokens = tokenize("x").unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::StandaloneExpr(Expr::Variable(String::from("x")))];
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::StandaloneExpr(Expr::Variable(String::from("x"))))];
         assert_eq!(expected, result);
     }
 
@@ -2595,8 +3439,8 @@ print(y)",
     #[test]
     fn assign_boolean_expr() {
         let tokens = tokenize("x = not (True and False) or (False)").unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::Assign {
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::Assign {
             path: vec![String::from("x")],
             expression: Expr::BinaryOperation(
                 Box::new(Expr::UnaryExpression(
@@ -2610,7 +3454,7 @@ print(y)",
                 Operator::Or,
                 Box::new(Expr::BooleanValue(false)),
             ),
-        }];
+        })];
 
         assert_eq!(expected, result);
     }
@@ -2618,11 +3462,11 @@ print(y)",
     #[test]
     fn assign_string_expr() {
         let tokens = tokenize("x = 'abc'").unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::Assign {
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::Assign {
             path: vec![String::from("x")],
             expression: Expr::StringValue(String::from("abc")),
-        }];
+        })];
 
         assert_eq!(expected, result);
     }
@@ -2630,15 +3474,15 @@ print(y)",
     #[test]
     fn assign_string_concat_expr() {
         let tokens = tokenize("x = 'abc' + 'cde'").unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::Assign {
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::Assign {
             path: vec![String::from("x")],
             expression: Expr::BinaryOperation(
                 Box::new(Expr::StringValue(String::from("abc"))),
                 Operator::Plus,
                 Box::new(Expr::StringValue(String::from("cde"))),
             ),
-        }];
+        })];
 
         assert_eq!(expected, result);
     }
@@ -2683,15 +3527,58 @@ print(y)",
         assert_eq!(expected, result);
     }
 
+    #[test]
+    fn dict_of_stuff() {
+        let tokens = tokenize("{'one': 1, 2: [3]}").unwrap();
+        let result = parse(tokens);
+        let expected = Expr::Dict(vec![
+            (Expr::StringValue("one".to_string()), Expr::IntegerValue(1)),
+            (Expr::IntegerValue(2), Expr::Array(vec![Expr::IntegerValue(3)])),
+        ]);
+
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn empty_dict() {
+        let tokens = tokenize("{}").unwrap();
+        let result = parse(tokens);
+        assert_eq!(Expr::Dict(vec![]), result);
+    }
+
+    #[test]
+    fn not_in_operator() {
+        let tokens = tokenize("x not in y").unwrap();
+        let result = parse(tokens);
+        let expected = Expr::BinaryOperation(
+            Box::new(Expr::Variable("x".into())),
+            Operator::NotIn,
+            Box::new(Expr::Variable("y".into())),
+        );
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn assign_index() {
+        let tokens = tokenize("d['k'] = 1").unwrap();
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::AssignIndex {
+            target: Expr::Variable("d".into()),
+            index: Expr::StringValue("k".into()),
+            expression: Expr::IntegerValue(1),
+        })];
+        assert_eq!(expected, result);
+    }
+
     #[test]
     fn assign_array() {
         let tokens = tokenize("x = [1, 2]").unwrap();
-        let result = parse_ast(tokens);
+        let result = parse_ast(tokens).unwrap();
         let expr = Expr::Array(vec![Expr::IntegerValue(1), Expr::IntegerValue(2)]);
-        let expected = vec![AST::Assign {
+        let expected = vec![node(AST::Assign {
             path: vec![String::from("x")],
             expression: expr,
-        }];
+        })];
         assert_eq!(expected, result);
     }
 
@@ -2699,11 +3586,11 @@ print(y)",
     fn member_acessor() {
         let tokens = tokenize("obj.prop").unwrap();
         println!("{:?}", tokens);
-        let result = parse_ast(tokens);
-        let expected = vec![AST::StandaloneExpr(Expr::MemberAccess(
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::StandaloneExpr(Expr::MemberAccess(
             Box::new(Expr::Variable("obj".into())),
             "prop".into(),
-        ))];
+        )))];
         assert_eq!(expected, result);
     }
 
@@ -2711,11 +3598,11 @@ print(y)",
     fn assign_member() {
         let tokens = tokenize("obj.prop = 1").unwrap();
         println!("{:?}", tokens);
-        let result = parse_ast(tokens);
-        let expected = vec![AST::Assign {
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::Assign {
             path: vec!["obj".into(), "prop".into()],
             expression: Expr::IntegerValue(1),
-        }];
+        })];
         assert_eq!(expected, result);
     }
 
@@ -2723,8 +3610,8 @@ print(y)",
     fn member_compare() {
         let tokens = tokenize("self.current >= self.max").unwrap();
         println!("{:?}", tokens);
-        let result = parse_ast(tokens);
-        let expected = vec![AST::StandaloneExpr(
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::StandaloneExpr(
             Expr::BinaryOperation(
                 Box::new(Expr::MemberAccess(
                     Box::new(Expr::Variable("self".into())),
@@ -2736,7 +3623,64 @@ print(y)",
                     "max".into(),
                 ))
             )    
-        )];
+        ))];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn tuple_literals() {
+        let tokens = tokenize("(1, 'two')").unwrap();
+        let expected = Expr::Tuple(vec![Expr::IntegerValue(1), Expr::StringValue("two".into())]);
+        assert_eq!(expected, parse(tokens));
+
+        let tokens = tokenize("(1,)").unwrap();
+        assert_eq!(Expr::Tuple(vec![Expr::IntegerValue(1)]), parse(tokens));
+
+        let tokens = tokenize("()").unwrap();
+        assert_eq!(Expr::Tuple(vec![]), parse(tokens));
+
+        let tokens = tokenize("(1)").unwrap();
+        assert_eq!(Expr::IntegerValue(1), parse(tokens));
+    }
+
+    #[test]
+    fn swap_with_bare_tuples() {
+        let tokens = tokenize("a, b = b, a").unwrap();
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::UnpackAssign {
+            targets: vec![Expr::Variable("a".into()), Expr::Variable("b".into())],
+            expression: Expr::Tuple(vec![Expr::Variable("b".into()), Expr::Variable("a".into())]),
+        })];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn return_bare_tuple() {
+        let tokens = tokenize("return x, y").unwrap();
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::Return(Some(Expr::Tuple(vec![
+            Expr::Variable("x".into()),
+            Expr::Variable("y".into()),
+        ]))))];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn for_unpacking_pairs() {
+        let tokens = tokenize("for k, (a, b) in pairs:\n    print(k)").unwrap();
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::ForStatement {
+            target: Expr::Tuple(vec![
+                Expr::Variable("k".into()),
+                Expr::Tuple(vec![Expr::Variable("a".into()), Expr::Variable("b".into())]),
+            ]),
+            list_expression: Expr::Variable("pairs".into()),
+            body: vec![node(AST::StandaloneExpr(Expr::FunctionCall(
+                Box::new(Expr::Variable("print".into())),
+                vec![Expr::Variable("k".into())],
+                ExprSpan::default(),
+            )))],
+        })];
         assert_eq!(expected, result);
     }
 
@@ -2749,15 +3693,16 @@ for item in list:
 ",
         )
         .unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::ForStatement {
-            item_name: "item".into(),
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::ForStatement {
+            target: Expr::Variable("item".into()),
             list_expression: Expr::Variable("list".into()),
-            body: vec![AST::StandaloneExpr(Expr::FunctionCall(
+            body: vec![node(AST::StandaloneExpr(Expr::FunctionCall(
                 Box::new(Expr::Variable("print".into())),
                 vec![Expr::Variable("item".into())],
-            ))],
-        }];
+                ExprSpan::default(),
+            )))],
+        })];
         assert_eq!(expected, result);
     }
 
@@ -2770,15 +3715,16 @@ def function(x):
 ",
         )
         .unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::DeclareFunction {
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::DeclareFunction {
             function_name: "function".into(),
             parameters: vec![FunctionParameter::Simple("x".into())],
-            body: vec![AST::StandaloneExpr(Expr::FunctionCall(
+            body: vec![node(AST::StandaloneExpr(Expr::FunctionCall(
                 Box::new(Expr::Variable("print".into())),
                 vec![Expr::Variable("x".into())],
-            ))],
-        }];
+                ExprSpan::default(),
+            )))],
+        })];
         assert_eq!(expected, result);
     }
 
@@ -2791,15 +3737,16 @@ def function():
 ",
         )
         .unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::DeclareFunction {
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::DeclareFunction {
             function_name: "function".into(),
             parameters: vec![],
-            body: vec![AST::StandaloneExpr(Expr::FunctionCall(
+            body: vec![node(AST::StandaloneExpr(Expr::FunctionCall(
                 Box::new(Expr::Variable("print".into())),
                 vec![Expr::Variable("x".into())],
-            ))],
-        }];
+                ExprSpan::default(),
+            )))],
+        })];
         assert_eq!(expected, result);
     }
 
@@ -2812,17 +3759,18 @@ def function(x,y,z):
 ",
         )
         .unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::DeclareFunction {
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::DeclareFunction {
             function_name: "function".into(),
             parameters: vec![FunctionParameter::Simple("x".into()), 
                              FunctionParameter::Simple("y".into()), 
                              FunctionParameter::Simple("z".into())],
-            body: vec![AST::StandaloneExpr(Expr::FunctionCall(
+            body: vec![node(AST::StandaloneExpr(Expr::FunctionCall(
                 Box::new(Expr::Variable("print".into())),
                 vec![Expr::Variable("x".into())],
-            ))],
-        }];
+                ExprSpan::default(),
+            )))],
+        })];
         assert_eq!(expected, result);
     }
 
@@ -2835,12 +3783,12 @@ def function(x):
 ",
         )
         .unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::DeclareFunction {
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::DeclareFunction {
             function_name: "function".into(),
             parameters: vec![FunctionParameter::Simple("x".into())],
-            body: vec![AST::Return(None)],
-        }];
+            body: vec![node(AST::Return(None))],
+        })];
         assert_eq!(expected, result);
     }
 
@@ -2853,16 +3801,16 @@ def function(x):
 ",
         )
         .unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::DeclareFunction {
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::DeclareFunction {
             function_name: "function".into(),
             parameters: vec![FunctionParameter::Simple("x".into())],
-            body: vec![AST::Return(Some(Expr::BinaryOperation(
+            body: vec![node(AST::Return(Some(Expr::BinaryOperation(
                 Box::new(Expr::Variable("x".into())),
                 Operator::Plus,
                 Box::new(Expr::IntegerValue(1)),
-            )))],
-        }];
+            ))))],
+        })];
         assert_eq!(expected, result);
     }
 
@@ -2875,16 +3823,52 @@ def function(x, y = None):
 ",
         )
         .unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::DeclareFunction {
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::DeclareFunction {
             function_name: "function".into(),
             parameters: vec![FunctionParameter::Simple("x".into()), FunctionParameter::DefaultValue("y".into(), Expr::None)],
-            body: vec![AST::Return(Some(Expr::BinaryOperation(
+            body: vec![node(AST::Return(Some(Expr::BinaryOperation(
                 Box::new(Expr::Variable("x".into())),
                 Operator::Plus,
                 Box::new(Expr::Variable("y".into())),
-            )))],
-        }];
+            ))))],
+        })];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn class_definition_with_bases() {
+        let tokens = tokenize(
+            "
+class Child(Base, mixins.Other):
+    pass
+",
+        )
+        .unwrap();
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::ClassDeclaration {
+            class_name: "Child".into(),
+            bases: vec![
+                Expr::Variable("Base".into()),
+                Expr::MemberAccess(Box::new(Expr::Variable("mixins".into())), "Other".into()),
+            ],
+            body: vec![node(AST::Pass)],
+        })];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn member_access_on_call_result_in_binary_operation() {
+        let tokens = tokenize("\"a\" + f().name").unwrap();
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::StandaloneExpr(Expr::BinaryOperation(
+            Box::new(Expr::StringValue("a".into())),
+            Operator::Plus,
+            Box::new(Expr::MemberAccess(
+                Box::new(Expr::FunctionCall(Box::new(Expr::Variable("f".into())), vec![], ExprSpan::default())),
+                "name".into(),
+            )),
+        )))];
         assert_eq!(expected, result);
     }
 
@@ -2906,65 +3890,68 @@ class SomeClass:
 ",
         )
         .unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::ClassDeclaration {
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::ClassDeclaration {
             class_name: "SomeClass".into(),
+            bases: vec![],
             body: vec![
-                AST::Assign {
+                node(AST::Assign {
                     path: vec!["y".into()],
                     expression: Expr::IntegerValue(99),
-                },
-                AST::DeclareFunction {
+                }),
+                node(AST::DeclareFunction {
                     function_name: "__init__".into(),
                     parameters: vec![FunctionParameter::Simple("self".into())],
-                    body: vec![AST::Assign {
+                    body: vec![node(AST::Assign {
                         path: vec!["self".into(), "x".into()],
                         expression: Expr::IntegerValue(1),
-                    }],
-                },
-                AST::DeclareFunction {
+                    })],
+                }),
+                node(AST::DeclareFunction {
                     function_name: "test".into(),
                     parameters: vec![FunctionParameter::Simple("self".into())],
-                    body: vec![AST::Return(Some(Expr::BinaryOperation(
+                    body: vec![node(AST::Return(Some(Expr::BinaryOperation(
                         Box::new(Expr::MemberAccess(
                             Box::new(Expr::Variable("self".into())),
                             "x".into(),
                         )),
                         Operator::Plus,
                         Box::new(Expr::IntegerValue(1)),
-                    )))],
-                },
-                AST::DeclareFunction {
+                    ))))],
+                }),
+                node(AST::DeclareFunction {
                     function_name: "static".into(),
                     parameters: vec![],
-                    body: vec![AST::Return(Some(Expr::StringValue(
+                    body: vec![node(AST::Return(Some(Expr::StringValue(
                         "static func".to_owned(),
-                    )))],
-                },
+                    ))))],
+                }),
             ],
-        }];
+        })];
         assert_eq!(expected, result);
     }
 
     #[test]
     fn access_at_index() {
         let tokens = tokenize("list[1]").unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::StandaloneExpr(Expr::IndexAccess(
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::StandaloneExpr(Expr::IndexAccess(
             Box::new(Expr::Variable("list".into())),
             Box::new(Expr::IntegerValue(1)),
-        ))];
+            ExprSpan::default(),
+        )))];
         assert_eq!(expected, result);
     }
 
     #[test]
     fn access_at_string() {
         let tokens = tokenize("a_map[\"value\"]").unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::StandaloneExpr(Expr::IndexAccess(
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::StandaloneExpr(Expr::IndexAccess(
             Box::new(Expr::Variable("a_map".into())),
             Box::new(Expr::StringValue("value".into())),
-        ))];
+            ExprSpan::default(),
+        )))];
         assert_eq!(expected, result);
     }
 
@@ -2972,82 +3959,455 @@ class SomeClass:
     fn access_at_list() {
         //this is crazy
         let tokens = tokenize("a_map[[]]").unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::StandaloneExpr(Expr::IndexAccess(
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::StandaloneExpr(Expr::IndexAccess(
             Box::new(Expr::Variable("a_map".into())),
             Box::new(Expr::Array(vec![])),
-        ))];
+            ExprSpan::default(),
+        )))];
         assert_eq!(expected, result);
     }
 
     #[test]
     fn function_return_indexed() {
         let tokens = tokenize("some_call()[1]").unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::StandaloneExpr(Expr::IndexAccess(
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::StandaloneExpr(Expr::IndexAccess(
             Box::new(Expr::FunctionCall(
                 Box::new(Expr::Variable("some_call".into())),
                 vec![],
+                ExprSpan::default(),
             )),
             Box::new(Expr::IntegerValue(1)),
-        ))];
+            ExprSpan::default(),
+        )))];
         assert_eq!(expected, result);
     }
 
     #[test]
     fn function_argument_is_indexed() {
         let tokens = tokenize("some_call(var[1])").unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::StandaloneExpr(
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::StandaloneExpr(
             Expr::FunctionCall(
                 Box::new(Expr::Variable("some_call".into())),
                 vec![Expr::IndexAccess(
                     Box::new(Expr::Variable("var".into())),
                     Box::new(Expr::IntegerValue(1))
-                )],
+                , ExprSpan::default())],
+                ExprSpan::default(),
             )
-        )];
+        ))];
         assert_eq!(expected, result);
     }
 
     #[test]
     fn method_call_empty() {
         let tokens = tokenize("method.call()").unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::StandaloneExpr(Expr::FunctionCall(
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::StandaloneExpr(Expr::FunctionCall(
             Box::new(Expr::MemberAccess(
                 Box::new(Expr::Variable("method".into())),
                 "call".into(),
             )),
             vec![],
-        ))];
+            ExprSpan::default(),
+        )))];
         assert_eq!(expected, result);
     }
     #[test]
     fn method_call_oneparam() {
         let tokens = tokenize("method.call(1)").unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::StandaloneExpr(Expr::FunctionCall(
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::StandaloneExpr(Expr::FunctionCall(
             Box::new(Expr::MemberAccess(
                 Box::new(Expr::Variable("method".into())),
                 "call".into(),
             )),
             vec![Expr::IntegerValue(1)],
-        ))];
+            ExprSpan::default(),
+        )))];
         assert_eq!(expected, result);
     }
 
     #[test]
     fn method_call_manyparam() {
         let tokens = tokenize("method.call(1, 2)").unwrap();
-        let result = parse_ast(tokens);
-        let expected = vec![AST::StandaloneExpr(Expr::FunctionCall(
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::StandaloneExpr(Expr::FunctionCall(
             Box::new(Expr::MemberAccess(
                 Box::new(Expr::Variable("method".into())),
                 "call".into(),
             )),
             vec![Expr::IntegerValue(1), Expr::IntegerValue(2)],
-        ))];
+            ExprSpan::default(),
+        )))];
         assert_eq!(expected, result);
     }
+
+    #[test]
+    fn try_except_else_finally() {
+        let tokens = tokenize(
+            "
+try:
+    x = 1
+except IndexError as e:
+    x = 2
+except:
+    x = 3
+else:
+    x = 4
+finally:
+    x = 5
+",
+        )
+        .unwrap();
+        let result = parse_ast(tokens).unwrap();
+        let assign = |i: i128| node(AST::Assign {
+            path: vec!["x".into()],
+            expression: Expr::IntegerValue(i),
+        });
+        let expected = vec![node(AST::TryStatement {
+            body: vec![assign(1)],
+            handlers: vec![
+                ASTExceptHandler {
+                    exception_type: Some(Expr::Variable("IndexError".into())),
+                    bound_name: Some("e".into()),
+                    statements: vec![assign(2)],
+                },
+                ASTExceptHandler {
+                    exception_type: None,
+                    bound_name: None,
+                    statements: vec![assign(3)],
+                },
+            ],
+            else_body: Some(vec![assign(4)]),
+            finally_body: Some(vec![assign(5)]),
+        })];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn try_finally_nested_in_function() {
+        let tokens = tokenize(
+            "
+def function():
+    try:
+        return 1
+    finally:
+        print(2)
+    return 3
+",
+        )
+        .unwrap();
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::DeclareFunction {
+            function_name: "function".into(),
+            parameters: vec![],
+            body: vec![
+                node(AST::TryStatement {
+                    body: vec![node(AST::Return(Some(Expr::IntegerValue(1))))],
+                    handlers: vec![],
+                    else_body: None,
+                    finally_body: Some(vec![node(AST::StandaloneExpr(Expr::FunctionCall(
+                        Box::new(Expr::Variable("print".into())),
+                        vec![Expr::IntegerValue(2)],
+                        ExprSpan::default(),
+                    )))]),
+                }),
+                node(AST::Return(Some(Expr::IntegerValue(3)))),
+            ],
+        })];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn if_without_colon_is_error() {
+        let tokens = tokenize("if x\n    y = 1").unwrap();
+        let result = parse_ast(tokens);
+        assert_eq!(
+            result,
+            Err(ParseError::at("Expected colon after if expr", Span { line: 1, column: 5 }))
+        );
+    }
+
+    #[test]
+    fn statements_carry_their_line() {
+        let tokens = tokenize("x = 1\n\ny = 2").unwrap();
+        let result = parse_ast(tokens).unwrap();
+        assert_eq!(result[0].span, Span { line: 1, column: 1 });
+        assert_eq!(result[1].span, Span { line: 3, column: 1 });
+    }
+
+    #[test]
+    fn function_definition_with_varargs_kwonly_and_kwargs() {
+        let tokens = tokenize(
+            "
+def f(a, b=1, *args, c, **kw):
+    pass
+",
+        )
+        .unwrap();
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::DeclareFunction {
+            function_name: "f".into(),
+            parameters: vec![
+                FunctionParameter::Simple("a".into()),
+                FunctionParameter::DefaultValue("b".into(), Expr::IntegerValue(1)),
+                FunctionParameter::VarArgs(Some("args".into())),
+                FunctionParameter::Simple("c".into()),
+                FunctionParameter::KwArgs("kw".into()),
+            ],
+            body: vec![node(AST::Pass)],
+        })];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn function_call_with_keyword_and_unpacked_arguments() {
+        let tokens = tokenize("f(1, *rest, key=2, **options)").unwrap();
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::StandaloneExpr(Expr::FunctionCall(
+            Box::new(Expr::Variable("f".into())),
+            vec![
+                Expr::IntegerValue(1),
+                Expr::Starred(Box::new(Expr::Variable("rest".into()))),
+                Expr::KeywordArgument("key".into(), Box::new(Expr::IntegerValue(2))),
+                Expr::DoubleStarred(Box::new(Expr::Variable("options".into()))),
+            ],
+            ExprSpan::default(),
+        )))];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn positional_argument_after_keyword_is_error() {
+        let tokens = tokenize("f(a=1, 2)").unwrap();
+        let result = parse_ast(tokens);
+        assert_eq!(
+            result.unwrap_err().message,
+            "positional argument follows keyword argument"
+        );
+    }
+
+    #[test]
+    fn non_default_parameter_after_default_is_error() {
+        let tokens = tokenize("def f(a=1, b):\n    pass").unwrap();
+        let result = parse_ast(tokens);
+        assert_eq!(
+            result.unwrap_err().message,
+            "non-default argument follows default argument"
+        );
+    }
+
+    #[test]
+    fn global_and_nonlocal_statements() {
+        let tokens = tokenize("global a, b\nnonlocal c").unwrap();
+        let result = parse_ast(tokens).unwrap();
+        assert_eq!(result[0].ast, AST::Global(vec!["a".into(), "b".into()]));
+        assert_eq!(result[1].ast, AST::Nonlocal(vec!["c".into()]));
+    }
+
+    #[test]
+    fn try_without_handlers_is_error() {
+        let tokens = tokenize("try:\n    x = 1").unwrap();
+        let result = parse_ast(tokens);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn lambda_with_default_parameter() {
+        let tokens = tokenize("f = lambda x, y=2: x + y").unwrap();
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::Assign {
+            path: vec!["f".into()],
+            expression: Expr::Lambda(
+                vec![
+                    FunctionParameter::Simple("x".into()),
+                    FunctionParameter::DefaultValue("y".into(), Expr::IntegerValue(2)),
+                ],
+                Box::new(Expr::BinaryOperation(
+                    Box::new(Expr::Variable("x".into())),
+                    Operator::Plus,
+                    Box::new(Expr::Variable("y".into())),
+                )),
+                ExprSpan::default(),
+            ),
+        })];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn conditional_expression_is_right_associative() {
+        let tokens = tokenize("a if x else b if y else c").unwrap();
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::StandaloneExpr(Expr::Conditional(
+            Box::new(Expr::Variable("a".into())),
+            Box::new(Expr::Variable("x".into())),
+            Box::new(Expr::Conditional(
+                Box::new(Expr::Variable("b".into())),
+                Box::new(Expr::Variable("y".into())),
+                Box::new(Expr::Variable("c".into())),
+            )),
+        )))];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn conditional_expression_without_else_is_error() {
+        let tokens = tokenize("a if b").unwrap();
+        let result = parse_ast(tokens);
+        assert_eq!(result.unwrap_err().message, "expected 'else' after 'if' expression");
+    }
+
+    #[test]
+    fn list_comprehension_with_nested_for_and_condition() {
+        let tokens = tokenize("[x + y for x in xs for y in ys if x]").unwrap();
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::StandaloneExpr(Expr::ListComprehension(
+            Box::new(Expr::BinaryOperation(
+                Box::new(Expr::Variable("x".into())),
+                Operator::Plus,
+                Box::new(Expr::Variable("y".into())),
+            )),
+            vec![
+                ComprehensionClause {
+                    target: Expr::Variable("x".into()),
+                    iterable: Expr::Variable("xs".into()),
+                    conditions: vec![],
+                },
+                ComprehensionClause {
+                    target: Expr::Variable("y".into()),
+                    iterable: Expr::Variable("ys".into()),
+                    conditions: vec![Expr::Variable("x".into())],
+                },
+            ],
+        )))];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn dict_comprehension_set_comprehension_and_set_literal() {
+        let tokens = tokenize("{k: v for k, v in pairs}\n{x for x in xs}\n{1, 2}").unwrap();
+        let result = parse_ast(tokens).unwrap();
+        let clause = |target: Expr, iterable: &str| ComprehensionClause {
+            target,
+            iterable: Expr::Variable(iterable.into()),
+            conditions: vec![],
+        };
+        let expected = vec![
+            node(AST::StandaloneExpr(Expr::DictComprehension(
+                Box::new(Expr::Variable("k".into())),
+                Box::new(Expr::Variable("v".into())),
+                vec![clause(
+                    Expr::Tuple(vec![Expr::Variable("k".into()), Expr::Variable("v".into())]),
+                    "pairs",
+                )],
+            ))),
+            node(AST::StandaloneExpr(Expr::SetComprehension(
+                Box::new(Expr::Variable("x".into())),
+                vec![clause(Expr::Variable("x".into()), "xs")],
+            ))),
+            node(AST::StandaloneExpr(Expr::Set(vec![Expr::IntegerValue(1), Expr::IntegerValue(2)]))),
+        ];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn generator_expression_as_only_argument() {
+        let tokens = tokenize("f(x for x in xs)").unwrap();
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![node(AST::StandaloneExpr(Expr::FunctionCall(
+            Box::new(Expr::Variable("f".into())),
+            vec![Expr::GeneratorExpression(
+                Box::new(Expr::Variable("x".into())),
+                vec![ComprehensionClause {
+                    target: Expr::Variable("x".into()),
+                    iterable: Expr::Variable("xs".into()),
+                    conditions: vec![],
+                }],
+            )],
+            ExprSpan::default(),
+        )))];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn generator_expression_with_other_arguments_is_error() {
+        let tokens = tokenize("f(x for x in xs, 1)").unwrap();
+        let result = parse_ast(tokens);
+        assert_eq!(result.unwrap_err().message, "Generator expression must be parenthesized");
+    }
+
+    #[test]
+    fn yield_forms() {
+        let tokens = tokenize("yield\nyield a, b\nx = yield from xs").unwrap();
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![
+            node(AST::StandaloneExpr(Expr::Yield(None))),
+            node(AST::StandaloneExpr(Expr::Yield(Some(Box::new(Expr::Tuple(vec![
+                Expr::Variable("a".into()),
+                Expr::Variable("b".into()),
+            ])))))),
+            node(AST::Assign {
+                path: vec!["x".into()],
+                expression: Expr::YieldFrom(Box::new(Expr::Variable("xs".into()))),
+            }),
+        ];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn import_and_from_import() {
+        let tokens = tokenize("import a.b as c, d\nfrom e.f import (g, h as i)").unwrap();
+        let result = parse_ast(tokens).unwrap();
+        let expected = vec![
+            node(AST::Import(vec![
+                ImportedName { name: "a.b".into(), alias: Some("c".into()) },
+                ImportedName { name: "d".into(), alias: None },
+            ])),
+            node(AST::ImportFrom {
+                module: "e.f".into(),
+                names: vec![
+                    ImportedName { name: "g".into(), alias: None },
+                    ImportedName { name: "h".into(), alias: Some("i".into()) },
+                ],
+            }),
+        ];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn from_without_import_is_error() {
+        let tokens = tokenize("from a b").unwrap();
+        let result = parse_ast(tokens);
+        assert_eq!(result.unwrap_err().message, "Expected 'import' after module name");
+    }
+
+    #[test]
+    fn operator_without_operand_is_error() {
+        for source in ["if x ==:\n    pass", "print(1 +)", "1 +"] {
+            let tokens = tokenize(source).unwrap();
+            let error = parse_ast(tokens).unwrap_err();
+            assert!(error.message.ends_with("expected an expression after the operator"), "{}", error.message);
+        }
+    }
+
+    #[test]
+    fn truncated_input_is_error() {
+        for source in ["x =", "x *", "f(", "l[", "obj.", "def", "class", "lambda", "for x in", "x if", "x = [1,", "print(x[0", "if x:\n    y = (1,"] {
+            let tokens = tokenize(source).unwrap();
+            assert!(parse_ast(tokens).is_err(), "{}", source);
+        }
+        let error = parse_ast(tokenize("x = 1\ny = [1,\n  f(2,").unwrap()).unwrap_err();
+        assert_eq!((error.message.as_str(), error.span), ("'(' was never closed", Some(Span { line: 3, column: 4 })));
+    }
+
+    #[test]
+    fn unclosed_list_is_error() {
+        let tokens = tokenize("x = [1, 2\nprint(x)").unwrap();
+        assert!(parse_ast(tokens).is_err());
+        let tokens = tokenize("x = [1, 2,]\ny = (1,)").unwrap();
+        assert!(parse_ast(tokens).is_ok());
+    }
 }
@@ -9,7 +9,7 @@ const XOR_STR: &'static str = "__xor__";
 
 fn and_method(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
         .take_int();
@@ -57,7 +57,7 @@ fn and_method(vm: &VM, params: CallParams) -> MemoryAddress {
 
 fn or_method(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
         .take_int();
@@ -105,7 +105,7 @@ fn or_method(vm: &VM, params: CallParams) -> MemoryAddress {
 
 fn xor_method(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
         .take_int();
@@ -153,7 +153,7 @@ fn xor_method(vm: &VM, params: CallParams) -> MemoryAddress {
 
 fn not_method(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
         .take_int();
@@ -167,6 +167,7 @@ fn not_method(vm: &VM, params: CallParams) -> MemoryAddress {
 
 fn to_str(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
         .take_int();
@@ -179,7 +180,7 @@ fn to_str(vm: &VM, params: CallParams) -> MemoryAddress {
 
 fn repr(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
         .take_int();
@@ -190,9 +191,9 @@ fn repr(vm: &VM, params: CallParams) -> MemoryAddress {
     }
 }
 
-fn to_boolean(_vm: &VM, params: CallParams) -> MemoryAddress {
+fn to_boolean(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
 
     //no-op
     return call_params.bound_pyobj;
@@ -200,7 +201,7 @@ fn to_boolean(_vm: &VM, params: CallParams) -> MemoryAddress {
 
 fn to_int(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
         .take_int();
@@ -213,7 +214,7 @@ fn to_int(vm: &VM, params: CallParams) -> MemoryAddress {
 
 fn to_float(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
         .take_int();
@@ -228,7 +229,7 @@ macro_rules! create_unary_function {
     ($name:tt, $param_a:tt, $func:expr) => {
         fn $name(vm: &VM, params: CallParams) -> MemoryAddress {
             let call_params = params.as_method();
-            check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+            check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
             let self_data = vm
                 .get_raw_data_of_pyobj(call_params.bound_pyobj)
                 .take_int();
@@ -242,6 +243,16 @@ macro_rules! create_unary_function {
 }
 create_unary_function!(negation, a, if a == 1 { 0 } else { 1 });
 
+//Same as the hash of the equivalent int, True == 1
+fn hash(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let self_data = vm
+        .get_raw_data_of_pyobj(call_params.bound_pyobj)
+        .take_int();
+    vm.allocate_builtin_type_byname_raw("int", BuiltInTypeData::Int(self_data))
+}
+
 pub fn register_boolean_type(vm: &mut VM) -> MemoryAddress {
     //bool inherits from int
 
@@ -257,6 +268,7 @@ pub fn register_boolean_type(vm: &mut VM) -> MemoryAddress {
     vm.register_bounded_func(BUILTIN_MODULE, "bool", "__neg__", negation);
 
     vm.register_bounded_func(BUILTIN_MODULE, "bool", "__bool__", to_boolean);
+    vm.register_bounded_func(BUILTIN_MODULE, "bool", "__hash__", hash);
     vm.register_bounded_func(BUILTIN_MODULE, "bool", "__str__", to_str);
     vm.register_bounded_func(BUILTIN_MODULE, "bool", "__repr__", repr);
     vm.register_bounded_func(BUILTIN_MODULE, "bool", "__int__", to_int);
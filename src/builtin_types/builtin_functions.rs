@@ -1,24 +1,69 @@
 use crate::runtime::vm::*;
 use crate::runtime::datamodel::*;
 use crate::runtime::memory::*;
+use std::rc::Rc;
 
+fn raise_type_error(vm: &VM, message: String) -> MemoryAddress {
+    let exception = vm.create_exception("TypeError", message);
+    vm.raise_exception(exception);
+    return vm.special_values[&SpecialValue::NoneValue];
+}
+
+//Err is the exception raised by __str__, or the TypeError if it returned something else than a string
+fn str_of(vm: &VM, addr: MemoryAddress) -> Result<String, MemoryAddress> {
+    let (str_call_result, frame) = match vm.call_method(addr, "__str__", PositionalParameters::empty()) {
+        Some(result) => result,
+        None => return Ok(format!("<{} object>", vm.get_pyobj_type_name(addr))),
+    };
+    if let Some(exception) = frame.exception {
+        return Err(exception);
+    }
+    let str_raw = vm.get_raw_data_of_pyobj(str_call_result);
+    match &*str_raw {
+        BuiltInTypeData::String(s) => Ok(s.clone()),
+        _ => {
+            drop(str_raw);
+            let message = format!("__str__ returned non-string (type {})", vm.get_pyobj_type_name(str_call_result));
+            Err(vm.create_exception("TypeError", message))
+        }
+    }
+}
+
+//print(*objects, sep=' ', end='\n')
 fn create_print_fn(vm: &VM) -> MemoryAddress {
     let func = PyCallable {
-        code: Box::new(move |vm, params| -> MemoryAddress {
+        code: Rc::new(move |vm, params| -> MemoryAddress {
             let call_params = params.as_function();
-            check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
-            let (str_call_result, _) = vm
-                .call_method(call_params.params[0], "__str__", PositionalParameters::empty())
-                .unwrap();
-            let str_raw = vm.get_raw_data_of_pyobj(str_call_result);
-            match str_raw {
-                BuiltInTypeData::String(s) => {
-                    println!("{}", s);
+            let mut sep = " ".to_string();
+            let mut end = "\n".to_string();
+            for (name, value) in call_params.kwargs.iter() {
+                if name != "sep" && name != "end" {
+                    return raise_type_error(vm, format!("'{}' is an invalid keyword argument for print()", name));
                 }
-                _ => {
-                    panic!("__str__ returned something else than string");
+                //None has no raw data, so it's checked first
+                let text = match vm.get_pyobj_byaddr(*value).try_get_builtin() {
+                    _ if *value == vm.special_values[&SpecialValue::NoneValue] => None,
+                    Some(BuiltInTypeData::String(s)) => Some(s.clone()),
+                    _ => {
+                        let message = format!("{} must be None or a string, not {}", name, vm.get_pyobj_type_name(*value));
+                        return raise_type_error(vm, message);
+                    }
+                };
+                if name == "sep" {
+                    sep = text.unwrap_or(sep);
+                } else {
+                    end = text.unwrap_or(end);
                 }
             }
+            let all_strs: Result<Vec<String>, MemoryAddress> = call_params.params.iter().map(|addr| str_of(vm, *addr)).collect();
+            let all_strs = match all_strs {
+                Ok(all_strs) => all_strs,
+                Err(exception) => {
+                    vm.raise_exception(exception);
+                    return vm.special_values[&SpecialValue::NoneValue];
+                }
+            };
+            print!("{}{}", all_strs.join(&sep), end);
             return vm.special_values[&SpecialValue::NoneValue];
         }),
     };
@@ -27,8 +72,8 @@ fn create_print_fn(vm: &VM) -> MemoryAddress {
 
 fn create_printstack_fn(vm: &VM) -> MemoryAddress {
     let func = PyCallable {
-        code: Box::new(move |vm, params| -> MemoryAddress {
-            check_builtin_func_params!(params.func_name.unwrap(), 0, params.params.len());
+        code: Rc::new(move |vm, params| -> MemoryAddress {
+            check_builtin_func_params!(vm, params.func_name.unwrap(), 0, params.params);
             vm.print_stack();
             return vm.special_values[&SpecialValue::NoneValue];
         }),
@@ -37,9 +82,9 @@ fn create_printstack_fn(vm: &VM) -> MemoryAddress {
 }
 fn create_traceback_fn(vm: &VM) -> MemoryAddress {
     let func = PyCallable {
-        code: Box::new(move |vm, params| -> MemoryAddress {
-            check_builtin_func_params!(params.func_name.unwrap(), 0, params.params.len());
-            vm.print_traceback();
+        code: Rc::new(move |vm, params| -> MemoryAddress {
+            check_builtin_func_params!(vm, params.func_name.unwrap(), 0, params.params);
+            vm.print_call_stack();
             return vm.special_values[&SpecialValue::NoneValue];
         }),
     };
@@ -47,13 +92,21 @@ fn create_traceback_fn(vm: &VM) -> MemoryAddress {
 }
 fn create_len_fn(vm: &VM) -> MemoryAddress {
     let func = PyCallable {
-        code: Box::new(move |vm, params| -> MemoryAddress {
+        code: Rc::new(move |vm, params| -> MemoryAddress {
             let call_params = params.as_function();
-            check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
-            let (str_call_result, _) = vm
-                .call_method(call_params.params[0], "__len__", PositionalParameters::empty())
-                .unwrap();
-            return str_call_result;
+            check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+            match vm.call_method(call_params.params[0], "__len__", PositionalParameters::empty()) {
+                Some((length, frame)) => {
+                    if let Some(exception) = frame.exception {
+                        vm.raise_exception(exception);
+                    }
+                    length
+                }
+                None => {
+                    let message = format!("object of type '{}' has no len()", vm.get_pyobj_type_name(call_params.params[0]));
+                    raise_type_error(vm, message)
+                }
+            }
         }),
     };
     return vm.create_unbounded_callable_pyobj(func, Some("len".to_string()));
@@ -61,14 +114,14 @@ fn create_len_fn(vm: &VM) -> MemoryAddress {
 
 fn create_panic_fn(vm: &VM) -> MemoryAddress {
     let func = PyCallable {
-        code: Box::new(move |vm, params| -> MemoryAddress {
+        code: Rc::new(move |vm, params| -> MemoryAddress {
             let call_params = params.as_function();
-            check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
+            check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
             let (str_call_result, _) = vm
                 .call_method(call_params.params[0], "__str__", PositionalParameters::empty())
                 .unwrap();
             let str_raw = vm.get_raw_data_of_pyobj(str_call_result);
-            match str_raw {
+            match &*str_raw {
                 BuiltInTypeData::String(s) => {
                     panic!("{}", s);
                 }
@@ -81,6 +134,66 @@ fn create_panic_fn(vm: &VM) -> MemoryAddress {
     return vm.create_unbounded_callable_pyobj(func, Some("print".to_string()));
 }
 
+//Checks `type_addr` against a class or a tuple of classes, used by isinstance and issubclass
+fn subtype_of_any(vm: &VM, func_name: &str, type_addr: MemoryAddress, classinfo: MemoryAddress) -> Option<bool> {
+    let candidates = match &vm.get_pyobj_byaddr(classinfo).structure {
+        PyObjectStructure::Object { raw_data: BuiltInTypeData::Tuple(elements), .. } => elements.clone(),
+        _ => vec![classinfo],
+    };
+    if !candidates.iter().all(|candidate| is_type(vm, *candidate)) {
+        let exception = vm.create_exception(
+            "TypeError",
+            format!("{}() arg 2 must be a type or tuple of types", func_name),
+        );
+        vm.raise_exception(exception);
+        return None;
+    }
+    return Some(candidates.iter().any(|candidate| vm.is_subtype(type_addr, *candidate)));
+}
+
+fn is_type(vm: &VM, addr: MemoryAddress) -> bool {
+    matches!(vm.get_pyobj_byaddr(addr).structure, PyObjectStructure::Type { .. })
+}
+
+fn bool_value(vm: &VM, value: Option<bool>) -> MemoryAddress {
+    match value {
+        Some(true) => vm.builtin_type_addrs.true_val,
+        Some(false) => vm.builtin_type_addrs.false_val,
+        None => vm.special_values[&SpecialValue::NoneValue],
+    }
+}
+
+fn create_isinstance_fn(vm: &VM) -> MemoryAddress {
+    let func = PyCallable {
+        code: Rc::new(move |vm, params| -> MemoryAddress {
+            let call_params = params.as_function();
+            check_builtin_func_params!(vm, params.func_name.unwrap(), 2, call_params);
+            let type_addr = vm.get_pyobj_type_addr(call_params.params[0]);
+            let result = subtype_of_any(vm, "isinstance", type_addr, call_params.params[1]);
+            return bool_value(vm, result);
+        }),
+    };
+    return vm.create_unbounded_callable_pyobj(func, Some("isinstance".to_string()));
+}
+
+fn create_issubclass_fn(vm: &VM) -> MemoryAddress {
+    let func = PyCallable {
+        code: Rc::new(move |vm, params| -> MemoryAddress {
+            let call_params = params.as_function();
+            check_builtin_func_params!(vm, params.func_name.unwrap(), 2, call_params);
+            let type_addr = call_params.params[0];
+            if !is_type(vm, type_addr) {
+                let exception = vm.create_exception("TypeError", "issubclass() arg 1 must be a class".to_string());
+                vm.raise_exception(exception);
+                return vm.special_values[&SpecialValue::NoneValue];
+            }
+            let result = subtype_of_any(vm, "issubclass", type_addr, call_params.params[1]);
+            return bool_value(vm, result);
+        }),
+    };
+    return vm.create_unbounded_callable_pyobj(func, Some("issubclass".to_string()));
+}
+
 pub fn register_builtin_functions(vm: &mut VM) {
     let print_fn = create_print_fn(vm);
     let printstack_fn = create_printstack_fn(vm);
@@ -92,4 +205,8 @@ pub fn register_builtin_functions(vm: &mut VM) {
     vm.add_to_module(BUILTIN_MODULE, "traceback", traceback_fn);
     vm.add_to_module(BUILTIN_MODULE, "panic", panic_fn);
     vm.add_to_module(BUILTIN_MODULE, "len", len_fn);
+    let isinstance_fn = create_isinstance_fn(vm);
+    let issubclass_fn = create_issubclass_fn(vm);
+    vm.add_to_module(BUILTIN_MODULE, "isinstance", isinstance_fn);
+    vm.add_to_module(BUILTIN_MODULE, "issubclass", issubclass_fn);
 }
@@ -2,6 +2,7 @@ use crate::commons::float::Float;
 use crate::runtime::vm::*;
 use crate::runtime::datamodel::*;
 use crate::runtime::memory::*;
+use std::rc::Rc;
 
 
 fn create_function_1arg<FFloat>(
@@ -13,13 +14,13 @@ where
     FFloat: Fn(f64) -> f64 + 'static,
 {
     let func = PyCallable {
-        code: Box::new(move |vm, params| -> MemoryAddress {
+        code: Rc::new(move |vm, params| -> MemoryAddress {
             let call_params = params.as_function();
-            check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
+            check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
             let value_type_name = vm.get_pyobj_type_name(call_params.params[0]);
             let other_value = vm.get_raw_data_of_pyobj(call_params.params[0]);
 
-            return match value_type_name {
+            return match value_type_name.as_str() {
                 "int" => {
                     let parameter = other_value.take_int();
                     vm.allocate_builtin_type_byname_raw(
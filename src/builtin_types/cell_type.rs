@@ -0,0 +1,34 @@
+use crate::runtime::vm::*;
+use crate::runtime::datamodel::*;
+use crate::runtime::memory::*;
+use std::collections::BTreeMap;
+
+fn cell_repr(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let repr = match &*vm.get_raw_data_of_pyobj(call_params.bound_pyobj) {
+        BuiltInTypeData::Cell(Some(contents)) => format!("<cell: {} object>", vm.get_pyobj_type_name(*contents)),
+        _ => "<cell: empty>".to_string(),
+    };
+    vm.allocate_builtin_type_byname_raw("str", BuiltInTypeData::String(repr))
+}
+
+//Cells keep the variables captured by closures, see the LoadDeref and StoreDeref instructions.
+//Like in CPython, the type is not reachable by name.
+pub fn register_cell_type(vm: &mut VM) {
+    let cell = vm.allocate_and_write(PyObject {
+        type_addr: vm.special_values[&SpecialValue::Type],
+        properties: BTreeMap::new(),
+        structure: PyObjectStructure::Type {
+            name: String::from("cell"),
+            functions: BTreeMap::new(),
+            supertype: None,
+            mro: vec![],
+        },
+        is_const: false,
+    });
+    vm.make_const(cell);
+    vm.register_bounded_func_on_addr(cell, "__repr__", cell_repr);
+    vm.register_bounded_func_on_addr(cell, "__str__", cell_repr);
+    vm.builtin_type_addrs.cell = cell;
+}
@@ -1,21 +1,15 @@
 use crate::runtime::vm::*;
 use crate::runtime::datamodel::*;
 use crate::runtime::memory::*;
+use crate::bytecode::disassembler::disassemble;
 
 fn get_bytecode(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
-    let self_data = vm.get_function_bytecode(call_params.bound_pyobj);
-
-    let mut bytecode_repr = String::from("");
-
-    for data in self_data {
-        bytecode_repr.push_str(&format!("{:?}\n", data));
-    }
-
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let code = vm.get_raw_data_of_pyobj(call_params.bound_pyobj).take_code_object().code.clone();
     vm.allocate_builtin_type_byname_raw(
         "str",
-        BuiltInTypeData::String(bytecode_repr),
+        BuiltInTypeData::String(disassemble(&code)),
     )
 }
 
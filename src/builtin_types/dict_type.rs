@@ -0,0 +1,348 @@
+use crate::runtime::vm::*;
+use crate::runtime::datamodel::*;
+use crate::runtime::memory::*;
+
+//Keys are compared through __hash__ and __eq__, so instances of user classes can be keys.
+//These helpers return the exception to be raised in the Err case.
+
+fn raise(vm: &VM, exception_type: &str, message: String) -> MemoryAddress {
+    let exception = vm.create_exception(exception_type, message);
+    vm.raise_exception(exception);
+    return vm.special_values[&SpecialValue::NoneValue];
+}
+
+pub fn repr_of(vm: &VM, addr: MemoryAddress) -> String {
+    let (as_string, _) = vm.call_method(addr, "__repr__", PositionalParameters::empty()).unwrap();
+    return vm.get_raw_data_of_pyobj(as_string).take_string().clone();
+}
+
+pub fn hash_key(vm: &VM, key: MemoryAddress) -> Result<i128, MemoryAddress> {
+    let pyobj = vm.get_pyobj_byaddr(key);
+    if vm.get_method_addr_byname(pyobj.type_addr, "__hash__").is_some() {
+        let (result, frame) = vm.call_method(key, "__hash__", PositionalParameters::empty()).unwrap();
+        if let Some(exception) = frame.exception {
+            return Err(exception);
+        }
+        return match &vm.get_pyobj_byaddr(result).structure {
+            PyObjectStructure::Object { raw_data: BuiltInTypeData::Int(hash), .. } => Ok(*hash),
+            _ => Err(vm.create_exception("TypeError", "__hash__ method should return an integer".to_string())),
+        };
+    }
+    //classes that don't define __hash__ are hashed by identity, like in CPython
+    match &pyobj.structure {
+        PyObjectStructure::Object { raw_data: BuiltInTypeData::ClassInstance, .. }
+        | PyObjectStructure::Type { .. } => Ok(key.id() as i128),
+        _ => Err(vm.create_exception(
+            "TypeError",
+            format!("unhashable type: '{}'", vm.get_type_name(pyobj.type_addr)),
+        )),
+    }
+}
+
+pub fn keys_equal(vm: &VM, a: MemoryAddress, b: MemoryAddress) -> Result<bool, MemoryAddress> {
+    if a == b {
+        return Ok(true);
+    }
+    match vm.call_method(a, "__eq__", PositionalParameters::single(b)) {
+        Some((result, frame)) => match frame.exception {
+            Some(exception) => Err(exception),
+            None => Ok(vm.is_truthy(result)),
+        },
+        None => Ok(false),
+    }
+}
+
+//Returns the hash of the key and the index of its entry, if present
+pub fn find_entry(vm: &VM, dict: MemoryAddress, key: MemoryAddress) -> Result<(i128, Option<usize>), MemoryAddress> {
+    let hash = hash_key(vm, key)?;
+    //__eq__ may run Python code, so the candidates are copied before calling it
+    let candidates = vm.get_raw_data_of_pyobj(dict).take_dict().candidates(hash);
+    for index in candidates {
+        let entry_key = vm.get_raw_data_of_pyobj(dict).take_dict().entries[index].key;
+        if keys_equal(vm, entry_key, key)? {
+            return Ok((hash, Some(index)));
+        }
+    }
+    return Ok((hash, None));
+}
+
+pub fn dict_get(vm: &VM, dict: MemoryAddress, key: MemoryAddress) -> Result<Option<MemoryAddress>, MemoryAddress> {
+    let (_, found) = find_entry(vm, dict, key)?;
+    Ok(found.map(|index| vm.get_raw_data_of_pyobj(dict).take_dict().entries[index].value))
+}
+
+pub fn dict_set(vm: &VM, dict: MemoryAddress, key: MemoryAddress, value: MemoryAddress) -> Result<(), MemoryAddress> {
+    let (hash, found) = find_entry(vm, dict, key)?;
+    vm.increase_refcount(value);
+    if found.is_none() {
+        vm.increase_refcount(key);
+    }
+    let mut raw_data = vm.get_raw_data_of_pyobj_mut(dict);
+    let data = raw_data.take_dict_mut();
+    match found {
+        Some(index) => data.entries[index].value = value,
+        None => {
+            data.push(hash, key, value);
+        }
+    }
+    Ok(())
+}
+
+pub fn dict_remove(vm: &VM, dict: MemoryAddress, key: MemoryAddress) -> Result<Option<MemoryAddress>, MemoryAddress> {
+    let (_, found) = find_entry(vm, dict, key)?;
+    Ok(found.map(|index| vm.get_raw_data_of_pyobj_mut(dict).take_dict_mut().remove(index).value))
+}
+
+//Copies the entries of a dict, or of an iterable of key/value pairs, into the dict
+fn update_from(vm: &VM, dict: MemoryAddress, other: MemoryAddress) -> Result<(), MemoryAddress> {
+    if let PyObjectStructure::Object { raw_data: BuiltInTypeData::Dict(other_dict), .. } = &vm.get_pyobj_byaddr(other).structure {
+        let entries = other_dict.entries.clone();
+        for entry in entries {
+            dict_set(vm, dict, entry.key, entry.value)?;
+        }
+        return Ok(());
+    }
+    for item in vm.collect_iterable(other)? {
+        let pair = match &vm.get_pyobj_byaddr(item).structure {
+            PyObjectStructure::Object { raw_data: BuiltInTypeData::List(pair), .. }
+            | PyObjectStructure::Object { raw_data: BuiltInTypeData::Tuple(pair), .. } if pair.len() == 2 => pair.clone(),
+            _ => {
+                return Err(vm.create_exception(
+                    "TypeError",
+                    "dictionary update sequence element must be a pair".to_string(),
+                ))
+            }
+        };
+        dict_set(vm, dict, pair[0], pair[1])?;
+    }
+    return Ok(());
+}
+
+pub fn new_dict(vm: &VM) -> MemoryAddress {
+    vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.dict, BuiltInTypeData::Dict(DictData::new()))
+}
+
+fn new_list(vm: &VM, elements: Vec<MemoryAddress>) -> MemoryAddress {
+    vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.list, BuiltInTypeData::List(elements))
+}
+
+fn create_new(vm: &VM, params: CallParams) -> MemoryAddress {
+    if params.params.len() > 1 {
+        return raise(vm, "TypeError", format!("dict expected at most 1 argument, got {}", params.params.len()));
+    }
+    let dict = new_dict(vm);
+    if let Some(other) = params.params.params.first() {
+        if let Err(exception) = update_from(vm, dict, *other) {
+            vm.raise_exception(exception);
+            return dict;
+        }
+    }
+    //dict(a=1, b=2)
+    for (name, value) in params.params.kwargs.iter() {
+        let key = vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.string, BuiltInTypeData::String(name.clone()));
+        if let Err(exception) = dict_set(vm, dict, key, *value) {
+            vm.raise_exception(exception);
+            return dict;
+        }
+    }
+    return dict;
+}
+
+fn getitem(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    let key = call_params.params[0];
+    match dict_get(vm, call_params.bound_pyobj, key) {
+        Ok(Some(value)) => value,
+        Ok(None) => raise(vm, "KeyError", repr_of(vm, key)),
+        Err(exception) => {
+            vm.raise_exception(exception);
+            return vm.special_values[&SpecialValue::NoneValue];
+        }
+    }
+}
+
+fn setitem(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 2, call_params);
+    if let Err(exception) = dict_set(vm, call_params.bound_pyobj, call_params.params[0], call_params.params[1]) {
+        vm.raise_exception(exception);
+    }
+    return vm.special_values[&SpecialValue::NoneValue];
+}
+
+fn contains(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    match find_entry(vm, call_params.bound_pyobj, call_params.params[0]) {
+        Ok((_, Some(_))) => vm.builtin_type_addrs.true_val,
+        Ok((_, None)) => vm.builtin_type_addrs.false_val,
+        Err(exception) => {
+            vm.raise_exception(exception);
+            return vm.special_values[&SpecialValue::NoneValue];
+        }
+    }
+}
+
+fn get(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), call_params);
+    if call_params.params.is_empty() || call_params.params.len() > 2 {
+        return raise(vm, "TypeError", format!("get expected 1 or 2 arguments, got {}", call_params.params.len()));
+    }
+    let default = match call_params.params.get(1) {
+        Some(default) => *default,
+        None => vm.special_values[&SpecialValue::NoneValue],
+    };
+    match dict_get(vm, call_params.bound_pyobj, call_params.params[0]) {
+        Ok(Some(value)) => value,
+        Ok(None) => default,
+        Err(exception) => {
+            vm.raise_exception(exception);
+            return vm.special_values[&SpecialValue::NoneValue];
+        }
+    }
+}
+
+fn pop(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), call_params);
+    if call_params.params.is_empty() || call_params.params.len() > 2 {
+        return raise(vm, "TypeError", format!("pop expected 1 or 2 arguments, got {}", call_params.params.len()));
+    }
+    let key = call_params.params[0];
+    match dict_remove(vm, call_params.bound_pyobj, key) {
+        Ok(Some(value)) => value,
+        Ok(None) => match call_params.params.get(1) {
+            Some(default) => *default,
+            None => raise(vm, "KeyError", repr_of(vm, key)),
+        },
+        Err(exception) => {
+            vm.raise_exception(exception);
+            return vm.special_values[&SpecialValue::NoneValue];
+        }
+    }
+}
+
+fn update(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    if let Err(exception) = update_from(vm, call_params.bound_pyobj, call_params.params[0]) {
+        vm.raise_exception(exception);
+    }
+    return vm.special_values[&SpecialValue::NoneValue];
+}
+
+fn keys(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let keys = vm.get_raw_data_of_pyobj(call_params.bound_pyobj).take_dict().entries.iter().map(|entry| entry.key).collect();
+    new_list(vm, keys)
+}
+
+fn values(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let values = vm.get_raw_data_of_pyobj(call_params.bound_pyobj).take_dict().entries.iter().map(|entry| entry.value).collect();
+    new_list(vm, values)
+}
+
+fn items(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let entries = vm.get_raw_data_of_pyobj(call_params.bound_pyobj).take_dict().entries.clone();
+    let pairs = entries
+        .iter()
+        .map(|entry| vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.tuple, BuiltInTypeData::Tuple(vec![entry.key, entry.value])))
+        .collect();
+    new_list(vm, pairs)
+}
+
+fn len(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let dict_len = vm.get_raw_data_of_pyobj(call_params.bound_pyobj).take_dict().len();
+    vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.int, BuiltInTypeData::Int(dict_len as i128))
+}
+
+//Iterates over a snapshot of the keys, using the list_iterator from the standard library
+fn iter(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let keys = vm.get_raw_data_of_pyobj(call_params.bound_pyobj).take_dict().entries.iter().map(|entry| entry.key).collect();
+    let keys = new_list(vm, keys);
+    let list_iterator = vm.find_in_module(BUILTIN_MODULE, "list_iterator").expect("list_iterator not found");
+    let (result, _) = vm.run_function(PositionalParameters::single(keys), list_iterator, None);
+    return result;
+}
+
+fn equals(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    let other = call_params.params[0];
+    let other_len = match &vm.get_pyobj_byaddr(other).structure {
+        PyObjectStructure::Object { raw_data: BuiltInTypeData::Dict(other_dict), .. } => other_dict.len(),
+        _ => return vm.builtin_type_addrs.false_val,
+    };
+    let entries = vm.get_raw_data_of_pyobj(call_params.bound_pyobj).take_dict().entries.clone();
+    if entries.len() != other_len {
+        return vm.builtin_type_addrs.false_val;
+    }
+    for entry in entries {
+        let equal = match dict_get(vm, other, entry.key) {
+            Ok(Some(other_value)) => keys_equal(vm, entry.value, other_value),
+            Ok(None) => Ok(false),
+            Err(exception) => Err(exception),
+        };
+        match equal {
+            Ok(true) => {}
+            Ok(false) => return vm.builtin_type_addrs.false_val,
+            Err(exception) => {
+                vm.raise_exception(exception);
+                return vm.special_values[&SpecialValue::NoneValue];
+            }
+        }
+    }
+    return vm.builtin_type_addrs.true_val;
+}
+
+fn repr(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    if !vm.repr_enter(call_params.bound_pyobj) {
+        return vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.string, BuiltInTypeData::String(String::from("{...}")));
+    }
+    let entries = vm.get_raw_data_of_pyobj(call_params.bound_pyobj).take_dict().entries.clone();
+    let all_reprs: Vec<String> = entries
+        .iter()
+        .map(|entry| format!("{}: {}", repr_of(vm, entry.key), repr_of(vm, entry.value)))
+        .collect();
+    vm.repr_leave(call_params.bound_pyobj);
+    vm.allocate_type_byaddr_raw(
+        vm.builtin_type_addrs.string,
+        BuiltInTypeData::String(format!("{{{}}}", all_reprs.join(", "))),
+    )
+}
+
+pub fn register_dict_type(vm: &mut VM) -> MemoryAddress {
+    let dict_type = vm.create_type(BUILTIN_MODULE, "dict", None);
+    vm.builtin_type_addrs.dict = dict_type;
+
+    vm.register_type_unbounded_func(dict_type, "__new__", create_new);
+
+    vm.register_bounded_func(BUILTIN_MODULE, "dict", "__getitem__", getitem);
+    vm.register_bounded_func(BUILTIN_MODULE, "dict", "__setitem__", setitem);
+    vm.register_bounded_func(BUILTIN_MODULE, "dict", "__contains__", contains);
+    vm.register_bounded_func(BUILTIN_MODULE, "dict", "__len__", len);
+    vm.register_bounded_func(BUILTIN_MODULE, "dict", "__iter__", iter);
+    vm.register_bounded_func(BUILTIN_MODULE, "dict", "__eq__", equals);
+    vm.register_bounded_func(BUILTIN_MODULE, "dict", "__repr__", repr);
+    vm.register_bounded_func(BUILTIN_MODULE, "dict", "__str__", repr);
+    vm.register_bounded_func(BUILTIN_MODULE, "dict", "get", get);
+    vm.register_bounded_func(BUILTIN_MODULE, "dict", "keys", keys);
+    vm.register_bounded_func(BUILTIN_MODULE, "dict", "values", values);
+    vm.register_bounded_func(BUILTIN_MODULE, "dict", "items", items);
+    vm.register_bounded_func(BUILTIN_MODULE, "dict", "pop", pop);
+    vm.register_bounded_func(BUILTIN_MODULE, "dict", "update", update);
+    return dict_type;
+}
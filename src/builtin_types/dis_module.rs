@@ -0,0 +1,62 @@
+use crate::runtime::vm::*;
+use crate::runtime::datamodel::*;
+use crate::runtime::memory::*;
+use crate::runtime::importer;
+use crate::ast::lexer;
+use crate::ast::parser;
+use crate::bytecode::compiler::compile_file;
+use crate::bytecode::disassembler::disassemble;
+use crate::bytecode::program::CodeObject;
+use crate::commons::errors::HorseError;
+use std::rc::Rc;
+
+fn compile_string(source: &str) -> Result<CodeObject, HorseError> {
+    let tokens = lexer::tokenize(source)?;
+    let ast = parser::parse_ast(tokens)?;
+    let mut program = compile_file(ast, "<dis>")?;
+    Ok(program.code_objects.remove(0))
+}
+
+//The code of functions, methods, generators and code objects. Strings are compiled as a module.
+fn code_of(vm: &VM, addr: MemoryAddress) -> Result<Rc<CodeObject>, MemoryAddress> {
+    match &vm.get_pyobj_byaddr(addr).structure {
+        PyObjectStructure::UserDefinedFunction { code, .. } => return Ok(code.code.clone()),
+        PyObjectStructure::BoundMethod { function_address, .. } => return code_of(vm, *function_address),
+        PyObjectStructure::Object { raw_data, .. } => match raw_data {
+            BuiltInTypeData::CodeObject(code) => return Ok(code.code.clone()),
+            BuiltInTypeData::Generator(data) => return Ok(data.code.code.clone()),
+            BuiltInTypeData::String(source) => {
+                return compile_string(source).map(Rc::new).map_err(|e| importer::syntax_error(vm, e, "<dis>"));
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+    let message = format!("don't know how to disassemble {} objects", vm.get_pyobj_type_name(addr));
+    Err(vm.create_exception("TypeError", message))
+}
+
+//dis(x): prints the bytecode of x
+fn create_dis_fn(vm: &VM) -> MemoryAddress {
+    let func = PyCallable {
+        code: Rc::new(move |vm, params| -> MemoryAddress {
+            let call_params = params.as_function();
+            check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+            match code_of(vm, call_params.params[0]) {
+                Ok(code) => print!("{}", disassemble(&code)),
+                Err(exception) => vm.raise_exception(exception),
+            }
+            return vm.special_values[&SpecialValue::NoneValue];
+        }),
+    };
+    return vm.create_unbounded_callable_pyobj(func, Some("dis".to_string()));
+}
+
+//A native module, already in the module table, so `import dis` never looks for a file
+pub fn register_dis_module(vm: &mut VM) {
+    let module = vm.create_module("dis");
+    let name = vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.string, BuiltInTypeData::String("dis".to_string()));
+    vm.set_in_module_addr(module, "__name__", name);
+    let dis_fn = create_dis_fn(vm);
+    vm.set_in_module_addr(module, "dis", dis_fn);
+}
@@ -0,0 +1,166 @@
+use crate::runtime::vm::*;
+use crate::runtime::datamodel::*;
+use crate::runtime::memory::*;
+use std::collections::BTreeMap;
+
+fn get_message(vm: &VM, exception: MemoryAddress) -> String {
+    match &*vm.get_raw_data_of_pyobj(exception) {
+        BuiltInTypeData::String(s) => s.clone(),
+        _ => String::new(),
+    }
+}
+
+fn to_str(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let message = get_message(vm, call_params.bound_pyobj);
+    vm.allocate_builtin_type_byname_raw("str", BuiltInTypeData::String(message))
+}
+
+fn repr(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let message = get_message(vm, call_params.bound_pyobj);
+    let type_addr = vm.get_pyobj_byaddr(call_params.bound_pyobj).type_addr;
+    let type_name = vm.get_type_name(type_addr);
+    vm.allocate_builtin_type_byname_raw(
+        "str",
+        BuiltInTypeData::String(format!("{}('{}')", type_name, message)),
+    )
+}
+
+//Exception instances store their message as raw string data
+fn new_exception(vm: &VM, type_addr: MemoryAddress, arg: Option<MemoryAddress>) -> MemoryAddress {
+    let message = match arg {
+        Some(arg) => {
+            let (str_result, _) = vm
+                .call_method(arg, "__str__", PositionalParameters::empty())
+                .unwrap();
+            vm.get_raw_data_of_pyobj(str_result).take_string().clone()
+        }
+        None => String::new(),
+    };
+    let instance = vm.allocate_type_byaddr_raw(type_addr, BuiltInTypeData::String(message));
+    vm.increase_refcount(instance);
+    vm.set_attribute(instance, "__traceback__", vm.special_values[&SpecialValue::NoneValue]);
+    return instance;
+}
+
+pub fn register_exception_new(vm: &VM, type_addr: MemoryAddress) {
+    vm.register_type_unbounded_func(type_addr, "__new__", move |method_vm: &VM, call_params: CallParams| -> MemoryAddress {
+        check_builtin_func_params!(method_vm, method_vm.get_type_name(type_addr), call_params.params);
+        new_exception(method_vm, type_addr, call_params.params.params.first().copied())
+    });
+}
+
+//SystemExit also keeps its argument as `code`: the exit status, or a message to print, when nobody catches it
+pub fn create_system_exit(vm: &VM, code: Option<MemoryAddress>) -> MemoryAddress {
+    let type_addr = vm.find_in_module(BUILTIN_MODULE, "SystemExit").unwrap();
+    let exception = new_exception(vm, type_addr, code);
+    let code = code.unwrap_or(vm.special_values[&SpecialValue::NoneValue]);
+    vm.increase_refcount(code);
+    vm.set_attribute(exception, "code", code);
+    return exception;
+}
+
+//Lets subclasses that define __init__ set the message with super().__init__(message)
+fn init(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, "__init__", call_params);
+    let message = match call_params.params.first() {
+        Some(arg) => {
+            let (str_result, _) = vm
+                .call_method(*arg, "__str__", PositionalParameters::empty())
+                .unwrap();
+            vm.get_raw_data_of_pyobj(str_result).take_string().clone()
+        }
+        None => String::new(),
+    };
+    let mut exception = vm.get_pyobj_byaddr_mut(call_params.bound_pyobj);
+    if let PyObjectStructure::Object { raw_data, .. } = &mut exception.structure {
+        *raw_data = BuiltInTypeData::String(message);
+    }
+    return vm.special_values[&SpecialValue::NoneValue];
+}
+
+fn traceback_repr(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    vm.allocate_builtin_type_byname_raw("str", BuiltInTypeData::String("<traceback object>".to_string()))
+}
+
+fn create_exception_type(vm: &VM, name: &str, supertype: Option<MemoryAddress>) -> MemoryAddress {
+    let type_addr = vm.create_type(BUILTIN_MODULE, name, supertype);
+    register_exception_new(vm, type_addr);
+    return type_addr;
+}
+
+fn set_supertype(vm: &VM, type_addr: MemoryAddress, new_supertype: MemoryAddress) {
+    vm.set_bases(type_addr, &[new_supertype], vm.get_mro(new_supertype));
+}
+
+pub fn register_exception_types(vm: &mut VM) {
+    let base_exception = create_exception_type(vm, "BaseException", Some(vm.builtin_type_addrs.object));
+    vm.register_bounded_func_on_addr(base_exception, "__init__", init);
+    vm.register_bounded_func_on_addr(base_exception, "__str__", to_str);
+    vm.register_bounded_func_on_addr(base_exception, "__repr__", repr);
+
+    let exception = create_exception_type(vm, "Exception", Some(base_exception));
+    //raised by generator.close(), not an Exception so that `except Exception` doesn't swallow it
+    create_exception_type(vm, "GeneratorExit", Some(base_exception));
+    //raised when the host interrupts the program, like Ctrl-C does
+    create_exception_type(vm, "KeyboardInterrupt", Some(base_exception));
+    //raised by sys.exit(), also not an Exception
+    let system_exit = vm.create_type(BUILTIN_MODULE, "SystemExit", Some(base_exception));
+    vm.register_type_unbounded_func(system_exit, "__new__", |method_vm: &VM, call_params: CallParams| -> MemoryAddress {
+        check_builtin_func_params!(method_vm, "SystemExit", call_params.params);
+        create_system_exit(method_vm, call_params.params.params.first().copied())
+    });
+    for name in &[
+        "TypeError",
+        "ValueError",
+        "NameError",
+        "AttributeError",
+        "ZeroDivisionError",
+        "OverflowError",
+        "RuntimeError",
+        "KeyError",
+        "ImportError",
+        "SyntaxError",
+        "MemoryError",
+        "TimeoutError",
+    ] {
+        create_exception_type(vm, name, Some(exception));
+    }
+    let import_error = vm.find_in_module(BUILTIN_MODULE, "ImportError").unwrap();
+    create_exception_type(vm, "ModuleNotFoundError", Some(import_error));
+    let name_error = vm.find_in_module(BUILTIN_MODULE, "NameError").unwrap();
+    create_exception_type(vm, "UnboundLocalError", Some(name_error));
+    let runtime_error = vm.find_in_module(BUILTIN_MODULE, "RuntimeError").unwrap();
+    create_exception_type(vm, "RecursionError", Some(runtime_error));
+
+    //StopIteration is created together with the VM because the interpreter needs it for loops
+    let stop_iteration = vm.special_values[&SpecialValue::StopIterationType];
+    set_supertype(vm, stop_iteration, exception);
+    register_exception_new(vm, stop_iteration);
+
+    vm.builtin_type_addrs.exception = exception;
+
+    //holds the frames an exception went through, set as __traceback__ when it's raised.
+    //Not added to the builtins module: the name is taken by the traceback() debugging function.
+    let traceback = vm.allocate_and_write(PyObject {
+        type_addr: vm.special_values[&SpecialValue::Type],
+        properties: BTreeMap::new(),
+        structure: PyObjectStructure::Type {
+            name: String::from("traceback"),
+            functions: BTreeMap::new(),
+            supertype: None,
+            mro: vec![],
+        },
+        is_const: false,
+    });
+    vm.make_const(traceback);
+    vm.register_bounded_func_on_addr(traceback, "__repr__", traceback_repr);
+    vm.register_bounded_func_on_addr(traceback, "__str__", traceback_repr);
+    vm.builtin_type_addrs.traceback = traceback;
+}
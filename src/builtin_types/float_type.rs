@@ -8,12 +8,12 @@ macro_rules! create_compare_function {
     ($name:tt, $param_a:tt, $param_b:tt, $compare:expr) => {
         fn $name(vm: &VM, params: CallParams) -> MemoryAddress {
             let call_params = params.as_method();
-            check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
+            check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
             let other_type_name = vm.get_pyobj_type_name(call_params.params[0]);
             let self_data = vm
                 .get_raw_data_of_pyobj(call_params.bound_pyobj)
                 .take_float();
-            return match other_type_name {
+            return match other_type_name.as_str() {
                 "bool" | "int" => {
                     let other_int = vm.get_raw_data_of_pyobj(call_params.params[0]).take_int();
                     let $param_a = self_data;
@@ -47,12 +47,12 @@ macro_rules! create_binop_function {
     ($name:tt, $param_a:tt, $param_b:tt, $binop:expr) => {
         fn $name(vm: &VM, params: CallParams) -> MemoryAddress {
             let call_params = params.as_method();
-            check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
+            check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
             let other_type_name = vm.get_pyobj_type_name(call_params.params[0]);
             let self_data = vm
                 .get_raw_data_of_pyobj(call_params.bound_pyobj)
                 .take_float();
-            return match other_type_name {
+            return match other_type_name.as_str() {
                 "int" => {
                     let other_int = vm.get_raw_data_of_pyobj(call_params.params[0]).take_int();
                     let $param_a = self_data;
@@ -81,7 +81,7 @@ macro_rules! create_unary_function {
     ($name:tt, $param_a:tt, $func:expr) => {
         fn $name(vm: &VM, params: CallParams) -> MemoryAddress {
             let call_params = params.as_method();
-            check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+            check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
             let self_data = vm
                 .get_raw_data_of_pyobj(call_params.bound_pyobj)
                 .take_float();
@@ -109,7 +109,7 @@ create_unary_function!(positive, a, a);
 
 fn to_boolean(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
         .take_float();
@@ -120,24 +120,42 @@ fn to_boolean(vm: &VM, params: CallParams) -> MemoryAddress {
     }
 }
 
-fn to_float(_vm: &VM, params: CallParams) -> MemoryAddress {
+fn to_float(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
     return call_params.bound_pyobj;
 }
 
 fn to_int(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
         .take_float();
     vm.allocate_builtin_type_byname_raw("int", BuiltInTypeData::Int(self_data as i128))
 }
 
+//Integral floats hash like the equivalent int, since 1.0 == 1
+fn hash(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let self_data = vm
+        .get_raw_data_of_pyobj(call_params.bound_pyobj)
+        .take_float();
+    let hash = if self_data.fract() == 0.0 && self_data.abs() < 1e38 {
+        self_data as i128
+    } else {
+        self_data.to_bits() as i128
+    };
+    vm.allocate_type_byaddr_raw(
+        vm.builtin_type_addrs.int,
+        BuiltInTypeData::Int(hash),
+    )
+}
+
 fn to_str(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
         .take_float();
@@ -147,7 +165,7 @@ fn to_str(vm: &VM, params: CallParams) -> MemoryAddress {
 
 fn repr(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
         .take_float();
@@ -164,6 +182,7 @@ pub fn register_float_type(vm: &mut VM) -> MemoryAddress {
     vm.register_bounded_func(BUILTIN_MODULE, "float", "__lt__", less_than);
     vm.register_bounded_func(BUILTIN_MODULE, "float", "__le__", less_equals);
     vm.register_bounded_func(BUILTIN_MODULE, "float", "__ne__", not_equals);
+    vm.register_bounded_func(BUILTIN_MODULE, "float", "__hash__", hash);
 
     vm.register_bounded_func(BUILTIN_MODULE, "float", "__add__", add);
     vm.register_bounded_func(BUILTIN_MODULE, "float", "__mod__", modulus);
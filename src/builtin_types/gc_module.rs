@@ -0,0 +1,79 @@
+use crate::runtime::vm::*;
+use crate::runtime::datamodel::*;
+use crate::runtime::memory::*;
+use std::rc::Rc;
+
+fn new_int(vm: &VM, value: usize) -> MemoryAddress {
+    vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.int, BuiltInTypeData::Int(value as i128))
+}
+
+fn bool_value(vm: &VM, value: bool) -> MemoryAddress {
+    if value {
+        vm.builtin_type_addrs.true_val
+    } else {
+        vm.builtin_type_addrs.false_val
+    }
+}
+
+//the gc functions take no arguments
+type GcFunction = fn(&VM) -> MemoryAddress;
+
+fn create_gc_fn(vm: &VM, name: &str, run: GcFunction) -> MemoryAddress {
+    let func = PyCallable {
+        code: Rc::new(move |vm, params| -> MemoryAddress {
+            let call_params = params.as_function();
+            check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+            run(vm)
+        }),
+    };
+    return vm.create_unbounded_callable_pyobj(func, Some(name.to_string()));
+}
+
+//collect(): the number of objects freed. Native code waiting on the caller may hold objects
+//the collector can't see, like print calling a __str__ that calls collect(): then nothing is collected.
+fn collect(vm: &VM) -> MemoryAddress {
+    let freed = if vm.native_calls() > 1 { 0 } else { vm.collect_garbage() };
+    new_int(vm, freed)
+}
+
+fn enable(vm: &VM) -> MemoryAddress {
+    vm.memory.set_collection_enabled(true);
+    vm.special_values[&SpecialValue::NoneValue]
+}
+
+fn disable(vm: &VM) -> MemoryAddress {
+    vm.memory.set_collection_enabled(false);
+    vm.special_values[&SpecialValue::NoneValue]
+}
+
+fn isenabled(vm: &VM) -> MemoryAddress {
+    bool_value(vm, vm.memory.collection_enabled())
+}
+
+//get_count(): objects allocated and not freed since the last collection. There is only one generation,
+//the counts of the other two in CPython's tuple are always 0.
+fn get_count(vm: &VM) -> MemoryAddress {
+    let counts = vec![new_int(vm, vm.memory.allocation_count()), new_int(vm, 0), new_int(vm, 0)];
+    for count in counts.iter() {
+        vm.increase_refcount(*count);
+    }
+    vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.tuple, BuiltInTypeData::Tuple(counts))
+}
+
+//A native module, like dis and sys
+pub fn register_gc_module(vm: &mut VM) {
+    let module = vm.create_module("gc");
+    let name = vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.string, BuiltInTypeData::String("gc".to_string()));
+    vm.set_in_module_addr(module, "__name__", name);
+    let functions: [(&str, GcFunction); 5] = [
+        ("collect", collect),
+        ("enable", enable),
+        ("disable", disable),
+        ("isenabled", isenabled),
+        ("get_count", get_count),
+    ];
+    for (name, run) in functions.iter() {
+        let function = create_gc_fn(vm, name, *run);
+        vm.set_in_module_addr(module, name, function);
+    }
+}
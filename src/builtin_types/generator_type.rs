@@ -0,0 +1,95 @@
+use crate::runtime::vm::*;
+use crate::runtime::datamodel::*;
+use crate::runtime::memory::*;
+use std::collections::BTreeMap;
+
+//Generators are made by calling a function that has a yield, see VM::run_function and VM::resume_generator
+
+fn resume(vm: &VM, generator: MemoryAddress, input: GeneratorInput) -> MemoryAddress {
+    match vm.resume_generator(generator, input) {
+        Ok(yielded) => yielded,
+        Err(exception) => {
+            vm.raise_exception(exception);
+            return vm.special_values[&SpecialValue::NoneValue];
+        }
+    }
+}
+
+fn iter(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    return call_params.bound_pyobj;
+}
+
+fn next(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let none = vm.special_values[&SpecialValue::NoneValue];
+    resume(vm, call_params.bound_pyobj, GeneratorInput::Send(none))
+}
+
+fn send(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    resume(vm, call_params.bound_pyobj, GeneratorInput::Send(call_params.params[0]))
+}
+
+//Accepts an exception instance or type, like the raise statement
+fn throw(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    resume(vm, call_params.bound_pyobj, GeneratorInput::Throw(call_params.params[0]))
+}
+
+//Raises GeneratorExit where the generator is suspended, so its finally blocks run
+fn close(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let none = vm.special_values[&SpecialValue::NoneValue];
+    let generator_exit = vm.create_exception("GeneratorExit", String::new());
+    let exception = match vm.resume_generator(call_params.bound_pyobj, GeneratorInput::Throw(generator_exit)) {
+        Ok(_) => vm.create_exception("RuntimeError", String::from("generator ignored GeneratorExit")),
+        Err(exception) => exception,
+    };
+    let generator_exit_type = vm.find_in_module(BUILTIN_MODULE, "GeneratorExit").unwrap();
+    let stop_iteration_type = vm.special_values[&SpecialValue::StopIterationType];
+    if !vm.exception_matches(exception, generator_exit_type) && !vm.exception_matches(exception, stop_iteration_type) {
+        vm.raise_exception(exception);
+    }
+    return none;
+}
+
+fn repr(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let name = match &*vm.get_raw_data_of_pyobj(call_params.bound_pyobj) {
+        BuiltInTypeData::Generator(data) => data.code.code.objname.clone(),
+        _ => String::new(),
+    };
+    let repr = format!("<generator object {} at {:p}>", name, call_params.bound_pyobj);
+    vm.allocate_builtin_type_byname_raw("str", BuiltInTypeData::String(repr))
+}
+
+//Like in CPython, the type is not reachable by name
+pub fn register_generator_type(vm: &mut VM) {
+    let generator = vm.allocate_and_write(PyObject {
+        type_addr: vm.special_values[&SpecialValue::Type],
+        properties: BTreeMap::new(),
+        structure: PyObjectStructure::Type {
+            name: String::from("generator"),
+            functions: BTreeMap::new(),
+            supertype: None,
+            mro: vec![],
+        },
+        is_const: false,
+    });
+    vm.make_const(generator);
+    vm.register_bounded_func_on_addr(generator, "__iter__", iter);
+    vm.register_bounded_func_on_addr(generator, "__next__", next);
+    vm.register_bounded_func_on_addr(generator, "send", send);
+    vm.register_bounded_func_on_addr(generator, "throw", throw);
+    vm.register_bounded_func_on_addr(generator, "close", close);
+    vm.register_bounded_func_on_addr(generator, "__repr__", repr);
+    vm.register_bounded_func_on_addr(generator, "__str__", repr);
+    vm.builtin_type_addrs.generator = generator;
+}
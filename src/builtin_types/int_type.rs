@@ -7,7 +7,7 @@ use crate::runtime::memory::*;
 macro_rules! create_compare_function {
     ($name:tt, $param_a:tt, $param_b:tt, $compare:expr) => {
         fn $name(vm: &VM, params: CallParams) -> MemoryAddress {
-            check_builtin_func_params!(params.func_name.as_ref().unwrap(), 2, params.params.len());
+            check_builtin_func_params!(vm, params.func_name.as_ref().unwrap(), 2, params.params);
             let call_params = params.as_method();
             let other_type_addr = vm.get_pyobj_type_addr(call_params.params[0]);
             let self_data = vm
@@ -34,7 +34,7 @@ macro_rules! create_compare_function {
                     return vm.builtin_type_addrs.false_val;
                 }
             } else {
-                return vm.builtin_type_addrs.false_val;
+                return vm.special_values[&SpecialValue::NotImplementedValue];
             }
         }
     };
@@ -43,15 +43,14 @@ macro_rules! create_compare_function {
 macro_rules! create_binop_function {
     ($name:tt, $param_a:tt, $param_b:tt, $binop:expr) => {
         fn $name(vm: &VM, params: CallParams) -> MemoryAddress {
-            check_builtin_func_params!(params.func_name.as_ref().unwrap(), 2, params.params.len());
+            check_builtin_func_params!(vm, params.func_name.as_ref().unwrap(), 2, params.params);
             let call_params = params.as_method();
-            let other_data = vm.get_raw_data_of_pyobj(call_params.params[0]);
             let other_type_addr = vm.get_pyobj_type_addr(call_params.params[0]);
             let self_data = vm
                 .get_raw_data_of_pyobj(call_params.bound_pyobj)
                 .take_int();
             if other_type_addr == vm.builtin_type_addrs.int {
-                let other_int = other_data.take_int();
+                let other_int = vm.get_raw_data_of_pyobj(call_params.params[0]).take_int();
                 let $param_a = self_data;
                 let $param_b = other_int;
                 vm.allocate_type_byaddr_raw(
@@ -59,7 +58,7 @@ macro_rules! create_binop_function {
                     BuiltInTypeData::Int($binop),
                 )
             } else if other_type_addr == vm.builtin_type_addrs.float {
-                let other_float = other_data.take_float();
+                let other_float = vm.get_raw_data_of_pyobj(call_params.params[0]).take_float();
                 let $param_a = self_data as f64;
                 let $param_b = other_float;
                 vm.allocate_type_byaddr_raw(
@@ -77,7 +76,7 @@ macro_rules! create_unary_function {
     ($name:tt, $param_a:tt, $func:expr) => {
         fn $name(vm: &VM, params: CallParams) -> MemoryAddress {
             let call_params = params.as_method();
-            check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+            check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
             let self_data = vm
                 .get_raw_data_of_pyobj(call_params.bound_pyobj)
                 .take_int();
@@ -103,14 +102,14 @@ create_binop_function!(sub, a, b, a - b);
 create_binop_function!(mul, a, b, a * b);
 
 fn truediv(vm: &VM, params: CallParams) -> MemoryAddress {
-    check_builtin_func_params!(params.func_name.as_ref().unwrap(), 2, params.params.len());
+    check_builtin_func_params!(vm, params.func_name.as_ref().unwrap(), 2, params.params);
     let call_params = params.as_method();
     let other_type_name = vm.get_pyobj_type_name(call_params.params[0]);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
         .take_int();
 
-    return match other_type_name {
+    return match other_type_name.as_str() {
         "int" => {
             let other_int = vm.get_raw_data_of_pyobj(call_params.params[0]).take_int();
             vm.allocate_type_byaddr_raw(
@@ -132,16 +131,16 @@ fn truediv(vm: &VM, params: CallParams) -> MemoryAddress {
 create_unary_function!(negation, a, a * -1);
 create_unary_function!(positive, a, a);
 
-fn int(_vm: &VM, params: CallParams) -> MemoryAddress {
+fn int(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
     //no-op
     return call_params.bound_pyobj;
 }
 
 fn float(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
         .take_int();
@@ -153,7 +152,7 @@ fn float(vm: &VM, params: CallParams) -> MemoryAddress {
 
 fn to_str(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
         .take_int();
@@ -165,7 +164,7 @@ fn to_str(vm: &VM, params: CallParams) -> MemoryAddress {
 
 fn repr(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
         .take_int();
@@ -177,7 +176,7 @@ fn repr(vm: &VM, params: CallParams) -> MemoryAddress {
 
 fn to_boolean(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
         .take_int();
@@ -188,6 +187,19 @@ fn to_boolean(vm: &VM, params: CallParams) -> MemoryAddress {
     }
 }
 
+//Equal numbers must hash the same, so this is also used for bool and integral floats
+fn hash(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let self_data = vm
+        .get_raw_data_of_pyobj(call_params.bound_pyobj)
+        .take_int();
+    vm.allocate_type_byaddr_raw(
+        vm.builtin_type_addrs.int,
+        BuiltInTypeData::Int(self_data),
+    )
+}
+
 pub fn register_int_type(vm: &mut VM) -> MemoryAddress {
     let int_type = vm.create_type(BUILTIN_MODULE, "int", None);
 
@@ -197,6 +209,7 @@ pub fn register_int_type(vm: &mut VM) -> MemoryAddress {
     vm.register_bounded_func(BUILTIN_MODULE, "int", "__lt__", less_than);
     vm.register_bounded_func(BUILTIN_MODULE, "int", "__le__", less_equals);
     vm.register_bounded_func(BUILTIN_MODULE, "int", "__ne__", not_equals);
+    vm.register_bounded_func(BUILTIN_MODULE, "int", "__hash__", hash);
 
     vm.register_bounded_func(BUILTIN_MODULE, "int", "__add__", add);
     vm.register_bounded_func(BUILTIN_MODULE, "int", "__mod__", modulus);
@@ -1,18 +1,22 @@
 use crate::runtime::vm::*;
 use crate::runtime::datamodel::*;
 use crate::runtime::memory::*;
+use crate::builtin_types::dict_type::keys_equal;
 
 
 fn concat(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj_mut(call_params.bound_pyobj)
         .take_list()
         .clone();
+    if vm.get_pyobj_type_addr(call_params.params[0]) != vm.builtin_type_addrs.list {
+        return vm.special_values[&SpecialValue::NotImplementedValue];
+    }
     let other_data = vm.get_raw_data_of_pyobj(call_params.params[0]);
 
-    match other_data {
+    match &*other_data {
         BuiltInTypeData::List(values) => {
             let mut result = vec![];
             result.extend(self_data);
@@ -22,97 +26,72 @@ fn concat(vm: &VM, params: CallParams) -> MemoryAddress {
                 BuiltInTypeData::List(result),
             );
         }
-        _ => {
-            let other_type_name = vm.get_pyobj_type_name(call_params.params[0]);
-            panic!(
-                "can only concatenate list (not \"{}\") to list",
-                other_type_name
-            );
-        }
+        _ => vm.special_values[&SpecialValue::NotImplementedValue],
     }
 }
 
 fn extend(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
-    let other_data = vm.get_raw_data_of_pyobj(call_params.params[0]);
-    match other_data {
-        BuiltInTypeData::List(values) => {
-            let cloned = values.clone();
-            let self_data = vm
-                .get_raw_data_of_pyobj_mut(call_params.bound_pyobj)
-                .take_list_mut();
-            (*self_data).extend(cloned);
-            return call_params.bound_pyobj;
-        }
-        _ => {
-            let other_type_name = vm.get_pyobj_type_name(call_params.params[0]);
-            panic!(
-                "horse only supports extending from list (not \"{}\") for now",
-                other_type_name
-            );
-        }
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    if vm.get_pyobj_type_addr(call_params.params[0]) != vm.builtin_type_addrs.list {
+        let other_type_name = vm.get_pyobj_type_name(call_params.params[0]);
+        let message = format!("horse only supports extending from list (not \"{}\") for now", other_type_name);
+        let exception = vm.create_exception("TypeError", message);
+        vm.raise_exception(exception);
+        return vm.special_values[&SpecialValue::NoneValue];
     }
+    let cloned = vm.get_raw_data_of_pyobj(call_params.params[0]).take_list().clone();
+    for value in cloned.iter() {
+        vm.increase_refcount(*value);
+    }
+    vm.get_raw_data_of_pyobj_mut(call_params.bound_pyobj).take_list_mut().extend(cloned);
+    return vm.special_values[&SpecialValue::NoneValue];
 }
 
 fn append(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
-    let self_data = vm
-        .get_raw_data_of_pyobj_mut(call_params.bound_pyobj)
-        .take_list_mut();
-    self_data.push(call_params.params[0]);
-    return call_params.bound_pyobj;
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    vm.get_raw_data_of_pyobj_mut(call_params.bound_pyobj)
+        .take_list_mut()
+        .push(call_params.params[0]);
+    //the list keeps the element alive
+    vm.increase_refcount(call_params.params[0]);
+    return vm.special_values[&SpecialValue::NoneValue];
+}
+
+fn lists_equal(vm: &VM, this: MemoryAddress, other: MemoryAddress) -> Result<bool, MemoryAddress> {
+    let other_elements = match &vm.get_pyobj_byaddr(other).structure {
+        PyObjectStructure::Object { raw_data: BuiltInTypeData::List(elements), .. } => elements.clone(),
+        _ => return Ok(false),
+    };
+    let this_elements = vm.get_raw_data_of_pyobj(this).take_list().clone();
+    if this_elements.len() != other_elements.len() {
+        return Ok(false);
+    }
+    for (a, b) in this_elements.iter().zip(other_elements.iter()) {
+        if !keys_equal(vm, *a, *b)? {
+            return Ok(false);
+        }
+    }
+    return Ok(true);
 }
 
 fn equals(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
-    let this_list = vm
-        .get_raw_data_of_pyobj(call_params.bound_pyobj)
-        .take_list();
-    let other_data = vm.get_raw_data_of_pyobj(call_params.params[0]);
-
-    match other_data {
-        BuiltInTypeData::List(other_list) => {
-            if this_list.len() != other_list.len() {
-                return vm.builtin_type_addrs.false_val;
-            }
-            let mut list_equals = true;
-            for ptr_self in this_list.iter() {
-                for ptr_other in other_list.iter() {
-                    if ptr_self == ptr_other {
-                        continue;
-                    }
-                    let result = vm.call_method(*ptr_self, "__eq__", PositionalParameters::single(*ptr_other));
-                    match result {
-                        Some((eq_result, _)) => {
-                            if eq_result == vm.builtin_type_addrs.false_val {
-                                list_equals = false;
-                                break;
-                            }
-                        }
-                        None => {
-                            list_equals = false;
-                        }
-                    }
-                }
-            }
-            if list_equals {
-                return vm.builtin_type_addrs.false_val;
-            } else {
-                return vm.builtin_type_addrs.true_val;
-            }
-        }
-        _ => {
-            return vm.builtin_type_addrs.false_val;
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    match lists_equal(vm, call_params.bound_pyobj, call_params.params[0]) {
+        Ok(true) => vm.builtin_type_addrs.true_val,
+        Ok(false) => vm.builtin_type_addrs.false_val,
+        Err(exception) => {
+            vm.raise_exception(exception);
+            return vm.special_values[&SpecialValue::NoneValue];
         }
     }
 }
 
 fn not_equals(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
     let result = vm.call_method(call_params.bound_pyobj, "__eq__", PositionalParameters::single(call_params.params[0]));
     match result {
         Some((eq_result, _)) => {
@@ -130,40 +109,14 @@ fn not_equals(vm: &VM, params: CallParams) -> MemoryAddress {
 
 fn repr(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
-    let this_list = vm
-        .get_raw_data_of_pyobj(call_params.bound_pyobj)
-        .take_list();
-    let mut buffer = String::from("[");
-
-    let all_reprs: Vec<String> = this_list
-        .iter()
-        .map(|ptr_self| {
-            let (as_string, _) = vm.call_method(*ptr_self, "__repr__", PositionalParameters::empty()).unwrap();
-            return vm
-                .get_pyobj_byaddr(as_string)
-                .try_get_builtin()
-                .unwrap()
-                .take_string()
-                .clone();
-        })
-        .collect();
-
-    buffer = buffer + all_reprs.join(",").as_str();
-    buffer.push(']');
-
-    vm.allocate_type_byaddr_raw(
-        vm.builtin_type_addrs.string,
-        BuiltInTypeData::String(buffer),
-    )
-}
-
-fn to_str(vm: &VM, params: CallParams) -> MemoryAddress {
-    let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    if !vm.repr_enter(call_params.bound_pyobj) {
+        return vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.string, BuiltInTypeData::String(String::from("[...]")));
+    }
     let this_list = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
-        .take_list();
+        .take_list()
+        .clone();
     let mut buffer = String::from("[");
 
     let all_reprs: Vec<String> = this_list
@@ -176,6 +129,7 @@ fn to_str(vm: &VM, params: CallParams) -> MemoryAddress {
                 .clone();
         })
         .collect();
+    vm.repr_leave(call_params.bound_pyobj);
 
     buffer = buffer + all_reprs.join(", ").as_str();
     buffer.push(']');
@@ -188,11 +142,11 @@ fn to_str(vm: &VM, params: CallParams) -> MemoryAddress {
 
 fn len(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
-    let this_list = vm
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let list_len = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
-        .take_list();
-    let list_len = this_list.len();
+        .take_list()
+        .len();
     vm.allocate_type_byaddr_raw(
         vm.builtin_type_addrs.int,
         BuiltInTypeData::Int(list_len as i128),
@@ -202,28 +156,42 @@ fn len(vm: &VM, params: CallParams) -> MemoryAddress {
 
 fn iter(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
-    //construct a list_iterator
-    //find it in builtin module
-    let iterator_class = vm.find_in_module(MAIN_MODULE, "list_iterator").expect("list_iterator type not found");
-    let new = vm.try_load_function_addr(iterator_class); //this is the __new__ method, try_load_function_addr automatically gets the __new__ function
-    let (result, _) = vm.run_function(PositionalParameters::single(call_params.bound_pyobj), new, None);
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    //list_iterator is a generator function from the standard library
+    let list_iterator = vm.find_in_module(BUILTIN_MODULE, "list_iterator").expect("list_iterator not found");
+    let (result, _) = vm.run_function(PositionalParameters::single(call_params.bound_pyobj), list_iterator, None);
     return result;
 }
 
+//The index of a list or tuple. Raises the TypeError and returns None if it is not an int.
+pub fn sequence_index(vm: &VM, index: MemoryAddress, sequence: &str) -> Option<i128> {
+    //None and instances of classes have no raw data
+    if let Some(BuiltInTypeData::Int(i)) = vm.get_pyobj_byaddr(index).try_get_builtin() {
+        return Some(*i);
+    }
+    let message = format!("{} indices must be integers, not {}", sequence, vm.get_pyobj_type_name(index));
+    let exception = vm.create_exception("TypeError", message);
+    vm.raise_exception(exception);
+    None
+}
+
 fn getitem(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
     let this_list = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
-        .take_list();
+        .take_list()
+        .clone();
     
-    let index = vm.get_raw_data_of_pyobj(call_params.params[0]).take_int();
+    let index = match sequence_index(vm, call_params.params[0], "list") {
+        Some(index) => index,
+        None => return vm.special_values[&SpecialValue::NoneValue],
+    };
 
     if index as usize >= this_list.len() {
-        let exception = vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.index_err, BuiltInTypeData::String("list index out of range".into()));
+        let exception = vm.create_exception("IndexError", "list index out of range".into());
         vm.raise_exception(exception);
-        return exception;
+        return vm.special_values[&SpecialValue::NoneValue];
     } else {
         let value_at_index = this_list[index as usize];
         return value_at_index
@@ -231,40 +199,62 @@ fn getitem(vm: &VM, params: CallParams) -> MemoryAddress {
 
 }
 
+fn setitem(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 2, call_params);
+    let index = match sequence_index(vm, call_params.params[0], "list") {
+        Some(index) => index,
+        None => return vm.special_values[&SpecialValue::NoneValue],
+    };
+    let value = call_params.params[1];
+    let list_len = vm
+        .get_raw_data_of_pyobj(call_params.bound_pyobj)
+        .take_list()
+        .len();
+
+    if index < 0 || index as usize >= list_len {
+        let exception = vm.create_exception("IndexError", "list assignment index out of range".into());
+        vm.raise_exception(exception);
+    } else {
+        vm.get_raw_data_of_pyobj_mut(call_params.bound_pyobj).take_list_mut()[index as usize] = value;
+        vm.increase_refcount(value);
+    }
+    return vm.special_values[&SpecialValue::NoneValue];
+}
+
+fn contains(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    let this_list = vm
+        .get_raw_data_of_pyobj(call_params.bound_pyobj)
+        .take_list()
+        .clone();
+    let item = call_params.params[0];
+
+    for element in this_list {
+        if element == item {
+            return vm.builtin_type_addrs.true_val;
+        }
+        if let Some((eq_result, _)) = vm.call_method(element, "__eq__", PositionalParameters::single(item)) {
+            if vm.is_truthy(eq_result) {
+                return vm.builtin_type_addrs.true_val;
+            }
+        }
+    }
+    return vm.builtin_type_addrs.false_val;
+}
+
 fn create_new(vm: &VM, params: CallParams) -> MemoryAddress {
     if params.params.len() == 0 {
-        return vm
-            .allocate_builtin_type_byname_raw("str", BuiltInTypeData::String(String::from("")));
-    } else {
-        if params.params.len() == 0 {
-            return vm.allocate_type_byaddr_raw(
-                vm.builtin_type_addrs.list,
-                BuiltInTypeData::List(vec![]),
-            );
-        } else {
-            check_builtin_func_params!("list", 1, params.params.len());
-            let iterator_call = vm.call_method(params.params.params[0], "__iter__", PositionalParameters::empty());
-            let mut results = vec![];
-            loop {
-                match iterator_call {
-                    Some((addr, _)) => {
-                        //start consuming the iterator
-                        let (result, frame) = vm.call_method(addr, "__next__", PositionalParameters::empty()).unwrap();
-                        if frame.exception.is_some() {
-                            break;
-                        } else {
-                            results.push(result);
-                        }
-                    },
-                    None => panic!("Object passed to list does not have __iter__ method"),
-                }
-            }   
-            return vm.allocate_type_byaddr_raw(
-                vm.builtin_type_addrs.list,
-                BuiltInTypeData::List(results),
-            );
+        return vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.list, BuiltInTypeData::List(vec![]));
+    }
+    check_builtin_func_params!(vm, "list", 1, params.params);
+    match vm.collect_iterable(params.params.params[0]) {
+        Ok(elements) => vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.list, BuiltInTypeData::List(elements)),
+        Err(exception) => {
+            vm.raise_exception(exception);
+            vm.special_values[&SpecialValue::NoneValue]
         }
-       
     }
 }
 
@@ -278,9 +268,11 @@ pub fn register_list_type(vm: &mut VM) -> MemoryAddress {
     vm.register_bounded_func(BUILTIN_MODULE, "list", "__eq__", equals);
     vm.register_bounded_func(BUILTIN_MODULE, "list", "__neq__", not_equals);
     vm.register_bounded_func(BUILTIN_MODULE, "list", "__repr__", repr);
-    vm.register_bounded_func(BUILTIN_MODULE, "list", "__str__", to_str);
+    vm.register_bounded_func(BUILTIN_MODULE, "list", "__str__", repr);
     vm.register_bounded_func(BUILTIN_MODULE, "list", "__len__", len);
     vm.register_bounded_func(BUILTIN_MODULE, "list", "__getitem__", getitem);
+    vm.register_bounded_func(BUILTIN_MODULE, "list", "__setitem__", setitem);
+    vm.register_bounded_func(BUILTIN_MODULE, "list", "__contains__", contains);
     vm.register_bounded_func(BUILTIN_MODULE, "list", "__iter__", iter);
     vm.register_bounded_func(BUILTIN_MODULE, "list", "append", append);
     vm.register_bounded_func(BUILTIN_MODULE, "list", "extend", extend);
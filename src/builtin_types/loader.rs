@@ -1,21 +1,65 @@
 use crate::runtime::vm::*;
-use crate::ast::lexer;
-use crate::ast::parser;
-use crate::bytecode::compiler::*;
+use crate::runtime::datamodel::BUILTIN_MODULE;
+use crate::bytecode::cache;
+use crate::commons::errors::HorseError;
 use crate::runtime::interpreter;
+use std::env;
+use std::path::PathBuf;
 
-pub fn run_loader(vm: &mut VM) {
+//The standard library is built into the binary, so horse runs from any directory.
+//Files are loaded in this order: list_iterator first, the native __iter__ of lists, tuples, dicts and sets need it.
+const STDLIB_BUILTINS: &[(&str, &str)] = &[
+    ("list_iterator.py", include_str!("../../stdlib/__builtins__/list_iterator.py")),
+    ("range.py", include_str!("../../stdlib/__builtins__/range.py")),
+    ("map.py", include_str!("../../stdlib/__builtins__/map.py")),
+    ("filter.py", include_str!("../../stdlib/__builtins__/filter.py")),
+    ("test_utils.py", include_str!("../../stdlib/__builtins__/test_utils.py")),
+];
 
-    //bool inherits from int
-    for entry in std::fs::read_dir("./stdlib/__builtins__").unwrap() {
-        let dir = entry.unwrap();
-        //println!("Loading source {:?}", dir.path());
-        let source = std::fs::read_to_string(dir.path());
-        let tokens = lexer::tokenize(&source.unwrap()).unwrap();
-        let expr = parser::parse_ast(tokens);
-        let program = compile(expr);
-        interpreter::execute_program(vm, program);
+//For development: files in $HORSE_STDLIB/__builtins__ replace the built in ones with the same name,
+//so changes to the standard library don't need a rebuild
+fn override_dir() -> Option<PathBuf> {
+    env::var_os("HORSE_STDLIB").map(|dir| PathBuf::from(dir).join(BUILTIN_MODULE))
+}
+
+pub fn run_loader(vm: &mut VM) -> Result<(), HorseError> {
+    let override_dir = override_dir();
+    for (name, embedded_source) in STDLIB_BUILTINS.iter() {
+        let overridden = override_dir
+            .as_ref()
+            .map(|dir| dir.join(name))
+            .and_then(|path| std::fs::read_to_string(&path).ok().map(|source| (path, source)));
+        //overridden files are cached next to them, the built in ones in the user cache directory
+        let (filename, source, cache_file) = match overridden {
+            Some((path, source)) => (path.to_string_lossy().to_string(), source, cache::cache_file_for(&path)),
+            None => (
+                format!("stdlib/{}/{}", BUILTIN_MODULE, name),
+                embedded_source.to_string(),
+                cache::user_cache_dir().map(|dir| dir.join(BUILTIN_MODULE).join(name).with_extension(cache::CACHE_EXTENSION)),
+            ),
+        };
+        let program = cache::compile_cached(&source, &filename, cache_file.as_deref())?;
+        //what the standard library defines is visible from every module, like the native builtins
+        interpreter::execute_program_in_module(vm, program, BUILTIN_MODULE)?;
         vm.clear_stacks();
     }
-    
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_stdlib_file_is_embedded() {
+        let mut on_disk: Vec<String> = std::fs::read_dir("./stdlib/__builtins__")
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        on_disk.sort();
+        let mut embedded: Vec<String> = STDLIB_BUILTINS.iter().map(|(name, _)| name.to_string()).collect();
+        embedded.sort();
+        assert_eq!(on_disk, embedded);
+    }
 }
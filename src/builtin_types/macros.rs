@@ -1,10 +1,22 @@
+//$params is anything with the positional `params` and the `kwargs` of the call.
+//Without the expected number of arguments, only the keyword arguments are checked.
 macro_rules! check_builtin_func_params {
-    ($name:expr, $expected:expr, $received:expr) => {
-        if $expected != $received {
-            panic!(
-                "{}() expected {} arguments, got {}",
-                $name, $expected, $received
+    ($vm:expr, $name:expr, $params:expr) => {
+        if !$params.kwargs.is_empty() {
+            let exception = $vm.create_exception("TypeError", format!("{}() takes no keyword arguments", $name));
+            $vm.raise_exception(exception);
+            return $vm.special_values[&$crate::runtime::vm::SpecialValue::NoneValue];
+        }
+    };
+    ($vm:expr, $name:expr, $expected:expr, $params:expr) => {
+        check_builtin_func_params!($vm, $name, $params);
+        if $expected != $params.params.len() {
+            let exception = $vm.create_exception(
+                "TypeError",
+                format!("{}() expected {} arguments, got {}", $name, $expected, $params.params.len()),
             );
+            $vm.raise_exception(exception);
+            return $vm.special_values[&$crate::runtime::vm::SpecialValue::NoneValue];
         }
     };
 }
@@ -8,13 +8,25 @@ pub mod builtin_math;
 pub mod float_type;
 pub mod int_type;
 pub mod list_type;
+pub mod tuple_type;
+pub mod dict_type;
+pub mod set_type;
 pub mod string_type;
+pub mod exception_types;
 pub mod index_error;
 pub mod code_object;
+pub mod cell_type;
+pub mod generator_type;
+pub mod module_type;
+pub mod dis_module;
+pub mod sys_module;
+pub mod gc_module;
 pub mod loader;
 pub mod none_type;
+pub mod object_type;
 
 pub fn register_builtins(vm: &mut VM) {
+    object_type::register_object_type(vm);
     int_type::register_int_type(vm);
     float_type::register_float_type(vm);
     builtin_math::register_builtin_functions(vm);
@@ -22,7 +34,17 @@ pub fn register_builtins(vm: &mut VM) {
     boolean_type::register_boolean_type(vm);
     string_type::register_string_type(vm);
     list_type::register_list_type(vm);
+    tuple_type::register_tuple_type(vm);
+    dict_type::register_dict_type(vm);
+    set_type::register_set_type(vm);
+    exception_types::register_exception_types(vm);
     index_error::register_indexerr_type(vm);
     code_object::register_codeobject_type(vm);
+    cell_type::register_cell_type(vm);
+    generator_type::register_generator_type(vm);
+    module_type::register_module_type(vm);
+    dis_module::register_dis_module(vm);
+    sys_module::register_sys_module(vm);
+    gc_module::register_gc_module(vm);
     none_type::register_none_type_methods(vm);
 }
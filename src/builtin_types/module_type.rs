@@ -0,0 +1,24 @@
+use crate::runtime::vm::*;
+use crate::runtime::datamodel::*;
+use crate::runtime::memory::*;
+
+fn repr(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let module = call_params.bound_pyobj;
+    let name = vm.get_module_name(module);
+    let repr = match vm.find_in_module_addr(module, "__file__") {
+        Some(file) => format!("<module '{}' from '{}'>", name, vm.get_raw_data_of_pyobj(file).take_string()),
+        None => format!("<module '{}'>", name),
+    };
+    vm.allocate_builtin_type_byname_raw("str", BuiltInTypeData::String(repr))
+}
+
+//The module type is created with the VM, before there are strings for the name of the main module
+pub fn register_module_type(vm: &mut VM) {
+    let module_type = vm.special_values[&SpecialValue::ModuleType];
+    vm.register_bounded_func_on_addr(module_type, "__repr__", repr);
+    vm.register_bounded_func_on_addr(module_type, "__str__", repr);
+    let main_name = vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.string, BuiltInTypeData::String(MAIN_MODULE.to_string()));
+    vm.set_in_module(MAIN_MODULE, "__name__", main_name);
+}
@@ -3,7 +3,7 @@ use crate::runtime::vm::*;
 use crate::runtime::datamodel::*;
 use crate::runtime::memory::*;
 fn to_str(vm: &VM, params: CallParams) -> MemoryAddress {
-    check_builtin_func_params!(params.func_name.unwrap(), 1, params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, params.params);
  
     vm.allocate_type_byaddr_raw(
         vm.builtin_type_addrs.string,
@@ -11,13 +11,14 @@ fn to_str(vm: &VM, params: CallParams) -> MemoryAddress {
     )
 }
 fn to_boolean(vm: &VM, params: CallParams) -> MemoryAddress {
-    check_builtin_func_params!(params.func_name.unwrap(), 0, params.params.len());
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
     return vm.builtin_type_addrs.false_val;
 }
 
 fn equals(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
     let self_pyobj = vm.get_pyobj_byaddr(call_params.params[0]);
 
     match self_pyobj.structure {
@@ -26,10 +27,18 @@ fn equals(vm: &VM, params: CallParams) -> MemoryAddress {
     }
 }
 
+fn hash(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.int, BuiltInTypeData::Int(0))
+}
+
 pub fn register_none_type_methods(vm: &mut VM) {
     let none_type_addr = vm.special_values[&SpecialValue::NoneType];
 
     vm.register_bounded_func_on_addr(none_type_addr, "__str__", to_str);
+    vm.register_bounded_func_on_addr(none_type_addr, "__repr__", to_str);
     vm.register_bounded_func_on_addr(none_type_addr, "__eq__", equals);
     vm.register_bounded_func_on_addr(none_type_addr, "__bool__", to_boolean);
+    vm.register_bounded_func_on_addr(none_type_addr, "__hash__", hash);
 }
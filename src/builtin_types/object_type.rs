@@ -0,0 +1,115 @@
+use crate::runtime::vm::*;
+use crate::runtime::datamodel::*;
+use crate::runtime::memory::*;
+
+//`object` is the implicit base of user classes, so super().__init__() always finds something to call.
+//Builtin types don't have it in their MRO, but isinstance/issubclass treat everything as an object.
+
+fn raise(vm: &VM, exception_type: &str, message: String) -> MemoryAddress {
+    let exception = vm.create_exception(exception_type, message);
+    vm.raise_exception(exception);
+    return vm.special_values[&SpecialValue::NoneValue];
+}
+
+fn create_new(vm: &VM, params: CallParams) -> MemoryAddress {
+    check_builtin_func_params!(vm, "object", params.params);
+    if params.params.len() != 0 {
+        return raise(vm, "TypeError", "object() takes no arguments".to_string());
+    }
+    vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.object, BuiltInTypeData::ClassInstance)
+}
+
+//Reached when a class doesn't define __init__, or when super().__init__() gets to the end of the MRO
+fn init(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, "__init__", call_params);
+    if !call_params.params.is_empty() {
+        let type_name = vm.get_pyobj_type_name(call_params.bound_pyobj).to_string();
+        return raise(vm, "TypeError", format!("{}() takes no arguments", type_name));
+    }
+    return vm.special_values[&SpecialValue::NoneValue];
+}
+
+fn is_type(vm: &VM, addr: MemoryAddress) -> bool {
+    matches!(vm.get_pyobj_byaddr(addr).structure, PyObjectStructure::Type { .. })
+}
+
+//super() without arguments takes the class where the calling function was defined
+//and the calling function's first argument, like CPython does with the __class__ cell.
+fn create_super(vm: &VM, params: CallParams) -> MemoryAddress {
+    check_builtin_func_params!(vm, "super", params.params);
+    let (this_class, instance) = match params.params.len() {
+        0 => {
+            let found = {
+                let stack = vm.stack.borrow();
+                //the last frame belongs to this native call
+                let caller = &stack[stack.len() - 2];
+                let this_class = caller
+                    .function
+                    .and_then(|function| vm.get_obj_property(function, "__class__"));
+                let instance = caller.local_namespace.first().copied();
+                this_class.zip(instance)
+            };
+            match found {
+                Some(found) => found,
+                None => return raise(vm, "RuntimeError", "super(): no arguments".to_string()),
+            }
+        }
+        2 => (params.params.params[0], params.params.params[1]),
+        received => {
+            return raise(vm, "TypeError", format!("super() expected 0 or 2 arguments, got {}", received));
+        }
+    };
+
+    if !is_type(vm, this_class) {
+        return raise(vm, "TypeError", "super() argument 1 must be a type".to_string());
+    }
+    let instance_type = vm.get_pyobj_type_addr(instance);
+    if !vm.is_subtype(instance_type, this_class) {
+        return raise(vm, "TypeError", "super(type, obj): obj must be an instance or subtype of type".to_string());
+    }
+
+    let super_type = vm.find_in_module(BUILTIN_MODULE, "super").unwrap();
+    vm.allocate_type_byaddr_raw(super_type, BuiltInTypeData::Super { this_class, instance })
+}
+
+fn super_repr(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let this_class = match &*vm.get_raw_data_of_pyobj(call_params.bound_pyobj) {
+        BuiltInTypeData::Super { this_class, .. } => *this_class,
+        _ => return raise(vm, "TypeError", "descriptor requires a 'super' object".to_string()),
+    };
+    let class_name = vm.get_type_name(this_class).to_string();
+    vm.allocate_type_byaddr_raw(
+        vm.builtin_type_addrs.string,
+        BuiltInTypeData::String(format!("<super: <class '{}'>>", class_name)),
+    )
+}
+
+fn type_repr(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let type_name = vm.get_type_name(call_params.bound_pyobj).to_string();
+    vm.allocate_type_byaddr_raw(
+        vm.builtin_type_addrs.string,
+        BuiltInTypeData::String(format!("<class '{}'>", type_name)),
+    )
+}
+
+pub fn register_object_type(vm: &mut VM) -> MemoryAddress {
+    let object_type = vm.create_type(BUILTIN_MODULE, "object", None);
+    vm.builtin_type_addrs.object = object_type;
+    vm.register_type_unbounded_func(object_type, "__new__", create_new);
+    vm.register_bounded_func(BUILTIN_MODULE, "object", "__init__", init);
+
+    let super_type = vm.create_type(BUILTIN_MODULE, "super", None);
+    vm.register_type_unbounded_func(super_type, "__new__", create_super);
+    vm.register_bounded_func(BUILTIN_MODULE, "super", "__repr__", super_repr);
+    vm.register_bounded_func(BUILTIN_MODULE, "super", "__str__", super_repr);
+
+    let type_type = vm.special_values[&SpecialValue::Type];
+    vm.register_bounded_func_on_addr(type_type, "__repr__", type_repr);
+    vm.register_bounded_func_on_addr(type_type, "__str__", type_repr);
+    return object_type;
+}
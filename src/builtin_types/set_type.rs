@@ -0,0 +1,161 @@
+use crate::runtime::vm::*;
+use crate::runtime::datamodel::*;
+use crate::runtime::memory::*;
+use crate::builtin_types::dict_type::{dict_remove, dict_set, find_entry, repr_of};
+
+//Elements are hashed and compared like dict keys, through __hash__ and __eq__.
+
+fn raise(vm: &VM, exception_type: &str, message: String) -> MemoryAddress {
+    let exception = vm.create_exception(exception_type, message);
+    vm.raise_exception(exception);
+    return vm.special_values[&SpecialValue::NoneValue];
+}
+
+pub fn new_set(vm: &VM) -> MemoryAddress {
+    vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.set, BuiltInTypeData::Set(DictData::new()))
+}
+
+pub fn set_add(vm: &VM, set: MemoryAddress, element: MemoryAddress) -> Result<(), MemoryAddress> {
+    dict_set(vm, set, element, vm.special_values[&SpecialValue::NoneValue])
+}
+
+fn elements_of(vm: &VM, set: MemoryAddress) -> Vec<MemoryAddress> {
+    vm.get_raw_data_of_pyobj(set).take_dict().entries.iter().map(|entry| entry.key).collect()
+}
+
+fn create_new(vm: &VM, params: CallParams) -> MemoryAddress {
+    check_builtin_func_params!(vm, "set", params.params);
+    if params.params.len() > 1 {
+        return raise(vm, "TypeError", format!("set expected at most 1 argument, got {}", params.params.len()));
+    }
+    let set = new_set(vm);
+    if let Some(iterable) = params.params.params.first() {
+        let result = vm
+            .collect_iterable(*iterable)
+            .and_then(|elements| elements.into_iter().try_for_each(|element| set_add(vm, set, element)));
+        if let Err(exception) = result {
+            vm.raise_exception(exception);
+        }
+    }
+    return set;
+}
+
+fn add(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    if let Err(exception) = set_add(vm, call_params.bound_pyobj, call_params.params[0]) {
+        vm.raise_exception(exception);
+    }
+    return vm.special_values[&SpecialValue::NoneValue];
+}
+
+//remove raises KeyError for a missing element, discard ignores it
+fn remove_element(vm: &VM, params: CallParams, missing_is_error: bool) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    let element = call_params.params[0];
+    match dict_remove(vm, call_params.bound_pyobj, element) {
+        Ok(None) if missing_is_error => raise(vm, "KeyError", repr_of(vm, element)),
+        Ok(_) => vm.special_values[&SpecialValue::NoneValue],
+        Err(exception) => {
+            vm.raise_exception(exception);
+            return vm.special_values[&SpecialValue::NoneValue];
+        }
+    }
+}
+
+fn remove(vm: &VM, params: CallParams) -> MemoryAddress {
+    remove_element(vm, params, true)
+}
+
+fn discard(vm: &VM, params: CallParams) -> MemoryAddress {
+    remove_element(vm, params, false)
+}
+
+fn contains(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    match find_entry(vm, call_params.bound_pyobj, call_params.params[0]) {
+        Ok((_, Some(_))) => vm.builtin_type_addrs.true_val,
+        Ok((_, None)) => vm.builtin_type_addrs.false_val,
+        Err(exception) => {
+            vm.raise_exception(exception);
+            return vm.special_values[&SpecialValue::NoneValue];
+        }
+    }
+}
+
+fn len(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let set_len = vm.get_raw_data_of_pyobj(call_params.bound_pyobj).take_dict().len();
+    vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.int, BuiltInTypeData::Int(set_len as i128))
+}
+
+//Iterates over a snapshot of the elements, like dict.__iter__
+fn iter(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let elements = elements_of(vm, call_params.bound_pyobj);
+    let snapshot = vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.list, BuiltInTypeData::List(elements));
+    let list_iterator = vm.find_in_module(BUILTIN_MODULE, "list_iterator").expect("list_iterator not found");
+    let (result, _) = vm.run_function(PositionalParameters::single(snapshot), list_iterator, None);
+    return result;
+}
+
+fn equals(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    let other = call_params.params[0];
+    let other_len = match &vm.get_pyobj_byaddr(other).structure {
+        PyObjectStructure::Object { raw_data: BuiltInTypeData::Set(other_set), .. } => other_set.len(),
+        _ => return vm.builtin_type_addrs.false_val,
+    };
+    let elements = elements_of(vm, call_params.bound_pyobj);
+    if elements.len() != other_len {
+        return vm.builtin_type_addrs.false_val;
+    }
+    for element in elements {
+        match find_entry(vm, other, element) {
+            Ok((_, Some(_))) => {}
+            Ok((_, None)) => return vm.builtin_type_addrs.false_val,
+            Err(exception) => {
+                vm.raise_exception(exception);
+                return vm.special_values[&SpecialValue::NoneValue];
+            }
+        }
+    }
+    return vm.builtin_type_addrs.true_val;
+}
+
+fn repr(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let elements = elements_of(vm, call_params.bound_pyobj);
+    //`{}` is an empty dict, so the empty set has its own repr
+    let buffer = if elements.is_empty() {
+        String::from("set()")
+    } else {
+        let all_reprs: Vec<String> = elements.iter().map(|element| repr_of(vm, *element)).collect();
+        format!("{{{}}}", all_reprs.join(", "))
+    };
+    vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.string, BuiltInTypeData::String(buffer))
+}
+
+pub fn register_set_type(vm: &mut VM) -> MemoryAddress {
+    let set_type = vm.create_type(BUILTIN_MODULE, "set", None);
+    vm.builtin_type_addrs.set = set_type;
+
+    vm.register_type_unbounded_func(set_type, "__new__", create_new);
+
+    vm.register_bounded_func(BUILTIN_MODULE, "set", "__contains__", contains);
+    vm.register_bounded_func(BUILTIN_MODULE, "set", "__len__", len);
+    vm.register_bounded_func(BUILTIN_MODULE, "set", "__iter__", iter);
+    vm.register_bounded_func(BUILTIN_MODULE, "set", "__eq__", equals);
+    vm.register_bounded_func(BUILTIN_MODULE, "set", "__repr__", repr);
+    vm.register_bounded_func(BUILTIN_MODULE, "set", "__str__", repr);
+    vm.register_bounded_func(BUILTIN_MODULE, "set", "add", add);
+    vm.register_bounded_func(BUILTIN_MODULE, "set", "remove", remove);
+    vm.register_bounded_func(BUILTIN_MODULE, "set", "discard", discard);
+    return set_type;
+}
@@ -2,40 +2,40 @@ use crate::commons::float::Float;
 use crate::runtime::vm::*;
 use crate::runtime::datamodel::*;
 use crate::runtime::memory::*;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 
 
 fn create_concat(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
-        .take_string();
+        .take_string()
+        .clone();
     let other_type_name = vm.get_pyobj_type_name(call_params.params[0]);
 
     if other_type_name == "str" {
         let other_str = vm
             .get_raw_data_of_pyobj(call_params.params[0])
-            .take_string();
+            .take_string()
+            .clone();
         let new_str = format!("{}{}", self_data, other_str);
         vm.allocate_builtin_type_byname_raw("str", BuiltInTypeData::String(new_str))
     } else {
-        panic!(
-            "can only concatenate str (not \"{}\") to str, {:?}",
-            other_type_name,
-            unsafe {&*call_params.params[0]}
-        );
+        vm.special_values[&SpecialValue::NotImplementedValue]
     }
 }
 
 fn create_eq(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
     let self_data = vm.get_raw_data_of_pyobj(call_params.bound_pyobj);
     let other_type_name = vm.get_pyobj_type_name(call_params.params[0]);
 
     if other_type_name == "str" {
         let other_str = vm.get_raw_data_of_pyobj(call_params.params[0]);
-        if self_data == other_str {
+        if *self_data == *other_str {
             return vm.builtin_type_addrs.true_val;
         } else {
             return vm.builtin_type_addrs.false_val;
@@ -47,52 +47,54 @@ fn create_eq(vm: &VM, params: CallParams) -> MemoryAddress {
 
 fn create_neq(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
     let self_data = vm.get_raw_data_of_pyobj(call_params.bound_pyobj);
     let other_type_name = vm.get_pyobj_type_name(call_params.params[0]);
 
     if other_type_name == "str" {
         let other_str = vm.get_raw_data_of_pyobj(call_params.params[0]);
 
-        if self_data == other_str {
+        if *self_data == *other_str {
             return vm.builtin_type_addrs.false_val;
         } else {
             return vm.builtin_type_addrs.true_val;
         }
     } else {
-        return vm.builtin_type_addrs.false_val;
+        return vm.special_values[&SpecialValue::NotImplementedValue];
     }
 }
 
 fn create_to_int(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
-        .take_string();
+        .take_string()
+        .clone();
     let as_int = self_data.parse::<i128>().unwrap();
     vm.allocate_builtin_type_byname_raw("int", BuiltInTypeData::Int(as_int))
 }
 
 fn create_to_float(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 1, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
-        .take_string();
+        .take_string()
+        .clone();
     let as_float = self_data.parse::<f64>().unwrap();
     vm.allocate_builtin_type_byname_raw("float", BuiltInTypeData::Float(Float(as_float)))
 }
 
-fn create_to_str(_vm: &VM, params: CallParams) -> MemoryAddress {
+fn create_to_str(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
     return call_params.bound_pyobj;
 }
 
 fn create_repr(vm: &VM, params: CallParams) -> MemoryAddress {
     let call_params = params.as_method();
-    check_builtin_func_params!(params.func_name.unwrap(), 0, call_params.params.len());
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
     let self_data = vm
         .get_raw_data_of_pyobj(call_params.bound_pyobj)
         .take_string()
@@ -103,17 +105,34 @@ fn create_repr(vm: &VM, params: CallParams) -> MemoryAddress {
     )
 }
 
+//DefaultHasher::new() always uses the same keys, so hashes are stable between runs
+fn hash(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let self_data = vm
+        .get_raw_data_of_pyobj(call_params.bound_pyobj)
+        .take_string()
+        .clone();
+    let mut hasher = DefaultHasher::new();
+    self_data.hash(&mut hasher);
+    vm.allocate_builtin_type_byname_raw("int", BuiltInTypeData::Int(hasher.finish() as i128))
+}
+
 fn create_new(vm: &VM, params: CallParams) -> MemoryAddress {
     if params.params.len() == 0 {
         return vm
             .allocate_builtin_type_byname_raw("str", BuiltInTypeData::String(String::from("")));
     } else {
-        check_builtin_func_params!("str", 1, params.params.len());
+        check_builtin_func_params!(vm, "str", 1, params.params);
         //try call the __str__ method on the parameter
         let string_call = vm.call_method(params.params.params[0], "__str__", PositionalParameters::empty());
         match string_call {
             Some((addr, _)) => addr,
-            None => panic!("Object passed to str does not have __str__ method"),
+            //like print does
+            None => {
+                let description = format!("<{} object>", vm.get_pyobj_type_name(params.params.params[0]));
+                vm.allocate_builtin_type_byname_raw("str", BuiltInTypeData::String(description))
+            }
         }
     }
 }
@@ -121,11 +140,12 @@ fn create_new(vm: &VM, params: CallParams) -> MemoryAddress {
 macro_rules! create_transform_function {
     ($name:tt, $param_a:tt, $func:expr) => {
         fn $name(vm: &VM, params: CallParams) -> MemoryAddress {
-            check_builtin_func_params!(params.func_name.unwrap(), 0, params.params.len());
             let call_params = params.as_method();
+            check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
             let self_data = vm
                 .get_raw_data_of_pyobj(call_params.bound_pyobj)
-                .take_string();
+                .take_string()
+                .clone();
             let $param_a = self_data;
             let transformed = $func;
             vm.allocate_builtin_type_byname_raw("str", BuiltInTypeData::String(transformed))
@@ -143,6 +163,7 @@ pub fn register_string_type(vm: &mut VM) -> MemoryAddress {
     vm.register_bounded_func_on_addr(string_type, "__add__", create_concat);
     vm.register_bounded_func_on_addr(string_type, "__eq__", create_eq);
     vm.register_bounded_func_on_addr(string_type, "__neq__", create_neq);
+    vm.register_bounded_func_on_addr(string_type, "__hash__", hash);
     vm.register_bounded_func_on_addr(string_type, "__int__", create_to_int);
     vm.register_bounded_func_on_addr(string_type, "__float__", create_to_float);
     vm.register_bounded_func_on_addr(string_type, "__repr__", create_repr);
@@ -0,0 +1,49 @@
+use crate::runtime::vm::*;
+use crate::runtime::datamodel::*;
+use crate::runtime::memory::*;
+use std::rc::Rc;
+use crate::builtin_types::exception_types::create_system_exit;
+
+fn new_string(vm: &VM, value: &str) -> MemoryAddress {
+    vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.string, BuiltInTypeData::String(value.to_string()))
+}
+
+//exit(code=None): raises SystemExit, main.rs turns its code into the exit status
+fn create_exit_fn(vm: &VM) -> MemoryAddress {
+    let func = PyCallable {
+        code: Rc::new(move |vm, params| -> MemoryAddress {
+            let call_params = params.as_function();
+            check_builtin_func_params!(vm, "exit", call_params);
+            if call_params.params.len() > 1 {
+                let message = format!("exit expected at most 1 argument, got {}", call_params.params.len());
+                let exception = vm.create_exception("TypeError", message);
+                vm.raise_exception(exception);
+                return vm.special_values[&SpecialValue::NoneValue];
+            }
+            let exception = create_system_exit(vm, call_params.params.first().copied());
+            vm.raise_exception(exception);
+            return vm.special_values[&SpecialValue::NoneValue];
+        }),
+    };
+    return vm.create_unbounded_callable_pyobj(func, Some("exit".to_string()));
+}
+
+//The command line arguments: the script (or -c, or the module's file) first, then the ones after it
+pub fn set_argv(vm: &VM, argv: &[String]) {
+    let module = vm.find_module("sys").unwrap();
+    let elements: Vec<MemoryAddress> = argv.iter().map(|arg| new_string(vm, arg)).collect();
+    for element in elements.iter() {
+        vm.increase_refcount(*element);
+    }
+    let list = vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.list, BuiltInTypeData::List(elements));
+    vm.set_in_module_addr(module, "argv", list);
+}
+
+//A native module, like dis
+pub fn register_sys_module(vm: &mut VM) {
+    let module = vm.create_module("sys");
+    vm.set_in_module_addr(module, "__name__", new_string(vm, "sys"));
+    let exit_fn = create_exit_fn(vm);
+    vm.set_in_module_addr(module, "exit", exit_fn);
+    set_argv(vm, &[String::new()]);
+}
@@ -0,0 +1,208 @@
+use crate::runtime::vm::*;
+use crate::runtime::datamodel::*;
+use crate::runtime::memory::*;
+use crate::builtin_types::dict_type::{hash_key, keys_equal, repr_of};
+use crate::builtin_types::list_type::sequence_index;
+
+//Tuples are immutable: there is no __setitem__, so `t[0] = 1` raises TypeError in the interpreter.
+
+fn raise(vm: &VM, exception: MemoryAddress) -> MemoryAddress {
+    vm.raise_exception(exception);
+    return vm.special_values[&SpecialValue::NoneValue];
+}
+
+pub fn new_tuple(vm: &VM, elements: Vec<MemoryAddress>) -> MemoryAddress {
+    for element in elements.iter() {
+        vm.increase_refcount(*element);
+    }
+    vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.tuple, BuiltInTypeData::Tuple(elements))
+}
+
+fn create_new(vm: &VM, params: CallParams) -> MemoryAddress {
+    if params.params.len() == 0 {
+        return new_tuple(vm, vec![]);
+    }
+    check_builtin_func_params!(vm, "tuple", 1, params.params);
+    match vm.collect_iterable(params.params.params[0]) {
+        Ok(elements) => new_tuple(vm, elements),
+        Err(exception) => raise(vm, exception),
+    }
+}
+
+fn getitem(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    let this_tuple = vm
+        .get_raw_data_of_pyobj(call_params.bound_pyobj)
+        .take_tuple()
+        .clone();
+    let index = match sequence_index(vm, call_params.params[0], "tuple") {
+        Some(index) => index,
+        None => return vm.special_values[&SpecialValue::NoneValue],
+    };
+
+    if index < 0 || index as usize >= this_tuple.len() {
+        let exception = vm.create_exception("IndexError", "tuple index out of range".into());
+        return raise(vm, exception);
+    }
+    return this_tuple[index as usize];
+}
+
+fn len(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let tuple_len = vm
+        .get_raw_data_of_pyobj(call_params.bound_pyobj)
+        .take_tuple()
+        .len();
+    vm.allocate_type_byaddr_raw(
+        vm.builtin_type_addrs.int,
+        BuiltInTypeData::Int(tuple_len as i128),
+    )
+}
+
+//list_iterator only needs len() and indexing, so it works for tuples too
+fn iter(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let list_iterator = vm.find_in_module(BUILTIN_MODULE, "list_iterator").expect("list_iterator not found");
+    let (result, _) = vm.run_function(PositionalParameters::single(call_params.bound_pyobj), list_iterator, None);
+    return result;
+}
+
+fn concat(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    let other = call_params.params[0];
+    match &vm.get_pyobj_byaddr(other).structure {
+        PyObjectStructure::Object { raw_data: BuiltInTypeData::Tuple(other_elements), .. } => {
+            let mut result = vm
+                .get_raw_data_of_pyobj(call_params.bound_pyobj)
+                .take_tuple()
+                .clone();
+            result.extend(other_elements.iter().cloned());
+            return new_tuple(vm, result);
+        }
+        _ => {
+            let exception = vm.create_exception(
+                "TypeError",
+                format!("can only concatenate tuple (not \"{}\") to tuple", vm.get_pyobj_type_name(other)),
+            );
+            return raise(vm, exception);
+        }
+    }
+}
+
+fn contains(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    let this_tuple = vm
+        .get_raw_data_of_pyobj(call_params.bound_pyobj)
+        .take_tuple()
+        .clone();
+    for element in this_tuple {
+        match keys_equal(vm, element, call_params.params[0]) {
+            Ok(true) => return vm.builtin_type_addrs.true_val,
+            Ok(false) => {}
+            Err(exception) => return raise(vm, exception),
+        }
+    }
+    return vm.builtin_type_addrs.false_val;
+}
+
+fn tuples_equal(vm: &VM, this: MemoryAddress, other: MemoryAddress) -> Result<bool, MemoryAddress> {
+    let other_elements = match &vm.get_pyobj_byaddr(other).structure {
+        PyObjectStructure::Object { raw_data: BuiltInTypeData::Tuple(elements), .. } => elements.clone(),
+        _ => return Ok(false),
+    };
+    let this_elements = vm.get_raw_data_of_pyobj(this).take_tuple().clone();
+    if this_elements.len() != other_elements.len() {
+        return Ok(false);
+    }
+    for (a, b) in this_elements.iter().zip(other_elements.iter()) {
+        if !keys_equal(vm, *a, *b)? {
+            return Ok(false);
+        }
+    }
+    return Ok(true);
+}
+
+fn equals(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    match tuples_equal(vm, call_params.bound_pyobj, call_params.params[0]) {
+        Ok(true) => vm.builtin_type_addrs.true_val,
+        Ok(false) => vm.builtin_type_addrs.false_val,
+        Err(exception) => raise(vm, exception),
+    }
+}
+
+fn not_equals(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 1, call_params);
+    match tuples_equal(vm, call_params.bound_pyobj, call_params.params[0]) {
+        Ok(true) => vm.builtin_type_addrs.false_val,
+        Ok(false) => vm.builtin_type_addrs.true_val,
+        Err(exception) => raise(vm, exception),
+    }
+}
+
+//Tuples are hashable when all their elements are, so they can be dict keys
+fn hash(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let this_tuple = vm
+        .get_raw_data_of_pyobj(call_params.bound_pyobj)
+        .take_tuple()
+        .clone();
+    let mut hash: i64 = 0x345678;
+    for element in this_tuple {
+        match hash_key(vm, element) {
+            Ok(element_hash) => hash = hash.wrapping_mul(1000003) ^ (element_hash as i64),
+            Err(exception) => return raise(vm, exception),
+        }
+    }
+    vm.allocate_type_byaddr_raw(
+        vm.builtin_type_addrs.int,
+        BuiltInTypeData::Int(hash as i128),
+    )
+}
+
+fn repr(vm: &VM, params: CallParams) -> MemoryAddress {
+    let call_params = params.as_method();
+    check_builtin_func_params!(vm, params.func_name.unwrap(), 0, call_params);
+    let this_tuple = vm
+        .get_raw_data_of_pyobj(call_params.bound_pyobj)
+        .take_tuple()
+        .clone();
+    let all_reprs: Vec<String> = this_tuple.iter().map(|element| repr_of(vm, *element)).collect();
+    //a single element tuple needs the trailing comma, otherwise it looks like a parenthesized expression
+    let buffer = if all_reprs.len() == 1 {
+        format!("({},)", all_reprs[0])
+    } else {
+        format!("({})", all_reprs.join(", "))
+    };
+    vm.allocate_type_byaddr_raw(
+        vm.builtin_type_addrs.string,
+        BuiltInTypeData::String(buffer),
+    )
+}
+
+pub fn register_tuple_type(vm: &mut VM) -> MemoryAddress {
+    let tuple_type = vm.create_type(BUILTIN_MODULE, "tuple", None);
+    vm.builtin_type_addrs.tuple = tuple_type;
+
+    vm.register_type_unbounded_func(tuple_type, "__new__", create_new);
+
+    vm.register_bounded_func(BUILTIN_MODULE, "tuple", "__getitem__", getitem);
+    vm.register_bounded_func(BUILTIN_MODULE, "tuple", "__len__", len);
+    vm.register_bounded_func(BUILTIN_MODULE, "tuple", "__iter__", iter);
+    vm.register_bounded_func(BUILTIN_MODULE, "tuple", "__add__", concat);
+    vm.register_bounded_func(BUILTIN_MODULE, "tuple", "__contains__", contains);
+    vm.register_bounded_func(BUILTIN_MODULE, "tuple", "__eq__", equals);
+    vm.register_bounded_func(BUILTIN_MODULE, "tuple", "__ne__", not_equals);
+    vm.register_bounded_func(BUILTIN_MODULE, "tuple", "__hash__", hash);
+    vm.register_bounded_func(BUILTIN_MODULE, "tuple", "__repr__", repr);
+    vm.register_bounded_func(BUILTIN_MODULE, "tuple", "__str__", repr);
+    return tuple_type;
+}
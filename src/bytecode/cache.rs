@@ -0,0 +1,554 @@
+use crate::ast::lexer;
+use crate::ast::parser;
+use crate::bytecode::compiler::compile_file;
+use crate::bytecode::program::*;
+use crate::commons::errors::HorseError;
+use crate::commons::float::Float;
+use std::convert::{TryFrom, TryInto};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+//Compiled programs are cached in .hpyc files, in a __pycache__ directory next to the source.
+//The file starts with a header: the magic bytes, the bytecode version and a hash of the source.
+//A cache file is only used when both match, otherwise the source is compiled again and the cache rewritten.
+//Everything after the header is the program: integers are little endian, strings and lists are
+//prefixed by their length, enums by a tag byte. The tags must never be reused for something else,
+//changing them means bumping BYTECODE_VERSION.
+
+const MAGIC: &[u8; 4] = b"HPYC";
+pub const CACHE_EXTENSION: &str = "hpyc";
+pub const CACHE_DIR: &str = "__pycache__";
+
+//FNV-1a, the std hashers are not guaranteed to give the same result across rust versions
+pub fn source_hash(source: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in source.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    return hash;
+}
+
+//`dir/name.py` is cached in `dir/__pycache__/name.hpyc`
+pub fn cache_file_for(source_file: &Path) -> Option<PathBuf> {
+    let stem = source_file.file_stem()?;
+    let dir = source_file.parent().unwrap_or_else(|| Path::new(""));
+    let mut name = stem.to_os_string();
+    name.push(".");
+    name.push(CACHE_EXTENSION);
+    Some(dir.join(CACHE_DIR).join(name))
+}
+
+//Where the cache of sources that are not files (the embedded standard library) goes:
+//$HORSE_CACHE_DIR, or the horse directory in the user cache directory
+pub fn user_cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("HORSE_CACHE_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    if let Some(dir) = env::var_os("XDG_CACHE_HOME") {
+        return Some(PathBuf::from(dir).join("horse"));
+    }
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache").join("horse"))
+}
+
+//Setting HORSE_DONT_WRITE_BYTECODE still reads existing caches, but never writes them
+fn writes_bytecode() -> bool {
+    match env::var_os("HORSE_DONT_WRITE_BYTECODE") {
+        Some(value) => value.is_empty(),
+        None => true,
+    }
+}
+
+fn compile_source(source: &str, filename: &str) -> Result<Program, HorseError> {
+    let tokens = lexer::tokenize(source)?;
+    let ast = parser::parse_ast(tokens)?;
+    Ok(compile_file(ast, filename)?)
+}
+
+//Compiles the source, or loads it from the cache file if the cache is up to date.
+//Failing to read or write the cache is not an error, the source is just compiled.
+pub fn compile_cached(source: &str, filename: &str, cache_file: Option<&Path>) -> Result<Program, HorseError> {
+    let cache_file = match cache_file {
+        Some(cache_file) => cache_file,
+        None => return compile_source(source, filename),
+    };
+    let hash = source_hash(source);
+    if let Some(mut program) = fs::read(cache_file).ok().and_then(|bytes| decode(&bytes, hash)) {
+        //the same file can be reached by different paths, like `./a.py` and `a.py`
+        for code_object in program.code_objects.iter_mut() {
+            code_object.set_filename(filename);
+        }
+        return Ok(program);
+    }
+    let program = compile_source(source, filename)?;
+    if writes_bytecode() {
+        write_cache(cache_file, &encode(&program, hash));
+    }
+    Ok(program)
+}
+
+//Written to a temporary file first, so other processes never read a half written cache
+fn write_cache(cache_file: &Path, bytes: &[u8]) {
+    if let Some(dir) = cache_file.parent() {
+        if fs::create_dir_all(dir).is_err() {
+            return;
+        }
+    }
+    let temp_file = cache_file.with_extension(format!("{}.{}", CACHE_EXTENSION, std::process::id()));
+    if fs::write(&temp_file, bytes).is_err() || fs::rename(&temp_file, cache_file).is_err() {
+        let _ = fs::remove_file(&temp_file);
+    }
+}
+
+pub fn encode(program: &Program, source_hash: u64) -> Vec<u8> {
+    let mut writer = Writer { bytes: MAGIC.to_vec() };
+    writer.u64(program.version);
+    writer.u64(source_hash);
+    writer.usize(program.code_objects.len());
+    for code_object in program.code_objects.iter() {
+        writer.code_object(code_object);
+    }
+    return writer.bytes;
+}
+
+//None if the bytes are not a cache of this source for the current bytecode version, or are corrupted
+pub fn decode(bytes: &[u8], source_hash: u64) -> Option<Program> {
+    let mut reader = Reader { bytes, position: 0 };
+    if reader.take(MAGIC.len())? != MAGIC || reader.u64()? != BYTECODE_VERSION || reader.u64()? != source_hash {
+        return None;
+    }
+    let code_objects = reader.list(|reader| reader.code_object())?;
+    if reader.position != bytes.len() {
+        return None;
+    }
+    Some(Program {
+        version: BYTECODE_VERSION,
+        code_objects,
+    })
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn usize(&mut self, value: usize) {
+        self.u64(value as u64);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.usize(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn strings(&mut self, values: &[String]) {
+        self.usize(values.len());
+        for value in values.iter() {
+            self.str(value);
+        }
+    }
+
+    fn code_object(&mut self, code_object: &CodeObject) {
+        self.usize(code_object.instructions.len());
+        for instruction in code_object.instructions.iter() {
+            self.instruction(instruction);
+        }
+        self.strings(&code_object.names);
+        self.strings(&code_object.params);
+        self.usize(code_object.kwonly_count);
+        self.bool(code_object.varargs);
+        self.bool(code_object.varkeywords);
+        self.strings(&code_object.cellvars);
+        self.strings(&code_object.freevars);
+        self.usize(code_object.consts.len());
+        for constval in code_object.consts.iter() {
+            self.constval(constval);
+        }
+        self.str(&code_object.objname);
+        self.bool(code_object.main);
+        self.bool(code_object.generator);
+        self.str(&code_object.filename);
+        self.usize(code_object.line_table.len());
+        for entry in code_object.line_table.iter() {
+            self.usize(entry.instruction);
+            self.usize(entry.line);
+        }
+    }
+
+    fn constval(&mut self, constval: &Const) {
+        match constval {
+            Const::Integer(value) => {
+                self.u8(0);
+                self.bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            Const::Float(Float(value)) => {
+                self.u8(1);
+                self.u64(value.to_bits());
+            }
+            Const::Boolean(value) => {
+                self.u8(2);
+                self.bool(*value);
+            }
+            Const::String(value) => {
+                self.u8(3);
+                self.str(value);
+            }
+            Const::CodeObject(code_object) => {
+                self.u8(4);
+                self.code_object(code_object);
+            }
+            Const::None => self.u8(5),
+        }
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        match instruction {
+            Instruction::LoadConst(index) => { self.u8(0); self.usize(*index); }
+            Instruction::LoadAttr(name) => { self.u8(1); self.str(name); }
+            Instruction::MakeFunction => self.u8(2),
+            Instruction::MakeClass { number_bases } => { self.u8(3); self.usize(*number_bases); }
+            Instruction::StoreName(index) => { self.u8(4); self.usize(*index); }
+            Instruction::StoreAttr(index) => { self.u8(5); self.usize(*index); }
+            Instruction::LoadName(index) => { self.u8(6); self.usize(*index); }
+            Instruction::LoadGlobal(index) => { self.u8(7); self.usize(*index); }
+            Instruction::StoreGlobal(index) => { self.u8(8); self.usize(*index); }
+            Instruction::LoadDeref(index) => { self.u8(9); self.usize(*index); }
+            Instruction::StoreDeref(index) => { self.u8(10); self.usize(*index); }
+            Instruction::BuildClosure { cells } => {
+                self.u8(11);
+                self.usize(cells.len());
+                for cell in cells.iter() {
+                    self.usize(*cell);
+                }
+            }
+            Instruction::CallFunction { number_arguments } => { self.u8(12); self.usize(*number_arguments); }
+            Instruction::CallFunctionKw { number_arguments, keywords } => {
+                self.u8(13);
+                self.usize(*number_arguments);
+                self.strings(keywords);
+            }
+            Instruction::CallFunctionEx => self.u8(14),
+            Instruction::JumpIfFalseAndPopStack(target) => { self.u8(15); self.usize(*target); }
+            Instruction::JumpUnconditional(target) => { self.u8(16); self.usize(*target); }
+            Instruction::JumpForward(delta) => { self.u8(17); self.usize(*delta); }
+            Instruction::PopJumpForwardIfFalse(delta) => { self.u8(18); self.usize(*delta); }
+            Instruction::ReturnValue => self.u8(19),
+            Instruction::PopTop => self.u8(20),
+            Instruction::BinaryAdd => self.u8(21),
+            Instruction::BinaryModulus => self.u8(22),
+            Instruction::BinarySubtract => self.u8(23),
+            Instruction::BinaryMultiply => self.u8(24),
+            Instruction::BinaryTrueDivision => self.u8(25),
+            Instruction::CompareLessEquals => self.u8(26),
+            Instruction::CompareGreaterEquals => self.u8(27),
+            Instruction::CompareGreaterThan => self.u8(28),
+            Instruction::CompareLessThan => self.u8(29),
+            Instruction::CompareEquals => self.u8(30),
+            Instruction::CompareNotEquals => self.u8(31),
+            Instruction::CompareIn => self.u8(32),
+            Instruction::CompareNotIn => self.u8(33),
+            Instruction::BuildList { number_elements } => { self.u8(34); self.usize(*number_elements); }
+            Instruction::BuildTuple { number_elements } => { self.u8(35); self.usize(*number_elements); }
+            Instruction::BuildTupleUnpack { number_elements } => { self.u8(36); self.usize(*number_elements); }
+            Instruction::BuildMapUnpackWithCall { number_entries } => { self.u8(37); self.usize(*number_entries); }
+            Instruction::UnpackSequence { number_elements } => { self.u8(38); self.usize(*number_elements); }
+            Instruction::BuildMap { number_entries } => { self.u8(39); self.usize(*number_entries); }
+            Instruction::BuildSet { number_elements } => { self.u8(40); self.usize(*number_elements); }
+            Instruction::IndexAccess => self.u8(41),
+            Instruction::StoreIndex => self.u8(42),
+            Instruction::ForIter(target) => { self.u8(43); self.usize(*target); }
+            Instruction::YieldValue => self.u8(44),
+            Instruction::YieldFrom => self.u8(45),
+            Instruction::ImportName(name) => { self.u8(46); self.str(name); }
+            Instruction::ImportFrom(name) => { self.u8(47); self.str(name); }
+            Instruction::Raise => self.u8(48),
+            Instruction::Reraise => self.u8(49),
+            Instruction::SetupExcept(target) => { self.u8(50); self.usize(*target); }
+            Instruction::SetupFinally(target) => { self.u8(51); self.usize(*target); }
+            Instruction::PopBlock => self.u8(52),
+            Instruction::BeginFinally => self.u8(53),
+            Instruction::EndFinally => self.u8(54),
+            Instruction::JumpIfNotExceptionMatch(target) => { self.u8(55); self.usize(*target); }
+            Instruction::BreakThroughBlocks { blocks, destination } => {
+                self.u8(56);
+                self.usize(*blocks);
+                self.usize(*destination);
+            }
+            Instruction::UnresolvedBreak => self.u8(57),
+            Instruction::UnresolvedBreakThroughBlocks(blocks) => { self.u8(58); self.usize(*blocks); }
+            Instruction::UnresolvedStoreAttr(name) => { self.u8(59); self.str(name); }
+            Instruction::UnresolvedStoreName(name) => { self.u8(60); self.str(name); }
+            Instruction::UnresolvedLoadName(name) => { self.u8(61); self.str(name); }
+            Instruction::UnresolvedLoadGlobal(name) => { self.u8(62); self.str(name); }
+            Instruction::UnresolvedStoreGlobal(name) => { self.u8(63); self.str(name); }
+            Instruction::UnresolvedLoadDeref(name) => { self.u8(64); self.str(name); }
+            Instruction::UnresolvedStoreDeref(name) => { self.u8(65); self.str(name); }
+            Instruction::UnresolvedClosure { code, class_body } => {
+                self.u8(66);
+                self.usize(*code);
+                self.bool(*class_body);
+            }
+            Instruction::UnresolvedLine(line) => { self.u8(67); self.usize(*line); }
+        }
+    }
+}
+
+//Every read returns None when the bytes run out or don't make sense
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let end = self.position.checked_add(length)?;
+        let taken = self.bytes.get(self.position..end)?;
+        self.position = end;
+        Some(taken)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn usize(&mut self) -> Option<usize> {
+        usize::try_from(self.u64()?).ok()
+    }
+
+    fn bool(&mut self) -> Option<bool> {
+        match self.u8()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let length = self.usize()?;
+        String::from_utf8(self.take(length)?.to_vec()).ok()
+    }
+
+    //Not preallocated, a corrupted length runs out of bytes instead of allocating a huge vector
+    fn list<T>(&mut self, mut read: impl FnMut(&mut Self) -> Option<T>) -> Option<Vec<T>> {
+        let length = self.usize()?;
+        let mut values = vec![];
+        for _ in 0..length {
+            values.push(read(self)?);
+        }
+        Some(values)
+    }
+
+    fn strings(&mut self) -> Option<Vec<String>> {
+        self.list(|reader| reader.string())
+    }
+
+    fn code_object(&mut self) -> Option<CodeObject> {
+        Some(CodeObject {
+            instructions: self.list(|reader| reader.instruction())?,
+            names: self.strings()?,
+            params: self.strings()?,
+            kwonly_count: self.usize()?,
+            varargs: self.bool()?,
+            varkeywords: self.bool()?,
+            cellvars: self.strings()?,
+            freevars: self.strings()?,
+            consts: self.list(|reader| reader.constval())?,
+            objname: self.string()?,
+            main: self.bool()?,
+            generator: self.bool()?,
+            filename: self.string()?,
+            line_table: self.list(|reader| {
+                Some(LineTableEntry {
+                    instruction: reader.usize()?,
+                    line: reader.usize()?,
+                })
+            })?,
+        })
+    }
+
+    fn constval(&mut self) -> Option<Const> {
+        let constval = match self.u8()? {
+            0 => Const::Integer(i128::from_le_bytes(self.take(16)?.try_into().ok()?)),
+            1 => Const::Float(Float(f64::from_bits(self.u64()?))),
+            2 => Const::Boolean(self.bool()?),
+            3 => Const::String(self.string()?),
+            4 => Const::CodeObject(Box::new(self.code_object()?)),
+            5 => Const::None,
+            _ => return None,
+        };
+        Some(constval)
+    }
+
+    fn instruction(&mut self) -> Option<Instruction> {
+        let instruction = match self.u8()? {
+            0 => Instruction::LoadConst(self.usize()?),
+            1 => Instruction::LoadAttr(self.string()?),
+            2 => Instruction::MakeFunction,
+            3 => Instruction::MakeClass { number_bases: self.usize()? },
+            4 => Instruction::StoreName(self.usize()?),
+            5 => Instruction::StoreAttr(self.usize()?),
+            6 => Instruction::LoadName(self.usize()?),
+            7 => Instruction::LoadGlobal(self.usize()?),
+            8 => Instruction::StoreGlobal(self.usize()?),
+            9 => Instruction::LoadDeref(self.usize()?),
+            10 => Instruction::StoreDeref(self.usize()?),
+            11 => Instruction::BuildClosure { cells: self.list(|reader| reader.usize())? },
+            12 => Instruction::CallFunction { number_arguments: self.usize()? },
+            13 => Instruction::CallFunctionKw {
+                number_arguments: self.usize()?,
+                keywords: self.strings()?,
+            },
+            14 => Instruction::CallFunctionEx,
+            15 => Instruction::JumpIfFalseAndPopStack(self.usize()?),
+            16 => Instruction::JumpUnconditional(self.usize()?),
+            17 => Instruction::JumpForward(self.usize()?),
+            18 => Instruction::PopJumpForwardIfFalse(self.usize()?),
+            19 => Instruction::ReturnValue,
+            20 => Instruction::PopTop,
+            21 => Instruction::BinaryAdd,
+            22 => Instruction::BinaryModulus,
+            23 => Instruction::BinarySubtract,
+            24 => Instruction::BinaryMultiply,
+            25 => Instruction::BinaryTrueDivision,
+            26 => Instruction::CompareLessEquals,
+            27 => Instruction::CompareGreaterEquals,
+            28 => Instruction::CompareGreaterThan,
+            29 => Instruction::CompareLessThan,
+            30 => Instruction::CompareEquals,
+            31 => Instruction::CompareNotEquals,
+            32 => Instruction::CompareIn,
+            33 => Instruction::CompareNotIn,
+            34 => Instruction::BuildList { number_elements: self.usize()? },
+            35 => Instruction::BuildTuple { number_elements: self.usize()? },
+            36 => Instruction::BuildTupleUnpack { number_elements: self.usize()? },
+            37 => Instruction::BuildMapUnpackWithCall { number_entries: self.usize()? },
+            38 => Instruction::UnpackSequence { number_elements: self.usize()? },
+            39 => Instruction::BuildMap { number_entries: self.usize()? },
+            40 => Instruction::BuildSet { number_elements: self.usize()? },
+            41 => Instruction::IndexAccess,
+            42 => Instruction::StoreIndex,
+            43 => Instruction::ForIter(self.usize()?),
+            44 => Instruction::YieldValue,
+            45 => Instruction::YieldFrom,
+            46 => Instruction::ImportName(self.string()?),
+            47 => Instruction::ImportFrom(self.string()?),
+            48 => Instruction::Raise,
+            49 => Instruction::Reraise,
+            50 => Instruction::SetupExcept(self.usize()?),
+            51 => Instruction::SetupFinally(self.usize()?),
+            52 => Instruction::PopBlock,
+            53 => Instruction::BeginFinally,
+            54 => Instruction::EndFinally,
+            55 => Instruction::JumpIfNotExceptionMatch(self.usize()?),
+            56 => Instruction::BreakThroughBlocks {
+                blocks: self.usize()?,
+                destination: self.usize()?,
+            },
+            57 => Instruction::UnresolvedBreak,
+            58 => Instruction::UnresolvedBreakThroughBlocks(self.usize()?),
+            59 => Instruction::UnresolvedStoreAttr(self.string()?),
+            60 => Instruction::UnresolvedStoreName(self.string()?),
+            61 => Instruction::UnresolvedLoadName(self.string()?),
+            62 => Instruction::UnresolvedLoadGlobal(self.string()?),
+            63 => Instruction::UnresolvedStoreGlobal(self.string()?),
+            64 => Instruction::UnresolvedLoadDeref(self.string()?),
+            65 => Instruction::UnresolvedStoreDeref(self.string()?),
+            66 => Instruction::UnresolvedClosure {
+                code: self.usize()?,
+                class_body: self.bool()?,
+            },
+            67 => Instruction::UnresolvedLine(self.usize()?),
+            _ => return None,
+        };
+        Some(instruction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "
+import importable.shapes
+from importable import greeting as hello
+x = [1, 2.5, True, None, 'text', 12345678901234567890123]
+def counter(start, *args, step=1, **kwargs):
+    total = start
+    def inner():
+        nonlocal total
+        total = total + step
+        yield total
+    return inner
+class Point(object):
+    def __init__(self, x):
+        self.x = x
+try:
+    for i in x:
+        if i in {1: 2}:
+            break
+finally:
+    y = {'a'}
+";
+
+    #[test]
+    fn roundtrip_gives_the_same_program() {
+        let program = compile_source(SOURCE, "source.py").unwrap();
+        let hash = source_hash(SOURCE);
+        let decoded = decode(&encode(&program, hash), hash).unwrap();
+        assert_eq!(decoded, program);
+    }
+
+    #[test]
+    fn cache_of_other_source_or_version_is_rejected() {
+        let program = compile_source(SOURCE, "source.py").unwrap();
+        let bytes = encode(&program, source_hash(SOURCE));
+        assert_eq!(decode(&bytes, source_hash("x = 1")), None);
+
+        let old_version = Program {
+            version: BYTECODE_VERSION - 1,
+            code_objects: program.code_objects.clone(),
+        };
+        assert_eq!(decode(&encode(&old_version, source_hash(SOURCE)), source_hash(SOURCE)), None);
+    }
+
+    #[test]
+    fn corrupted_cache_is_rejected() {
+        let program = compile_source(SOURCE, "source.py").unwrap();
+        let hash = source_hash(SOURCE);
+        let bytes = encode(&program, hash);
+        assert_eq!(decode(&bytes[..bytes.len() - 1], hash), None);
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(decode(&trailing, hash), None);
+        assert_eq!(decode(b"HPY", hash), None);
+    }
+
+    #[test]
+    fn cache_file_is_in_pycache_next_to_the_source() {
+        assert_eq!(
+            cache_file_for(Path::new("pkg/module.py")),
+            Some(PathBuf::from("pkg/__pycache__/module.hpyc"))
+        );
+        assert_eq!(
+            cache_file_for(Path::new("script.py")),
+            Some(PathBuf::from("__pycache__/script.hpyc"))
+        );
+    }
+}
@@ -1,51 +1,63 @@
 use crate::bytecode::program::*;
 use crate::ast::lexer::*;
 use crate::ast::parser::*;
+use crate::commons::errors::CompileError;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
-fn process_constval(constval: Const, const_map: &mut BTreeMap<Const, usize>) -> Vec<Instruction> {
-   let loadconst_idx = if !const_map.contains_key(&constval) {
+fn const_index(constval: Const, const_map: &mut BTreeMap<Const, usize>) -> usize {
+    if !const_map.contains_key(&constval) {
         let len = const_map.len();
         const_map.insert(constval, len);
         len
     } else {
         *const_map.get(&constval).unwrap()
-    };
+    }
+}
 
-    return vec![Instruction::LoadConst(loadconst_idx)];
+fn process_constval(constval: Const, const_map: &mut BTreeMap<Const, usize>) -> Vec<Instruction> {
+    return vec![Instruction::LoadConst(const_index(constval, const_map))];
 }
 
-fn compile_expr(expr: &Expr, const_map: &mut BTreeMap<Const, usize>) -> Vec<Instruction> {
+//Calls and subscripts say their line, so errors in a statement spanning several lines point at the
+//right one. Expressions made up by the compiler have no line.
+fn line_of(span: &ExprSpan) -> Option<Instruction> {
+    match span.0.line {
+        0 => None,
+        line => Some(Instruction::UnresolvedLine(line)),
+    }
+}
+
+fn compile_expr(expr: &Expr, const_map: &mut BTreeMap<Const, usize>) -> Result<Vec<Instruction>, CompileError> {
     match expr {
         //TODO change Expr to Const(Const::Integer) so that it 
         //becomes easier to do this const stuff
         Expr::IntegerValue(i) => {
             let constval = Const::Integer(*i);
-            return process_constval(constval, const_map);
+            return Ok(process_constval(constval, const_map));
         },
         Expr::FloatValue(f) => {
             let constval = Const::Float(*f);
-            return process_constval(constval, const_map);
+            return Ok(process_constval(constval, const_map));
         },
         Expr::BooleanValue(b) => {
             let constval = Const::Boolean(*b);
-            return process_constval(constval, const_map);
+            return Ok(process_constval(constval, const_map));
         },
         Expr::StringValue(s) => {
             let constval = Const::String(s.clone());
-            return process_constval(constval, const_map);
+            return Ok(process_constval(constval, const_map));
         },
         Expr::None => {
             let constval = Const::None;
-            return process_constval(constval, const_map);         
+            return Ok(process_constval(constval, const_map));         
         }
         Expr::MemberAccess(expr, name) => {
-            let mut lhs_program: Vec<Instruction> = compile_expr(expr, const_map);
+            let mut lhs_program: Vec<Instruction> = compile_expr(expr, const_map)?;
             let mut final_instructions = vec![];
             final_instructions.append(&mut lhs_program);
             final_instructions.push(Instruction::LoadAttr(name.clone()));
-            return final_instructions
+            return Ok(final_instructions);
         }
         Expr::BinaryOperation(lhs, op, rhs) => {
             match op {
@@ -57,8 +69,8 @@ fn compile_expr(expr: &Expr, const_map: &mut BTreeMap<Const, usize>) -> Vec<Inst
                         _ => panic!("operator not implemented: {:?}", op),
                     };
 
-                    let mut lhs_program: Vec<Instruction> = compile_expr(lhs, const_map);
-                    let mut rhs_program: Vec<Instruction> = compile_expr(rhs, const_map);
+                    let mut lhs_program: Vec<Instruction> = compile_expr(lhs, const_map)?;
+                    let mut rhs_program: Vec<Instruction> = compile_expr(rhs, const_map)?;
 
                     let call = Instruction::CallFunction {
                         number_arguments: 1
@@ -70,11 +82,11 @@ fn compile_expr(expr: &Expr, const_map: &mut BTreeMap<Const, usize>) -> Vec<Inst
                     final_instructions.append(&mut rhs_program);
                     final_instructions.push(call);
 
-                    return final_instructions;
+                    return Ok(final_instructions);
                 },
                 _ => {
-                    let mut lhs_program: Vec<Instruction> = compile_expr(lhs, const_map);
-                    let mut rhs_program: Vec<Instruction> = compile_expr(rhs, const_map);
+                    let mut lhs_program: Vec<Instruction> = compile_expr(lhs, const_map)?;
+                    let mut rhs_program: Vec<Instruction> = compile_expr(rhs, const_map)?;
                     let mut final_instructions = vec![];
 
                     final_instructions.append(&mut lhs_program);
@@ -91,13 +103,15 @@ fn compile_expr(expr: &Expr, const_map: &mut BTreeMap<Const, usize>) -> Vec<Inst
                         Operator::GreaterEquals => Instruction::CompareGreaterEquals,
                         Operator::LessEquals => Instruction::CompareLessEquals,
                         Operator::NotEquals => Instruction::CompareNotEquals,
+                        Operator::In => Instruction::CompareIn,
+                        Operator::NotIn => Instruction::CompareNotIn,
                         _ => {
-                            panic!("Operator not implemented: {:?}", op)
+                            return Err(CompileError::new(format!("Operator not implemented: {:?}", op)))
                         }
                     };
                     final_instructions.push(opcode);
 
-                    return final_instructions;
+                    return Ok(final_instructions);
                 }
             }
         }
@@ -106,10 +120,10 @@ fn compile_expr(expr: &Expr, const_map: &mut BTreeMap<Const, usize>) -> Vec<Inst
                 Operator::Plus => vec![Instruction::LoadAttr(String::from("__pos__"))],
                 Operator::Not => vec![Instruction::LoadAttr(String::from("__not__"))],
                 Operator::Minus => vec![Instruction::LoadAttr(String::from("__neg__"))],
-                _ => panic!("operator not implemented: {:?}", op),
+                _ => return Err(CompileError::new(format!("Operator not implemented: {:?}", op))),
             };
 
-            let mut rhs_program: Vec<Instruction> = compile_expr(rhs, const_map);
+            let mut rhs_program: Vec<Instruction> = compile_expr(rhs, const_map)?;
             let call = Instruction::CallFunction {
                 number_arguments: 0
             };
@@ -119,105 +133,482 @@ fn compile_expr(expr: &Expr, const_map: &mut BTreeMap<Const, usize>) -> Vec<Inst
             final_instructions.append(&mut load_attr);
             final_instructions.push(call);
 
-            return final_instructions;
+            return Ok(final_instructions);
         }
-        Expr::FunctionCall(fcall_expr, params) => {
+        Expr::FunctionCall(fcall_expr, params, span) => {
             //setup order of params
             let mut final_instructions = vec![];
 
-            let method_to_call_instrs: Vec<Instruction> = compile_expr(fcall_expr, const_map);
+            let method_to_call_instrs: Vec<Instruction> = compile_expr(fcall_expr, const_map)?;
             final_instructions.extend(method_to_call_instrs);
             let len_params = params.len();
-        
+
+            let has_unpacking = params.iter().any(|p| matches!(p, Expr::Starred(_) | Expr::DoubleStarred(_)));
+            if has_unpacking {
+                final_instructions.append(&mut compile_call_with_unpacking(params, span, const_map)?);
+                return Ok(final_instructions);
+            }
+
+            let mut keywords = vec![];
             for param_expr in params {
-                final_instructions.append(&mut compile_expr(param_expr, const_map));
+                match param_expr {
+                    Expr::KeywordArgument(name, value) => {
+                        keywords.push(name.clone());
+                        final_instructions.append(&mut compile_expr(value, const_map)?);
+                    }
+                    _ => final_instructions.append(&mut compile_expr(param_expr, const_map)?),
+                }
             }
 
-            final_instructions.push(Instruction::CallFunction {
-                number_arguments: len_params,
-            });
-            return final_instructions;
+            final_instructions.extend(line_of(span));
+            if keywords.is_empty() {
+                final_instructions.push(Instruction::CallFunction {
+                    number_arguments: len_params,
+                });
+            } else {
+                final_instructions.push(Instruction::CallFunctionKw {
+                    number_arguments: len_params,
+                    keywords,
+                });
+            }
+            return Ok(final_instructions);
+        },
+        Expr::KeywordArgument(..) | Expr::Starred(_) | Expr::DoubleStarred(_) => {
+            Err(CompileError::new("keyword arguments and unpacking can only be used in function calls"))
         },
-        Expr::IndexAccess(expr, index) =>  {
+        Expr::IndexAccess(expr, index, span) =>  {
             let mut final_instructions = vec![];
-            let indexed_value: Vec<Instruction> = compile_expr(expr, const_map);
-            let index_value: Vec<Instruction> = compile_expr(index, const_map);
+            let indexed_value: Vec<Instruction> = compile_expr(expr, const_map)?;
+            let index_value: Vec<Instruction> = compile_expr(index, const_map)?;
             final_instructions.extend(indexed_value);
             final_instructions.extend(index_value);
+            final_instructions.extend(line_of(span));
             final_instructions.push(Instruction::IndexAccess);
-            return final_instructions;
+            return Ok(final_instructions);
         }
         Expr::Array(exprs) => {
             let mut final_instructions = vec![];
             let number_elements = exprs.len();
             for expr in exprs {
-                final_instructions.append(&mut compile_expr(expr, const_map));
+                final_instructions.append(&mut compile_expr(expr, const_map)?);
             }
 
             final_instructions.push(Instruction::BuildList { number_elements });
-            return final_instructions;
+            return Ok(final_instructions);
         },
-        Expr::Variable(var_name) => vec![Instruction::UnresolvedLoadName(var_name.clone())],
-        Expr::Parenthesized(_) => panic!("Parenthesized expr should not leak to compiler"),
-        
+        Expr::Tuple(exprs) => {
+            let mut final_instructions = vec![];
+            let number_elements = exprs.len();
+            for expr in exprs {
+                final_instructions.append(&mut compile_expr(expr, const_map)?);
+            }
+
+            final_instructions.push(Instruction::BuildTuple { number_elements });
+            return Ok(final_instructions);
+        },
+        Expr::Dict(entries) => {
+            let mut final_instructions = vec![];
+            let number_entries = entries.len();
+            for (key, value) in entries {
+                final_instructions.append(&mut compile_expr(key, const_map)?);
+                final_instructions.append(&mut compile_expr(value, const_map)?);
+            }
+
+            final_instructions.push(Instruction::BuildMap { number_entries });
+            return Ok(final_instructions);
+        },
+        Expr::Lambda(parameters, body, span) => {
+            let body = vec![ASTNode { ast: AST::Return(Some(*body.clone())), span: span.0 }];
+            compile_function(String::from("<lambda>"), parameters, body, const_map)
+        }
+        Expr::Conditional(body, condition, orelse) => {
+            let mut final_instructions = compile_expr(condition, const_map)?;
+            let body = compile_expr(body, const_map)?;
+            let orelse = compile_expr(orelse, const_map)?;
+            //+1 to also skip the jump over the else part
+            final_instructions.push(Instruction::PopJumpForwardIfFalse(body.len() + 1));
+            final_instructions.extend(body);
+            final_instructions.push(Instruction::JumpForward(orelse.len()));
+            final_instructions.extend(orelse);
+            Ok(final_instructions)
+        }
+        Expr::Set(exprs) => {
+            let mut final_instructions = vec![];
+            let number_elements = exprs.len();
+            for expr in exprs {
+                final_instructions.append(&mut compile_expr(expr, const_map)?);
+            }
+
+            final_instructions.push(Instruction::BuildSet { number_elements });
+            return Ok(final_instructions);
+        },
+        Expr::ListComprehension(element, clauses) => {
+            let add_element = AST::StandaloneExpr(call_on_result("append", vec![*element.clone()]));
+            compile_comprehension("<listcomp>", Some(Expr::Array(vec![])), add_element, clauses, const_map)
+        }
+        Expr::SetComprehension(element, clauses) => {
+            let add_element = AST::StandaloneExpr(call_on_result("add", vec![*element.clone()]));
+            compile_comprehension("<setcomp>", Some(Expr::Set(vec![])), add_element, clauses, const_map)
+        }
+        Expr::DictComprehension(key, value, clauses) => {
+            let add_element = AST::AssignIndex {
+                target: comprehension_result(),
+                index: *key.clone(),
+                expression: *value.clone(),
+            };
+            compile_comprehension("<dictcomp>", Some(Expr::Dict(vec![])), add_element, clauses, const_map)
+        }
+        Expr::GeneratorExpression(element, clauses) => {
+            let add_element = AST::StandaloneExpr(Expr::Yield(Some(element.clone())));
+            compile_comprehension("<genexpr>", None, add_element, clauses, const_map)
+        }
+        Expr::Yield(value) => {
+            let mut final_instructions = match value {
+                Some(value) => compile_expr(value, const_map)?,
+                None => process_constval(Const::None, const_map),
+            };
+            final_instructions.push(Instruction::YieldValue);
+            Ok(final_instructions)
+        }
+        Expr::YieldFrom(iterable) => {
+            let mut final_instructions = compile_expr(iterable, const_map)?;
+            final_instructions.push(Instruction::LoadAttr("__iter__".into()));
+            final_instructions.push(Instruction::CallFunction { number_arguments: 0 });
+            //the first value sent to the sub-iterator is always None
+            final_instructions.append(&mut process_constval(Const::None, const_map));
+            final_instructions.push(Instruction::YieldFrom);
+            Ok(final_instructions)
+        }
+        Expr::Variable(var_name) => Ok(vec![Instruction::UnresolvedLoadName(var_name.clone())]),
+        //the parser removes them, but the parentheses don't change what the expression does anyway
+        Expr::Parenthesized(inner) => compile_expr(inner, const_map),
     }
 }
 
+//`f(a, *b, c=1, **d)` builds a tuple with all the positional arguments and a dict with the
+//keyword ones, and then calls with CallFunctionEx
+fn compile_call_with_unpacking(params: &[Expr], span: &ExprSpan, const_map: &mut BTreeMap<Const, usize>) -> Result<Vec<Instruction>, CompileError> {
+    let mut final_instructions = vec![];
+
+    //consecutive positional arguments are grouped in a tuple, each *iterable is its own group
+    let mut number_groups = 0;
+    let mut pending_positional = 0;
+    for param in params {
+        match param {
+            Expr::Starred(iterable) => {
+                if pending_positional > 0 {
+                    final_instructions.push(Instruction::BuildTuple { number_elements: pending_positional });
+                    number_groups += 1;
+                    pending_positional = 0;
+                }
+                final_instructions.append(&mut compile_expr(iterable, const_map)?);
+                number_groups += 1;
+            }
+            Expr::KeywordArgument(..) | Expr::DoubleStarred(_) => {}
+            positional => {
+                final_instructions.append(&mut compile_expr(positional, const_map)?);
+                pending_positional += 1;
+            }
+        }
+    }
+    if pending_positional > 0 {
+        final_instructions.push(Instruction::BuildTuple { number_elements: pending_positional });
+        number_groups += 1;
+    }
+    final_instructions.push(Instruction::BuildTupleUnpack { number_elements: number_groups });
+
+    //same for keyword arguments: name=value pairs are grouped in a dict, each **mapping is its own group
+    let mut number_groups = 0;
+    let mut pending_keywords = 0;
+    for param in params {
+        match param {
+            Expr::KeywordArgument(name, value) => {
+                final_instructions.append(&mut process_constval(Const::String(name.clone()), const_map));
+                final_instructions.append(&mut compile_expr(value, const_map)?);
+                pending_keywords += 1;
+            }
+            Expr::DoubleStarred(mapping) => {
+                if pending_keywords > 0 {
+                    final_instructions.push(Instruction::BuildMap { number_entries: pending_keywords });
+                    number_groups += 1;
+                    pending_keywords = 0;
+                }
+                final_instructions.append(&mut compile_expr(mapping, const_map)?);
+                number_groups += 1;
+            }
+            _ => {}
+        }
+    }
+    if pending_keywords > 0 {
+        final_instructions.push(Instruction::BuildMap { number_entries: pending_keywords });
+        number_groups += 1;
+    }
+    final_instructions.push(Instruction::BuildMapUnpackWithCall { number_entries: number_groups });
+
+    final_instructions.extend(line_of(span));
+    final_instructions.push(Instruction::CallFunctionEx);
+    return Ok(final_instructions);
+}
+
 struct ConstAndIndex {
     constval: Const,
     index: usize
 }
 
-pub fn resolve_loads_stores(code: &mut CodeObject) {
-    let mut names_indices = BTreeMap::new();
+//Names declared with `global` and `nonlocal` in a body, without looking into nested functions and classes
+fn scope_declarations(body: &[ASTNode], globals: &mut Vec<String>, nonlocals: &mut Vec<String>) {
+    for node in body.iter() {
+        match &node.ast {
+            AST::Global(names) => globals.extend(names.iter().cloned()),
+            AST::Nonlocal(names) => nonlocals.extend(names.iter().cloned()),
+            AST::IfStatement { true_branch, elifs, final_else } => {
+                scope_declarations(&true_branch.statements, globals, nonlocals);
+                for elif in elifs.iter() {
+                    scope_declarations(&elif.statements, globals, nonlocals);
+                }
+                if let Some(else_body) = final_else {
+                    scope_declarations(else_body, globals, nonlocals);
+                }
+            }
+            AST::WhileStatement { body, .. } | AST::ForStatement { body, .. } => {
+                scope_declarations(body, globals, nonlocals);
+            }
+            AST::TryStatement { body, handlers, else_body, finally_body } => {
+                scope_declarations(body, globals, nonlocals);
+                for handler in handlers.iter() {
+                    scope_declarations(&handler.statements, globals, nonlocals);
+                }
+                for block in else_body.iter().chain(finally_body.iter()) {
+                    scope_declarations(block, globals, nonlocals);
+                }
+            }
+            _ => {}
+        }
+    }
+}
 
-    for name in code.params.iter() {
-        names_indices.insert(name.clone(), names_indices.len());
+//Points the bare raises of an except block at the variable holding its exception. Returns whether there was any.
+//Nested functions and classes are left alone, and so are nested except blocks, which re-raise their own exception.
+fn bind_bare_raises(body: &mut [ASTNode], exception_name: &str) -> bool {
+    let mut found = false;
+    for node in body.iter_mut() {
+        match &mut node.ast {
+            AST::Raise(None) => {
+                node.ast = AST::Reraise(exception_name.to_string());
+                found = true;
+            }
+            AST::IfStatement { true_branch, elifs, final_else } => {
+                found |= bind_bare_raises(&mut true_branch.statements, exception_name);
+                for elif in elifs.iter_mut() {
+                    found |= bind_bare_raises(&mut elif.statements, exception_name);
+                }
+                if let Some(else_body) = final_else {
+                    found |= bind_bare_raises(else_body, exception_name);
+                }
+            }
+            AST::WhileStatement { body, .. } | AST::ForStatement { body, .. } => {
+                found |= bind_bare_raises(body, exception_name);
+            }
+            AST::TryStatement { body, else_body, finally_body, .. } => {
+                found |= bind_bare_raises(body, exception_name);
+                for block in else_body.iter_mut().chain(finally_body.iter_mut()) {
+                    found |= bind_bare_raises(block, exception_name);
+                }
+            }
+            _ => {}
+        }
     }
+    return found;
+}
 
+//Loads and stores of names declared `global` or `nonlocal` don't go to the local slots.
+//Must run on the instructions of the whole body, before resolve_scopes.
+fn apply_declarations(code: &mut CodeObject, globals: &[String], nonlocals: &[String], module_level: bool) -> Result<(), CompileError> {
+    if module_level && !nonlocals.is_empty() {
+        return Err(CompileError::new("nonlocal declaration not allowed at module level"));
+    }
+    for name in nonlocals.iter() {
+        if globals.contains(name) {
+            return Err(CompileError::new(format!("name '{}' is nonlocal and global", name)));
+        }
+    }
+    for (names, kind) in [(globals, "global"), (nonlocals, "nonlocal")] {
+        if let Some(name) = names.iter().find(|name| code.params.contains(name)) {
+            return Err(CompileError::new(format!("name '{}' is parameter and {}", name, kind)));
+        }
+    }
 
-    //Find all variable stores and set slots for each one of them
+    //kept even if unused, so resolve_scopes checks that they are bound somewhere
+    code.freevars = nonlocals.to_vec();
+    for instruction in code.instructions.iter_mut() {
+        let replacement = match instruction {
+            Instruction::UnresolvedLoadName(name) if globals.contains(name) => Instruction::UnresolvedLoadGlobal(name.clone()),
+            Instruction::UnresolvedStoreName(name) if globals.contains(name) => Instruction::UnresolvedStoreGlobal(name.clone()),
+            Instruction::UnresolvedLoadName(name) if nonlocals.contains(name) => Instruction::UnresolvedLoadDeref(name.clone()),
+            Instruction::UnresolvedStoreName(name) if nonlocals.contains(name) => Instruction::UnresolvedStoreDeref(name.clone()),
+            _ => continue,
+        };
+        *instruction = replacement;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeKind {
+    Module,
+    Function,
+    Class,
+}
+
+//What a function makes visible to the functions nested in it
+#[derive(Debug, Clone)]
+pub struct EnclosingScope {
+    //locals and nonlocals
+    bound: BTreeSet<String>,
+    globals: BTreeSet<String>,
+}
+
+//A name is free if the nearest enclosing function that knows it binds it
+fn is_free(name: &str, enclosing: &[EnclosingScope]) -> bool {
+    for scope in enclosing.iter().rev() {
+        if scope.globals.contains(name) {
+            return false;
+        }
+        if scope.bound.contains(name) {
+            return true;
+        }
+    }
+    false
+}
+
+fn push_unique(names: &mut Vec<String>, name: &str) {
+    if !names.iter().any(|existing| existing == name) {
+        names.push(name.to_string());
+    }
+}
+
+//Decides where each name of this code object and the ones nested in it lives:
+//local slots, cells shared with nested functions, or the module globals.
+//At module level every name is a global. Class bodies keep their names in local slots
+//(they become the class namespace), but nested functions can't see them.
+pub fn resolve_scopes(code: &mut CodeObject, kind: ScopeKind, enclosing: &[EnclosingScope]) -> Result<(), CompileError> {
+    let mut locals: Vec<String> = code.params.clone();
+    let mut globals = BTreeSet::new();
+    //apply_declarations left the names declared nonlocal here
+    let nonlocals: Vec<String> = code.freevars.clone();
     for instruction in code.instructions.iter() {
-        if let Instruction::UnresolvedStoreName(name) = instruction {
-            if !names_indices.contains_key(name) {
-                names_indices.insert(name.clone(), names_indices.len());
+        match instruction {
+            Instruction::UnresolvedStoreName(name) => push_unique(&mut locals, name),
+            Instruction::UnresolvedLoadGlobal(name) | Instruction::UnresolvedStoreGlobal(name) => {
+                globals.insert(name.clone());
             }
+            _ => {}
         }
-        if let Instruction::UnresolvedStoreAttr(attr) = instruction {
-            if !names_indices.contains_key(attr) {
-                names_indices.insert(attr.clone(), names_indices.len());
+    }
+    for name in nonlocals.iter() {
+        if !is_free(name, enclosing) {
+            return Err(CompileError::new(format!("no binding for nonlocal '{}' found", name)));
+        }
+    }
+
+    let mut freevars = nonlocals.clone();
+    if kind != ScopeKind::Module {
+        for instruction in code.instructions.iter() {
+            if let Instruction::UnresolvedLoadName(name) = instruction {
+                if !locals.contains(name) && is_free(name, enclosing) {
+                    push_unique(&mut freevars, name);
+                }
             }
         }
     }
 
-    //Instead of storing values in string names (hashing strings is slooooooooooooooooow), store variables in
-    //integer slots 
-    let new_instructions: Vec<Instruction> = code.instructions.iter().map(|instruction| {
-        return if let Instruction::UnresolvedLoadName(name) = instruction {
-            match names_indices.get(name) {
-                Some(idx) => Instruction::LoadName(*idx),
-                None => {
-                    let cur_size = names_indices.len();
-                    names_indices.insert(name.clone(), cur_size);
-
-                    if code.params.contains(&name) {
-                        Instruction::LoadName(cur_size)
+    let mut nested_enclosing = enclosing.to_vec();
+    if kind == ScopeKind::Function {
+        nested_enclosing.push(EnclosingScope {
+            bound: locals.iter().chain(nonlocals.iter()).cloned().collect(),
+            globals,
+        });
+    }
+
+    //a local used by a nested function becomes a cell, anything else it uses comes from our own closure
+    let mut cellvars: Vec<String> = vec![];
+    let mut nested_freevars: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    for instruction in code.instructions.iter() {
+        if let Instruction::UnresolvedClosure { code: index, class_body } = instruction {
+            if nested_freevars.contains_key(index) {
+                continue;
+            }
+            if let Const::CodeObject(nested) = &mut code.consts[*index] {
+                let nested_kind = if *class_body { ScopeKind::Class } else { ScopeKind::Function };
+                resolve_scopes(nested, nested_kind, &nested_enclosing)?;
+                for name in nested.freevars.iter() {
+                    if kind == ScopeKind::Function && locals.contains(name) {
+                        push_unique(&mut cellvars, name);
                     } else {
-                        Instruction::LoadGlobal(cur_size)
+                        push_unique(&mut freevars, name);
                     }
                 }
+                nested_freevars.insert(*index, nested.freevars.clone());
             }
         }
-        else if let Instruction::UnresolvedStoreName(name) = instruction {
-            let idx = names_indices.get(name).unwrap();
-            Instruction::StoreName(*idx)
-        }
-        else if let Instruction::UnresolvedStoreAttr(name) = instruction {
-            let idx = names_indices.get(name).unwrap();
-            Instruction::StoreAttr(*idx)
+    }
+
+    let cells: Vec<String> = cellvars.iter().chain(freevars.iter()).cloned().collect();
+    let cell_index = |name: &str| cells.iter().position(|cell| cell == name).unwrap();
+
+    let mut names_indices = BTreeMap::new();
+    for name in code.params.iter() {
+        names_indices.insert(name.clone(), names_indices.len());
+    }
+
+    //Find all variable stores and set slots for each one of them
+    for instruction in code.instructions.iter() {
+        if let Instruction::UnresolvedStoreName(name) | Instruction::UnresolvedStoreAttr(name) = instruction {
+            if !names_indices.contains_key(name) && !cellvars.contains(name) {
+                names_indices.insert(name.clone(), names_indices.len());
+            }
         }
-        else {
-            instruction.clone()
+    }
+
+    //Instead of storing values in string names (hashing strings is slooooooooooooooooow), store variables in
+    //integer slots 
+    let mut name_index = |name: &String| -> usize {
+        let len = names_indices.len();
+        *names_indices.entry(name.clone()).or_insert(len)
+    };
+    let new_instructions: Vec<Instruction> = code.instructions.iter().map(|instruction| {
+        match instruction {
+            Instruction::UnresolvedLoadName(name) => {
+                if kind == ScopeKind::Module {
+                    Instruction::LoadGlobal(name_index(name))
+                } else if cellvars.contains(name) {
+                    Instruction::LoadDeref(cell_index(name))
+                } else if locals.contains(name) {
+                    Instruction::LoadName(name_index(name))
+                } else if freevars.contains(name) {
+                    Instruction::LoadDeref(cell_index(name))
+                } else {
+                    Instruction::LoadGlobal(name_index(name))
+                }
+            }
+            Instruction::UnresolvedStoreName(name) => {
+                if kind == ScopeKind::Module {
+                    Instruction::StoreGlobal(name_index(name))
+                } else if cellvars.contains(name) {
+                    Instruction::StoreDeref(cell_index(name))
+                } else {
+                    Instruction::StoreName(name_index(name))
+                }
+            }
+            Instruction::UnresolvedLoadGlobal(name) => Instruction::LoadGlobal(name_index(name)),
+            Instruction::UnresolvedStoreGlobal(name) => Instruction::StoreGlobal(name_index(name)),
+            Instruction::UnresolvedLoadDeref(name) => Instruction::LoadDeref(cell_index(name)),
+            Instruction::UnresolvedStoreDeref(name) => Instruction::StoreDeref(cell_index(name)),
+            Instruction::UnresolvedStoreAttr(name) => Instruction::StoreAttr(name_index(name)),
+            Instruction::UnresolvedClosure { code: index, .. } => Instruction::BuildClosure {
+                cells: nested_freevars[index].iter().map(|name| cell_index(name)).collect(),
+            },
+            _ => instruction.clone(),
         }
     }).collect();
 
@@ -229,29 +620,51 @@ pub fn resolve_loads_stores(code: &mut CodeObject) {
 
     code.instructions = new_instructions;
     code.names = indices_names;
+    code.cellvars = cellvars;
+    code.freevars = freevars;
+    Ok(())
 }
 
-pub fn compile_repl(ast: Vec<AST>) -> Program {
-
-    let mut compiled = compile(ast);
-    println!("{:?}", compiled.code_objects);
-    let instructions = &mut compiled.code_objects.last_mut().unwrap().instructions;
+//Whether the last statement is an expression, the REPL shows its value
+pub fn ends_with_expression(ast: &[ASTNode]) -> bool {
+    matches!(ast.last(), Some(ASTNode { ast: AST::StandaloneExpr(_), .. }))
+}
 
-    let last_pop_location = instructions.len() - 3;
+pub fn compile_repl(ast: Vec<ASTNode>) -> Result<Program, CompileError> {
+    compile_repl_file(ast, "<stdin>")
+}
 
-    if let Instruction::PopTop = instructions[last_pop_location] {
-        instructions.remove(last_pop_location);
-        compiled.code_objects.last_mut().unwrap().instructions.pop();
+//Like compile_file, but the value of a final expression statement stays on top of the stack
+pub fn compile_repl_file(ast: Vec<ASTNode>, filename: &str) -> Result<Program, CompileError> {
+    let shows_value = ends_with_expression(&ast);
+    let mut compiled = compile_file(ast, filename)?;
+    let instructions = &mut compiled.code_objects.iter_mut().find(|code| code.main).unwrap().instructions;
+    //the expression is followed by its PopTop, then the LoadConst None and ReturnValue that end the module
+    if shows_value && instructions.len() >= 3 && instructions[instructions.len() - 3] == Instruction::PopTop {
+        instructions.truncate(instructions.len() - 3);
     }
-    return compiled;
+    return Ok(compiled);
+}
+
+#[allow(dead_code)]
+pub fn compile(ast: Vec<ASTNode>) -> Result<Program, CompileError> {
+    compile_file(ast, "<string>")
 }
 
-pub fn compile(ast: Vec<AST>) -> Program {
+pub fn compile_file(ast: Vec<ASTNode>, filename: &str) -> Result<Program, CompileError> {
 
     let mut all_results = vec![];
-    let mut compile_result = compile_ast(ast, 0, &mut all_results, &mut BTreeMap::new());
+    let (mut globals, mut nonlocals) = (vec![], vec![]);
+    scope_declarations(&ast, &mut globals, &mut nonlocals);
+    let mut compile_result = compile_ast(ast, 0, &mut all_results, &mut BTreeMap::new())?;
+    resolve_lines(&mut compile_result);
     compile_result.main = true;
-    resolve_loads_stores(&mut compile_result);
+    if yields(&compile_result) {
+        return Err(CompileError::new("'yield' outside function"));
+    }
+    apply_declarations(&mut compile_result, &globals, &nonlocals, true)?;
+    resolve_scopes(&mut compile_result, ScopeKind::Module, &[])?;
+    compile_result.set_filename(filename);
     
     /*for inst in compile_result.instructions.iter() {
         if let Instruction::LoadConst(x) = inst {
@@ -263,10 +676,10 @@ pub fn compile(ast: Vec<AST>) -> Program {
 
     all_results.insert(0, compile_result);
 
-    Program {
-        version: 1,
+    Ok(Program {
+        version: BYTECODE_VERSION,
         code_objects: all_results
-    }
+    })
 }
 
 
@@ -290,6 +703,55 @@ fn generate_assign_path(remaining_parts: &[String], is_first: bool) -> Vec<Instr
     return instructions;
 }
 
+//Stores TOS into an assignment target. Tuple targets unpack TOS and store each element in order.
+fn compile_store(target: &Expr, const_map: &mut BTreeMap<Const, usize>) -> Result<Vec<Instruction>, CompileError> {
+    match target {
+        Expr::Variable(name) => Ok(vec![Instruction::UnresolvedStoreName(name.clone())]),
+        Expr::MemberAccess(expr, name) => {
+            let mut instructions = compile_expr(expr, const_map)?;
+            instructions.push(Instruction::UnresolvedStoreAttr(name.clone()));
+            Ok(instructions)
+        }
+        Expr::IndexAccess(expr, index, span) => {
+            let mut instructions = compile_expr(expr, const_map)?;
+            instructions.append(&mut compile_expr(index, const_map)?);
+            instructions.extend(line_of(span));
+            instructions.push(Instruction::StoreIndex);
+            Ok(instructions)
+        }
+        Expr::Tuple(targets) | Expr::Array(targets) => compile_unpack(targets, const_map),
+        _ => Err(CompileError::new(format!("cannot assign to {:?}", target))),
+    }
+}
+
+fn compile_unpack(targets: &[Expr], const_map: &mut BTreeMap<Const, usize>) -> Result<Vec<Instruction>, CompileError> {
+    let mut instructions = vec![Instruction::UnpackSequence { number_elements: targets.len() }];
+    for target in targets {
+        instructions.append(&mut compile_store(target, const_map)?);
+    }
+    Ok(instructions)
+}
+
+//Breaks that leave a try block must pop its blocks (and run the finally body) before jumping out of the loop
+fn breaks_through_blocks(instructions: Vec<Instruction>, blocks: usize) -> Vec<Instruction> {
+    if blocks == 0 {
+        return instructions;
+    }
+    instructions.into_iter().map(|instr| match instr {
+        Instruction::UnresolvedBreak => Instruction::UnresolvedBreakThroughBlocks(blocks),
+        Instruction::UnresolvedBreakThroughBlocks(inner) => Instruction::UnresolvedBreakThroughBlocks(inner + blocks),
+        _ => instr
+    }).collect()
+}
+
+fn resolve_breaks(instructions: Vec<Instruction>, destination: usize) -> Vec<Instruction> {
+    instructions.into_iter().map(|instr| match instr {
+        Instruction::UnresolvedBreak => Instruction::JumpUnconditional(destination),
+        Instruction::UnresolvedBreakThroughBlocks(blocks) => Instruction::BreakThroughBlocks { blocks, destination },
+        _ => instr
+    }).collect()
+}
+
 fn build_fully_qualified_name(prefix: Option<String>, name: &str) -> String {
     match prefix {
         Some(s) => (s + "." + name).to_string(),
@@ -297,15 +759,150 @@ fn build_fully_qualified_name(prefix: Option<String>, name: &str) -> String {
     }
 }
 
-pub fn compile_ast_internal(ast: Vec<AST>, offset: usize, qualified_prefix: Option<String>, ensure_return: bool, results: &mut Vec<CodeObject>, const_map: &mut BTreeMap<Const, usize>) -> CodeObject {
+//The hidden variable where a comprehension collects its elements.
+//Names starting with a dot can't clash with the ones in the source.
+fn comprehension_result() -> Expr {
+    Expr::Variable(String::from(".result"))
+}
+
+fn call_on_result(method: &str, arguments: Vec<Expr>) -> Expr {
+    Expr::FunctionCall(Box::new(Expr::MemberAccess(Box::new(comprehension_result()), method.to_string())), arguments, ExprSpan::default())
+}
+
+//A comprehension runs in a function of its own, called right away with the first iterable,
+//which is evaluated in the enclosing scope. `[f(x) for x in xs if p(x)]` becomes
+//    def <listcomp>(.0):
+//        .result = []
+//        for x in .0:
+//            if p(x):
+//                .result.append(f(x))
+//        return .result
+//called with `xs`. Every other clause nests another for or if inside the previous one.
+//Generator expressions have no result: they yield each element instead, and calling them makes a generator.
+fn compile_comprehension(name: &str, empty_result: Option<Expr>, add_element: AST, clauses: &[ComprehensionClause], const_map: &mut BTreeMap<Const, usize>) -> Result<Vec<Instruction>, CompileError> {
+    let statement = |ast| ASTNode { ast, span: Span::default() };
+    let mut body = vec![statement(add_element)];
+    for (index, clause) in clauses.iter().enumerate().rev() {
+        for condition in clause.conditions.iter().rev() {
+            body = vec![statement(AST::IfStatement {
+                true_branch: ASTIfStatement { expression: condition.clone(), statements: body },
+                elifs: vec![],
+                final_else: None,
+            })];
+        }
+        let iterable = if index == 0 { Expr::Variable(String::from(".0")) } else { clause.iterable.clone() };
+        body = vec![statement(AST::ForStatement { target: clause.target.clone(), list_expression: iterable, body })];
+    }
+    if let Some(empty_result) = empty_result {
+        body.insert(0, statement(AST::Assign { path: vec![String::from(".result")], expression: empty_result }));
+        body.push(statement(AST::Return(Some(comprehension_result()))));
+    }
+
+    let mut instructions = compile_function(name.to_string(), &[FunctionParameter::Simple(String::from(".0"))], body, const_map)?;
+    instructions.append(&mut compile_expr(&clauses[0].iterable, const_map)?);
+    instructions.push(Instruction::CallFunction { number_arguments: 1 });
+    Ok(instructions)
+}
+
+//Nested functions are in the consts, so only yields in this very body count
+fn yields(code: &CodeObject) -> bool {
+    code.instructions.iter().any(|instruction| matches!(instruction, Instruction::YieldValue | Instruction::YieldFrom))
+}
+
+//Builds a function object from a def or a lambda, leaving it on the stack
+fn compile_function(qualname: String, parameters: &[FunctionParameter], body: Vec<ASTNode>, const_map: &mut BTreeMap<Const, usize>) -> Result<Vec<Instruction>, CompileError> {
+    let (mut globals, mut nonlocals) = (vec![], vec![]);
+    scope_declarations(&body, &mut globals, &mut nonlocals);
+    let mut new_const_map = BTreeMap::new();
+    let mut func_instructions = compile_ast_internal(body, 0, Some(qualname.clone()), true, &mut vec![], &mut new_const_map)?;
+    resolve_lines(&mut func_instructions);
+    func_instructions.main = false;
+    func_instructions.generator = yields(&func_instructions);
+
+    //keyword-only parameters go after the positional ones, *args and **kwargs at the end
+    let mut positional = vec![];
+    let mut keyword_only = vec![];
+    let mut varargs = None;
+    let mut varkeywords = None;
+    for param in parameters.iter() {
+        match param {
+            FunctionParameter::Simple(name) | FunctionParameter::DefaultValue(name, _) => {
+                if varargs.is_none() {
+                    positional.push(name.clone());
+                } else {
+                    keyword_only.push(name.clone());
+                }
+            }
+            FunctionParameter::VarArgs(name) => varargs = Some(name.clone()),
+            FunctionParameter::KwArgs(name) => varkeywords = Some(name.clone()),
+        }
+    }
+    func_instructions.kwonly_count = keyword_only.len();
+    func_instructions.params = positional;
+    func_instructions.params.extend(keyword_only);
+    if let Some(Some(name)) = &varargs {
+        func_instructions.params.push(name.clone());
+        func_instructions.varargs = true;
+    }
+    if let Some(name) = varkeywords {
+        func_instructions.params.push(name);
+        func_instructions.varkeywords = true;
+    }
+
+    //we must generate the bytecode for default values.
+    //Positional defaults go in a list, keyword-only defaults in a dict by name
+    let mut number_of_default_parameters = 0;
+    let mut number_of_kwonly_defaults = 0;
+    let mut default_instructions = vec![];
+    let mut kwonly_default_instructions = vec![];
+    let mut after_star = false;
+    for param in parameters.iter() {
+        match param {
+            FunctionParameter::DefaultValue(name, expr) if after_star => {
+                kwonly_default_instructions.append(&mut process_constval(Const::String(name.clone()), const_map));
+                kwonly_default_instructions.extend(compile_expr(expr, const_map)?);
+                number_of_kwonly_defaults += 1;
+            }
+            FunctionParameter::DefaultValue(_, expr) => {
+                default_instructions.extend(compile_expr(expr, const_map)?);
+                number_of_default_parameters += 1;
+            }
+            FunctionParameter::VarArgs(_) => after_star = true,
+            _ => {}
+        }
+    }
+
+    apply_declarations(&mut func_instructions, &globals, &nonlocals, false)?;
+
+    let code_idx = const_index(Const::CodeObject(Box::new(func_instructions)), const_map);
+    let constval_name = Const::String(qualname.clone());
+    let mut name_idx = process_constval(constval_name, const_map);
+
+    let mut instructions = default_instructions;
+    instructions.push(Instruction::BuildList { number_elements:number_of_default_parameters });
+    instructions.extend(kwonly_default_instructions);
+    instructions.push(Instruction::BuildMap { number_entries: number_of_kwonly_defaults });
+    instructions.push(Instruction::LoadConst(code_idx));
+    instructions.push(Instruction::UnresolvedClosure { code: code_idx, class_body: false });
+    instructions.append(&mut name_idx);
+    instructions.push(Instruction::MakeFunction);
+    Ok(instructions)
+}
+
+pub fn compile_ast_internal(ast: Vec<ASTNode>, offset: usize, qualified_prefix: Option<String>, ensure_return: bool, results: &mut Vec<CodeObject>, const_map: &mut BTreeMap<Const, usize>) -> Result<CodeObject, CompileError> {
     let mut all_instructions = vec![];
-    for ast_item in ast {
-        match ast_item {
+    let mut line_table = vec![];
+    for ast_node in ast {
+        line_table.push(LineTableEntry {
+            instruction: offset + all_instructions.len(),
+            line: ast_node.span.line,
+        });
+        match ast_node.ast {
             AST::Assign {
                 path,
                 expression,
             } => {
-                all_instructions.append(&mut compile_expr(&expression, const_map));
+                all_instructions.append(&mut compile_expr(&expression, const_map)?);
                 if path.len() == 1 {
                     all_instructions.push(Instruction::UnresolvedStoreName(path[0].clone()));
                 } else {
@@ -313,75 +910,72 @@ pub fn compile_ast_internal(ast: Vec<AST>, offset: usize, qualified_prefix: Opti
                     all_instructions.extend(instructions_for_assign);
                 }
             }
+            AST::UnpackAssign {
+                targets,
+                expression,
+            } => {
+                all_instructions.append(&mut compile_expr(&expression, const_map)?);
+                all_instructions.append(&mut compile_unpack(&targets, const_map)?);
+            }
+            AST::AssignIndex {
+                target,
+                index,
+                expression,
+            } => {
+                all_instructions.append(&mut compile_expr(&expression, const_map)?);
+                all_instructions.append(&mut compile_expr(&target, const_map)?);
+                all_instructions.append(&mut compile_expr(&index, const_map)?);
+                all_instructions.push(Instruction::StoreIndex);
+            }
             AST::StandaloneExpr(expr) => {
-                all_instructions.append(&mut compile_expr(&expr, const_map));
+                all_instructions.append(&mut compile_expr(&expr, const_map)?);
                 all_instructions.push(Instruction::PopTop);
             },
+            //only modules are compiled without a qualified name
+            AST::Return(_) if qualified_prefix.is_none() => {
+                return Err(CompileError::new("'return' outside function"));
+            }
             AST::Return(Some(expr)) => {
-                all_instructions.append(&mut compile_expr(&expr, const_map));
+                all_instructions.append(&mut compile_expr(&expr, const_map)?);
                 all_instructions.push(Instruction::ReturnValue);
             }
             AST::Return(None) => {
-                all_instructions.push(Instruction::LoadConst(const_map[&Const::None]));
+                all_instructions.append(&mut process_constval(Const::None, const_map));
                 all_instructions.push(Instruction::ReturnValue);
             }
-            AST::ClassDeclaration{class_name, body} => {
+            AST::ClassDeclaration{class_name, bases, body} => {
                 let qualname = build_fully_qualified_name(qualified_prefix.clone(), &class_name);
 
+                let (mut globals, mut nonlocals) = (vec![], vec![]);
+                scope_declarations(&body, &mut globals, &mut nonlocals);
                 let mut new_const_map = BTreeMap::new();
-                let mut class_decl_function = compile_ast_internal(body, 0, Some(qualname.clone()), true, results, &mut new_const_map);
+                let mut class_decl_function = compile_ast_internal(body, 0, Some(qualname.clone()), true, results, &mut new_const_map)?;
+                resolve_lines(&mut class_decl_function);
                 class_decl_function.main = false;
-                resolve_loads_stores(&mut class_decl_function);
-                let constval_code = Const::CodeObject(class_decl_function);
-                let mut code_idx = process_constval(constval_code, const_map);
+                if yields(&class_decl_function) {
+                    return Err(CompileError::new("'yield' outside function"));
+                }
+                apply_declarations(&mut class_decl_function, &globals, &nonlocals, false)?;
+                //names are resolved once the whole module is compiled, see resolve_scopes
+                let code_idx = const_index(Const::CodeObject(Box::new(class_decl_function)), const_map);
                 let constval_name = Const::String(qualname.clone());
                 let mut name_idx = process_constval(constval_name, const_map);
 
-                all_instructions.append(&mut code_idx);
+                for base in bases.iter() {
+                    all_instructions.append(&mut compile_expr(base, const_map)?);
+                }
+                all_instructions.push(Instruction::LoadConst(code_idx));
+                all_instructions.push(Instruction::UnresolvedClosure { code: code_idx, class_body: true });
                 all_instructions.append(&mut name_idx);
-                all_instructions.push(Instruction::MakeClass);
+                all_instructions.push(Instruction::MakeClass { number_bases: bases.len() });
                 all_instructions.push(Instruction::UnresolvedStoreName(class_name.clone()));
             }
             AST::DeclareFunction{function_name, parameters, body} => {
                 let qualname = build_fully_qualified_name(qualified_prefix.clone(), &function_name);
-
-                let mut new_const_map = BTreeMap::new();
-                let mut func_instructions = compile_ast_internal(body, 0, Some(qualname.clone()), true, results, &mut new_const_map);
-                func_instructions.main = false;
-               
-                func_instructions.params = parameters.iter()
-                    .map(|x| match x {
-                        FunctionParameter::Simple(x) => x.clone(),
-                        FunctionParameter::DefaultValue(x, _) => x.clone()
-                    }).collect();
-
-                //we must generate the bytecode for default values
-                let mut number_of_default_parameters = 0;
-                let mut default_instructions = vec![];
-                for param in parameters.iter() {
-                    if let FunctionParameter::DefaultValue(_, expr) = param {
-                        let compiled_expr = compile_expr(expr, const_map);
-                        default_instructions.extend(compiled_expr);
-                        number_of_default_parameters += 1;
-                    }
-                }
-
-                resolve_loads_stores(&mut func_instructions);
-
-                let constval_code = Const::CodeObject(func_instructions);
-                let mut code_idx = process_constval(constval_code, const_map);
-                let constval_name = Const::String(qualname.clone());
-                let mut name_idx = process_constval(constval_name, const_map);
-
-                all_instructions.extend(default_instructions);
-                all_instructions.push(Instruction::BuildList { number_elements:number_of_default_parameters });
-                all_instructions.append(&mut code_idx);
-                all_instructions.append(&mut name_idx);
-                all_instructions.push(Instruction::MakeFunction(number_of_default_parameters > 0));
+                all_instructions.append(&mut compile_function(qualname, &parameters, body, const_map)?);
                 all_instructions.push(Instruction::UnresolvedStoreName(function_name.clone()));
-                
             }
-            AST::ForStatement{item_name, list_expression, body} => {
+            AST::ForStatement{target, list_expression, body} => {
                 //this should behave like this:
                 /*
                 iterator = list_expression.__iter__()
@@ -401,7 +995,7 @@ pub fn compile_ast_internal(ast: Vec<AST>, offset: usize, qualified_prefix: Opti
                 //let's just copy python then
 
 
-                let list_expr_instructions = compile_expr(&list_expression, const_map);
+                let list_expr_instructions = compile_expr(&list_expression, const_map)?;
                 all_instructions.extend(list_expr_instructions);
 
                 all_instructions.push(Instruction::LoadAttr("__iter__".into()));
@@ -412,30 +1006,28 @@ pub fn compile_ast_internal(ast: Vec<AST>, offset: usize, qualified_prefix: Opti
                 //Need to compute the body first to get an offset
                 //and then we add to the beginning of the loop the ForIter instruction
 
-                let compiled_body = compile_ast_internal(body, 0, qualified_prefix.clone(), false, results, const_map);
+                //the body starts after the ForIter and the stores of the item
+                let store_item = compile_store(&target, const_map)?;
+                let compiled_body = compile_ast_internal(body, offset_before_for + 1 + store_item.len(), qualified_prefix.clone(), false, results, const_map)?;
+                line_table.extend_from_slice(&compiled_body.line_table);
                 let mut body_instructions = vec![];
-                body_instructions.push(Instruction::UnresolvedStoreName(item_name.clone()));
+                body_instructions.extend(store_item);
                 body_instructions.extend(compiled_body.instructions);
                 
                 //+2 because we are considering the ForIter and JumpUnconditional instructions
                 //before generating the instructions
-                let offset_after_loop = offset_before_for + body_instructions.len() + 2;
+                let offset_break = offset_before_for + body_instructions.len() + 2;
+                //ForIter pops the iterator when it's exhausted, but a break leaves it on the stack.
+                //Breaks go to a PopTop right after the loop, the normal exit skips it
+                let offset_after_loop = offset_break + 1;
                 
-                let mut compiled_body_with_resolved_breaks: Vec<Instruction> = body_instructions
-                    .into_iter()
-                    .map(|instr| -> Instruction {
-                        if let Instruction::UnresolvedBreak = instr {
-                            Instruction::JumpUnconditional(offset_after_loop)
-                        } else {
-                            instr
-                        }
-                    })
-                    .collect();
+                let mut compiled_body_with_resolved_breaks = resolve_breaks(body_instructions, offset_break);
                 
                 //create the loop now, pointing to the end of the loop
                 compiled_body_with_resolved_breaks.insert(0, Instruction::ForIter(offset_after_loop));
                 //this has to jump back to the ForIter instruction so it loops
                 compiled_body_with_resolved_breaks.push(Instruction::JumpUnconditional(offset_before_for));
+                compiled_body_with_resolved_breaks.push(Instruction::PopTop);
        
                 all_instructions.extend(compiled_body_with_resolved_breaks);
             
@@ -445,7 +1037,7 @@ pub fn compile_ast_internal(ast: Vec<AST>, offset: usize, qualified_prefix: Opti
                 elifs: _,
                 final_else,
             } => {
-                let mut if_expr_compiled = compile_expr(&true_branch.expression, const_map);
+                let mut if_expr_compiled = compile_expr(&true_branch.expression, const_map)?;
                 all_instructions.append(&mut if_expr_compiled);
 
                 //+1 is because there will be a instruction before
@@ -453,7 +1045,8 @@ pub fn compile_ast_internal(ast: Vec<AST>, offset: usize, qualified_prefix: Opti
                 let offset_before_if = offset + all_instructions.len() + 1;
 
                 let mut true_branch_compiled =
-                    compile_ast_internal(true_branch.statements, offset_before_if, qualified_prefix.clone(), false, results, const_map);
+                    compile_ast_internal(true_branch.statements, offset_before_if, qualified_prefix.clone(), false, results, const_map)?;
+                line_table.extend_from_slice(&true_branch_compiled.line_table);
                 //generate a jump to the code right after the true branch
 
                 //if there is an else: statement, the true branch must jump to after the false branch
@@ -467,7 +1060,9 @@ pub fn compile_ast_internal(ast: Vec<AST>, offset: usize, qualified_prefix: Opti
                     ));
                     all_instructions.append(&mut true_branch_compiled.instructions);
 
-                    let mut false_branch_compiled = compile_ast_internal(else_ast, offset_after_true_branch, qualified_prefix.clone(), false, results, const_map);
+                    let mut false_branch_compiled = compile_ast_internal(else_ast, offset_after_true_branch, qualified_prefix.clone(), false, results, const_map)?;
+
+                    line_table.extend_from_slice(&false_branch_compiled.line_table);
 
                     //+1 because there will be an instruction
                     //in the true branch that will jump to *after* the false branch
@@ -486,30 +1081,129 @@ pub fn compile_ast_internal(ast: Vec<AST>, offset: usize, qualified_prefix: Opti
             }
             AST::WhileStatement { expression, body } => {
                 let offset_before_while = all_instructions.len() + offset;
-                let mut compiled_expr = compile_expr(&expression, const_map);
+                let mut compiled_expr = compile_expr(&expression, const_map)?;
                 //+1 for the jump if false
-                let offset_after_expr = all_instructions.len() + compiled_expr.len() + 1;
-                let compiled_body = compile_ast_internal(body, offset_after_expr, qualified_prefix.clone(), false, results, const_map);
+                let offset_after_expr = offset + all_instructions.len() + compiled_expr.len() + 1;
+                let compiled_body = compile_ast_internal(body, offset_after_expr, qualified_prefix.clone(), false, results, const_map)?;
+                line_table.extend_from_slice(&compiled_body.line_table);
                 all_instructions.append(&mut compiled_expr);
                 let offset_after_body = offset_after_expr + compiled_body.instructions.len() + 1;
                 all_instructions.push(Instruction::JumpIfFalseAndPopStack(offset_after_body));
 
-                let mut compiled_body_with_resolved_breaks: Vec<Instruction> = compiled_body.instructions
-                    .into_iter()
-                    .map(|instr| -> Instruction {
-                        if let Instruction::UnresolvedBreak = instr {
-                            Instruction::JumpUnconditional(offset_after_body)
-                        } else {
-                            instr
-                        }
-                    })
-                    .collect();
+                let mut compiled_body_with_resolved_breaks = resolve_breaks(compiled_body.instructions, offset_after_body);
 
                 all_instructions.append(&mut compiled_body_with_resolved_breaks);
                 all_instructions.push(Instruction::JumpUnconditional(offset_before_while));
             }
-            AST::Raise(expr) => {
-                let mut if_expr_compiled = compile_expr(&expr, const_map);
+            AST::TryStatement { body, handlers, else_body, finally_body } => {
+                //SetupExcept and SetupFinally push a block on the frame, pointing to the handler code.
+                //When an exception is raised, the VM unwinds to the innermost block and jumps to its handler.
+                //In the normal path the blocks are removed with PopBlock.
+                //The layout is:
+                //  SetupFinally(finally)   (if there is a finally body)
+                //  SetupExcept(handlers)   (if there are except clauses)
+                //  body
+                //  PopBlock
+                //  else body
+                //  JumpUnconditional(end)
+                //handlers:
+                //  for each handler: exception type, JumpIfNotExceptionMatch(next handler), store name, body, JumpUnconditional(end)
+                //  Reraise (no handler matched)
+                //end:
+                //  PopBlock, BeginFinally
+                //finally:
+                //  finally body
+                //  EndFinally
+                let start = offset + all_instructions.len();
+                let has_handlers = !handlers.is_empty();
+                let finally_blocks = if finally_body.is_some() { 1 } else { 0 };
+                let except_blocks = if has_handlers { 1 } else { 0 };
+
+                let mut try_instructions = vec![];
+                if finally_body.is_some() {
+                    //the actual destination is only known after compiling everything
+                    try_instructions.push(Instruction::SetupFinally(0));
+                }
+                let setup_except_index = try_instructions.len();
+                if has_handlers {
+                    try_instructions.push(Instruction::SetupExcept(0));
+                }
+
+                let compiled_body = compile_ast_internal(body, start + try_instructions.len(), qualified_prefix.clone(), false, results, const_map)?;
+
+                line_table.extend_from_slice(&compiled_body.line_table);
+                try_instructions.extend(breaks_through_blocks(compiled_body.instructions, except_blocks + finally_blocks));
+
+                let mut jumps_to_end = vec![];
+                if has_handlers {
+                    try_instructions.push(Instruction::PopBlock);
+                    if let Some(else_ast) = else_body {
+                        let compiled_else = compile_ast_internal(else_ast, start + try_instructions.len(), qualified_prefix.clone(), false, results, const_map)?;
+                        line_table.extend_from_slice(&compiled_else.line_table);
+                        try_instructions.extend(breaks_through_blocks(compiled_else.instructions, finally_blocks));
+                    }
+                    jumps_to_end.push(try_instructions.len());
+                    try_instructions.push(Instruction::JumpUnconditional(0));
+                    try_instructions[setup_except_index] = Instruction::SetupExcept(start + try_instructions.len());
+
+                    //the VM pushes the exception onto the stack before jumping to the handlers
+                    for mut handler in handlers {
+                        let mut no_match_jump_index = None;
+                        if let Some(exception_type) = &handler.exception_type {
+                            try_instructions.extend(compile_expr(exception_type, const_map)?);
+                            no_match_jump_index = Some(try_instructions.len());
+                            try_instructions.push(Instruction::JumpIfNotExceptionMatch(0));
+                        }
+                        //a bare raise needs the exception in a variable, a hidden one if the handler doesn't name it
+                        let exception_name = handler
+                            .bound_name
+                            .clone()
+                            .unwrap_or_else(|| format!(".exception{}", start + try_instructions.len()));
+                        let reraises = bind_bare_raises(&mut handler.statements, &exception_name);
+                        if handler.bound_name.is_some() || reraises {
+                            try_instructions.push(Instruction::UnresolvedStoreName(exception_name));
+                        } else {
+                            try_instructions.push(Instruction::PopTop);
+                        }
+                        let compiled_handler = compile_ast_internal(handler.statements, start + try_instructions.len(), qualified_prefix.clone(), false, results, const_map)?;
+                        line_table.extend_from_slice(&compiled_handler.line_table);
+                        try_instructions.extend(breaks_through_blocks(compiled_handler.instructions, finally_blocks));
+                        jumps_to_end.push(try_instructions.len());
+                        try_instructions.push(Instruction::JumpUnconditional(0));
+                        if let Some(index) = no_match_jump_index {
+                            try_instructions[index] = Instruction::JumpIfNotExceptionMatch(start + try_instructions.len());
+                        }
+                    }
+                    //no handler matched, the exception is still on the stack
+                    try_instructions.push(Instruction::Reraise);
+                }
+
+                let offset_end = start + try_instructions.len();
+                for index in jumps_to_end {
+                    try_instructions[index] = Instruction::JumpUnconditional(offset_end);
+                }
+
+                if let Some(finally_ast) = finally_body {
+                    try_instructions.push(Instruction::PopBlock);
+                    try_instructions.push(Instruction::BeginFinally);
+                    try_instructions[0] = Instruction::SetupFinally(start + try_instructions.len());
+                    let compiled_finally = compile_ast_internal(finally_ast, start + try_instructions.len(), qualified_prefix.clone(), false, results, const_map)?;
+                    line_table.extend_from_slice(&compiled_finally.line_table);
+                    try_instructions.extend(compiled_finally.instructions);
+                    try_instructions.push(Instruction::EndFinally);
+                }
+
+                all_instructions.extend(try_instructions);
+            }
+            AST::Raise(None) => {
+                return Err(CompileError::new("bare 'raise' is only supported inside an except block"));
+            }
+            AST::Reraise(name) => {
+                all_instructions.push(Instruction::UnresolvedLoadName(name));
+                all_instructions.push(Instruction::Reraise);
+            }
+            AST::Raise(Some(expr)) => {
+                let mut if_expr_compiled = compile_expr(&expr, const_map)?;
                 all_instructions.append(&mut if_expr_compiled);
                 all_instructions.push(Instruction::Raise);
                 if !const_map.contains_key(&Const::None) {
@@ -531,17 +1225,100 @@ pub fn compile_ast_internal(ast: Vec<AST>, offset: usize, qualified_prefix: Opti
                 //Perhaps other features such as generators, for comprehensions, etc really need blocks? I doubt it.
                 all_instructions.push(Instruction::UnresolvedBreak);
             }
+            AST::Import(names) => {
+                for imported in names {
+                    all_instructions.push(Instruction::ImportName(imported.name.clone()));
+                    match imported.alias {
+                        Some(alias) => all_instructions.push(Instruction::UnresolvedStoreName(alias)),
+                        None => {
+                            //`import a.b.c` imports all three, but only binds the top level package `a`
+                            let top_level = imported.name.split('.').next().unwrap().to_string();
+                            if top_level != imported.name {
+                                all_instructions.push(Instruction::PopTop);
+                                all_instructions.push(Instruction::ImportName(top_level.clone()));
+                            }
+                            all_instructions.push(Instruction::UnresolvedStoreName(top_level));
+                        }
+                    }
+                }
+            }
+            AST::ImportFrom { module, names } => {
+                all_instructions.push(Instruction::ImportName(module));
+                for imported in names {
+                    all_instructions.push(Instruction::ImportFrom(imported.name.clone()));
+                    all_instructions.push(Instruction::UnresolvedStoreName(imported.alias.unwrap_or(imported.name)));
+                }
+                all_instructions.push(Instruction::PopTop);
+            }
+            AST::Pass => {}
+            //handled by apply_declarations on the whole body
+            AST::Global(_) | AST::Nonlocal(_) => {}
         }
     }
 
-    make_code_object(all_instructions, qualified_prefix.unwrap_or("__main__".to_owned()), const_map, ensure_return)
+    //loops resolve the breaks in their body, any left in a function or module body are not in a loop
+    let unresolved_break = all_instructions
+        .iter()
+        .any(|instruction| matches!(instruction, Instruction::UnresolvedBreak | Instruction::UnresolvedBreakThroughBlocks(_)));
+    if ensure_return && unresolved_break {
+        return Err(CompileError::new("'break' outside loop"));
+    }
+
+    Ok(make_code_object(all_instructions, line_table, qualified_prefix.unwrap_or("__main__".to_owned()), const_map, ensure_return))
 }
 
-pub fn compile_ast(ast: Vec<AST>, offset: usize, results: &mut Vec<CodeObject>, const_map: &mut BTreeMap<Const, usize>) -> CodeObject {
+pub fn compile_ast(ast: Vec<ASTNode>, offset: usize, results: &mut Vec<CodeObject>, const_map: &mut BTreeMap<Const, usize>) -> Result<CodeObject, CompileError> {
     compile_ast_internal(ast,offset,None,true,results,const_map)
 }
 
-fn make_code_object(instrs: Vec<Instruction>, name: String, const_map: &mut BTreeMap<Const, usize>, ensure_return: bool) -> CodeObject {
+//Removes the UnresolvedLine instructions of a complete code object, they become line table entries.
+//Jumps are moved to where their targets end up.
+fn resolve_lines(code: &mut CodeObject) {
+    //where each instruction goes, plus where the end of the code goes
+    let mut moved_to = vec![];
+    let mut kept = 0;
+    for instruction in code.instructions.iter() {
+        moved_to.push(kept);
+        if !matches!(instruction, Instruction::UnresolvedLine(_)) {
+            kept += 1;
+        }
+    }
+    moved_to.push(kept);
+
+    let forward = |from: usize, delta: usize| moved_to[from + 1 + delta] - moved_to[from] - 1;
+    let mut instructions = vec![];
+    let mut expression_lines = vec![];
+    for (index, instruction) in code.instructions.drain(..).enumerate() {
+        let resolved = match instruction {
+            Instruction::UnresolvedLine(line) => {
+                expression_lines.push(LineTableEntry { instruction: moved_to[index], line });
+                continue;
+            }
+            Instruction::JumpIfFalseAndPopStack(target) => Instruction::JumpIfFalseAndPopStack(moved_to[target]),
+            Instruction::JumpUnconditional(target) => Instruction::JumpUnconditional(moved_to[target]),
+            Instruction::ForIter(target) => Instruction::ForIter(moved_to[target]),
+            Instruction::SetupExcept(target) => Instruction::SetupExcept(moved_to[target]),
+            Instruction::SetupFinally(target) => Instruction::SetupFinally(moved_to[target]),
+            Instruction::JumpIfNotExceptionMatch(target) => Instruction::JumpIfNotExceptionMatch(moved_to[target]),
+            Instruction::BreakThroughBlocks { blocks, destination } => {
+                Instruction::BreakThroughBlocks { blocks, destination: moved_to[destination] }
+            }
+            Instruction::JumpForward(delta) => Instruction::JumpForward(forward(index, delta)),
+            Instruction::PopJumpForwardIfFalse(delta) => Instruction::PopJumpForwardIfFalse(forward(index, delta)),
+            other => other,
+        };
+        instructions.push(resolved);
+    }
+    code.instructions = instructions;
+
+    for entry in code.line_table.iter_mut() {
+        entry.instruction = moved_to[entry.instruction];
+    }
+    code.line_table.extend(expression_lines);
+    code.line_table.sort_by_key(|entry| entry.instruction);
+}
+
+fn make_code_object(instrs: Vec<Instruction>, mut line_table: Vec<LineTableEntry>, name: String, const_map: &mut BTreeMap<Const, usize>, ensure_return: bool) -> CodeObject {
 
     let mut vec_const = vec![];
     for (constval, index) in const_map.iter() {
@@ -552,18 +1329,32 @@ fn make_code_object(instrs: Vec<Instruction>, name: String, const_map: &mut BTre
     }
     vec_const.sort_unstable_by(|a, b| a.index.cmp(&b.index));
 
+    //nested blocks are compiled separately, so entries may come out of order
+    line_table.sort_by_key(|entry| entry.instruction);
+    //statements made up by the compiler, like the return in a lambda body, have no line
+    line_table.retain(|entry| entry.line != 0);
+
     let mut code_obj = CodeObject {
         instructions: instrs,
         names: vec![],
         params: vec![],
+        kwonly_count: 0,
+        varargs: false,
+        varkeywords: false,
+        cellvars: vec![],
+        freevars: vec![],
         consts: vec_const.into_iter().map(|x| x.constval).collect(),
         main: false,
-        objname: name
+        generator: false,
+        objname: name,
+        filename: String::new(),
+        line_table,
     };
 
     if ensure_return {
-        match code_obj.instructions.last().unwrap() {
-            Instruction::ReturnValue => { /*unchanged*/ },
+        //a body with only `pass` has no instructions at all
+        match code_obj.instructions.last() {
+            Some(Instruction::ReturnValue) => { /*unchanged*/ },
             _ => {
                 if !const_map.contains_key(&Const::None) {
                     const_map.insert(Const::None, const_map.len());
@@ -585,20 +1376,33 @@ mod tests {
     use crate::runtime::interpreter;
     use crate::runtime::vm::VM;
 
+    //the recursion limit is sized for the main thread of the binary, test threads have a smaller stack
     #[test]
     fn run_pytests() -> std::io::Result<()> {
+        std::thread::Builder::new()
+            .stack_size(8 << 20)
+            .spawn(run_all_pytests)?
+            .join()
+            .unwrap()
+    }
 
+    fn run_all_pytests() -> std::io::Result<()> {
         for entry in std::fs::read_dir("./pytests")? {
             let dir = entry?;
+            //directories hold the modules imported by the tests
+            if !dir.path().is_file() {
+                continue;
+            }
             println!("Loading source {:?}", dir.path());
             let source = std::fs::read_to_string(dir.path());
             let mut vm = VM::new();
             register_builtins(&mut vm);
-            loader::run_loader(&mut vm);
+            vm.search_path = vec![std::path::PathBuf::from("./pytests")];
+            loader::run_loader(&mut vm).unwrap();
             let tokens = tokenize(&source.unwrap()).unwrap();
-            let expr = parse_ast(tokens);
-            let program = compile(expr);
-            interpreter::execute_program(&mut vm, program);
+            let expr = parse_ast(tokens).unwrap();
+            let program = compile(expr).unwrap();
+            interpreter::execute_program(&mut vm, program).unwrap();
         }
         
         Ok(())
@@ -609,10 +1413,10 @@ mod tests {
         let mut vm = VM::new();
         register_builtins(&mut vm);
         let tokens = tokenize("1").unwrap();
-        let expr = parse_ast(tokens);
-        let program = compile_repl(expr);
-        interpreter::execute_program(&mut vm, program);
-        let stack_pop = vm.get_stack_offset(-1);
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile_repl(expr).unwrap();
+        interpreter::execute_program(&mut vm, program).unwrap();
+        let stack_pop = vm.top_stack();
         let stack_value = vm.get_raw_data_of_pyobj(stack_pop).take_int();
         assert_eq!(stack_value, 1);
     }
@@ -622,10 +1426,10 @@ mod tests {
         let mut vm = VM::new();
         register_builtins(&mut vm);
         let tokens = tokenize("1.0").unwrap();
-        let expr = parse_ast(tokens);
-        let program = compile_repl(expr);
-        interpreter::execute_program(&mut vm, program);
-        let stack_pop = vm.get_stack_offset(-1);
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile_repl(expr).unwrap();
+        interpreter::execute_program(&mut vm, program).unwrap();
+        let stack_pop = vm.top_stack();
         let stack_value = vm.get_raw_data_of_pyobj(stack_pop).take_float();
         assert_eq!(stack_value, 1.0);
     }
@@ -635,10 +1439,10 @@ mod tests {
         let mut vm = VM::new();
         register_builtins(&mut vm);
         let tokens = tokenize("True").unwrap();
-        let expr = parse_ast(tokens);
-        let program = compile_repl(expr);
-        interpreter::execute_program(&mut vm, program);
-        let stack_pop = vm.get_stack_offset(-1);
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile_repl(expr).unwrap();
+        interpreter::execute_program(&mut vm, program).unwrap();
+        let stack_pop = vm.top_stack();
         let stack_value = vm.get_raw_data_of_pyobj(stack_pop).take_int();
         assert_eq!(stack_value, 1);
     }
@@ -648,10 +1452,10 @@ mod tests {
         let mut vm = VM::new();
         register_builtins(&mut vm);
         let tokens = tokenize("False").unwrap();
-        let expr = parse_ast(tokens);
-        let program = compile_repl(expr);
-        interpreter::execute_program(&mut vm, program);
-        let stack_pop = vm.get_stack_offset(-1);
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile_repl(expr).unwrap();
+        interpreter::execute_program(&mut vm, program).unwrap();
+        let stack_pop = vm.top_stack();
         let stack_value = vm.get_raw_data_of_pyobj(stack_pop).take_int();
         assert_eq!(stack_value, 0);
     }
@@ -661,10 +1465,10 @@ mod tests {
         let mut vm = VM::new();
         register_builtins(&mut vm);
         let tokens = tokenize("1 + 1").unwrap();
-        let expr = parse_ast(tokens);
-        let program = compile_repl(expr);
-        interpreter::execute_program(&mut vm, program);
-        let stack_pop = vm.get_stack_offset(-1);
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile_repl(expr).unwrap();
+        interpreter::execute_program(&mut vm, program).unwrap();
+        let stack_pop = vm.top_stack();
         let stack_value = vm.get_raw_data_of_pyobj(stack_pop).take_int();
         assert_eq!(stack_value, 2);
     }
@@ -674,11 +1478,11 @@ mod tests {
         let mut vm = VM::new();
         register_builtins(&mut vm);
         let tokens = tokenize("1 + 3.5").unwrap();
-        let expr = parse_ast(tokens);
-        let program = compile_repl(expr);
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile_repl(expr).unwrap();
         println!("program: {:?}", program.code_objects);
-        interpreter::execute_program(&mut vm, program);
-        let stack_pop = vm.get_stack_offset(-1);
+        interpreter::execute_program(&mut vm, program).unwrap();
+        let stack_pop = vm.top_stack();
         let stack_value = vm.get_raw_data_of_pyobj(stack_pop).take_float();
         assert_eq!(stack_value, 4.5);
     }
@@ -690,10 +1494,10 @@ mod tests {
         let mut vm = VM::new();
         register_builtins(&mut vm);
         let tokens = tokenize("-(5.0 / 9.0)").unwrap();
-        let expr = parse_ast(tokens);
-        let program = compile_repl(expr);
-        interpreter::execute_program(&mut vm, program);
-        let stack_pop = vm.get_stack_offset(-1);
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile_repl(expr).unwrap();
+        interpreter::execute_program(&mut vm, program).unwrap();
+        let stack_pop = vm.top_stack();
         let stack_value = vm.get_raw_data_of_pyobj(stack_pop).take_float();
         assert_eq!(stack_value, expected_result);
     }
@@ -705,10 +1509,10 @@ mod tests {
         let mut vm = VM::new();
         register_builtins(&mut vm);
         let tokens = tokenize("-(5.0 / 9.0) * 32.0").unwrap();
-        let expr = parse_ast(tokens);
-        let program = compile_repl(expr);
-        interpreter::execute_program(&mut vm, program);
-        let stack_pop = vm.get_stack_offset(-1);
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile_repl(expr).unwrap();
+        interpreter::execute_program(&mut vm, program).unwrap();
+        let stack_pop = vm.top_stack();
         let stack_value = vm.get_raw_data_of_pyobj(stack_pop).take_float();
         assert_eq!(stack_value, expected_result);
     }
@@ -720,10 +1524,10 @@ mod tests {
         let mut vm = VM::new();
         register_builtins(&mut vm);
         let tokens = tokenize("1.0 - (5.0 / 9.0)").unwrap();
-        let expr = parse_ast(tokens);
-        let program = compile_repl(expr);
-        interpreter::execute_program(&mut vm, program);
-        let stack_pop = vm.get_stack_offset(-1);
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile_repl(expr).unwrap();
+        interpreter::execute_program(&mut vm, program).unwrap();
+        let stack_pop = vm.top_stack();
         let stack_value = vm.get_raw_data_of_pyobj(stack_pop).take_float();
         assert_eq!(stack_value, expected_result);
     }
@@ -734,10 +1538,10 @@ mod tests {
         let mut vm = VM::new();
         register_builtins(&mut vm);
         let tokens = tokenize("(-(5.0 / 9.0) * 32.0) / (1.0 - (5.0 / 9.0))").unwrap();
-        let expr = parse_ast(tokens);
-        let program = compile_repl(expr);
-        interpreter::execute_program(&mut vm, program);
-        let stack_pop = vm.get_stack_offset(-1);
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile_repl(expr).unwrap();
+        interpreter::execute_program(&mut vm, program).unwrap();
+        let stack_pop = vm.top_stack();
         let stack_value = vm.get_raw_data_of_pyobj(stack_pop).take_float();
         assert_eq!(stack_value, expected_result);
     }
@@ -750,10 +1554,10 @@ mod tests {
         register_builtins(&mut vm);
         let tokens =
             tokenize("cos(sin(-(5.0 / 9.0) * 32.0)) / tanh(cos(1.0) - (5.0 / 9.0))").unwrap();
-        let expr = parse_ast(tokens);
-        let program = compile_repl(expr);
-        interpreter::execute_program(&mut vm, program);
-        let stack_pop = vm.get_stack_offset(-1);
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile_repl(expr).unwrap();
+        interpreter::execute_program(&mut vm, program).unwrap();
+        let stack_pop = vm.top_stack();
         let stack_value = vm.get_raw_data_of_pyobj(stack_pop).take_float();
         assert_eq!(stack_value, expected_result);
     }
@@ -764,10 +1568,10 @@ mod tests {
         let mut vm = VM::new();
         register_builtins(&mut vm);
         let tokens = tokenize("sin(1.0)").unwrap();
-        let expr = parse_ast(tokens);
-        let program = compile_repl(expr);
-        interpreter::execute_program(&mut vm, program);
-        let stack_pop = vm.get_stack_offset(-1);
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile_repl(expr).unwrap();
+        interpreter::execute_program(&mut vm, program).unwrap();
+        let stack_pop = vm.top_stack();
         let stack_value = vm.get_raw_data_of_pyobj(stack_pop).take_float();
         assert_eq!(stack_value, expected_result);
     }
@@ -777,10 +1581,11 @@ mod tests {
         let mut vm = VM::new();
         register_builtins(&mut vm);
         let tokens = tokenize("x = 1 + 2").unwrap();
-        let expr = parse_ast(tokens);
-        let program = compile_repl(expr);
-        interpreter::execute_program(&mut vm, program);
-        let x = vm.get_local(0).unwrap();
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile_repl(expr).unwrap();
+        interpreter::execute_program(&mut vm, program).unwrap();
+        //module level names are globals
+        let x = vm.find_in_module(MAIN_MODULE, "x").unwrap();
         let stack_value = vm.get_raw_data_of_pyobj(x).take_int();
         assert_eq!(stack_value, 3);
     }
@@ -790,11 +1595,11 @@ mod tests {
         let mut vm = VM::new();
         register_builtins(&mut vm);
         let tokens = tokenize("\"abc\" + 'cde'").unwrap();
-        let expr = parse_ast(tokens);
-        let program = compile_repl(expr);
-        interpreter::execute_program(&mut vm, program);
-        let stack_top = vm.get_stack_offset(-1);
-        let stack_value = vm.get_raw_data_of_pyobj(stack_top).take_string();
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile_repl(expr).unwrap();
+        interpreter::execute_program(&mut vm, program).unwrap();
+        let stack_top = vm.top_stack();
+        let stack_value = vm.get_raw_data_of_pyobj(stack_top).take_string().clone();
         assert_eq!(stack_value, "abccde");
     }
 
@@ -803,10 +1608,10 @@ mod tests {
         let mut vm = VM::new();
         register_builtins(&mut vm);
         let tokens = tokenize("True and False").unwrap();
-        let expr = parse_ast(tokens);
-        let program = compile_repl(expr);
-        interpreter::execute_program(&mut vm, program);
-        let stack_top = vm.get_stack_offset(-1);
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile_repl(expr).unwrap();
+        interpreter::execute_program(&mut vm, program).unwrap();
+        let stack_top = vm.top_stack();
         let stack_value = vm.get_raw_data_of_pyobj(stack_top).take_int();
         assert_eq!(stack_value, 0);
     }
@@ -817,10 +1622,10 @@ mod tests {
         let mut vm = VM::new();
         register_builtins(&mut vm);
         let tokens = tokenize("\"abc\".lower").unwrap();
-        let expr = parse_ast(tokens);
-        let program =  compile_repl(expr);
-        interpreter::execute_program(&mut vm, program);
-        let stack_top = vm.get_stack_offset(-1);
+        let expr = parse_ast(tokens).unwrap();
+        let program =  compile_repl(expr).unwrap();
+        interpreter::execute_program(&mut vm, program).unwrap();
+        let stack_top = vm.top_stack();
         let stack_value = vm.get_pyobj_byaddr(stack_top);
         match &stack_value.structure {
             PyObjectStructure::BoundMethod { .. } => {
@@ -837,14 +1642,14 @@ mod tests {
         let mut vm = VM::new();
         register_builtins(&mut vm);
         let tokens = tokenize("__builtins__.float").unwrap();
-        let expr = parse_ast(tokens);
-        let program = compile_repl(expr);
-        interpreter::execute_program(&mut vm, program);
-        let stack_top = vm.get_stack_offset(-1);
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile_repl(expr).unwrap();
+        interpreter::execute_program(&mut vm, program).unwrap();
+        let stack_top = vm.top_stack();
         let stack_value = vm.get_pyobj_byaddr(stack_top);
         match &stack_value.structure {
             PyObjectStructure::Type {
-                name, functions:_, supertype: _
+                name, ..
             } => {
                 if name == "float" {
                     Ok(())                   
@@ -868,9 +1673,294 @@ class SomeClass:
     def __init__(self):        
         self.x = 1
 ").unwrap();
-        let expr = parse_ast(tokens);
-        let program = compile_repl(expr);
-        interpreter::execute_program(&mut vm, program);
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile_repl(expr).unwrap();
+        interpreter::execute_program(&mut vm, program).unwrap();
         Ok(())
     }
+
+    #[test]
+    fn unsupported_operator_is_compile_error() {
+        let tokens = tokenize("1 << 2").unwrap();
+        let expr = parse_ast(tokens).unwrap();
+        assert!(compile(expr).is_err());
+    }
+
+    fn nested_code_object(code: &CodeObject) -> &CodeObject {
+        code.consts
+            .iter()
+            .find_map(|constval| match constval {
+                Const::CodeObject(nested) => Some(nested.as_ref()),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn captured_variables_become_cells() {
+        let tokens = tokenize("
+def outer(a):
+    b = 1
+    def inner():
+        return a + b
+    return inner
+").unwrap();
+        let program = compile(parse_ast(tokens).unwrap()).unwrap();
+        let outer = nested_code_object(&program.code_objects[0]);
+        let inner = nested_code_object(outer);
+        assert_eq!(outer.cellvars, vec!["a".to_string(), "b".to_string()]);
+        assert!(outer.freevars.is_empty());
+        assert_eq!(inner.freevars, vec!["a".to_string(), "b".to_string()]);
+        assert!(outer.instructions.contains(&Instruction::BuildClosure { cells: vec![0, 1] }));
+        assert!(inner.instructions.contains(&Instruction::LoadDeref(0)));
+    }
+
+    #[test]
+    fn module_level_names_are_globals() {
+        let tokens = tokenize("x = 1\ndef f():\n    return x").unwrap();
+        let program = compile(parse_ast(tokens).unwrap()).unwrap();
+        let module = &program.code_objects[0];
+        assert_eq!(module.instructions[1], Instruction::StoreGlobal(0));
+        let function = nested_code_object(module);
+        assert_eq!(function.instructions[0], Instruction::LoadGlobal(0));
+    }
+
+    #[test]
+    fn nonlocal_without_binding_is_compile_error() {
+        let tokens = tokenize("def f():\n    nonlocal x\n    x = 1").unwrap();
+        match compile(parse_ast(tokens).unwrap()) {
+            Err(error) => assert_eq!(error.message, "no binding for nonlocal 'x' found"),
+            Ok(_) => panic!("nonlocal without binding should not compile"),
+        }
+    }
+
+    #[test]
+    fn uncaught_exception_is_runtime_error() {
+        let mut vm = VM::new();
+        register_builtins(&mut vm);
+        let tokens = tokenize("x = 1\nundefined_name").unwrap();
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile(expr).unwrap();
+        let error = interpreter::execute_program(&mut vm, program).unwrap_err();
+        assert_eq!(error.exception_type, "NameError");
+        assert_eq!(error.message, "name 'undefined_name' is not defined");
+    }
+
+    #[test]
+    fn line_table_maps_instructions_to_lines() {
+        let tokens = tokenize("x = 1\n\ny = x").unwrap();
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile(expr).unwrap();
+        let main = &program.code_objects[0];
+        assert_eq!(main.line_for(0), Some(1));
+        assert_eq!(main.line_for(main.instructions.len() - 1), Some(3));
+    }
+
+    #[test]
+    fn uncaught_exception_records_traceback() {
+        let mut vm = VM::new();
+        register_builtins(&mut vm);
+        let tokens = tokenize("def f(items):\n    return items[10]\n\nf([1])").unwrap();
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile(expr).unwrap();
+        let error = interpreter::execute_program(&mut vm, program).unwrap_err();
+        let traceback: Vec<(String, Option<usize>)> = vm
+            .get_traceback(error.exception)
+            .into_iter()
+            .map(|entry| (entry.function_name, entry.line))
+            .collect();
+        assert_eq!(traceback, vec![("<module>".to_string(), Some(4)), ("f".to_string(), Some(2))]);
+        assert_eq!(error.exception_type, "IndexError");
+    }
+
+    #[test]
+    fn calls_and_subscripts_record_their_own_line() {
+        let mut vm = VM::new();
+        register_builtins(&mut vm);
+        let source = "f = lambda v: v[5]\nx = [1]\ni = 0\nwhile i < len(\n  x):\n    i = i + (x[0] if x else\n        x[1])\ny = [\n  x[0],\n  f(x)\n]";
+        let program = compile(parse_ast(tokenize(source).unwrap()).unwrap()).unwrap();
+        let error = interpreter::execute_program(&mut vm, program).unwrap_err();
+        let traceback: Vec<(String, Option<usize>)> = vm
+            .get_traceback(error.exception)
+            .into_iter()
+            .map(|entry| (entry.function_name, entry.line))
+            .collect();
+        assert_eq!(traceback, vec![("<module>".to_string(), Some(10)), ("<lambda>".to_string(), Some(1))]);
+        assert_eq!(error.exception_type, "IndexError");
+    }
+
+    #[test]
+    fn raised_class_records_traceback() {
+        let mut vm = VM::new();
+        register_builtins(&mut vm);
+        let tokens = tokenize("def inner():\n    raise ValueError\n\ndef outer():\n    inner()\n\nouter()").unwrap();
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile(expr).unwrap();
+        let error = interpreter::execute_program(&mut vm, program).unwrap_err();
+        let traceback: Vec<(String, Option<usize>)> = vm
+            .get_traceback(error.exception)
+            .into_iter()
+            .map(|entry| (entry.function_name, entry.line))
+            .collect();
+        let expected = vec![("<module>".to_string(), Some(7)), ("outer".to_string(), Some(5)), ("inner".to_string(), Some(2))];
+        assert_eq!(traceback, expected);
+        assert_eq!(error.exception_type, "ValueError");
+    }
+
+    #[test]
+    fn unmatched_handler_does_not_repeat_traceback_entry() {
+        let mut vm = VM::new();
+        register_builtins(&mut vm);
+        let tokens = tokenize("try:
+    raise ValueError('x')
+except TypeError:
+    pass").unwrap();
+        let expr = parse_ast(tokens).unwrap();
+        let program = compile(expr).unwrap();
+        let error = interpreter::execute_program(&mut vm, program).unwrap_err();
+        let lines: Vec<Option<usize>> = vm
+            .get_traceback(error.exception)
+            .into_iter()
+            .map(|entry| entry.line)
+            .collect();
+        assert_eq!(lines, vec![Some(2)]);
+    }
+
+    #[test]
+    fn heap_limit_raises_memory_error() {
+        let mut vm = VM::new();
+        register_builtins(&mut vm);
+        vm.heap_limit = Some(vm.heap_stats().bytes + 100_000);
+        //the list is freed when the exception leaves fill, so the handler has room to run
+        let tokens = tokenize("def fill():
+    x = []
+    while True:
+        x.append([1, 2, 3, 4, 5, 6, 7, 8])
+caught = False
+try:
+    fill()
+except MemoryError:
+    caught = True
+x = []
+while True:
+    x.append(1)").unwrap();
+        let program = compile(parse_ast(tokens).unwrap()).unwrap();
+        let error = interpreter::execute_program(&mut vm, program).unwrap_err();
+        assert_eq!(error.exception_type, "MemoryError");
+        let caught = vm.find_in_module(MAIN_MODULE, "caught").unwrap();
+        assert_eq!(caught, vm.builtin_type_addrs.true_val);
+    }
+
+    #[test]
+    fn instruction_budget_raises_timeout_error() {
+        let mut vm = VM::new();
+        register_builtins(&mut vm);
+        vm.set_instruction_budget(Some(10_000));
+        //the handler runs, but after its grace instructions every instruction raises, so the loop stops
+        let tokens = tokenize("caught = 0
+cleaned = False
+while True:
+    try:
+        try:
+            while True:
+                pass
+        finally:
+            cleaned = True
+    except TimeoutError:
+        caught = caught + 1").unwrap();
+        let program = compile(parse_ast(tokens).unwrap()).unwrap();
+        let error = interpreter::execute_program(&mut vm, program).unwrap_err();
+        assert_eq!(error.exception_type, "TimeoutError");
+        let caught = vm.find_in_module(MAIN_MODULE, "caught").unwrap();
+        assert_eq!(vm.get_raw_data_of_pyobj(caught).take_int(), 1);
+        let cleaned = vm.find_in_module(MAIN_MODULE, "cleaned").unwrap();
+        assert_eq!(cleaned, vm.builtin_type_addrs.true_val);
+    }
+
+    #[test]
+    fn deadline_and_interrupt_stop_the_program() {
+        let mut vm = VM::new();
+        register_builtins(&mut vm);
+        vm.set_timeout(Some(std::time::Duration::from_millis(10)));
+        let tokens = tokenize("while True:
+    try:
+        while True:
+            pass
+    except TimeoutError:
+        pass").unwrap();
+        let program = compile(parse_ast(tokens).unwrap()).unwrap();
+        let error = interpreter::execute_program(&mut vm, program).unwrap_err();
+        assert_eq!(error.exception_type, "TimeoutError");
+
+        vm.clear_stacks();
+        vm.set_timeout(None);
+        let interrupt = vm.interrupt_handle();
+        let setter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            interrupt.store(true, std::sync::atomic::Ordering::Relaxed);
+        });
+        //KeyboardInterrupt is not an Exception
+        let tokens = tokenize("try:
+    while True:
+        pass
+except Exception:
+    pass").unwrap();
+        let program = compile(parse_ast(tokens).unwrap()).unwrap();
+        let error = interpreter::execute_program(&mut vm, program).unwrap_err();
+        setter.join().unwrap();
+        assert_eq!(error.exception_type, "KeyboardInterrupt");
+    }
+
+    #[test]
+    fn yield_outside_function_is_compile_error() {
+        for source in ["yield 1", "class A:\n    x = yield"] {
+            let tokens = tokenize(source).unwrap();
+            match compile(parse_ast(tokens).unwrap()) {
+                Err(error) => assert_eq!(error.message, "'yield' outside function"),
+                Ok(_) => panic!("yield outside a function should not compile"),
+            }
+        }
+    }
+
+    #[test]
+    fn return_outside_function_is_compile_error() {
+        for source in ["return", "if x:\n    return 1"] {
+            let tokens = tokenize(source).unwrap();
+            match compile(parse_ast(tokens).unwrap()) {
+                Err(error) => assert_eq!(error.message, "'return' outside function"),
+                Ok(_) => panic!("return outside a function should not compile"),
+            }
+        }
+        let tokens = tokenize("def f():\n    return").unwrap();
+        assert!(compile(parse_ast(tokens).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn break_outside_loop_is_compile_error() {
+        for source in ["break", "if x:\n    break", "while True:\n    def f():\n        break"] {
+            let tokens = tokenize(source).unwrap();
+            match compile(parse_ast(tokens).unwrap()) {
+                Err(error) => assert_eq!(error.message, "'break' outside loop"),
+                Ok(_) => panic!("break outside a loop should not compile"),
+            }
+        }
+        let tokens = tokenize("while True:\n    if x:\n        break").unwrap();
+        assert!(compile(parse_ast(tokens).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn only_functions_with_yield_are_generators() {
+        let tokens = tokenize("def gen():\n    def helper():\n        return 1\n    yield helper()").unwrap();
+        let program = compile(parse_ast(tokens).unwrap()).unwrap();
+        let gen = program.code_objects[0].consts.iter().find_map(|constval| match constval {
+            Const::CodeObject(code) => Some(code),
+            _ => None,
+        }).unwrap();
+        assert!(gen.generator);
+        let helper = gen.consts.iter().find_map(|constval| match constval {
+            Const::CodeObject(code) => Some(code),
+            _ => None,
+        }).unwrap();
+        assert!(!helper.generator);
+    }
 }
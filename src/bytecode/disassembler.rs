@@ -0,0 +1,225 @@
+use crate::bytecode::program::*;
+use crate::commons::float::Float;
+use std::collections::BTreeSet;
+
+//Human readable listing of a code object, used by `horse --dis` and the dis module.
+//Each line has the source line (where it changes), `>>` on jump targets, the offset, the opcode,
+//its operand and, in parentheses, what the operand refers to. The code objects in the consts
+//(functions, classes, lambdas, comprehensions) are listed after it, recursively.
+pub fn disassemble(codeobj: &CodeObject) -> String {
+    let mut listing = String::new();
+    disassemble_into(codeobj, &mut listing);
+    return listing;
+}
+
+fn disassemble_into(codeobj: &CodeObject, listing: &mut String) {
+    let targets = jump_targets(codeobj);
+    let mut last_line = None;
+    for (offset, instruction) in codeobj.instructions.iter().enumerate() {
+        let line = codeobj.line_for(offset);
+        let line_column = match line {
+            Some(line) if line_changes(codeobj, offset) && last_line != Some(line) => line.to_string(),
+            _ => String::new(),
+        };
+        last_line = line.or(last_line);
+        let marker = if targets.contains(&offset) { ">>" } else { "" };
+        let (operand, meaning) = describe(codeobj, offset, instruction);
+        let mut arguments = vec![operand];
+        if let Some(meaning) = meaning.filter(|meaning| !meaning.is_empty()) {
+            arguments.push(format!("({})", meaning));
+        }
+        arguments.retain(|argument| !argument.is_empty());
+        let text = format!("{:>4} {:>3} {:>5} {:<24}{}", line_column, marker, offset, opcode_name(instruction), arguments.join(" "));
+        listing.push_str(text.trim_end());
+        listing.push('\n');
+    }
+
+    for constval in codeobj.consts.iter() {
+        if let Const::CodeObject(nested) = constval {
+            listing.push_str(&format!("\nDisassembly of {}:\n", code_object_repr(nested)));
+            disassemble_into(nested, listing);
+        }
+    }
+}
+
+//A new line number is only shown where an entry of the line table starts
+fn line_changes(codeobj: &CodeObject, offset: usize) -> bool {
+    codeobj.line_table.iter().any(|entry| entry.instruction == offset)
+}
+
+//The variant name, without the operands Debug prints after it
+fn opcode_name(instruction: &Instruction) -> String {
+    let debug = format!("{:?}", instruction);
+    let end = debug.find(['(', ' ']).unwrap_or(debug.len());
+    debug[..end].to_string()
+}
+
+fn jump_target(offset: usize, instruction: &Instruction) -> Option<usize> {
+    match instruction {
+        Instruction::JumpIfFalseAndPopStack(target)
+        | Instruction::JumpUnconditional(target)
+        | Instruction::ForIter(target)
+        | Instruction::SetupExcept(target)
+        | Instruction::SetupFinally(target)
+        | Instruction::JumpIfNotExceptionMatch(target) => Some(*target),
+        Instruction::BreakThroughBlocks { destination, .. } => Some(*destination),
+        Instruction::JumpForward(delta) | Instruction::PopJumpForwardIfFalse(delta) => Some(offset + 1 + delta),
+        _ => None,
+    }
+}
+
+fn jump_targets(codeobj: &CodeObject) -> BTreeSet<usize> {
+    codeobj
+        .instructions
+        .iter()
+        .enumerate()
+        .filter_map(|(offset, instruction)| jump_target(offset, instruction))
+        .collect()
+}
+
+fn name_at(names: &[String], index: usize) -> String {
+    names.get(index).cloned().unwrap_or_else(|| String::from("?"))
+}
+
+//Cells are numbered with the cellvars first, then the freevars
+fn cell_name(codeobj: &CodeObject, index: usize) -> String {
+    codeobj
+        .cellvars
+        .iter()
+        .chain(codeobj.freevars.iter())
+        .nth(index)
+        .cloned()
+        .unwrap_or_else(|| String::from("?"))
+}
+
+fn const_at(codeobj: &CodeObject, index: usize) -> String {
+    codeobj.consts.get(index).map(const_repr).unwrap_or_else(|| String::from("?"))
+}
+
+fn describe(codeobj: &CodeObject, offset: usize, instruction: &Instruction) -> (String, Option<String>) {
+    if let Some(target) = jump_target(offset, instruction) {
+        let operand = match instruction {
+            Instruction::BreakThroughBlocks { blocks, .. } => blocks.to_string(),
+            Instruction::JumpForward(delta) | Instruction::PopJumpForwardIfFalse(delta) => delta.to_string(),
+            _ => target.to_string(),
+        };
+        return (operand, Some(format!("to {}", target)));
+    }
+    match instruction {
+        Instruction::LoadConst(index) => (index.to_string(), Some(const_at(codeobj, *index))),
+        Instruction::StoreName(index)
+        | Instruction::StoreAttr(index)
+        | Instruction::LoadName(index)
+        | Instruction::LoadGlobal(index)
+        | Instruction::StoreGlobal(index) => (index.to_string(), Some(name_at(&codeobj.names, *index))),
+        Instruction::LoadDeref(index) | Instruction::StoreDeref(index) => {
+            (index.to_string(), Some(cell_name(codeobj, *index)))
+        }
+        Instruction::BuildClosure { cells } => {
+            let indexes: Vec<String> = cells.iter().map(|cell| cell.to_string()).collect();
+            let names: Vec<String> = cells.iter().map(|cell| cell_name(codeobj, *cell)).collect();
+            (indexes.join(", "), Some(names.join(", ")))
+        }
+        Instruction::CallFunctionKw { number_arguments, keywords } => {
+            (number_arguments.to_string(), Some(keywords.join(", ")))
+        }
+        Instruction::MakeClass { number_bases: number }
+        | Instruction::CallFunction { number_arguments: number }
+        | Instruction::BuildList { number_elements: number }
+        | Instruction::BuildTuple { number_elements: number }
+        | Instruction::BuildTupleUnpack { number_elements: number }
+        | Instruction::BuildMapUnpackWithCall { number_entries: number }
+        | Instruction::UnpackSequence { number_elements: number }
+        | Instruction::BuildMap { number_entries: number }
+        | Instruction::BuildSet { number_elements: number }
+        | Instruction::UnresolvedBreakThroughBlocks(number)
+        | Instruction::UnresolvedLine(number) => (number.to_string(), None),
+        Instruction::LoadAttr(name)
+        | Instruction::ImportName(name)
+        | Instruction::ImportFrom(name)
+        | Instruction::UnresolvedStoreAttr(name)
+        | Instruction::UnresolvedStoreName(name)
+        | Instruction::UnresolvedLoadName(name)
+        | Instruction::UnresolvedLoadGlobal(name)
+        | Instruction::UnresolvedStoreGlobal(name)
+        | Instruction::UnresolvedLoadDeref(name)
+        | Instruction::UnresolvedStoreDeref(name) => (String::new(), Some(name.clone())),
+        Instruction::UnresolvedClosure { code, .. } => (code.to_string(), Some(const_at(codeobj, *code))),
+        _ => (String::new(), None),
+    }
+}
+
+//Code made up by the compiler, like the body of a comprehension, may have no lines
+fn code_object_repr(codeobj: &CodeObject) -> String {
+    match codeobj.line_table.first() {
+        Some(entry) => format!("<code object {}, file \"{}\", line {}>", codeobj.objname, codeobj.filename, entry.line),
+        None => format!("<code object {}, file \"{}\">", codeobj.objname, codeobj.filename),
+    }
+}
+
+//Constants are shown the way Python would write them
+fn const_repr(constval: &Const) -> String {
+    match constval {
+        Const::Integer(value) => value.to_string(),
+        Const::Float(Float(value)) => format!("{:?}", value),
+        Const::Boolean(true) => String::from("True"),
+        Const::Boolean(false) => String::from("False"),
+        Const::String(value) => {
+            let escaped = value.replace('\\', "\\\\").replace('\'', "\\'").replace('\n', "\\n");
+            format!("'{}'", escaped)
+        }
+        Const::CodeObject(codeobj) => code_object_repr(codeobj),
+        Const::None => String::from("None"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::lexer::tokenize;
+    use crate::ast::parser::parse_ast;
+    use crate::bytecode::compiler::compile_file;
+
+    fn disassemble_source(source: &str) -> Vec<String> {
+        let program = compile_file(parse_ast(tokenize(source).unwrap()).unwrap(), "test.py").unwrap();
+        disassemble(&program.code_objects[0]).lines().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn shows_lines_offsets_and_resolved_operands() {
+        let listing = disassemble_source("x = 'a'\nprint(x)\n");
+        assert_eq!(
+            listing,
+            vec![
+                "   1         0 LoadConst               0 ('a')",
+                "             1 StoreGlobal             0 (x)",
+                "   2         2 LoadGlobal              1 (print)",
+                "             3 LoadGlobal              0 (x)",
+                "             4 CallFunction            1",
+                "             5 PopTop",
+                "             6 LoadConst               1 (None)",
+                "             7 ReturnValue",
+            ]
+        );
+    }
+
+    #[test]
+    fn marks_jump_targets() {
+        let listing = disassemble_source("while x:\n    x = x - 1\n");
+        let jump = listing.iter().find(|line| line.contains("JumpIfFalseAndPopStack")).unwrap();
+        let target = jump.rsplit("to ").next().unwrap().trim_end_matches(')');
+        //the offset column is right after the line number and the jump target marker
+        let target_line = listing.iter().find(|line| line[9..14].trim() == target).unwrap();
+        assert_eq!(target_line[4..8].trim(), ">>");
+        assert!(!jump.contains(">>"));
+    }
+
+    #[test]
+    fn lists_nested_code_objects() {
+        let listing = disassemble_source("def f(a):\n    def g():\n        return a\n    return g\n");
+        assert!(listing.iter().any(|line| line.contains("LoadConst") && line.contains("(<code object f, file \"test.py\", line 2>)")));
+        assert!(listing.contains(&String::from("Disassembly of <code object f, file \"test.py\", line 2>:")));
+        assert!(listing.contains(&String::from("Disassembly of <code object f.g, file \"test.py\", line 3>:")));
+        assert!(listing.iter().any(|line| line.contains("LoadDeref") && line.ends_with("(a)")));
+    }
+}
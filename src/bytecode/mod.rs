@@ -1,2 +1,4 @@
+pub mod cache;
 pub mod compiler;
+pub mod disassembler;
 pub mod program;
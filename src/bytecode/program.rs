@@ -6,7 +6,8 @@ pub enum Const {
     Float(Float),
     Boolean(bool),
     String(String),
-    CodeObject(CodeObject),
+    //boxed, code objects are much bigger than the other constants
+    CodeObject(Box<CodeObject>),
     None
 }
 
@@ -14,15 +15,32 @@ pub enum Const {
 pub enum Instruction {
     LoadConst(usize),
     LoadAttr(String),
-    MakeFunction(bool),
-    MakeClass,
+    //TOS is the qualified name, TOS1 the closure tuple, TOS2 the code object,
+    //TOS3 a dict with the keyword-only defaults and TOS4 a list with the positional defaults
+    MakeFunction,
+    //TOS is the qualified name, TOS1 the closure tuple, TOS2 the code object, then the bases
+    MakeClass { number_bases: usize },
     StoreName(usize),
     StoreAttr(usize),
     LoadName(usize),
+    //Module level names, and names declared `global` in a function
     LoadGlobal(usize),
+    StoreGlobal(usize),
+    //Cells are numbered with the cellvars first, then the freevars
+    LoadDeref(usize),
+    StoreDeref(usize),
+    //Pushes a tuple with these cells of the current frame, they become the closure of the next function made
+    BuildClosure { cells: Vec<usize> },
     CallFunction { number_arguments: usize },
+    //The last keywords.len() arguments are passed by keyword, in the same order as the names
+    CallFunctionKw { number_arguments: usize, keywords: Vec<String> },
+    //TOS is a dict with the keyword arguments, TOS1 a tuple with the positional ones, TOS2 the callable
+    CallFunctionEx,
     JumpIfFalseAndPopStack(usize),
     JumpUnconditional(usize),
+    //Relative to the next instruction, for jumps inside expressions that don't know their own offset
+    JumpForward(usize),
+    PopJumpForwardIfFalse(usize),
     ReturnValue,
     PopTop,
     BinaryAdd,
@@ -36,26 +54,130 @@ pub enum Instruction {
     CompareLessThan,
     CompareEquals,
     CompareNotEquals,
+    //TOS is the container, TOS1 the item. Calls __contains__ on the container.
+    CompareIn,
+    CompareNotIn,
     BuildList { number_elements: usize },
+    BuildTuple { number_elements: usize },
+    //Pops number_elements iterables and pushes a tuple with all their elements, for `f(*a, *b)`
+    BuildTupleUnpack { number_elements: usize },
+    //Pops number_entries dicts and pushes them merged into one, for `f(**a, **b)`.
+    //Repeated keys raise TypeError naming the callable, which is below the positional arguments tuple.
+    BuildMapUnpackWithCall { number_entries: usize },
+    //Replaces TOS, an iterable, with its elements. The first element ends up on top.
+    UnpackSequence { number_elements: usize },
+    //Pops number_entries key/value pairs, pushed in key, value order
+    BuildMap { number_entries: usize },
+    BuildSet { number_elements: usize },
     IndexAccess,
+    //TOS is the index, TOS1 the indexed object and TOS2 the value. Calls __setitem__.
+    StoreIndex,
     ForIter(usize),
+    //Suspends the generator, TOS is the yielded value. When resumed, TOS is the value sent in.
+    YieldValue,
+    //TOS is the value to send, TOS1 the sub-iterator. Yields whatever the sub-iterator yields,
+    //running again until it raises StopIteration, then pushes its value.
+    YieldFrom,
+    //Imports the module with this dotted name, and its parent packages, and pushes it
+    ImportName(String),
+    //TOS is a module. Pushes its attribute with this name, importing it if it's a submodule.
+    ImportFrom(String),
     Raise,
+    //Raises TOS again without adding the current line to its traceback
+    Reraise,
+    //Pushes an exception handler block. The operand is where the handler code starts.
+    SetupExcept(usize),
+    //Pushes a finally block. The operand is where the finally body starts.
+    SetupFinally(usize),
+    PopBlock,
+    //Marks that the finally body is being entered normally, not because of an exception or return
+    BeginFinally,
+    //End of the finally body: resumes whatever was happening before it (exception, return, break)
+    EndFinally,
+    //TOS is an exception type or a tuple of them, TOS1 is the exception. Pops TOS and jumps if the exception doesn't match.
+    JumpIfNotExceptionMatch(usize),
+    //A break that has to leave some try blocks before jumping out of the loop
+    BreakThroughBlocks { blocks: usize, destination: usize },
     UnresolvedBreak,
+    UnresolvedBreakThroughBlocks(usize),
     UnresolvedStoreAttr(String),
     UnresolvedStoreName(String),
-    UnresolvedLoadName(String)
+    UnresolvedLoadName(String),
+    //names declared `global` or `nonlocal` in the function being compiled
+    UnresolvedLoadGlobal(String),
+    UnresolvedStoreGlobal(String),
+    UnresolvedLoadDeref(String),
+    UnresolvedStoreDeref(String),
+    //The closure of the code object at this const index. Its free variables are only known
+    //once the whole module is compiled, then this becomes a BuildClosure.
+    UnresolvedClosure { code: usize, class_body: bool },
+    //The source line of the next instructions, for calls and subscripts on a later line than
+    //their statement. Removed once the code object is complete, see compiler::resolve_lines.
+    UnresolvedLine(usize),
+}
+
+//Instructions from `instruction` onwards (until the next entry) come from this source line
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LineTableEntry {
+    pub instruction: usize,
+    pub line: usize,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct CodeObject {
     pub instructions: Vec<Instruction>,
     pub names: Vec<String>,
+    //positional parameters first, then the keyword-only ones, then the *args and **kwargs names
     pub params: Vec<String>,
+    pub kwonly_count: usize,
+    pub varargs: bool,
+    pub varkeywords: bool,
+    //locals captured by nested functions, they live in cells instead of the local slots
+    pub cellvars: Vec<String>,
+    //variables of enclosing functions used here, the cells come from the closure
+    pub freevars: Vec<String>,
     pub consts: Vec<Const>,
     pub objname: String,
-    pub main: bool
+    pub main: bool,
+    //calling it returns a generator instead of running the body
+    pub generator: bool,
+    pub filename: String,
+    //sorted by instruction index
+    pub line_table: Vec<LineTableEntry>,
 }
 
+impl CodeObject {
+    //Parameters that can be passed by position
+    pub fn positional_count(&self) -> usize {
+        self.params.len() - self.kwonly_count - self.varargs as usize - self.varkeywords as usize
+    }
+
+
+    //Source line of the instruction at the given index
+    pub fn line_for(&self, instruction: usize) -> Option<usize> {
+        self.line_table
+            .iter()
+            .take_while(|entry| entry.instruction <= instruction)
+            .last()
+            .map(|entry| entry.line)
+    }
+
+    //Sets the file name on this code object and on every nested one (functions, classes)
+    pub fn set_filename(&mut self, filename: &str) {
+        self.filename = filename.to_string();
+        for constval in self.consts.iter_mut() {
+            if let Const::CodeObject(codeobj) = constval {
+                codeobj.set_filename(filename);
+            }
+        }
+    }
+}
+
+//Bump it whenever the instructions or the way things compile change,
+//so the .hpyc caches made by older versions are compiled again (see bytecode::cache)
+pub const BYTECODE_VERSION: u64 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Program {
     //bytecode compatibility version
     //needs to recompile if bytecode has different version
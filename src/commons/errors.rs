@@ -0,0 +1,180 @@
+use crate::ast::lexer::Span;
+use crate::runtime::memory::MemoryAddress;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexError {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    pub message: String,
+}
+
+//An uncaught Python exception. The exception value is kept so embedders can inspect it
+//while the VM is still alive, the type name and message are already rendered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeError {
+    pub exception_type: String,
+    pub message: String,
+    pub exception: MemoryAddress,
+}
+
+//A bug in horse that stopped the code running, like a panic in the VM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InternalError {
+    pub message: String,
+}
+
+//A Value that is not of the Rust type it was converted to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversionError {
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HorseError {
+    Lex(LexError),
+    Parse(ParseError),
+    Compile(CompileError),
+    Runtime(RuntimeError),
+    Conversion(ConversionError),
+    Internal(InternalError),
+}
+
+impl LexError {
+    pub fn at(message: impl Into<String>, span: Span) -> LexError {
+        LexError { message: message.into(), span: Some(span) }
+    }
+}
+
+impl ParseError {
+    pub fn at(message: impl Into<String>, span: Span) -> ParseError {
+        ParseError { message: message.into(), span: Some(span) }
+    }
+}
+
+impl CompileError {
+    pub fn new(message: impl Into<String>) -> CompileError {
+        CompileError { message: message.into() }
+    }
+}
+
+impl HorseError {
+    //Process exit code used by main.rs, so scripts can tell syntax errors from runtime failures
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            HorseError::Lex(_) | HorseError::Parse(_) | HorseError::Compile(_) => 2,
+            HorseError::Runtime(_) | HorseError::Conversion(_) | HorseError::Internal(_) => 1,
+        }
+    }
+}
+
+fn fmt_syntax_error(f: &mut fmt::Formatter<'_>, message: &str, span: Option<Span>) -> fmt::Result {
+    match span {
+        Some(span) => write!(f, "SyntaxError: {} (line {}, column {})", message, span.line, span.column),
+        None => write!(f, "SyntaxError: {}", message),
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_syntax_error(f, &self.message, self.span)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_syntax_error(f, &self.message, self.span)
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CompileError: {}", self.message)
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.message.is_empty() {
+            write!(f, "{}", self.exception_type)
+        } else {
+            write!(f, "{}: {}", self.exception_type, self.message)
+        }
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ConversionError: expected {}, found {}", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+impl fmt::Display for HorseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HorseError::Lex(e) => e.fmt(f),
+            HorseError::Parse(e) => e.fmt(f),
+            HorseError::Compile(e) => e.fmt(f),
+            HorseError::Runtime(e) => e.fmt(f),
+            HorseError::Conversion(e) => e.fmt(f),
+            HorseError::Internal(e) => e.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for InternalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "InternalError: {}", self.message)
+    }
+}
+
+impl std::error::Error for HorseError {}
+
+impl From<LexError> for HorseError {
+    fn from(e: LexError) -> Self {
+        HorseError::Lex(e)
+    }
+}
+
+impl From<ParseError> for HorseError {
+    fn from(e: ParseError) -> Self {
+        HorseError::Parse(e)
+    }
+}
+
+impl From<CompileError> for HorseError {
+    fn from(e: CompileError) -> Self {
+        HorseError::Compile(e)
+    }
+}
+
+impl From<RuntimeError> for HorseError {
+    fn from(e: RuntimeError) -> Self {
+        HorseError::Runtime(e)
+    }
+}
+
+impl From<ConversionError> for HorseError {
+    fn from(e: ConversionError) -> Self {
+        HorseError::Conversion(e)
+    }
+}
+
+impl From<InternalError> for HorseError {
+    fn from(e: InternalError) -> Self {
+        HorseError::Internal(e)
+    }
+}
@@ -1 +1,2 @@
-pub mod float;
\ No newline at end of file
+pub mod errors;
+pub mod float;
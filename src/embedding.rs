@@ -0,0 +1,485 @@
+use crate::ast::{lexer, parser};
+use crate::builtin_types;
+use crate::builtin_types::dict_type::{dict_set, new_dict};
+use crate::bytecode::{cache, compiler, disassembler};
+use crate::commons::errors::*;
+use crate::commons::float::Float;
+use crate::runtime::datamodel::*;
+use crate::runtime::memory::*;
+use crate::runtime::vm::*;
+use crate::runtime::{importer, interpreter};
+use crate::names::Names;
+use crate::value::Value;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+//A Python interpreter with the builtins and the standard library loaded.
+//Code runs in the __main__ module, whose globals are kept from one run to the next.
+pub struct Interpreter {
+    pub(crate) vm: VM,
+}
+
+impl Interpreter {
+    pub fn new() -> Result<Interpreter, HorseError> {
+        let mut vm = VM::new();
+        builtin_types::register_builtins(&mut vm);
+        builtin_types::loader::run_loader(&mut vm)?;
+        vm.search_path = importer::search_path(Path::new("."));
+        Ok(Interpreter { vm })
+    }
+
+    //Runs statements, like Python's exec()
+    pub fn exec(&mut self, source: &str) -> Result<(), HorseError> {
+        self.exec_as(source, "<string>")
+    }
+
+    //Like exec, with the file name shown in tracebacks
+    pub fn exec_as(&mut self, source: &str, filename: &str) -> Result<(), HorseError> {
+        self.run(source, filename, None)
+    }
+
+    //Runs the source of a script, its bytecode is cached next to it
+    pub fn exec_file(&mut self, source: &str, path: &Path) -> Result<(), HorseError> {
+        let cache_file = cache::cache_file_for(path);
+        self.run(source, &path.to_string_lossy(), cache_file.as_deref())
+    }
+
+    //Every run starts on a fresh stack, whatever the previous one left on it, and with new time limits
+    fn run(&mut self, source: &str, filename: &str, cache_file: Option<&Path>) -> Result<(), HorseError> {
+        self.guarded(|this| {
+            let program = cache::compile_cached(source, filename, cache_file)?;
+            this.vm.clear_stacks();
+            this.vm.start_limits();
+            interpreter::execute_program(&mut this.vm, program)?;
+            Ok(())
+        })
+    }
+
+    //Evaluates a single expression, like Python's eval()
+    pub fn eval(&mut self, source: &str) -> Result<Value, HorseError> {
+        self.guarded(|this| {
+            let ast = parser::parse_ast(lexer::tokenize(source)?)?;
+            if ast.len() != 1 || !compiler::ends_with_expression(&ast) {
+                return Err(CompileError::new("eval() expects a single expression").into());
+            }
+            let program = compiler::compile_repl_file(ast, "<string>")?;
+            this.vm.clear_stacks();
+            this.vm.start_limits();
+            interpreter::execute_program(&mut this.vm, program)?;
+            let result = this.vm.pop_stack();
+            this.to_value(result)
+        })
+    }
+
+    //Runs an input of an interactive session. When it ends with an expression whose value is not None,
+    //the value is bound to `_` and its repr is returned.
+    pub fn exec_interactive(&mut self, input: &str) -> Result<Option<String>, HorseError> {
+        self.guarded(|this| {
+            let ast = parser::parse_ast(lexer::tokenize(input)?)?;
+            let shows_value = compiler::ends_with_expression(&ast);
+            let program = compiler::compile_repl(ast)?;
+            this.vm.clear_stacks();
+            this.vm.start_limits();
+            interpreter::execute_program(&mut this.vm, program)?;
+            if !shows_value {
+                return Ok(None);
+            }
+            let result = this.vm.pop_stack();
+            if result == this.vm.special_values[&SpecialValue::NoneValue] {
+                return Ok(None);
+            }
+            this.vm.set_in_module(MAIN_MODULE, "_", result);
+            let repr = this.vm.call_method(result, "__repr__", PositionalParameters::empty());
+            Ok(repr.map(|(addr, _)| this.vm.get_raw_data_of_pyobj(addr).take_string().clone()))
+        })
+    }
+
+    //A panic in the VM is a bug in horse, not in the code it ran: it's returned as an InternalError,
+    //and the next run starts on a fresh stack with the same globals
+    fn guarded<T>(&mut self, run: impl FnOnce(&mut Interpreter) -> Result<T, HorseError>) -> Result<T, HorseError> {
+        match panic::catch_unwind(AssertUnwindSafe(|| run(self))) {
+            Ok(result) => result,
+            Err(payload) => {
+                let message = match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+                    (Some(message), _) => message.to_string(),
+                    (_, Some(message)) => message.clone(),
+                    _ => String::from("the interpreter panicked"),
+                };
+                Err(InternalError { message }.into())
+            }
+        }
+    }
+
+    pub fn get_global(&self, name: &str) -> Result<Value, HorseError> {
+        match self.vm.find_in_module(MAIN_MODULE, name) {
+            Some(addr) => self.to_value(addr),
+            None => Err(self.error("NameError", format!("name '{}' is not defined", name))),
+        }
+    }
+
+    pub fn set_global(&mut self, name: &str, value: impl Into<Value>) -> Result<(), HorseError> {
+        let addr = self.to_object(&value.into())?;
+        self.vm.set_in_module(MAIN_MODULE, name, addr);
+        Ok(())
+    }
+
+    //Calls a global of __main__, a function or a class, with positional arguments
+    pub fn call(&mut self, name: &str, args: &[Value]) -> Result<Value, HorseError> {
+        self.guarded(|this| {
+            let function = match this.vm.find_in_module(MAIN_MODULE, name) {
+                Some(function) => function,
+                None => return Err(this.error("NameError", format!("name '{}' is not defined", name))),
+            };
+            this.vm.clear_stacks();
+            this.vm.start_limits();
+            let params = args.iter().map(|arg| this.to_object(arg)).collect::<Result<Vec<_>, _>>()?;
+            //nothing else keeps the arguments alive while the function runs, the callee doesn't count its own references
+            for param in params.iter() {
+                this.vm.increase_refcount(*param);
+            }
+            this.vm.pin(&params);
+            let arguments = params.clone();
+            let (result, popped_frame) = this.vm.run_function(PositionalParameters { params, kwargs: vec![] }, function, None);
+            this.vm.unpin(arguments.len());
+            for argument in arguments {
+                this.vm.decrease_refcount(argument);
+            }
+            match popped_frame.exception {
+                Some(exception) => Err(this.vm.exception_to_error(exception).into()),
+                None => this.to_value(result),
+            }
+        })
+    }
+
+    //sys.argv
+    pub fn set_argv(&mut self, argv: &[String]) {
+        builtin_types::sys_module::set_argv(&self.vm, argv);
+    }
+
+    //Imports look for modules in this directory first, then in $HORSEPATH
+    pub fn set_script_dir(&mut self, dir: &Path) {
+        self.vm.search_path = importer::search_path(dir);
+    }
+
+    //The file of a module to run as __main__, like `horse -m` does
+    pub fn find_main_module(&self, name: &str) -> Result<PathBuf, RuntimeError> {
+        importer::find_main_module(&self.vm, name).map_err(|exception| self.vm.exception_to_error(exception))
+    }
+
+    //Approximate bytes the objects can take before MemoryError is raised, None for no limit
+    pub fn set_heap_limit(&mut self, limit: Option<usize>) {
+        self.vm.heap_limit = limit;
+    }
+
+    pub fn heap_stats(&self) -> HeapStats {
+        self.vm.heap_stats()
+    }
+
+    //Instructions each exec, eval or call can run before TimeoutError is raised, None for no budget
+    pub fn set_instruction_budget(&self, budget: Option<u64>) {
+        self.vm.set_instruction_budget(budget);
+    }
+
+    //How long each exec, eval or call can run before TimeoutError is raised, None for no deadline
+    pub fn set_timeout(&self, timeout: Option<Duration>) {
+        self.vm.set_timeout(timeout);
+    }
+
+    //Storing true in it stops the running code with KeyboardInterrupt, from any thread
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        self.vm.interrupt_handle()
+    }
+
+    //The exit status of an uncaught SystemExit: 0 for None, the number for an int, and 1 for
+    //anything else, which format_error shows. None for other errors.
+    pub fn system_exit_status(&self, error: &HorseError) -> Option<i32> {
+        let code = match error {
+            HorseError::Runtime(e) => self.system_exit_code(e)?,
+            _ => return None,
+        };
+        match code {
+            Some(code) => match &*self.vm.get_raw_data_of_pyobj(code) {
+                BuiltInTypeData::Int(status) => Some(*status as i32),
+                _ => Some(1),
+            },
+            None => Some(0),
+        }
+    }
+
+    //The code of a SystemExit (None inside when it has none), None for other exceptions
+    fn system_exit_code(&self, error: &RuntimeError) -> Option<Option<MemoryAddress>> {
+        let system_exit = self.vm.find_in_module(BUILTIN_MODULE, "SystemExit").unwrap();
+        if !self.vm.exception_matches(error.exception, system_exit) {
+            return None;
+        }
+        match self.vm.get_pyobj_byaddr(error.exception).properties.get("code") {
+            Some(code) if *code != self.vm.special_values[&SpecialValue::NoneValue] => Some(Some(*code)),
+            _ => Some(None),
+        }
+    }
+
+    //The error the way CPython reports it when nothing catches it: syntax errors point at the offending
+    //line and column, runtime errors show their traceback, and a SystemExit only shows a code that is
+    //not an int. Must be called before running more code.
+    pub fn format_error(&self, error: &HorseError, source: &str, filename: &str) -> String {
+        let (message, span) = match error {
+            HorseError::Lex(e) => (&e.message, e.span),
+            HorseError::Parse(e) => (&e.message, e.span),
+            HorseError::Runtime(e) => {
+                return match self.system_exit_code(e) {
+                    //the message of a SystemExit is str(code)
+                    Some(Some(code)) if !matches!(&*self.vm.get_raw_data_of_pyobj(code), BuiltInTypeData::Int(_)) => {
+                        format!("{}\n", e.message)
+                    }
+                    Some(_) => String::new(),
+                    None => self.vm.format_traceback(e.exception),
+                };
+            }
+            _ => return format!("{}\n", error),
+        };
+        let mut text = String::new();
+        if let Some(span) = span {
+            text.push_str(&format!("  File \"{}\", line {}\n", filename, span.line));
+            if let Some(line) = source.lines().nth(span.line - 1) {
+                let trimmed = line.trim_start();
+                let indent = line.len() - trimmed.len();
+                text.push_str(&format!("    {}\n", trimmed.trim_end()));
+                text.push_str(&format!("    {}^\n", " ".repeat(span.column.saturating_sub(indent + 1))));
+            }
+        }
+        text.push_str(&format!("SyntaxError: {}\n", message));
+        text
+    }
+
+    //The bytecode the source compiles to, as `dis.dis` shows it
+    pub fn disassemble(&self, source: &str, filename: &str) -> Result<String, HorseError> {
+        let program = cache::compile_cached(source, filename, None)?;
+        Ok(disassembler::disassemble(&program.code_objects[0]))
+    }
+
+    //What the code can use, for completion
+    pub fn names(&self) -> Names {
+        Names::of(&self.vm)
+    }
+
+    fn error(&self, exception_type: &str, message: String) -> HorseError {
+        let exception = self.vm.create_exception(exception_type, message);
+        HorseError::Runtime(self.vm.exception_to_error(exception))
+    }
+
+    fn to_value(&self, addr: MemoryAddress) -> Result<Value, HorseError> {
+        self.to_value_inside(addr, &mut vec![])
+    }
+
+    //`containers` are the lists, tuples and dicts being converted, that contain this object
+    fn to_value_inside(&self, addr: MemoryAddress, containers: &mut Vec<MemoryAddress>) -> Result<Value, HorseError> {
+        let vm = &self.vm;
+        if addr == vm.special_values[&SpecialValue::NoneValue] {
+            return Ok(Value::None);
+        }
+        if addr == vm.builtin_type_addrs.true_val || addr == vm.builtin_type_addrs.false_val {
+            return Ok(Value::Bool(addr == vm.builtin_type_addrs.true_val));
+        }
+        //the elements are copied out, the object must not stay borrowed while they are converted
+        let (elements, entries, type_addr) = {
+            let pyobj = vm.get_pyobj_byaddr(addr);
+            match &pyobj.structure {
+                PyObjectStructure::Object { raw_data, .. } => match raw_data {
+                    BuiltInTypeData::Int(value) => return Ok(Value::Int(*value)),
+                    BuiltInTypeData::Float(value) => return Ok(Value::Float(value.0)),
+                    BuiltInTypeData::String(value) => return Ok(Value::Str(value.clone())),
+                    BuiltInTypeData::List(elements) | BuiltInTypeData::Tuple(elements) => {
+                        (elements.clone(), vec![], pyobj.type_addr)
+                    }
+                    BuiltInTypeData::Dict(dict) => {
+                        let entries = dict.entries.iter().map(|entry| (entry.key, entry.value)).collect();
+                        (vec![], entries, pyobj.type_addr)
+                    }
+                    _ => return Err(self.cannot_convert(pyobj.type_addr)),
+                },
+                _ => return Err(self.cannot_convert(pyobj.type_addr)),
+            }
+        };
+        if containers.contains(&addr) {
+            return Err(self.error("ValueError", String::from("can't convert a container that contains itself")));
+        }
+        containers.push(addr);
+        let converted = if type_addr == vm.builtin_type_addrs.dict {
+            let mut dict = vec![];
+            for (key, value) in entries {
+                dict.push((self.to_value_inside(key, containers)?, self.to_value_inside(value, containers)?));
+            }
+            Value::Dict(dict)
+        } else {
+            let mut values = vec![];
+            for element in elements {
+                values.push(self.to_value_inside(element, containers)?);
+            }
+            if type_addr == vm.builtin_type_addrs.tuple {
+                Value::Tuple(values)
+            } else {
+                Value::List(values)
+            }
+        };
+        containers.pop();
+        Ok(converted)
+    }
+
+    fn cannot_convert(&self, type_addr: MemoryAddress) -> HorseError {
+        let type_name = self.vm.get_type_name(type_addr);
+        self.error("TypeError", format!("can't convert a {} object to a Value", type_name))
+    }
+
+    //No garbage is collected while the objects are allocated, only between instructions
+    fn to_object(&self, value: &Value) -> Result<MemoryAddress, HorseError> {
+        let vm = &self.vm;
+        let types = &vm.builtin_type_addrs;
+        let addr = match value {
+            Value::None => vm.special_values[&SpecialValue::NoneValue],
+            Value::Bool(true) => types.true_val,
+            Value::Bool(false) => types.false_val,
+            Value::Int(value) => vm.allocate_type_byaddr_raw(types.int, BuiltInTypeData::Int(*value)),
+            Value::Float(value) => vm.allocate_type_byaddr_raw(types.float, BuiltInTypeData::Float(Float(*value))),
+            Value::Str(value) => vm.allocate_type_byaddr_raw(types.string, BuiltInTypeData::String(value.clone())),
+            Value::List(values) | Value::Tuple(values) => {
+                let elements = values.iter().map(|value| self.to_object(value)).collect::<Result<Vec<_>, _>>()?;
+                for element in elements.iter() {
+                    vm.increase_refcount(*element);
+                }
+                match value {
+                    Value::List(_) => vm.allocate_type_byaddr_raw(types.list, BuiltInTypeData::List(elements)),
+                    _ => vm.allocate_type_byaddr_raw(types.tuple, BuiltInTypeData::Tuple(elements)),
+                }
+            }
+            Value::Dict(entries) => {
+                let dict = new_dict(vm);
+                for (key, value) in entries {
+                    let (key, value) = (self.to_object(key)?, self.to_object(value)?);
+                    dict_set(vm, dict, key, value).map_err(|exception| vm.exception_to_error(exception))?;
+                }
+                dict
+            }
+        };
+        Ok(addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::convert::{TryFrom, TryInto};
+
+    #[test]
+    fn evaluates_and_keeps_globals() {
+        let mut interpreter = Interpreter::new().unwrap();
+        interpreter.exec("def add(a, b):\n    return a + b\nitems = [1, 'two', (3.5, None)]").unwrap();
+        assert_eq!(interpreter.eval("add(1, 2)").unwrap(), Value::Int(3));
+        assert_eq!(
+            interpreter.get_global("items").unwrap(),
+            Value::List(vec![Value::Int(1), Value::Str("two".into()), Value::Tuple(vec![Value::Float(3.5), Value::None])])
+        );
+        let sum: i128 = interpreter.call("add", &[Value::Int(40), Value::Int(2)]).unwrap().try_into().unwrap();
+        assert_eq!(sum, 42);
+        interpreter.exec("def count(n):\n    i = 0\n    while i < n:\n        i = i + 1\n    return i").unwrap();
+        assert_eq!(interpreter.call("count", &[Value::Int(100)]).unwrap(), Value::Int(100));
+        let joined: String = interpreter.call("add", &["a".into(), "b".into()]).unwrap().try_into().unwrap();
+        assert_eq!(joined, "ab");
+    }
+
+    #[test]
+    fn sets_globals_from_rust_values() {
+        let mut interpreter = Interpreter::new().unwrap();
+        let mut prices = HashMap::new();
+        prices.insert("apple".to_string(), 1.5);
+        interpreter.set_global("prices", prices).unwrap();
+        interpreter.set_global("counts", vec![1i128, 2, 3]).unwrap();
+        interpreter.exec("total = prices['apple'] * len(counts)").unwrap();
+        let total: f64 = interpreter.get_global("total").unwrap().try_into().unwrap();
+        assert_eq!(total, 4.5);
+        let prices: HashMap<String, f64> = interpreter.eval("prices").unwrap().try_into().unwrap();
+        assert_eq!(prices["apple"], 1.5);
+    }
+
+    #[test]
+    fn reports_errors() {
+        let mut interpreter = Interpreter::new().unwrap();
+        match interpreter.eval("[1][5]").unwrap_err() {
+            HorseError::Runtime(error) => assert_eq!(error.exception_type, "IndexError"),
+            error => panic!("unexpected error {:?}", error),
+        }
+        assert!(matches!(interpreter.eval("x = 1"), Err(HorseError::Compile(_))));
+        match interpreter.call("missing", &[]).unwrap_err() {
+            HorseError::Runtime(error) => assert_eq!(error.exception_type, "NameError"),
+            error => panic!("unexpected error {:?}", error),
+        }
+        interpreter.exec("class A:\n    pass\nloop = [1]\nloop.append(loop)").unwrap();
+        match interpreter.call("A", &[]).unwrap_err() {
+            HorseError::Runtime(error) => assert_eq!(error.message, "can't convert a A object to a Value"),
+            error => panic!("unexpected error {:?}", error),
+        }
+        assert!(interpreter.get_global("loop").is_err());
+        let error = String::try_from(interpreter.eval("1").unwrap()).unwrap_err();
+        assert_eq!(HorseError::from(error).exit_code(), 1);
+    }
+
+    #[test]
+    fn formats_errors_without_printing_them() {
+        let mut interpreter = Interpreter::new().unwrap();
+        let error = interpreter.exec_as("def f():\n    return [][0]\nf()", "app.py").unwrap_err();
+        assert_eq!(
+            interpreter.format_error(&error, "", "app.py"),
+            "Traceback (most recent call last):\n  File \"app.py\", line 3, in <module>\n  File \"app.py\", line 2, in f\nIndexError: list index out of range\n"
+        );
+        assert_eq!(interpreter.system_exit_status(&error), None);
+        let source = "x = 1 +";
+        let error = interpreter.exec(source).unwrap_err();
+        assert!(interpreter.format_error(&error, source, "<string>").starts_with("  File \"<string>\", line 1\n    x = 1 +\n"));
+        for (source, status, text) in [("sys.exit('bye')", 1, "bye\n"), ("sys.exit(3)", 3, ""), ("sys.exit()", 0, "")] {
+            let error = interpreter.exec(&format!("import sys\n{}", source)).unwrap_err();
+            assert_eq!(interpreter.system_exit_status(&error), Some(status));
+            assert_eq!(interpreter.format_error(&error, source, "<string>"), text);
+        }
+    }
+
+    #[test]
+    fn truncated_code_is_a_syntax_error() {
+        let mut interpreter = Interpreter::new().unwrap();
+        for source in ["f(", "x =", "l[", "obj."] {
+            assert!(matches!(interpreter.exec(source), Err(HorseError::Parse(_))), "{}", source);
+        }
+    }
+
+    #[test]
+    fn panics_are_returned_as_internal_errors() {
+        let mut interpreter = Interpreter::new().unwrap();
+        interpreter.exec("x = [1]\ndef f():\n    panic('in f')").unwrap();
+        match interpreter.exec("panic('boom')").unwrap_err() {
+            HorseError::Internal(error) => assert_eq!(error.message, "boom"),
+            error => panic!("unexpected error {:?}", error),
+        }
+        assert!(matches!(interpreter.call("f", &[]), Err(HorseError::Internal(_))));
+        assert!(matches!(interpreter.eval("f()"), Err(HorseError::Internal(_))));
+        assert_eq!(interpreter.eval("x").unwrap(), Value::List(vec![Value::Int(1)]));
+    }
+
+    #[test]
+    fn every_run_gets_new_limits() {
+        let mut interpreter = Interpreter::new().unwrap();
+        interpreter.exec("def count(n):\n    i = 0\n    while i < n:\n        i = i + 1\n    return i").unwrap();
+        interpreter.set_instruction_budget(Some(5_000));
+        interpreter.set_timeout(Some(std::time::Duration::from_millis(50)));
+        for _ in 0..3 {
+            assert_eq!(interpreter.call("count", &[Value::Int(100)]).unwrap(), Value::Int(100));
+            std::thread::sleep(std::time::Duration::from_millis(30));
+        }
+        match interpreter.eval("count(100000)").unwrap_err() {
+            HorseError::Runtime(error) => assert_eq!(error.exception_type, "TimeoutError"),
+            error => panic!("unexpected error {:?}", error),
+        }
+        assert_eq!(interpreter.eval("count(10)").unwrap(), Value::Int(10));
+    }
+}
@@ -0,0 +1,20 @@
+//horse as a library: an Interpreter to run Python code from Rust, and the Values passed in and out of it.
+//The tokens of the source are there too, for tools like the REPL of the horse binary.
+//The compiler and the VM stay private, so they can change without breaking the crates using horse.
+mod ast;
+mod commons;
+mod builtin_types;
+mod bytecode;
+#[macro_use]
+mod runtime;
+mod embedding;
+mod names;
+mod syntax;
+mod value;
+
+pub use crate::commons::errors::{CompileError, ConversionError, HorseError, InternalError, LexError, ParseError, RuntimeError};
+pub use crate::embedding::Interpreter;
+pub use crate::names::Names;
+pub use crate::runtime::memory::{HeapStats, KindStats};
+pub use crate::syntax::{keywords, tokenize, SourceToken, TokenKind};
+pub use crate::value::Value;
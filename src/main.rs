@@ -1,70 +1,311 @@
-mod ast;
-mod commons;
-mod builtin_types;
-mod bytecode;
-#[macro_use]
-mod runtime;
+mod repl_helper;
+
+use horse::{HorseError, Interpreter};
+use repl_helper::{needs_more_input, ReplHelper};
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 use std::env;
 use std::fs;
-use crate::ast::lexer;
-use crate::ast::parser;
+use std::io::{self, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+const USAGE: &str = "usage: horse [-i] [--dis] [--heap-stats] [--timeout secs] [--max-instructions n] [-c cmd | -m mod | file | -] [arg] ...";
+
+//What to run, taken from the command line
+enum Target {
+    Script(String),
+    //-c: the program is the argument
+    Command(String),
+    //-m: a module found in the search path, run as __main__
+    Module(String),
+    //`-`, or no script with stdin not being a terminal
+    Stdin,
+    Repl,
+}
+
+struct Options {
+    target: Target,
+    //-i: start the REPL after running the target, with its globals
+    interactive: bool,
+    //--dis: print the bytecode instead of running it
+    disassemble: bool,
+    //--heap-stats: print how many objects of each kind were allocated, on exit
+    heap_stats: bool,
+    //--timeout: seconds the program can run before TimeoutError is raised
+    timeout: Option<Duration>,
+    //--max-instructions: instructions the program can run before TimeoutError is raised
+    max_instructions: Option<u64>,
+    //sys.argv. The first one is the script, `-c`, `-`, or "" for the REPL
+    argv: Vec<String>,
+}
+
+//Options go before the target, everything after the target is passed to the program in sys.argv
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut interactive = false;
+    let mut disassemble = false;
+    let mut heap_stats = false;
+    let mut timeout = None;
+    let mut max_instructions = None;
+    //the option that takes the next argument as its value
+    let mut pending: Option<&str> = None;
+    for (index, arg) in args.iter().enumerate() {
+        let rest = &args[index + 1..];
+        match pending.take() {
+            Some("--timeout") => {
+                match arg.parse::<f64>() {
+                    Ok(seconds) if seconds >= 0.0 && seconds.is_finite() => {
+                        timeout = Some(Duration::from_secs_f64(seconds))
+                    }
+                    _ => return Err(format!("Invalid number of seconds for --timeout: {}", arg)),
+                }
+                continue;
+            }
+            Some(_) => {
+                match arg.parse::<u64>() {
+                    Ok(count) => max_instructions = Some(count),
+                    _ => return Err(format!("Invalid number of instructions for --max-instructions: {}", arg)),
+                }
+                continue;
+            }
+            None => {}
+        }
+        let (target, argv) = match arg.as_str() {
+            "-i" => {
+                interactive = true;
+                continue;
+            }
+            "--dis" => {
+                disassemble = true;
+                continue;
+            }
+            "--heap-stats" => {
+                heap_stats = true;
+                continue;
+            }
+            "--timeout" | "--max-instructions" => {
+                pending = Some(arg.as_str());
+                continue;
+            }
+            "-c" | "-m" => {
+                let value = match rest.first() {
+                    Some(value) => value.clone(),
+                    None => return Err(format!("Argument expected for the {} option", arg)),
+                };
+                let mut argv = vec![if arg == "-c" { arg.clone() } else { value.clone() }];
+                argv.extend(rest[1..].iter().cloned());
+                let target = if arg == "-c" { Target::Command(value) } else { Target::Module(value) };
+                (target, argv)
+            }
+            "-" => (Target::Stdin, args[index..].to_vec()),
+            option if option.starts_with('-') => return Err(format!("Unknown option: {}", option)),
+            script => (Target::Script(script.to_string()), args[index..].to_vec()),
+        };
+        return Ok(Options { target, interactive, disassemble, heap_stats, timeout, max_instructions, argv });
+    }
+    if let Some(option) = pending {
+        return Err(format!("Argument expected for the {} option", option));
+    }
+    let target = if interactive || io::stdin().is_terminal() { Target::Repl } else { Target::Stdin };
+    Ok(Options {
+        target,
+        interactive,
+        disassemble,
+        heap_stats,
+        timeout,
+        max_instructions,
+        argv: vec![String::new()],
+    })
+}
+
+//Reports the error and returns the exit status it should cause
+fn report_error(interpreter: &Interpreter, error: &HorseError, source: &str, filename: &str) -> i32 {
+    eprint!("{}", interpreter.format_error(error, source, filename));
+    interpreter.system_exit_status(error).unwrap_or_else(|| error.exit_code())
+}
+
+//Every exit after the program started running goes through here, so --heap-stats is printed however it ends
+fn exit(interpreter: &Interpreter, heap_stats: bool, status: i32) -> ! {
+    if heap_stats {
+        eprint!("{}", interpreter.heap_stats());
+    }
+    process::exit(status);
+}
+
+//The interrupt handle of the VM, for the SIGINT handler
+static INTERRUPT: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+extern "C" fn handle_sigint(_signal: libc::c_int) {
+    if let Some(interrupt) = INTERRUPT.get() {
+        interrupt.store(true, Ordering::Relaxed);
+    }
+}
+
+//Ctrl-C raises KeyboardInterrupt in the running program instead of killing the process.
+//At the REPL prompt rustyline reads Ctrl-C as a key, so this only matters while code runs.
+fn install_interrupt_handler(interpreter: &Interpreter) {
+    if INTERRUPT.set(interpreter.interrupt_handle()).is_err() {
+        return;
+    }
+    unsafe {
+        libc::signal(libc::SIGINT, handle_sigint as *const () as libc::sighandler_t);
+    }
+}
+
+fn read_file(path: &Path) -> String {
+    match fs::read_to_string(path) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("horse: can't open file '{}': {}", path.display(), e);
+            process::exit(2);
+        }
+    }
+}
 
 fn main() {
-    let mut vm = runtime::vm::VM::new();
-    builtin_types::register_builtins(&mut vm);
-    builtin_types::loader::run_loader(&mut vm);
     let args: Vec<String> = env::args().collect();
+    let options = match parse_args(&args[1..]) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
 
-    if args.len() == 2 {
-        let input =
-            fs::read_to_string(args[1].clone()).expect(&format!("Could not read file {}", args[1]));
-        let tokens = lexer::tokenize(input.as_str());
-        //println!("Tokens: {:?}", tokens);
-        let ast = parser::parse_ast(tokens.unwrap());
+    let mut interpreter = match Interpreter::new() {
+        Ok(interpreter) => interpreter,
+        Err(e) => {
+            eprintln!("Failed to load the standard library: {}", e);
+            process::exit(e.exit_code());
+        }
+    };
+    let mut argv = options.argv;
 
-        let program = bytecode::compiler::compile(ast);
-        runtime::interpreter::execute_program(&mut vm, program);
-       
-        return;
+    //the source, its file name, and the file it was read from, next to which its bytecode is cached
+    let program = match options.target {
+        Target::Script(path) => {
+            let script_dir = match Path::new(&path).parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            interpreter.set_script_dir(script_dir);
+            Some((read_file(Path::new(&path)), path.clone(), Some(PathBuf::from(path))))
+        }
+        Target::Command(command) => Some((command, String::from("<string>"), None)),
+        Target::Module(name) => {
+            let file = match interpreter.find_main_module(&name) {
+                Ok(file) => file,
+                Err(e) => {
+                    eprintln!("horse: {}", e.message);
+                    process::exit(1);
+                }
+            };
+            let filename = file.to_string_lossy().to_string();
+            argv[0] = filename.clone();
+            Some((read_file(&file), filename, Some(file)))
+        }
+        Target::Stdin => {
+            let mut input = String::new();
+            if let Err(e) = io::stdin().read_to_string(&mut input) {
+                eprintln!("horse: can't read stdin: {}", e);
+                process::exit(2);
+            }
+            Some((input, String::from("<stdin>"), None))
+        }
+        Target::Repl => None,
+    };
+    interpreter.set_argv(&argv);
+    install_interrupt_handler(&interpreter);
+    //every run, like each REPL input after -i, gets the whole budget and timeout
+    interpreter.set_timeout(options.timeout);
+    interpreter.set_instruction_budget(options.max_instructions);
+
+    if let Some((source, filename, file)) = program {
+        if options.disassemble {
+            match interpreter.disassemble(&source, &filename) {
+                Ok(bytecode) => print!("{}", bytecode),
+                Err(e) => process::exit(report_error(&interpreter, &e, &source, &filename)),
+            }
+            return;
+        }
+        let result = match file {
+            Some(file) => interpreter.exec_file(&source, &file),
+            None => interpreter.exec_as(&source, &filename),
+        };
+        if let Err(e) = result {
+            let status = report_error(&interpreter, &e, &source, &filename);
+            if !options.interactive {
+                exit(&interpreter, options.heap_stats, status);
+            }
+        }
+        if !options.interactive {
+            exit(&interpreter, options.heap_stats, 0);
+        }
     }
 
+    let status = repl(&mut interpreter);
+    exit(&interpreter, options.heap_stats, status);
+}
+
+//Returns the exit status
+fn repl(interpreter: &mut Interpreter) -> i32 {
     println!(
         "horse 0.0.1 (rustc {})",
         rustc_version_runtime::version()
     );
     println!("No help, copyright or licensing commands available. You're on your own.");
-    let mut rl = Editor::<()>::new();
+    let mut rl = Editor::<ReplHelper>::new();
+    rl.set_helper(Some(ReplHelper::new()));
     if rl.load_history("history.txt").is_err() {
         println!("No previous history.");
     }
+    let mut status = 0;
+    //lines of a statement that is not finished yet
+    let mut buffer = String::new();
     loop {
-        let readline = rl.readline(">>> ");
+        let prompt = if buffer.is_empty() { ">>> " } else { "... " };
+        if let Some(helper) = rl.helper_mut() {
+            helper.refresh(interpreter);
+        }
+        let readline = rl.readline(prompt);
         match readline {
-            Ok(input) => {
-                rl.add_history_entry(input.as_str());
-                if input == "\n" {
+            Ok(line) => {
+                //when stdin is not a terminal the line still has its newline
+                buffer.push_str(line.trim_end_matches(&['\n', '\r'][..]));
+                buffer.push('\n');
+                if needs_more_input(&buffer) {
+                    continue;
+                }
+                let input = std::mem::take(&mut buffer);
+                if input.trim().is_empty() {
                     continue;
                 }
-                if input == "exit\n" {
-                    return;
+                rl.add_history_entry(input.trim_end());
+                if input.trim() == "exit" {
+                    break;
                 }
-                let tokens = lexer::tokenize(input.as_str());
-                let ast = parser::parse_ast(tokens.unwrap());
-                let program = bytecode::compiler::compile_repl(ast);
-                runtime::interpreter::execute_program(&mut vm, program);
-                let result_addr = vm.get_stack_offset(-1);
-                let result_string = vm.call_method(result_addr, "__repr__", runtime::vm::PositionalParameters::empty());
-                match result_string {
-                    None => {}
-                    Some((addr, _)) => {
-                        let pyobj_str = vm.get_raw_data_of_pyobj(addr).take_string();
-                        println!("{}", pyobj_str);
+                //a Ctrl-C typed while no code was running doesn't interrupt this input
+                interpreter.interrupt_handle().store(false, Ordering::Relaxed);
+                //a bug in the VM is an InternalError, which ends this input but not the session
+                match interpreter.exec_interactive(input.as_str()) {
+                    Ok(Some(repr)) => println!("{}", repr),
+                    Ok(None) => {}
+                    Err(e) => {
+                        eprint!("{}", interpreter.format_error(&e, input.as_str(), "<stdin>"));
+                        if let Some(exit_status) = interpreter.system_exit_status(&e) {
+                            status = exit_status;
+                            break;
+                        }
                     }
                 }
-
-                vm.set_pc(0);
+            }
+            //discards the statement being typed, like CPython
+            Err(ReadlineError::Interrupted) if !buffer.is_empty() => {
+                buffer.clear();
+                println!("KeyboardInterrupt");
             }
             Err(ReadlineError::Interrupted) => {
                 println!("CTRL-C");
@@ -80,5 +321,9 @@ fn main() {
             }
         }
     }
-    rl.save_history("history.txt").unwrap();
+    //stdin may not be a terminal, or the history file not writable
+    if let Err(e) = rl.save_history("history.txt") {
+        eprintln!("horse: can't save history: {}", e);
+    }
+    status
 }
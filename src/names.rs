@@ -0,0 +1,81 @@
+use crate::runtime::datamodel::*;
+use crate::runtime::memory::MemoryAddress;
+use crate::runtime::vm::VM;
+use std::collections::{BTreeMap, BTreeSet};
+
+//How many dots deep module attributes are followed, like `package.module.function`
+const MODULE_DEPTH: usize = 3;
+
+//The names code can use, copied out of the interpreter, like the REPL completes them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Names {
+    //globals of __main__ and the builtins
+    pub globals: BTreeSet<String>,
+    //attribute names by the dotted path of the object, like "shapes" or "shapes.Square"
+    pub attributes: BTreeMap<String, BTreeSet<String>>,
+}
+
+fn namespace_names(vm: &VM, module: &str) -> Vec<(String, MemoryAddress)> {
+    let module = match vm.find_module(module) {
+        Some(module) => module,
+        None => return vec![],
+    };
+    match &vm.get_pyobj_byaddr(module).structure {
+        PyObjectStructure::Module { global_namespace, .. } => {
+            global_namespace.iter().map(|(name, addr)| (name.clone(), *addr)).collect()
+        }
+        _ => vec![],
+    }
+}
+
+fn type_functions(vm: &VM, type_addr: MemoryAddress, names: &mut BTreeSet<String>) {
+    for class in vm.get_mro(type_addr) {
+        if let PyObjectStructure::Type { functions, .. } = &vm.get_pyobj_byaddr(class).structure {
+            names.extend(functions.keys().cloned());
+        }
+    }
+}
+
+//The properties of the object, the methods of its type (or of itself, for types), and the globals of modules
+fn attribute_names(vm: &VM, addr: MemoryAddress) -> BTreeSet<String> {
+    let pyobj = vm.get_pyobj_byaddr(addr);
+    let mut names: BTreeSet<String> = pyobj.properties.keys().cloned().collect();
+    match &pyobj.structure {
+        PyObjectStructure::Module { global_namespace, .. } => names.extend(global_namespace.keys().cloned()),
+        PyObjectStructure::Type { .. } => type_functions(vm, addr, &mut names),
+        _ => type_functions(vm, pyobj.type_addr, &mut names),
+    }
+    return names;
+}
+
+fn is_module(vm: &VM, addr: MemoryAddress) -> bool {
+    matches!(vm.get_pyobj_byaddr(addr).structure, PyObjectStructure::Module { .. })
+}
+
+impl Names {
+    pub(crate) fn of(vm: &VM) -> Names {
+        let mut names = Names::default();
+        let mut globals = namespace_names(vm, BUILTIN_MODULE);
+        globals.extend(namespace_names(vm, MAIN_MODULE));
+        for (name, addr) in globals {
+            names.collect_attributes(vm, name.clone(), addr, 1);
+            names.globals.insert(name);
+        }
+        return names;
+    }
+
+    //Only the attributes of modules are followed further, everything else is one dot deep
+    fn collect_attributes(&mut self, vm: &VM, path: String, addr: MemoryAddress, depth: usize) {
+        let names = attribute_names(vm, addr);
+        if is_module(vm, addr) && depth < MODULE_DEPTH {
+            for name in names.iter() {
+                if let Some(attribute) = vm.find_in_module_addr(addr, name) {
+                    if attribute != addr {
+                        self.collect_attributes(vm, format!("{}.{}", path, name), attribute, depth + 1);
+                    }
+                }
+            }
+        }
+        self.attributes.insert(path, names);
+    }
+}
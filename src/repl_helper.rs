@@ -0,0 +1,214 @@
+use horse::{keywords, tokenize, Interpreter, Names, SourceToken, TokenKind};
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use std::borrow::Cow;
+use std::panic;
+
+const KEYWORD_COLOR: &str = "\x1b[35m";
+const STRING_COLOR: &str = "\x1b[32m";
+const NUMBER_COLOR: &str = "\x1b[36m";
+const RESET_COLOR: &str = "\x1b[0m";
+
+//Completion and highlighting for the REPL.
+//rustyline owns the helper while a line is read, so it can't look into the interpreter then:
+//the names are copied from it before each prompt, by `refresh`.
+#[derive(Default)]
+pub struct ReplHelper {
+    names: Names,
+}
+
+impl ReplHelper {
+    pub fn new() -> ReplHelper {
+        ReplHelper::default()
+    }
+
+    pub fn refresh(&mut self, interpreter: &Interpreter) {
+        self.names = interpreter.names();
+    }
+
+    //Where the word being completed starts, and what it can be completed with.
+    //Names starting with an underscore are only offered when the word starts with one.
+    pub fn candidates(&self, before_cursor: &str) -> (usize, Vec<String>) {
+        let word_start = before_cursor
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !(c.is_alphanumeric() || *c == '_' || *c == '.'))
+            .map(|(index, c)| index + c.len_utf8())
+            .unwrap_or(0);
+        let word = &before_cursor[word_start..];
+        let (options, partial): (Vec<&String>, &str) = match word.rsplit_once('.') {
+            Some((path, partial)) => match self.names.attributes.get(path) {
+                Some(attributes) => (attributes.iter().collect(), partial),
+                None => return (before_cursor.len(), vec![]),
+            },
+            None => (self.names.globals.iter().collect(), word),
+        };
+        let mut candidates: Vec<String> = options
+            .into_iter()
+            .cloned()
+            .chain(keywords().filter(|_| !word.contains('.')).map(String::from))
+            .filter(|name| name.starts_with(partial) && (partial.starts_with('_') || !name.starts_with('_')))
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        (before_cursor.len() - partial.len(), candidates)
+    }
+}
+
+//The tokens of what was typed so far, like `a =`. None if it doesn't lex, and a crash of the lexer
+//doesn't end the session either: the input is shown as it is and run, to report the error.
+fn tokens_of(source: &str) -> Option<Vec<SourceToken>> {
+    panic::catch_unwind(|| tokenize(source)).ok()?.ok()
+}
+
+//Colors keywords, strings and numbers using the horse lexer. None if the line doesn't lex yet,
+//like while a string is still being typed.
+pub fn highlight_line(line: &str) -> Option<String> {
+    let tokens = tokens_of(line)?;
+    let chars: Vec<char> = line.chars().collect();
+    let starts: Vec<usize> = tokens.iter().map(|token| token.column - 1).collect();
+    let mut highlighted: String = chars[..starts.first().copied().unwrap_or(chars.len())].iter().collect();
+    for (index, token) in tokens.iter().enumerate() {
+        let end = starts.get(index + 1).copied().unwrap_or(chars.len());
+        if token.line != 1 || end < starts[index] || end > chars.len() {
+            return None;
+        }
+        let segment: String = chars[starts[index]..end].iter().collect();
+        let text = segment.trim_end();
+        let color = match token.kind {
+            TokenKind::Keyword => Some(KEYWORD_COLOR),
+            TokenKind::String => Some(STRING_COLOR),
+            TokenKind::Number => Some(NUMBER_COLOR),
+            _ => None,
+        };
+        match color {
+            Some(color) if !text.is_empty() => {
+                highlighted.push_str(&format!("{}{}{}{}", color, text, RESET_COLOR, &segment[text.len()..]));
+            }
+            _ => highlighted.push_str(&segment),
+        }
+    }
+    Some(highlighted)
+}
+
+//Whether the source is an unfinished statement and another line should be read.
+//That's when a bracket is still open, or when a block was started (a line ending with `:`)
+//and no empty line ended it yet. Lexing errors are reported when the source runs.
+pub fn needs_more_input(source: &str) -> bool {
+    let tokens = match tokens_of(source) {
+        Some(tokens) => tokens,
+        None => return false,
+    };
+    let mut bracket_depth = 0;
+    let mut opens_block = false;
+    for (index, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::OpenBracket => bracket_depth += 1,
+            TokenKind::CloseBracket => bracket_depth -= 1,
+            TokenKind::Colon if bracket_depth == 0 => {
+                let ends_line = matches!(tokens.get(index + 1).map(|next| next.kind), None | Some(TokenKind::NewLine));
+                opens_block = opens_block || ends_line;
+            }
+            _ => {}
+        }
+    }
+    if bracket_depth > 0 {
+        return true;
+    }
+    let last_line = source.lines().last().unwrap_or("");
+    opens_block && !last_line.trim().is_empty()
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.candidates(&line[..pos]))
+    }
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        match highlight_line(line) {
+            Some(highlighted) => Cow::Owned(highlighted),
+            None => Cow::Borrowed(line),
+        }
+    }
+
+    //every typed char can change the colors, like a quote closing a string
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        true
+    }
+}
+
+impl Hinter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn helper_after(source: &str) -> ReplHelper {
+        let mut interpreter = Interpreter::new().unwrap();
+        interpreter.exec_as(source, "<stdin>").unwrap();
+        let mut helper = ReplHelper::new();
+        helper.refresh(&interpreter);
+        helper
+    }
+
+    #[test]
+    fn completes_globals_builtins_and_keywords() {
+        let helper = helper_after("counter = 1\ncount_down = 2\n");
+        assert_eq!(helper.candidates("x = coun"), (4, vec!["count_down".to_string(), "counter".to_string()]));
+        assert_eq!(helper.candidates("iss").1, vec!["issubclass".to_string()]);
+        assert_eq!(helper.candidates("whi").1, vec!["while".to_string()]);
+    }
+
+    #[test]
+    fn completes_attributes() {
+        let helper = helper_after("class Point:\n    def __init__(self):\n        self.x = 1\n    def norm(self):\n        return 0\np = Point()\n");
+        assert_eq!(helper.candidates("p.").1, vec!["norm".to_string(), "x".to_string()]);
+        assert_eq!(helper.candidates("print(p.n"), (8, vec!["norm".to_string()]));
+        assert!(helper.candidates("p.__").1.contains(&"__init__".to_string()));
+        assert!(helper.candidates("unknown.").1.is_empty());
+    }
+
+    #[test]
+    fn unfinished_input() {
+        assert!(!needs_more_input("x = 1\n"));
+        assert!(needs_more_input("x = [1,\n"));
+        assert!(needs_more_input("f(a,\n    b\n"));
+        assert!(!needs_more_input("f(a,\n    b)\n"));
+        assert!(needs_more_input("def f():\n"));
+        assert!(needs_more_input("def f():\n    return 1\n"));
+        assert!(!needs_more_input("def f():\n    return 1\n\n"));
+        assert!(needs_more_input("if x:\n    y = 1\nelse:\n"));
+        assert!(!needs_more_input("d = {1: 2}\n"));
+        assert!(!needs_more_input("f = lambda: 1\n"));
+        assert!(!needs_more_input("a ="));
+        assert!(!needs_more_input("x *"));
+    }
+
+    #[test]
+    fn highlights_with_the_lexer() {
+        assert_eq!(
+            highlight_line("if x: y = 'a b'  ").unwrap(),
+            format!("{}if{} x: y = {}'a b'{}  ", KEYWORD_COLOR, RESET_COLOR, STRING_COLOR, RESET_COLOR)
+        );
+        assert_eq!(highlight_line("x = 12"), Some(format!("x = {}12{}", NUMBER_COLOR, RESET_COLOR)));
+        assert_eq!(highlight_line("x = 'unterminated"), None);
+    }
+
+    #[test]
+    fn highlights_statements_being_typed() {
+        let helper = ReplHelper::new();
+        assert_eq!(helper.highlight("a =", 3), "a =");
+        assert_eq!(helper.highlight("x *", 3), "x *");
+    }
+}
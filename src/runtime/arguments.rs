@@ -0,0 +1,139 @@
+use crate::builtin_types::{dict_type, tuple_type};
+use crate::bytecode::program::CodeObject;
+use crate::runtime::datamodel::*;
+use crate::runtime::memory::*;
+use crate::runtime::vm::*;
+
+//Matches the arguments of a call with the parameters of a user defined function, the same way CPython does:
+//positional arguments fill the parameters in order and the extra ones go to *args,
+//keyword arguments go to the parameter with the same name or to **kwargs, then the defaults fill the rest.
+//For methods the bound object is already the first positional argument.
+//Returns the value of each parameter slot, in the order of code.params, or the TypeError message.
+pub fn bind_arguments(
+    vm: &VM,
+    code: &CodeObject,
+    qualname: &str,
+    defaults: &[MemoryAddress],
+    kwonly_defaults: &[(String, MemoryAddress)],
+    arguments: PositionalParameters,
+) -> Result<Vec<MemoryAddress>, String> {
+    let positional_count = code.positional_count();
+    let named_count = positional_count + code.kwonly_count;
+    let mut slots: Vec<Option<MemoryAddress>> = vec![None; named_count];
+
+    let given = arguments.params.len();
+    if given > positional_count && !code.varargs {
+        return Err(too_many_positional(qualname, positional_count, defaults.len(), given));
+    }
+    let extra_positional: Vec<MemoryAddress> = arguments.params.iter().skip(positional_count).copied().collect();
+    for (slot, value) in slots[..positional_count].iter_mut().zip(arguments.params) {
+        *slot = Some(value);
+    }
+
+    let mut extra_keywords = vec![];
+    for (name, value) in arguments.kwargs.into_iter() {
+        match code.params[..named_count].iter().position(|param| *param == name) {
+            Some(index) if slots[index].is_some() => {
+                return Err(format!("{}() got multiple values for argument '{}'", qualname, name));
+            }
+            Some(index) => slots[index] = Some(value),
+            None if code.varkeywords => {
+                if extra_keywords.iter().any(|(other, _)| *other == name) {
+                    return Err(format!("{}() got multiple values for argument '{}'", qualname, name));
+                }
+                extra_keywords.push((name, value));
+            }
+            None => {
+                return Err(format!("{}() got an unexpected keyword argument '{}'", qualname, name));
+            }
+        }
+    }
+
+    //defaults belong to the last positional parameters
+    let first_with_default = positional_count - defaults.len();
+    for (slot, default) in slots[first_with_default..positional_count].iter_mut().zip(defaults.iter()) {
+        if slot.is_none() {
+            *slot = Some(*default);
+        }
+    }
+    for (index, slot) in slots.iter_mut().enumerate().skip(positional_count) {
+        if slot.is_none() {
+            *slot = kwonly_defaults
+                .iter()
+                .find(|(name, _)| *name == code.params[index])
+                .map(|(_, value)| *value);
+        }
+    }
+
+    let missing_positional = missing_names(&code.params[..positional_count], &slots[..positional_count]);
+    if !missing_positional.is_empty() {
+        return Err(missing_arguments(qualname, "positional", &missing_positional));
+    }
+    let missing_kwonly = missing_names(&code.params[positional_count..named_count], &slots[positional_count..]);
+    if !missing_kwonly.is_empty() {
+        return Err(missing_arguments(qualname, "keyword-only", &missing_kwonly));
+    }
+
+    let mut result: Vec<MemoryAddress> = slots.into_iter().map(|slot| slot.unwrap()).collect();
+    //the caller holds a reference to the other arguments, these two are only held by the new frame
+    if code.varargs {
+        let varargs = tuple_type::new_tuple(vm, extra_positional);
+        vm.increase_refcount(varargs);
+        result.push(varargs);
+    }
+    if code.varkeywords {
+        let kwargs = dict_type::new_dict(vm);
+        for (name, value) in extra_keywords {
+            let key = vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.string, BuiltInTypeData::String(name));
+            //string keys can always be hashed
+            dict_type::dict_set(vm, kwargs, key, value).unwrap();
+        }
+        vm.increase_refcount(kwargs);
+        result.push(kwargs);
+    }
+    return Ok(result);
+}
+
+fn missing_names(params: &[String], slots: &[Option<MemoryAddress>]) -> Vec<String> {
+    params
+        .iter()
+        .zip(slots.iter())
+        .filter(|(_, slot)| slot.is_none())
+        .map(|(name, _)| format!("'{}'", name))
+        .collect()
+}
+
+fn plural(count: usize, word: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, word)
+    } else {
+        format!("{} {}s", count, word)
+    }
+}
+
+//"f() missing 2 required positional arguments: 'a' and 'b'"
+fn missing_arguments(qualname: &str, kind: &str, names: &[String]) -> String {
+    let listed = match names {
+        [single] => single.clone(),
+        [first, second] => format!("{} and {}", first, second),
+        [rest @ .., last] => format!("{}, and {}", rest.join(", "), last),
+        [] => String::new(),
+    };
+    format!(
+        "{}() missing {}: {}",
+        qualname,
+        plural(names.len(), &format!("required {} argument", kind)),
+        listed
+    )
+}
+
+//"f() takes from 1 to 2 positional arguments but 3 were given"
+fn too_many_positional(qualname: &str, positional_count: usize, number_defaults: usize, given: usize) -> String {
+    let expected = if number_defaults == 0 {
+        plural(positional_count, "positional argument")
+    } else {
+        format!("from {} to {} positional arguments", positional_count - number_defaults, positional_count)
+    };
+    let was_were = if given == 1 { "was" } else { "were" };
+    format!("{}() takes {} but {} {} given", qualname, expected, given, was_were)
+}
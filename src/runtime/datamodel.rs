@@ -4,6 +4,8 @@ use crate::runtime::memory::*;
 use crate::bytecode::program::CodeObject;
 use std::fmt::Debug;
 use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::rc::Rc;
 
 pub const BUILTIN_MODULE: &'static str = "__builtins__";
 pub const MAIN_MODULE: &'static str = "__main__";
@@ -16,8 +18,83 @@ pub enum BuiltInTypeData {
     Float(Float),
     String(String),
     List(Vec<MemoryAddress>),
+    Tuple(Vec<MemoryAddress>),
+    Dict(DictData),
+    //The elements are the keys, the values are unused
+    Set(DictData),
     ClassInstance,
-    CodeObject(CodeObjectContext)
+    CodeObject(CodeObjectContext),
+    Traceback(Vec<TracebackEntry>),
+    //super() bound to an instance, looks up attributes in the MRO after this_class
+    Super {
+        this_class: MemoryAddress,
+        instance: MemoryAddress,
+    },
+    //A variable shared between a function and the functions nested in it. None until it's assigned.
+    Cell(Option<MemoryAddress>),
+    Generator(GeneratorData),
+}
+
+//The frame of a generator function call, kept between calls to __next__ and send
+#[derive(Debug, Eq, PartialEq)]
+pub struct GeneratorData {
+    pub code: CodeObjectContext,
+    //taken out while the generator runs, and dropped once it finishes
+    pub frame: Option<Box<StackFrame>>,
+    pub running: bool,
+}
+
+//Entries are kept in insertion order. Comparing keys needs the VM (__eq__ may be Python code),
+//so this only finds the candidates with the same hash, builtin_types::dict_type does the rest.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct DictData {
+    pub entries: Vec<DictEntry>,
+    indices: HashMap<i128, Vec<usize>>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct DictEntry {
+    pub hash: i128,
+    pub key: MemoryAddress,
+    pub value: MemoryAddress,
+}
+
+impl DictData {
+    pub fn new() -> DictData {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    //Indices of the entries whose key has the given hash
+    pub fn candidates(&self, hash: i128) -> Vec<usize> {
+        self.indices.get(&hash).cloned().unwrap_or_default()
+    }
+
+    pub fn push(&mut self, hash: i128, key: MemoryAddress, value: MemoryAddress) {
+        self.indices.entry(hash).or_default().push(self.entries.len());
+        self.entries.push(DictEntry { hash, key, value });
+    }
+
+    pub fn remove(&mut self, index: usize) -> DictEntry {
+        let removed = self.entries.remove(index);
+        //entries after the removed one moved back by one
+        self.indices.clear();
+        for (i, entry) in self.entries.iter().enumerate() {
+            self.indices.entry(entry.hash).or_default().push(i);
+        }
+        return removed;
+    }
+}
+
+//One frame an exception went through, most recent call last
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct TracebackEntry {
+    pub filename: String,
+    pub line: Option<usize>,
+    pub function_name: String,
 }
 
 impl ToString for BuiltInTypeData {
@@ -29,8 +106,15 @@ impl ToString for BuiltInTypeData {
             BuiltInTypeData::List(_i) => {
                 return "a list".into()
             },
+            BuiltInTypeData::Tuple(_) => "a tuple".to_owned(),
+            BuiltInTypeData::Dict(_) => "a dict".to_owned(),
+            BuiltInTypeData::Set(_) => "a set".to_owned(),
             BuiltInTypeData::ClassInstance => "class instance".to_owned(),
-            BuiltInTypeData::CodeObject(_) => "code object".to_owned()
+            BuiltInTypeData::CodeObject(_) => "code object".to_owned(),
+            BuiltInTypeData::Traceback(_) => "traceback".to_owned(),
+            BuiltInTypeData::Super { .. } => "super".to_owned(),
+            BuiltInTypeData::Cell(_) => "cell".to_owned(),
+            BuiltInTypeData::Generator(_) => "generator".to_owned(),
 
         }
     }
@@ -72,6 +156,28 @@ impl BuiltInTypeData {
         }
     }
 
+    pub fn take_tuple(&self) -> &Vec<MemoryAddress> {
+        match self {
+            BuiltInTypeData::Tuple(t) => t,
+            _ => panic!("Tried to transform something into tuple unexpectedly"),
+        }
+    }
+
+    //sets use the same hash table as dicts
+    pub fn take_dict(&self) -> &DictData {
+        match self {
+            BuiltInTypeData::Dict(d) | BuiltInTypeData::Set(d) => d,
+            _ => panic!("Tried to transform something into dict unexpectedly"),
+        }
+    }
+
+    pub fn take_dict_mut(&mut self) -> &mut DictData {
+        match self {
+            BuiltInTypeData::Dict(d) | BuiltInTypeData::Set(d) => d,
+            _ => panic!("Tried to transform something into dict unexpectedly"),
+        }
+    }
+
     pub fn take_code_object(&self) -> &CodeObjectContext {
         match self {
             BuiltInTypeData::CodeObject(cobj) => cobj,
@@ -81,8 +187,12 @@ impl BuiltInTypeData {
 }
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct CodeObjectContext {
-    pub code: CodeObject,
-    pub consts: Vec<MemoryAddress>
+    //shared with the stack frames running it, so tracebacks can find source lines
+    pub code: Rc<CodeObject>,
+    //shared too, calls clone the context out of the function object before running it
+    pub consts: Rc<[MemoryAddress]>,
+    //the module whose globals the code uses
+    pub module: MemoryAddress,
 }
 #[derive(Debug, Eq, PartialEq)]
 pub struct ProgramContext {
@@ -107,7 +217,11 @@ pub enum PyObjectStructure {
     UserDefinedFunction {
         code: CodeObjectContext,
         qualname: String,
-        defaults: Vec<MemoryAddress>
+        //for the last positional parameters
+        defaults: Vec<MemoryAddress>,
+        kwonly_defaults: Vec<(String, MemoryAddress)>,
+        //one cell for each of code.freevars
+        closure: Vec<MemoryAddress>,
     },
     BoundMethod {
         function_address: MemoryAddress,
@@ -116,7 +230,10 @@ pub enum PyObjectStructure {
     Type {
         name: String,
         functions: BTreeMap<String, MemoryAddress>,
+        //the first base class
         supertype: Option<MemoryAddress>,
+        //method resolution order without the type itself, computed with C3 when the class is created
+        mro: Vec<MemoryAddress>,
     },
     Module {
         name: String,
@@ -0,0 +1,169 @@
+use crate::bytecode::cache;
+use crate::commons::errors::HorseError;
+use crate::runtime::datamodel::*;
+use crate::runtime::interpreter;
+use crate::runtime::memory::*;
+use crate::runtime::vm::*;
+use std::env;
+use std::path::{Path, PathBuf};
+
+//Modules are searched in the directory of the script first, then in the ones listed in HORSEPATH
+pub fn search_path(script_dir: &Path) -> Vec<PathBuf> {
+    let mut path = vec![script_dir.to_path_buf()];
+    if let Some(horsepath) = env::var_os("HORSEPATH") {
+        path.extend(env::split_paths(&horsepath).filter(|dir| !dir.as_os_str().is_empty()));
+    }
+    return path;
+}
+
+//A package is a directory with an __init__.py, which runs when the package is imported.
+//Returns the file to run and, for packages, the directory where the submodules are.
+fn find_module_file(search_path: &[PathBuf], name: &str) -> Option<(PathBuf, Option<PathBuf>)> {
+    for dir in search_path.iter() {
+        let package_dir = dir.join(name);
+        let init = package_dir.join("__init__.py");
+        if init.is_file() {
+            return Some((init, Some(package_dir)));
+        }
+        let file = dir.join(format!("{}.py", name));
+        if file.is_file() {
+            return Some((file, None));
+        }
+    }
+    return None;
+}
+
+//Syntax errors in an imported file are raised in the code doing the import
+pub fn syntax_error(vm: &VM, error: HorseError, filename: &str) -> MemoryAddress {
+    let (message, span) = match error {
+        HorseError::Lex(e) => (e.message, e.span),
+        HorseError::Parse(e) => (e.message, e.span),
+        HorseError::Compile(e) => (e.message, None),
+        HorseError::Runtime(e) => return e.exception,
+        HorseError::Conversion(e) => return vm.create_exception("TypeError", e.to_string()),
+        //only made at the API boundary, compiling doesn't give them
+        HorseError::Internal(e) => return vm.create_exception("RuntimeError", e.message),
+    };
+    let message = match span {
+        Some(span) => format!("{} ({}, line {})", message, filename, span.line),
+        None => format!("{} ({})", message, filename),
+    };
+    vm.create_exception("SyntaxError", message)
+}
+
+fn new_string(vm: &VM, value: String) -> MemoryAddress {
+    vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.string, BuiltInTypeData::String(value))
+}
+
+//The directory of a package, None for modules that are not packages
+fn package_dir(vm: &VM, module: MemoryAddress) -> Option<PathBuf> {
+    let path = vm.find_in_module_addr(module, "__path__")?;
+    match &*vm.get_raw_data_of_pyobj(path) {
+        BuiltInTypeData::List(dirs) => dirs
+            .first()
+            .map(|dir| PathBuf::from(vm.get_raw_data_of_pyobj(*dir).take_string())),
+        _ => None,
+    }
+}
+
+//Submodules are only searched in the directory of their package, which is imported first
+fn parent_and_search_path(vm: &VM, name: &str) -> Result<(Option<MemoryAddress>, Vec<PathBuf>), MemoryAddress> {
+    match name.rsplit_once('.') {
+        Some((parent_name, _)) => {
+            let parent = import_module(vm, parent_name)?;
+            match package_dir(vm, parent) {
+                Some(dir) => Ok((Some(parent), vec![dir])),
+                None => {
+                    let message = format!("No module named '{}'; '{}' is not a package", name, parent_name);
+                    Err(vm.create_exception("ModuleNotFoundError", message))
+                }
+            }
+        }
+        None => Ok((None, vm.search_path.clone())),
+    }
+}
+
+//Imports a module by its dotted name, importing the packages it's in first.
+//Modules are only run the first time, later imports get the same module object.
+//Returns the exception to be raised in the Err case.
+pub fn import_module(vm: &VM, name: &str) -> Result<MemoryAddress, MemoryAddress> {
+    if let Some(module) = vm.find_module(name) {
+        return Ok(module);
+    }
+    let (parent, search_path) = parent_and_search_path(vm, name)?;
+    let short_name = name.rsplit('.').next().unwrap();
+    let (file, package) = match find_module_file(&search_path, short_name) {
+        Some(found) => found,
+        None => return Err(vm.create_exception("ModuleNotFoundError", format!("No module named '{}'", name))),
+    };
+    let filename = file.to_string_lossy().to_string();
+    let source = match std::fs::read_to_string(&file) {
+        Ok(source) => source,
+        Err(e) => return Err(vm.create_exception("ImportError", format!("can't read '{}': {}", filename, e))),
+    };
+    let program = match cache::compile_cached(&source, &filename, cache::cache_file_for(&file).as_deref()) {
+        Ok(program) => program,
+        Err(e) => return Err(syntax_error(vm, e, &filename)),
+    };
+
+    //added before running it, so circular imports get the module as it is so far
+    let module = vm.create_module(name);
+    vm.set_in_module_addr(module, "__name__", new_string(vm, name.to_string()));
+    vm.set_in_module_addr(module, "__file__", new_string(vm, filename));
+    if let Some(dir) = package {
+        let dir = new_string(vm, dir.to_string_lossy().to_string());
+        let path = vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.list, BuiltInTypeData::List(vec![dir]));
+        vm.increase_refcount(dir);
+        vm.set_in_module_addr(module, "__path__", path);
+    }
+    if let Err(exception) = interpreter::execute_module(vm, program, module) {
+        vm.remove_module(name);
+        return Err(exception);
+    }
+    //submodules are attributes of their package
+    if let Some(parent) = parent {
+        vm.set_in_module_addr(parent, short_name, module);
+    }
+    return Ok(module);
+}
+
+//`from module import name`: an attribute of the module, or else a submodule of the package
+pub fn import_from(vm: &VM, module: MemoryAddress, name: &str) -> Result<MemoryAddress, MemoryAddress> {
+    if let Some(value) = vm.find_in_module_addr(module, name) {
+        return Ok(value);
+    }
+    let module_name = vm.get_module_name(module).to_string();
+    if package_dir(vm, module).is_some() {
+        match import_module(vm, &format!("{}.{}", module_name, name)) {
+            Ok(submodule) => return Ok(submodule),
+            Err(exception) => {
+                let not_found = vm.find_in_module(BUILTIN_MODULE, "ModuleNotFoundError").unwrap();
+                if !vm.exception_matches(exception, not_found) {
+                    return Err(exception);
+                }
+            }
+        }
+    }
+    let message = format!("cannot import name '{}' from '{}'", name, module_name);
+    Err(vm.create_exception("ImportError", message))
+}
+
+//The file `horse -m name` runs as __main__, found like an import would find it.
+//For a package, that's its __main__.py, after the package itself is imported.
+pub fn find_main_module(vm: &VM, name: &str) -> Result<PathBuf, MemoryAddress> {
+    let (_, search_path) = parent_and_search_path(vm, name)?;
+    let short_name = name.rsplit('.').next().unwrap();
+    match find_module_file(&search_path, short_name) {
+        Some((file, None)) => Ok(file),
+        Some((_, Some(package_dir))) => {
+            import_module(vm, name)?;
+            let main = package_dir.join("__main__.py");
+            if main.is_file() {
+                return Ok(main);
+            }
+            let message = format!("No module named {}.__main__; '{}' is a package and cannot be directly executed", name, name);
+            Err(vm.create_exception("ImportError", message))
+        }
+        None => Err(vm.create_exception("ModuleNotFoundError", format!("No module named '{}'", name))),
+    }
+}
@@ -3,8 +3,12 @@ use crate::commons::float::Float;
 use crate::runtime::vm::*;
 use crate::runtime::datamodel::*;
 use crate::runtime::memory::*;
+use crate::commons::errors::RuntimeError;
+use crate::runtime::importer;
+use crate::builtin_types::{dict_type, set_type, tuple_type};
 
 use smallvec::{smallvec, SmallVec};
+use std::rc::Rc;
 
 
 pub fn handle_function_call(vm: &VM, number_args: usize) {
@@ -14,12 +18,59 @@ pub fn handle_function_call(vm: &VM, number_args: usize) {
     }
 
     let function_addr = vm.pop_stack();
+    call_and_push_result(vm, function_addr, PositionalParameters::from_stack_popped(&temp_stack));
+}
+
+//The values of the keyword arguments are on top of the positional ones
+pub fn handle_function_call_kw(vm: &VM, number_args: usize, keywords: &[String]) {
+    let mut temp_stack:SmallVec<[MemoryAddress; 4]>= smallvec![];
+    for _ in 0..number_args {
+        temp_stack.push(vm.pop_stack());
+    }
+    let function_addr = vm.pop_stack();
+
+    let mut params = PositionalParameters::from_stack_popped(&temp_stack);
+    let keyword_values = params.params.split_off(number_args - keywords.len());
+    params.kwargs = keywords.iter().cloned().zip(keyword_values).collect();
+    call_and_push_result(vm, function_addr, params);
+}
+
+pub fn handle_function_call_ex(vm: &VM) {
+    let kwargs_dict = vm.pop_stack();
+    let args_tuple = vm.pop_stack();
+    let function_addr = vm.pop_stack();
+
+    let params = PositionalParameters {
+        params: vm.get_raw_data_of_pyobj(args_tuple).take_tuple().clone(),
+        //BuildMapUnpackWithCall already checked that the keys are strings
+        kwargs: vm
+            .get_raw_data_of_pyobj(kwargs_dict)
+            .take_dict()
+            .entries
+            .iter()
+            .map(|entry| (vm.get_raw_data_of_pyobj(entry.key).take_string().clone(), entry.value))
+            .collect(),
+    };
+    call_and_push_result(vm, function_addr, params);
+}
+
+fn call_and_push_result(vm: &VM, function_addr: MemoryAddress, params: PositionalParameters) {
+    let temp_stack: Vec<MemoryAddress> = params
+        .params
+        .iter()
+        .chain(params.kwargs.iter().map(|(_, value)| value))
+        .copied()
+        .collect();
 
     for addr in temp_stack.iter() {
         vm.increase_refcount(*addr);
     }
 
-    let (returned_value, popped_frame) = vm.run_function(PositionalParameters::from_stack_popped(&temp_stack), function_addr, None);
+    //the arguments the callee rebinds, and a bound method, are only reachable from here during the call
+    vm.pin(&temp_stack);
+    vm.pin(&[function_addr]);
+    let (returned_value, popped_frame) = vm.run_function(params, function_addr, None);
+    vm.unpin(temp_stack.len() + 1);
 
     //increase refcount so it survives the pop_stack_frame call.
     let refcount = vm.get_refcount(returned_value);
@@ -40,12 +91,20 @@ pub fn handle_function_call(vm: &VM, number_args: usize) {
     vm.push_onto_stack(returned_value);
 }
 
+//Raises an exception while evaluating an expression. None is pushed in place of the value
+//so the stack stays balanced until the exception is handled at the end of the instruction.
+fn raise_in_expression(vm: &VM, exception_type: &str, message: String) {
+    let exception = vm.create_exception(exception_type, message);
+    vm.raise_exception(exception);
+    vm.push_onto_stack(vm.special_values[&SpecialValue::NoneValue]);
+}
+
 pub fn handle_load_const(vm: &VM, code: &CodeObjectContext, index: usize) {
     let memory_address = code.consts[index];
     vm.push_onto_stack(memory_address);
 }
 
-fn get_const_memaddr(vm: &VM, const_data: &Const) -> MemoryAddress {
+fn get_const_memaddr(vm: &VM, const_data: &Const, module: MemoryAddress) -> MemoryAddress {
     let const_addr = match const_data {
         Const::Integer(i) => {
             vm.allocate_builtin_type_byname_raw("int", BuiltInTypeData::Int(*i))
@@ -58,7 +117,7 @@ fn get_const_memaddr(vm: &VM, const_data: &Const) -> MemoryAddress {
         }
         Const::CodeObject(codeobj) => {
             vm.allocate_builtin_type_byname_raw("code object", BuiltInTypeData::CodeObject(
-                register_codeobj_consts(vm, codeobj)))
+                register_codeobj_consts(vm, codeobj, module)))
         }
         Const::Boolean(b) => {
             if *b {
@@ -88,6 +147,79 @@ pub fn curry_self(vm: &VM, function: MemoryAddress, self_object: MemoryAddress)
     })
 }
 
+fn is_function(vm: &VM, addr: MemoryAddress) -> bool {
+    matches!(
+        vm.get_pyobj_byaddr(addr).structure,
+        PyObjectStructure::UserDefinedFunction { .. } | PyObjectStructure::NativeCallable { .. }
+    )
+}
+
+fn is_bound_native(vm: &VM, addr: MemoryAddress) -> bool {
+    matches!(vm.get_pyobj_byaddr(addr).structure, PyObjectStructure::NativeCallable { is_bound: true, .. })
+}
+
+//A native method loaded from its class, like `str.upper`, takes self as the first argument.
+//Natives read the raw data of self, so it must be an instance of the class that defines the method.
+fn unbound_native_method(vm: &VM, class: MemoryAddress, function: MemoryAddress, name: &str) -> MemoryAddress {
+    let owner = vm
+        .get_mro(class)
+        .into_iter()
+        .chain(std::iter::once(vm.builtin_type_addrs.object))
+        .find(|type_addr| vm.get_own_method_addr(*type_addr, name) == Some(function))
+        .unwrap_or(class);
+    let method_name = name.to_string();
+    let code = move |vm: &VM, call_params: CallParams| -> MemoryAddress {
+        let mut params = call_params.params;
+        let owner_name = vm.get_type_name(owner);
+        let message = if params.params.is_empty() {
+            Some(format!("unbound method {}.{}() needs an argument", owner_name, method_name))
+        } else if !vm.is_subtype(vm.get_pyobj_type_addr(params.params[0]), owner) {
+            Some(format!(
+                "descriptor '{}' for '{}' objects doesn't apply to a '{}' object",
+                method_name,
+                owner_name,
+                vm.get_pyobj_type_name(params.params[0])
+            ))
+        } else {
+            None
+        };
+        if let Some(message) = message {
+            let exception = vm.create_exception("TypeError", message);
+            vm.raise_exception(exception);
+            return vm.special_values[&SpecialValue::NoneValue];
+        }
+        let bound = params.params.remove(0);
+        let (result, frame) = vm.run_function(params, function, Some(bound));
+        if let Some(exception) = frame.exception {
+            vm.raise_exception(exception);
+        }
+        result
+    };
+    vm.create_unbounded_callable_pyobj(PyCallable { code: Rc::new(code) }, Some(name.to_string()))
+}
+
+//super() skips the MRO of the instance's type until the class where the method was defined
+fn handle_load_super_attr(vm: &VM, this_class: MemoryAddress, instance: MemoryAddress, attr_name: &str) {
+    let instance_type = vm.get_pyobj_type_addr(instance);
+    let found = vm
+        .get_mro(instance_type)
+        .into_iter()
+        .skip_while(|type_addr| *type_addr != this_class)
+        .skip(1)
+        .chain(std::iter::once(vm.builtin_type_addrs.object))
+        .find_map(|type_addr| vm.get_own_method_addr(type_addr, attr_name));
+
+    match found {
+        Some(addr) if is_function(vm, addr) => {
+            let bounded = curry_self(vm, addr, instance);
+            vm.increase_refcount(bounded);
+            vm.push_onto_stack(bounded);
+        }
+        Some(addr) => vm.push_onto_stack(addr),
+        None => raise_in_expression(vm, "AttributeError", format!("'super' object has no attribute '{}'", attr_name)),
+    }
+}
+
 pub fn handle_load_attr(vm: &VM, attr_name: &str) {
     let stack_top = vm.pop_stack();
 
@@ -98,27 +230,64 @@ pub fn handle_load_attr(vm: &VM, attr_name: &str) {
     //i.e. it will be passed to the function as the "self" parameter 
     let pyobj = vm.get_pyobj_byaddr(stack_top);
     //println!("Stack top value: {:?}", pyobj);
+    let type_addr = pyobj.type_addr;
+    let is_module = matches!(pyobj.structure, PyObjectStructure::Module { .. });
+    let is_type = matches!(pyobj.structure, PyObjectStructure::Type { .. });
+    //the object can't stay borrowed while methods are looked up and bound to it
+    let structure = match &pyobj.structure {
+        PyObjectStructure::Object { raw_data: BuiltInTypeData::ClassInstance, .. } => Some(BuiltInTypeData::ClassInstance),
+        PyObjectStructure::Object { raw_data: BuiltInTypeData::Super { this_class, instance }, .. } => {
+            Some(BuiltInTypeData::Super { this_class: *this_class, instance: *instance })
+        }
+        _ => None,
+    };
+    drop(pyobj);
 
-    if let PyObjectStructure::Object {raw_data, .. } = &pyobj.structure {
-        if let BuiltInTypeData::ClassInstance = &raw_data {
-
+    match structure {
+        Some(BuiltInTypeData::ClassInstance) => {
             //ok, so this is a class instance
             //try getting the method from the type
-            
-            let type_addr = pyobj.type_addr;
 
             //find method, also checks if it even is a method at attr_name
 
             let method_addr = vm.get_method_addr_byname(type_addr, attr_name);
 
             if let Some(m_addr) = method_addr {
-                //create bound method
-                let bounded = curry_self(vm, m_addr, stack_top);
-                vm.increase_refcount(bounded);
-                vm.push_onto_stack(bounded);
+                if is_function(vm, m_addr) {
+                    //create bound method
+                    let bounded = curry_self(vm, m_addr, stack_top);
+                    vm.increase_refcount(bounded);
+                    vm.push_onto_stack(bounded);
+                    return;
+                }
+            }
+        }
+        Some(BuiltInTypeData::Super { this_class, instance }) => {
+            handle_load_super_attr(vm, this_class, instance, attr_name);
+            return;
+        }
+        //class attributes and methods are loaded unbound: `Base.__init__(self)`
+        _ if is_type => {
+            if attr_name == "__mro__" {
+                let mro = vm.get_mro(stack_top);
+                for item in mro.iter() {
+                    vm.increase_refcount(*item);
+                }
+                let tuple = vm.allocate_type_byaddr_raw(vm.builtin_type_addrs.tuple, BuiltInTypeData::Tuple(mro));
+                vm.push_onto_stack(tuple);
+                return;
+            }
+            if let Some(addr) = vm.get_method_addr_byname(stack_top, attr_name) {
+                if is_bound_native(vm, addr) {
+                    let unbound = unbound_native_method(vm, stack_top, addr, attr_name);
+                    vm.push_onto_stack(unbound);
+                    return;
+                }
+                vm.push_onto_stack(addr);
                 return;
             }
         }
+        _ => {}
     }
 
     //first: attempt to load an object property
@@ -131,11 +300,14 @@ pub fn handle_load_attr(vm: &VM, attr_name: &str) {
     }
     //second: try to load a method name
 
-    let type_addr = pyobj.type_addr;
-
     let obj = vm.get_method_addr_byname(type_addr, attr_name);
     match obj {
         None => {}
+        //class attributes that aren't functions are shared by all instances
+        Some(addr) if !is_function(vm, addr) => {
+            vm.push_onto_stack(addr);
+            return;
+        }
         Some(addr) => {
             let bounded = curry_self(vm, addr, stack_top);
             vm.increase_refcount(bounded);
@@ -145,10 +317,10 @@ pub fn handle_load_attr(vm: &VM, attr_name: &str) {
     }
 
     //third: try to load a module function, property, etc
-    let obj = vm.find_in_module_addr(stack_top, attr_name);
+    let obj = if is_module { vm.find_in_module_addr(stack_top, attr_name) } else { None };
 
     match obj {
-        None => panic!("could not find attribute named {}", attr_name),
+        None => raise_in_expression(vm, "AttributeError", format!("'{}' object has no attribute '{}'", vm.get_type_name(type_addr), attr_name)),
         Some(addr) => {
             vm.push_onto_stack(addr);
         }
@@ -165,11 +337,12 @@ pub fn handle_load_global(vm: &VM, code_obj: &CodeObjectContext, name: usize) {
     }
     */
 
+    //globals shadow the builtins
     if let Some(name_str) = code_obj.code.names.get(name) {
-        if let Some(addr) = vm.find_in_module(BUILTIN_MODULE, name_str) {
+        if let Some(addr) = vm.find_in_module_addr(code_obj.module, name_str) {
             vm.push_onto_stack(addr); 
             return;
-        } else if let Some(addr) = vm.find_in_module(MAIN_MODULE, name_str) {
+        } else if let Some(addr) = vm.find_in_module(BUILTIN_MODULE, name_str) {
             vm.push_onto_stack(addr); 
             return;
         }
@@ -181,24 +354,99 @@ pub fn handle_load_global(vm: &VM, code_obj: &CodeObjectContext, name: usize) {
             return;
         }
         None => {
-            panic!("could not find global named {}", &code_obj.code.names[name])
+            raise_in_expression(vm, "NameError", format!("name '{}' is not defined", &code_obj.code.names[name]))
         }
     };
 }
 
 
 
+//The method tried on the right operand when the left one doesn't support the operation, and the operator symbol
+fn reflected_operator(method: &str) -> (&'static str, &'static str) {
+    match method {
+        "__add__" => ("__radd__", "+"),
+        "__sub__" => ("__rsub__", "-"),
+        "__mul__" => ("__rmul__", "*"),
+        "__mod__" => ("__rmod__", "%"),
+        "__truediv__" => ("__rtruediv__", "/"),
+        "__gt__" => ("__lt__", ">"),
+        "__ge__" => ("__le__", ">="),
+        "__lt__" => ("__gt__", "<"),
+        "__le__" => ("__ge__", "<="),
+        "__eq__" => ("__eq__", "=="),
+        "__ne__" => ("__ne__", "!="),
+        _ => panic!("Not a binary operator: {}", method),
+    }
+}
+
+//Calls left.method(right), then the reflected method on the right operand if the first one is missing
+//or returns NotImplemented, like Python does. The result (or None after an exception) is pushed.
+fn binary_operation(vm: &VM, left: MemoryAddress, right: MemoryAddress, method: &str) {
+    let (reflected, symbol) = reflected_operator(method);
+    let is_comparison = matches!(method, "__gt__" | "__ge__" | "__lt__" | "__le__" | "__eq__" | "__ne__");
+    let not_implemented = vm.special_values[&SpecialValue::NotImplementedValue];
+    let left_type = vm.get_pyobj_type_addr(left);
+    let right_type = vm.get_pyobj_type_addr(right);
+    //arithmetic is only reflected between different types
+    let attempts: &[(MemoryAddress, &str, MemoryAddress)] = if is_comparison || left_type != right_type {
+        &[(left, method, right), (right, reflected, left)]
+    } else {
+        &[(left, method, right)]
+    };
+    //the operands were popped, and a call releases its arguments when it returns: a new object
+    //like `[1]` would be freed by the first attempt. The counts are left for the collector.
+    vm.increase_refcount(left);
+    vm.increase_refcount(right);
+    vm.pin(&[left, right]);
+    for (object, method, other) in attempts.iter().copied() {
+        if vm.get_method_addr_byname(vm.get_pyobj_type_addr(object), method).is_none() {
+            continue;
+        }
+        vm.push_onto_stack(object);
+        handle_load_attr(vm, method);
+        vm.push_onto_stack(other);
+        handle_function_call(vm, 1);
+        if vm.get_current_exception().is_some() || vm.top_stack() != not_implemented {
+            vm.unpin(2);
+            return;
+        }
+        vm.pop_stack();
+    }
+    vm.unpin(2);
+    let left_name = vm.get_type_name(left_type);
+    let right_name = vm.get_type_name(right_type);
+    match method {
+        //objects that can't compare their values are only equal to themselves
+        "__eq__" | "__ne__" => {
+            let equal = (left == right) == (method == "__eq__");
+            vm.push_onto_stack(if equal { vm.builtin_type_addrs.true_val } else { vm.builtin_type_addrs.false_val });
+        }
+        _ if is_comparison => raise_in_expression(
+            vm,
+            "TypeError",
+            format!("'{}' not supported between instances of '{}' and '{}'", symbol, left_name, right_name),
+        ),
+        _ => raise_in_expression(
+            vm,
+            "TypeError",
+            format!("unsupported operand type(s) for {}: '{}' and '{}'", symbol, left_name, right_name),
+        ),
+    }
+}
+
 //optimization: if binary add, then we check the TOS and TOS-1. If both are numeric, then
 //we just do the operation here and now, very fast, without creating a new stack frame.
 //If both types are not numeric or not simple/common to be operated on, we just call __add__ on TOS-1 etc
 macro_rules! create_binary_operator {
-    ($method_name:tt, $param_a:tt, $param_b:tt, $operation:expr, $pycall:expr) => {
+    ($method_name:tt, $param_a:tt, $param_b:tt, $operation:expr, $int_operation:expr, $pycall:expr) => {
         fn $method_name(vm: &VM) {
             let tos = vm.pop_stack();
             let tos_1 = vm.pop_stack();
             let pyobj_tos = vm.get_pyobj_byaddr(tos);
             let pyobj_tos_1 = vm.get_pyobj_byaddr(tos_1);
             let result;
+            //the checked int operation overflowed or divided by zero
+            let mut int_failed = false;
             let mut refcount_tos: usize = 0;
             let mut refcount_tos_1: usize = 0;
             if let PyObjectStructure::Object {
@@ -216,9 +464,10 @@ macro_rules! create_binary_operator {
                     match raw_data_tos {
                         BuiltInTypeData::Int(j) => match raw_data_tos_1 {
                             BuiltInTypeData::Int(i) => {
-                                let $param_a = i;
-                                let $param_b = j;
-                                result = Some(BuiltInTypeData::Int($operation))
+                                let $param_a = *i;
+                                let $param_b = *j;
+                                result = $int_operation.map(BuiltInTypeData::Int);
+                                int_failed = result.is_none();
                             }
                             BuiltInTypeData::Float(f) => {
                                 let $param_a = f.0;
@@ -254,13 +503,19 @@ macro_rules! create_binary_operator {
             } else {
                 result = None;
             }
-            if result.is_none() {
-                //rebuild the stack to call method (optimization did not work)
-                //TODO terrible
-                vm.push_onto_stack(tos_1);
-                handle_load_attr(vm, $pycall);
-                vm.push_onto_stack(tos);
-                handle_function_call(vm, 1);
+            //the operands may be deallocated or have methods called on them below
+            drop(pyobj_tos);
+            drop(pyobj_tos_1);
+            if int_failed {
+                let divisor = vm.get_raw_data_of_pyobj(tos).take_int();
+                if divisor == 0 && $pycall == "__mod__" {
+                    raise_in_expression(vm, "ZeroDivisionError", String::from("integer division or modulo by zero"));
+                } else {
+                    raise_in_expression(vm, "OverflowError", String::from("integer overflow"));
+                }
+            } else if result.is_none() {
+                //optimization did not work, call the method
+                binary_operation(vm, tos_1, tos, $pycall);
             } else {
                 //:GarbageCollector
                 if refcount_tos == 0 {
@@ -355,14 +610,12 @@ macro_rules! create_compare_operator {
             } else {
                 result = None;
             }
+            drop(pyobj_tos);
+            drop(pyobj_tos_1);
 
             if result.is_none() {
-                //rebuild the stack to call method (optimization did not work)
-                //TODO terrible
-                vm.push_onto_stack(tos_1);
-                handle_load_attr(vm, $pycall);
-                vm.push_onto_stack(tos);
-                handle_function_call(vm, 1);
+                //optimization did not work, call the method
+                binary_operation(vm, tos_1, tos, $pycall);
             } else {
                 //:GarbageCollector @TODO Proper garbage collection, this is perhaps not the right thing to do.
                 /*
@@ -397,10 +650,10 @@ macro_rules! create_compare_operator {
     };
 }
 
-create_binary_operator!(handle_binary_add, a, b, a + b, "__add__");
-create_binary_operator!(handle_binary_mod, a, b, a % b, "__mod__");
-create_binary_operator!(handle_binary_sub, a, b, a - b, "__sub__");
-create_binary_operator!(handle_binary_mul, a, b, a * b, "__mul__");
+create_binary_operator!(handle_binary_add, a, b, a + b, a.checked_add(b), "__add__");
+create_binary_operator!(handle_binary_mod, a, b, a % b, a.checked_rem(b), "__mod__");
+create_binary_operator!(handle_binary_sub, a, b, a - b, a.checked_sub(b), "__sub__");
+create_binary_operator!(handle_binary_mul, a, b, a * b, a.checked_mul(b), "__mul__");
 
 create_compare_operator!(handle_compare_greater, a, b, a > b, "__gt__");
 create_compare_operator!(handle_compare_greater_eq, a, b, a >= b, "__ge__");
@@ -465,14 +718,11 @@ fn handle_binary_truediv(vm: &VM) {
     } else {
         result = None;
     }
+    drop(pyobj_tos);
+    drop(pyobj_tos_1);
 
     if result.is_none() {
-        //rebuild the stack to call method
-        //TODO terrible
-        vm.push_onto_stack(tos_1);
-        handle_load_attr(vm, "__truediv__");
-        vm.push_onto_stack(tos);
-        handle_function_call(vm, 1);
+        binary_operation(vm, tos_1, tos, "__truediv__");
     } else {
         //:GarbageCollector @TODO Proper garbage collection, this is perhaps not the right thing to do.
 
@@ -501,12 +751,11 @@ pub fn handle_load_name(vm: &VM, code_obj: &CodeObjectContext, name: usize) {
     match vm.get_local(name) {
         Some(addr) => vm.push_onto_stack(addr),
         None => match code_obj.code.names.get(name) {
-            //@TODO shouldn't it load from the main module first? Or even better, the current module being executed?
-            Some(name_str) => match vm.find_in_module(BUILTIN_MODULE, name_str) {
+            Some(name_str) => match vm.find_in_module_addr(code_obj.module, name_str) {
                 Some(addr) => vm.push_onto_stack(addr),
-                None => match vm.find_in_module(MAIN_MODULE, name_str) {
+                None => match vm.find_in_module(BUILTIN_MODULE, name_str) {
                     Some(addr) => vm.push_onto_stack(addr),
-                    None => panic!("Could not find name {}", name_str),
+                    None => raise_in_expression(vm, "NameError", format!("name '{}' is not defined", name_str)),
                 }
             },
             None => panic!("Could not find name")
@@ -524,32 +773,62 @@ pub fn handle_store_name(vm: &VM, name: usize) {
     vm.bind_local(name, addr)
 }
 
+pub fn handle_store_global(vm: &VM, code_obj: &CodeObjectContext, name: usize) {
+    let addr = vm.pop_stack();
+    vm.set_in_module_addr(code_obj.module, &code_obj.code.names[name], addr);
+}
+
+fn cell_name(code_obj: &CodeObjectContext, cell: usize) -> &str {
+    let cellvars = &code_obj.code.cellvars;
+    match cellvars.get(cell) {
+        Some(name) => name,
+        None => &code_obj.code.freevars[cell - cellvars.len()],
+    }
+}
+
+pub fn handle_load_deref(vm: &VM, code_obj: &CodeObjectContext, cell: usize) {
+    let cell_addr = vm.get_cell(cell);
+    match &*vm.get_raw_data_of_pyobj(cell_addr) {
+        BuiltInTypeData::Cell(Some(addr)) => vm.push_onto_stack(*addr),
+        _ => {
+            let name = cell_name(code_obj, cell);
+            if cell < code_obj.code.cellvars.len() {
+                let message = format!("local variable '{}' referenced before assignment", name);
+                raise_in_expression(vm, "UnboundLocalError", message);
+            } else {
+                let message = format!("free variable '{}' referenced before assignment in enclosing scope", name);
+                raise_in_expression(vm, "NameError", message);
+            }
+        }
+    }
+}
+
+pub fn handle_store_deref(vm: &VM, cell: usize) {
+    let addr = vm.pop_stack();
+    vm.increase_refcount(addr);
+    let cell_addr = vm.get_cell(cell);
+    if let BuiltInTypeData::Cell(contents) = &mut *vm.get_raw_data_of_pyobj_mut(cell_addr) {
+        if let Some(previous) = contents.replace(addr) {
+            vm.decrease_refcount(previous);
+        }
+    }
+}
+
+pub fn handle_build_closure(vm: &VM, cells: &[usize]) {
+    let closure: Vec<MemoryAddress> = cells.iter().map(|cell| vm.get_cell(*cell)).collect();
+    let tuple = tuple_type::new_tuple(vm, closure);
+    vm.push_onto_stack(tuple);
+}
+
 //returns true if jumped
 pub fn handle_jump_if_false_pop(vm: &VM, destination: usize) -> bool {
     let stack_top = vm.pop_stack();
-    let raw_value = vm.get_raw_data_of_pyobj(stack_top);
-
-    if let BuiltInTypeData::Int(x) = raw_value {
-        let result = if *x == 0 {
-            vm.set_pc(destination);
-            true
-        } else {
-            false
-        };
-        vm.decrease_refcount(stack_top);
-        return result;
-    } else {
-        let (as_boolean, _) = vm.call_method(stack_top, "__bool__", PositionalParameters::empty()).unwrap();
-        let raw_value = vm.get_raw_data_of_pyobj(as_boolean).take_int();
-        let result = if raw_value == 0 {
-            vm.set_pc(destination);
-            true
-        } else {
-            false
-        };
-        vm.decrease_refcount(stack_top);
-        return result;
+    //not released: the condition is often a variable, which the stack holds no reference to
+    let jumped = !vm.is_truthy(stack_top);
+    if jumped {
+        vm.set_pc(destination);
     }
+    return jumped;
 }
 
 pub fn handle_build_list(vm: &VM, size: usize) {
@@ -568,6 +847,143 @@ pub fn handle_build_list(vm: &VM, size: usize) {
     vm.push_onto_stack(built_list);
 }
 
+pub fn handle_build_tuple(vm: &VM, size: usize) {
+    let mut elements: Vec<MemoryAddress> = vec![];
+    for _ in 0..size {
+        elements.push(vm.pop_stack());
+    }
+    elements.reverse();
+
+    for element in elements.iter() {
+        vm.increase_refcount(*element);
+    }
+
+    let built_tuple = vm.allocate_type_byaddr_raw(
+        vm.builtin_type_addrs.tuple,
+        BuiltInTypeData::Tuple(elements),
+    );
+
+    vm.push_onto_stack(built_tuple);
+}
+
+//Elements are pushed in reverse so the stores that follow take them in order
+pub fn handle_unpack_sequence(vm: &VM, size: usize) {
+    let sequence = vm.pop_stack();
+    let elements = match vm.collect_iterable(sequence) {
+        Ok(elements) => elements,
+        Err(exception) => {
+            vm.raise_exception(exception);
+            return;
+        }
+    };
+
+    if elements.len() != size {
+        let message = if elements.len() < size {
+            format!("not enough values to unpack (expected {}, got {})", size, elements.len())
+        } else {
+            format!("too many values to unpack (expected {})", size)
+        };
+        let exception = vm.create_exception("ValueError", message);
+        vm.raise_exception(exception);
+        return;
+    }
+
+    for element in elements.iter().rev() {
+        vm.push_onto_stack(*element);
+    }
+}
+
+//Pairs are on the stack as key, value, key, value... with the last pair on top
+pub fn handle_build_map(vm: &VM, number_entries: usize) {
+    let mut elements: Vec<MemoryAddress> = vec![];
+    for _ in 0..number_entries * 2 {
+        elements.push(vm.pop_stack());
+    }
+    elements.reverse();
+
+    let built_dict = vm.allocate_type_byaddr_raw(
+        vm.builtin_type_addrs.dict,
+        BuiltInTypeData::Dict(DictData::new()),
+    );
+
+    for pair in elements.chunks(2) {
+        if let Err(exception) = dict_type::dict_set(vm, built_dict, pair[0], pair[1]) {
+            vm.raise_exception(exception);
+            break;
+        }
+    }
+
+    vm.push_onto_stack(built_dict);
+}
+
+pub fn handle_build_set(vm: &VM, number_elements: usize) {
+    let mut elements: Vec<MemoryAddress> = vec![];
+    for _ in 0..number_elements {
+        elements.push(vm.pop_stack());
+    }
+    elements.reverse();
+
+    let built_set = set_type::new_set(vm);
+    for element in elements {
+        if let Err(exception) = set_type::set_add(vm, built_set, element) {
+            vm.raise_exception(exception);
+            break;
+        }
+    }
+
+    vm.push_onto_stack(built_set);
+}
+
+//Stack is value, container, index (TOS), like CPython's STORE_SUBSCR
+pub fn handle_store_index(vm: &VM) {
+    let index_value = vm.pop_stack();
+    let indexed_value = vm.pop_stack();
+    let value = vm.pop_stack();
+
+    let result = vm.call_method(indexed_value, "__setitem__", PositionalParameters {
+        params: vec![index_value, value],
+        kwargs: vec![],
+    });
+    match result {
+        Some((_, popped_frame)) => {
+            if let Some(exception) = popped_frame.exception {
+                vm.raise_exception(exception);
+            }
+        }
+        None => {
+            let type_name = vm.get_type_name(vm.get_pyobj_byaddr(indexed_value).type_addr).to_string();
+            let exception = vm.create_exception("TypeError", format!("'{}' object does not support item assignment", type_name));
+            vm.raise_exception(exception);
+        }
+    }
+}
+
+//`item in container` calls container.__contains__(item)
+pub fn handle_compare_in(vm: &VM, negate: bool) {
+    let container = vm.pop_stack();
+    let item = vm.pop_stack();
+
+    match vm.call_method(container, "__contains__", PositionalParameters::single(item)) {
+        Some((result, popped_frame)) => {
+            if let Some(exception) = popped_frame.exception {
+                vm.raise_exception(exception);
+                vm.push_onto_stack(vm.special_values[&SpecialValue::NoneValue]);
+                return;
+            }
+            let found = vm.is_truthy(result);
+            if found != negate {
+                vm.push_onto_stack(vm.builtin_type_addrs.true_val);
+            } else {
+                vm.push_onto_stack(vm.builtin_type_addrs.false_val);
+            }
+        }
+        None => {
+            let type_name = vm.get_type_name(vm.get_pyobj_byaddr(container).type_addr).to_string();
+            raise_in_expression(vm, "TypeError", format!("argument of type '{}' is not iterable", type_name));
+        }
+    }
+}
+
 pub fn handle_jump_unconditional(vm: &VM, destination: usize) {
     vm.set_pc(destination);
 }
@@ -581,18 +997,248 @@ pub fn handle_store_attr(vm: &VM, code: &CodeObjectContext, attr_name: usize) {
     vm.increase_refcount(value);
 }
 
-pub fn execute_next_instruction(vm: &VM, code: &CodeObjectContext) {
+//The return value stays on top of the stack, the caller will pick it up.
+//If the return happens inside a try with a finally body, the finally body runs first.
+pub fn handle_return(vm: &VM, code: &CodeObjectContext, return_value: MemoryAddress) {
+    if !vm.return_through_finally(return_value) {
+        let instructions_len = code.code.instructions.len();
+        vm.set_pc(instructions_len);
+    }
+}
+
+//Resumes what was going on before the finally body started. Returns whether the pc should advance.
+pub fn handle_end_finally(vm: &VM, code: &CodeObjectContext) -> bool {
+    match vm.pop_finally_reason() {
+        FinallyReason::Normal => true,
+        FinallyReason::Exception(exception) => {
+            //the exception handling at the end of execute_next_instruction deals with it
+            vm.reraise_exception(exception);
+            true
+        }
+        FinallyReason::Return(return_value) => {
+            vm.push_onto_stack(return_value);
+            handle_return(vm, code, return_value);
+            false
+        }
+        FinallyReason::Break { blocks, destination } => {
+            vm.break_through_blocks(blocks, destination);
+            false
+        }
+    }
+}
+
+//Concatenates the iterables that were unpacked with `*` in a call
+pub fn handle_build_tuple_unpack(vm: &VM, number_elements: usize) {
+    let mut iterables = vec![];
+    for _ in 0..number_elements {
+        iterables.push(vm.pop_stack());
+    }
+    iterables.reverse();
+
+    let mut elements = vec![];
+    for iterable in iterables {
+        match vm.collect_iterable(iterable) {
+            Ok(items) => elements.extend(items),
+            Err(exception) => {
+                vm.raise_exception(exception);
+                vm.push_onto_stack(vm.special_values[&SpecialValue::NoneValue]);
+                return;
+            }
+        }
+    }
+    vm.push_onto_stack(tuple_type::new_tuple(vm, elements));
+}
+
+//Merges the dicts that were unpacked with `**` in a call, they become the keyword arguments
+pub fn handle_build_map_unpack_with_call(vm: &VM, number_entries: usize) {
+    let mut mappings = vec![];
+    for _ in 0..number_entries {
+        mappings.push(vm.pop_stack());
+    }
+    mappings.reverse();
+    //below the positional arguments tuple
+    let function_name = vm.get_function_name(vm.get_stack_offset(-1)).to_string();
+
+    let merged = dict_type::new_dict(vm);
+    for mapping in mappings {
+        let entries = match &*vm.get_raw_data_of_pyobj(mapping) {
+            BuiltInTypeData::Dict(dict) => dict.entries.clone(),
+            _ => {
+                let message = format!(
+                    "{}() argument after ** must be a mapping, not {}",
+                    function_name,
+                    vm.get_pyobj_type_name(mapping)
+                );
+                raise_in_expression(vm, "TypeError", message);
+                return;
+            }
+        };
+        for entry in entries {
+            let name = match &*vm.get_raw_data_of_pyobj(entry.key) {
+                BuiltInTypeData::String(name) => name.clone(),
+                _ => {
+                    raise_in_expression(vm, "TypeError", format!("{}() keywords must be strings", function_name));
+                    return;
+                }
+            };
+            match dict_type::dict_get(vm, merged, entry.key) {
+                Ok(Some(_)) => {
+                    let message = format!("{}() got multiple values for keyword argument '{}'", function_name, name);
+                    raise_in_expression(vm, "TypeError", message);
+                    return;
+                }
+                Ok(None) => {}
+                Err(exception) => {
+                    vm.raise_exception(exception);
+                    vm.push_onto_stack(vm.special_values[&SpecialValue::NoneValue]);
+                    return;
+                }
+            }
+            dict_type::dict_set(vm, merged, entry.key, entry.value).unwrap();
+        }
+    }
+    vm.push_onto_stack(merged);
+}
+
+pub fn handle_make_class(vm: &VM, number_bases: usize) {
+    let name_addr = vm.pop_stack();
+    let closure_addr = vm.pop_stack();
+    let codeobj_addr = vm.pop_stack();
+    let mut bases = vec![];
+    for _ in 0..number_bases {
+        bases.push(vm.pop_stack());
+    }
+    bases.reverse();
+
+    let class_name = vm.get_pyobj_byaddr(name_addr).try_get_builtin().unwrap().take_string().clone();
+
+    //only classes deriving from object can be extended: builtin types keep their values as raw data
+    //that a ClassInstance wouldn't have
+    for base in bases.iter() {
+        let is_type = matches!(vm.get_pyobj_byaddr(*base).structure, PyObjectStructure::Type { .. });
+        if !is_type {
+            let message = format!("bases must be types, not '{}'", vm.get_pyobj_type_name(*base));
+            raise_in_expression(vm, "TypeError", message);
+            return;
+        }
+        if !vm.get_mro(*base).contains(&vm.builtin_type_addrs.object) {
+            let message = format!("type '{}' is not an acceptable base type", vm.get_type_name(*base));
+            raise_in_expression(vm, "TypeError", message);
+            return;
+        }
+    }
+    if bases.is_empty() {
+        bases.push(vm.builtin_type_addrs.object);
+    }
+    let mro = match vm.compute_mro(&bases) {
+        Some(mro) => mro,
+        None => {
+            let base_names: Vec<String> = bases.iter().map(|base| vm.get_type_name(*base)).collect();
+            let message = format!(
+                "Cannot create a consistent method resolution order (MRO) for bases {}",
+                base_names.join(", ")
+            );
+            raise_in_expression(vm, "TypeError", message);
+            return;
+        }
+    };
+
+    let class_code = vm.get_pyobj_byaddr(codeobj_addr).try_get_builtin().unwrap().take_code_object().clone();
+            
+    vm.new_stack_frame(&class_name);
+    //class bodies have no cellvars, only the closure
+    vm.set_cells(vm.get_raw_data_of_pyobj(closure_addr).take_tuple().clone());
+    
+    //execute the class code
+    execute_code_object(vm, &class_code);
+
+    let popped_stack_frame = vm.pop_stack_frame();
+    if let Some(exception) = popped_stack_frame.exception {
+        vm.raise_exception(exception);
+        vm.push_onto_stack(vm.special_values[&SpecialValue::NoneValue]);
+        return;
+    }
+
+    let mut namespace = std::collections::BTreeMap::<String, MemoryAddress>::new();
+    
+    //and observe what changed in the current stack frame namespace 
+    let namespace_values = popped_stack_frame.local_namespace;
+    //a body with only `pass` binds nothing
+    for (name, value) in class_code.code.names.iter().zip(namespace_values.iter()) {
+        //Insert the value as-is in the namespace
+        namespace.insert(name.clone(), *value);
+    }
+
+    let type_addr = vm.allocate_type(&class_name, Some(bases[0]));
+    vm.set_bases(type_addr, &bases, mro);
+
+    //Registers the regular functions on the type, even those that take the self parameter
+    //They will be accessed using `ClassName.function_name`
+    for (key, value) in namespace.iter() {
+        //println!("Registering method addr {} on type {}", key, class_name);
+        vm.register_method_addr_on_type(type_addr, key, *value);
+        //methods remember their class, so super() knows where to continue the MRO lookup
+        let is_function = matches!(vm.get_pyobj_byaddr(*value).structure, PyObjectStructure::UserDefinedFunction { .. });
+        if is_function {
+            vm.set_attribute(*value, "__class__", type_addr);
+        }
+    }
+
+    //instances of exception subclasses keep the message as raw data, like the builtin exceptions
+    let base_exception = vm.find_in_module(BUILTIN_MODULE, "BaseException").unwrap();
+    let is_exception = vm.is_subtype(type_addr, base_exception);
+
+    vm.register_type_unbounded_func(type_addr, "__new__", move |method_vm: &VM, call_params: CallParams| -> MemoryAddress {
+        let instance = if is_exception {
+            let instance = method_vm.allocate_type_byaddr_raw(type_addr, BuiltInTypeData::String(String::new()));
+            method_vm.set_attribute(instance, "__traceback__", method_vm.special_values[&SpecialValue::NoneValue]);
+            instance
+        } else {
+            method_vm.allocate_type_byaddr_raw(type_addr, BuiltInTypeData::ClassInstance)
+        };
+        method_vm.increase_refcount(instance);
+        method_vm.increase_refcount(instance);
+
+        if let Some((_, frame)) = method_vm.call_method(instance, "__init__", call_params.params) {
+            if let Some(exception) = frame.exception {
+                method_vm.raise_exception(exception);
+            }
+        }
+        
+        return instance;
+    });
+
+    vm.push_onto_stack(type_addr);
+}
+
+//Returns false when a generator suspends, its frame must not run any further for now
+pub fn execute_next_instruction(vm: &VM, code: &CodeObjectContext) -> bool {
+    if let Some((exception_type, message)) = vm.check_interrupts() {
+        let exception = vm.create_exception(exception_type, String::from(message));
+        vm.raise_exception(exception);
+        unwind_or_finish(vm, code);
+        return true;
+    }
     let mut advance_pc = true;
+    let mut suspended = false;
     let instruction = code.code.instructions.get(vm.get_pc()).unwrap();
     //println!(">> {:?} {:?} at {:?}", vm.get_pc(), instruction, code.code.objname);
     //vm.print_stack();
     match instruction {
         Instruction::LoadConst(c) => handle_load_const(vm, code, *c),
         Instruction::CallFunction { number_arguments } => handle_function_call(vm, *number_arguments),
+        Instruction::CallFunctionKw { number_arguments, keywords } => {
+            handle_function_call_kw(vm, *number_arguments, keywords)
+        }
+        Instruction::CallFunctionEx => handle_function_call_ex(vm),
         Instruction::LoadName(name) => handle_load_name(vm, code, *name),
         Instruction::LoadGlobal(name) => handle_load_global(vm, code, *name),
         Instruction::LoadAttr(name) => handle_load_attr(vm, name),
         Instruction::StoreName(name) => handle_store_name(vm, *name),
+        Instruction::StoreGlobal(name) => handle_store_global(vm, code, *name),
+        Instruction::LoadDeref(cell) => handle_load_deref(vm, code, *cell),
+        Instruction::StoreDeref(cell) => handle_store_deref(vm, *cell),
+        Instruction::BuildClosure { cells } => handle_build_closure(vm, cells),
         Instruction::BinaryAdd => handle_binary_add(vm),
         Instruction::BinaryModulus => handle_binary_mod(vm),
         Instruction::BinarySubtract => handle_binary_sub(vm),
@@ -610,87 +1256,73 @@ pub fn execute_next_instruction(vm: &VM, code: &CodeObjectContext) {
         Instruction::BuildList { number_elements } => {
             handle_build_list(vm, *number_elements)
         }
+        Instruction::BuildTuple { number_elements } => {
+            handle_build_tuple(vm, *number_elements)
+        }
+        Instruction::UnpackSequence { number_elements } => {
+            handle_unpack_sequence(vm, *number_elements)
+        }
+        Instruction::BuildMap { number_entries } => {
+            handle_build_map(vm, *number_entries)
+        }
+        Instruction::BuildTupleUnpack { number_elements } => {
+            handle_build_tuple_unpack(vm, *number_elements)
+        }
+        Instruction::BuildSet { number_elements } => {
+            handle_build_set(vm, *number_elements)
+        }
+        Instruction::BuildMapUnpackWithCall { number_entries } => {
+            handle_build_map_unpack_with_call(vm, *number_entries)
+        }
+        Instruction::StoreIndex => handle_store_index(vm),
+        Instruction::CompareIn => handle_compare_in(vm, false),
+        Instruction::CompareNotIn => handle_compare_in(vm, true),
         Instruction::JumpUnconditional(destination) => {
             handle_jump_unconditional(vm, *destination);
             advance_pc = false;
         }
-        Instruction::MakeFunction(has_default_params) => {
+        Instruction::JumpForward(delta) => {
+            handle_jump_unconditional(vm, vm.get_pc() + 1 + delta);
+            advance_pc = false;
+        }
+        Instruction::PopJumpForwardIfFalse(delta) => {
+            advance_pc = !handle_jump_if_false_pop(vm, vm.get_pc() + 1 + delta)
+        }
+        Instruction::MakeFunction => {
             let name_addr = vm.pop_stack();
+            let closure_addr = vm.pop_stack();
             let codeobj_addr = vm.pop_stack();
 
             let qualname = vm.get_pyobj_byaddr(name_addr).try_get_builtin().unwrap().take_string().clone();
             let codeobj = vm.get_pyobj_byaddr(codeobj_addr).try_get_builtin().unwrap().take_code_object().clone();
-            
-
-
-            let function_addr = if *has_default_params {
-                //When there are default params, we will do something sneaky
-                //the previous instructions will actually build a list of positional arguments
-                //allowing the function to be called passing a variable numer of arguments.
-
-                //So yes, the top stack parameter is a list of memory addresses, a python list,
-                //containing the positional parameters.
-
-                //We'll have to calculate how many of the default parameters we will use 
-                //at the time the function is called.
-
-                //@TODO remember to add a check in the parser: default arguments must be the last parameters of the function.
-
-                let default_params = vm.pop_stack();
-                let as_list = vm.get_raw_data_of_pyobj(default_params).take_list();
-
-                vm.allocate_user_defined_function(codeobj, qualname.clone(), as_list.to_vec())
-            } else {
-                vm.allocate_user_defined_function(codeobj, qualname.clone(), vec![])
-            };
-            vm.add_to_module(MAIN_MODULE, qualname.as_str(), function_addr);
-            vm.push_onto_stack(function_addr);
-        }
-        Instruction::MakeClass => {
-            let name_addr = vm.pop_stack();
-            let codeobj_addr = vm.pop_stack();
 
-            let class_name = vm.get_pyobj_byaddr(name_addr).try_get_builtin().unwrap().take_string().clone();
-
-            let class_code = vm.get_pyobj_byaddr(codeobj_addr).try_get_builtin().unwrap().take_code_object().clone();
-                    
-            vm.new_stack_frame(&class_name);
-            
-            //execute the class code
-            execute_code_object(vm, &class_code);
-
-            let popped_stack_frame = vm.pop_stack_frame();
-
-            let mut namespace = std::collections::BTreeMap::<String, MemoryAddress>::new();
-            
-            //and observe what changed in the current stack frame namespace 
-            let namespace_values = popped_stack_frame.local_namespace;
-            for (index, name) in class_code.code.names.iter().enumerate() {
-                //Insert the value as-is in the namespace
-                namespace.insert(name.clone(), namespace_values[index]);
+            //The previous instructions built a dict with the defaults of keyword-only parameters,
+            //and a list with the defaults of the last positional parameters.
+            //We'll have to calculate how many of the default parameters we will use 
+            //at the time the function is called.
+            let kwonly_defaults_addr = vm.pop_stack();
+            let kwonly_defaults: Vec<(String, MemoryAddress)> = vm
+                .get_raw_data_of_pyobj(kwonly_defaults_addr)
+                .take_dict()
+                .entries
+                .iter()
+                .map(|entry| (vm.get_raw_data_of_pyobj(entry.key).take_string().clone(), entry.value))
+                .collect();
+            let default_params = vm.pop_stack();
+            let defaults = vm.get_raw_data_of_pyobj(default_params).take_list().to_vec();
+            for value in defaults.iter().chain(kwonly_defaults.iter().map(|(_, value)| value)) {
+                vm.increase_refcount(*value);
             }
 
-            let type_addr = vm.create_type(MAIN_MODULE, &class_name.clone(), None);
-
-            //Registers the regular functions on the type, even those that take the self parameter
-            //They will be accessed using `ClassName.function_name`
-            for (key, value) in namespace.iter() {
-                //println!("Registering method addr {} on type {}", key, class_name);
-                vm.register_method_addr_on_type(type_addr, key, *value);
+            let closure = vm.get_raw_data_of_pyobj(closure_addr).take_tuple().clone();
+            for cell in closure.iter() {
+                vm.increase_refcount(*cell);
             }
 
-            vm.register_type_unbounded_func(type_addr, "__new__", move |method_vm: &VM, call_params: CallParams| -> MemoryAddress {
-                let instance = method_vm.allocate_type_byaddr_raw(type_addr, BuiltInTypeData::ClassInstance);
-                method_vm.increase_refcount(instance);
-                method_vm.increase_refcount(instance);
-
-                method_vm.call_method(instance, "__init__", call_params.params);
-                
-                return instance;
-            });
-
-            vm.push_onto_stack(type_addr);
+            let function_addr = vm.allocate_user_defined_function(codeobj, qualname, defaults, kwonly_defaults, closure);
+            vm.push_onto_stack(function_addr);
         }
+        Instruction::MakeClass { number_bases } => handle_make_class(vm, *number_bases),
         Instruction::PopTop => {
             vm.pop_stack();
         }
@@ -698,8 +1330,8 @@ pub fn execute_next_instruction(vm: &VM, code: &CodeObjectContext) {
             let top = vm.top_stack();
             //increase counter because it is being used by the current function
             vm.increase_refcount(top);
-            let instructions_len = code.code.instructions.len();
-            vm.set_pc(instructions_len);
+            handle_return(vm, code, top);
+            advance_pc = false;
         }
         Instruction::StoreAttr(attr_name) => {
             handle_store_attr(vm, code, *attr_name);
@@ -708,32 +1340,96 @@ pub fn execute_next_instruction(vm: &VM, code: &CodeObjectContext) {
             let index_value = vm.pop_stack();
             let indexed_value = vm.pop_stack();
 
-            //for now we only accept integer indexing on lists and strings
-            let index_int = vm.get_raw_data_of_pyobj(index_value).take_int();
-            let list = vm.get_raw_data_of_pyobj(indexed_value).take_list();
-
-            vm.push_onto_stack(list[index_int as usize]);
+            //goes through __getitem__ so that out of range accesses raise IndexError
+            match vm.call_method(indexed_value, "__getitem__", PositionalParameters::single(index_value)) {
+                Some((result, popped_frame)) => {
+                    if let Some(exception) = popped_frame.exception {
+                        vm.raise_exception(exception);
+                    }
+                    vm.push_onto_stack(result);
+                }
+                None => {
+                    let type_name = vm.get_type_name(vm.get_pyobj_byaddr(indexed_value).type_addr).to_string();
+                    raise_in_expression(vm, "TypeError", format!("'{}' object is not subscriptable", type_name));
+                }
+            }
         }
         Instruction::Raise => {
             let exception_value = vm.pop_stack();
-            vm.raise_exception(exception_value);
+            //`raise ValueError` raises an instance of the class, which can hold the traceback
+            let is_class = matches!(vm.get_pyobj_byaddr(exception_value).structure, PyObjectStructure::Type { .. });
+            if is_class {
+                let (instance, frame) = vm.run_function(PositionalParameters::empty(), exception_value, None);
+                vm.raise_exception(frame.exception.unwrap_or(instance));
+            } else {
+                vm.raise_exception(exception_value);
+            }
+        }
+        Instruction::Reraise => {
+            let exception_value = vm.pop_stack();
+            vm.reraise_exception(exception_value);
+        }
+        Instruction::SetupExcept(handler) => vm.setup_block(BlockType::Except, *handler),
+        Instruction::SetupFinally(handler) => vm.setup_block(BlockType::Finally, *handler),
+        Instruction::PopBlock => {
+            vm.pop_block();
+        }
+        Instruction::BeginFinally => vm.push_finally_reason(FinallyReason::Normal),
+        Instruction::EndFinally => {
+            advance_pc = handle_end_finally(vm, code);
+        }
+        Instruction::JumpIfNotExceptionMatch(destination) => {
+            let exception_type = vm.pop_stack();
+            let exception = vm.top_stack();
+            if !vm.exception_matches_handler(exception, exception_type) {
+                vm.set_pc(*destination);
+                advance_pc = false;
+            }
+        }
+        Instruction::BreakThroughBlocks { blocks, destination } => {
+            vm.break_through_blocks(*blocks, *destination);
+            advance_pc = false;
+        }
+        Instruction::ImportName(name) => match importer::import_module(vm, name) {
+            Ok(module) => vm.push_onto_stack(module),
+            Err(exception) => vm.raise_exception(exception),
+        },
+        Instruction::ImportFrom(name) => match importer::import_from(vm, vm.top_stack(), name) {
+            Ok(value) => vm.push_onto_stack(value),
+            Err(exception) => vm.raise_exception(exception),
+        },
+        Instruction::YieldValue => suspended = true,
+        Instruction::YieldFrom => {
+            //keeps running YieldFrom until the sub-iterator is exhausted
+            suspended = handle_yield_from(vm);
+            advance_pc = false;
         }
         Instruction::ForIter(end_ptr) => {
             //TOS is the iterator object
             let iterator = vm.top_stack();
 
             //this assumes the iterator is on the top of the call already
-            let (next, popped_frame) = vm.call_method(iterator, "__next__", PositionalParameters::empty()).unwrap();
-            
-            //This effectivelly catches the exception. This is weird in python: why 
-            //use an ***exception*** to stop iteration? Makes no sense!
-            if let Some(exception_addr) = popped_frame.exception {
-                if exception_addr == vm.special_values[&SpecialValue::StopIterationType] {
-                    vm.set_pc(*end_ptr);
-                    advance_pc = false;
+            match vm.call_method(iterator, "__next__", PositionalParameters::empty()) {
+                //an __iter__ that returned something without __next__
+                None => {
+                    let message = format!("iter() returned non-iterator of type '{}'", vm.get_pyobj_type_name(iterator));
+                    let exception = vm.create_exception("TypeError", message);
+                    vm.raise_exception(exception);
                 }
-            } else {
-                vm.push_onto_stack(next);   
+                //This effectivelly catches the exception. This is weird in python: why 
+                //use an ***exception*** to stop iteration? Makes no sense!
+                Some((_, popped_frame)) if popped_frame.exception.is_some() => {
+                    let exception_addr = popped_frame.exception.unwrap();
+                    if vm.exception_matches(exception_addr, vm.special_values[&SpecialValue::StopIterationType]) {
+                        //the loop is over, the iterator is not needed anymore
+                        vm.pop_stack();
+                        vm.set_pc(*end_ptr);
+                        advance_pc = false;
+                    } else {
+                        vm.raise_exception(exception_addr);
+                    }
+                }
+                Some((next, _)) => vm.push_onto_stack(next),
             }
 
             //unimplemented!();
@@ -744,11 +1440,8 @@ pub fn execute_next_instruction(vm: &VM, code: &CodeObjectContext) {
     }
     
     if let Some(_) = vm.get_current_exception() {
-        //if an exception happened, then finish execution immediately, push None on stack
-        vm.push_onto_stack(vm.special_values[&SpecialValue::NoneValue]);
-        let instructions_len = code.code.instructions.len();
-        vm.set_pc(instructions_len);
         advance_pc = false;
+        unwind_or_finish(vm, code);
     }
 
 
@@ -756,73 +1449,154 @@ pub fn execute_next_instruction(vm: &VM, code: &CodeObjectContext) {
     if advance_pc {
         vm.jump_pc(1);
     }
+    return !suspended;
+}
+
+//Tries to find a handler in this frame, otherwise the exception goes to the caller
+fn unwind_or_finish(vm: &VM, code: &CodeObjectContext) {
+    if !vm.unwind_exception() {
+        //if an exception happened, then finish execution immediately, push None on stack
+        vm.push_onto_stack(vm.special_values[&SpecialValue::NoneValue]);
+        let instructions_len = code.code.instructions.len();
+        vm.set_pc(instructions_len);
+    }
+}
+
+//The sub-iterator of a `yield from` is done: it's replaced by its return value, the StopIteration's value
+fn finish_yield_from(vm: &VM, stop_iteration: MemoryAddress) {
+    vm.pop_stack();
+    let value = vm
+        .get_pyobj_byaddr(stop_iteration)
+        .properties
+        .get("value")
+        .copied()
+        .unwrap_or(vm.special_values[&SpecialValue::NoneValue]);
+    vm.push_onto_stack(value);
+}
+
+//Sends TOS to the sub-iterator below it. Returns whether the generator suspends with the value it yielded.
+fn handle_yield_from(vm: &VM) -> bool {
+    let sent = vm.pop_stack();
+    let sub_iterator = vm.top_stack();
+    let (method, params) = if sent == vm.special_values[&SpecialValue::NoneValue] {
+        ("__next__", PositionalParameters::empty())
+    } else {
+        ("send", PositionalParameters::single(sent))
+    };
+    let (result, popped_frame) = match vm.call_method(sub_iterator, method, params) {
+        Some(call) => call,
+        None => {
+            let message = format!("'{}' object has no attribute '{}'", vm.get_pyobj_type_name(sub_iterator), method);
+            vm.raise_exception(vm.create_exception("AttributeError", message));
+            return false;
+        }
+    };
+    match popped_frame.exception {
+        None => {
+            vm.push_onto_stack(result);
+            true
+        }
+        Some(exception) if vm.exception_matches(exception, vm.special_values[&SpecialValue::StopIterationType]) => {
+            finish_yield_from(vm, exception);
+            vm.jump_pc(1);
+            false
+        }
+        Some(exception) => {
+            vm.raise_exception(exception);
+            false
+        }
+    }
+}
+
+//Raises an exception where the generator is suspended, for generator.throw().
+//A `yield from` passes it on to the sub-iterator first, if it has a throw method.
+//Returns false if the sub-iterator yielded a value instead, which is left on the stack.
+pub fn throw_into_frame(vm: &VM, code: &CodeObjectContext, mut exception: MemoryAddress) -> bool {
+    if let Some(Instruction::YieldFrom) = code.code.instructions.get(vm.get_pc()) {
+        let sub_iterator = vm.top_stack();
+        let throw = vm.get_method_addr_byname(vm.get_pyobj_type_addr(sub_iterator), "throw");
+        if let Some(throw) = throw {
+            let (result, popped_frame) = vm.run_function(PositionalParameters::single(exception), throw, Some(sub_iterator));
+            match popped_frame.exception {
+                None => {
+                    vm.push_onto_stack(result);
+                    return false;
+                }
+                Some(raised) if vm.exception_matches(raised, vm.special_values[&SpecialValue::StopIterationType]) => {
+                    finish_yield_from(vm, raised);
+                    vm.jump_pc(1);
+                    return true;
+                }
+                Some(raised) => exception = raised,
+            }
+        }
+    }
+    vm.raise_exception(exception);
+    unwind_or_finish(vm, code);
+    return true;
 }
 
 pub fn execute_code_object(vm: &VM, code: &CodeObjectContext) {
+    vm.set_current_code(code.code.clone());
     loop {
         if vm.get_pc() >= code.code.instructions.len() {
             return;
         }
-       
-        execute_next_instruction(vm, &code);
+        vm.collect_garbage_if_due();
+        if vm.heap_limit_exceeded() {
+            let exception = vm.create_exception("MemoryError", String::from("heap limit exceeded"));
+            vm.raise_exception(exception);
+            unwind_or_finish(vm, code);
+            continue;
+        }
+
+        if !execute_next_instruction(vm, code) {
+            return;
+        }
     }
 }
 
 
-fn register_codeobj_consts(vm: &VM, codeobj: &CodeObject) -> CodeObjectContext {
+fn register_codeobj_consts(vm: &VM, codeobj: &CodeObject, module: MemoryAddress) -> CodeObjectContext {
     let mut consts = vec![];
     for c in codeobj.consts.iter() {
-        let memaddr = get_const_memaddr(vm, c);
+        let memaddr = get_const_memaddr(vm, c, module);
         consts.push(memaddr);
     }
     CodeObjectContext{
-        code: codeobj.clone(), 
-        consts: consts
+        code: Rc::new(codeobj.clone()), 
+        consts: consts.into(),
+        module,
     }
 }
 
-#[allow(dead_code)]
-//#[cfg(test)]
-fn print_codeobj(codeobj: &CodeObject, codeobj_name: Option<String>) {
-    for inst in codeobj.consts.iter() {
-        if let Const::CodeObject(obj) = inst {
-            print_codeobj(obj, Some(obj.objname.clone()));
-        }
-    }
-
-    println!("\nInstructions of code object {:?}", codeobj_name);
-    for (index, inst) in codeobj.instructions.iter().enumerate() {
-        if let Instruction::LoadConst(n) = inst {
-            let constval =  &codeobj.consts[*n];
-            if let Const::CodeObject(obj) = constval {
-                println!("{} - {:?} => code object {}", index, inst, obj.objname);
-            } else {
-                println!("{} - {:?} => constval = {:?}", index, inst, constval);
-            }
-        } 
-        else if let Instruction::LoadGlobal(n) = inst {
-            println!("{} - {:?} => global name = {:?}", index, inst, &codeobj.names[*n]);
-        }
-        else if let Instruction::LoadName(n) = inst {
-            println!("{} - {:?} => name = {:?}", index, inst, &codeobj.names[*n]);
-        }
-        else if let Instruction::StoreName(n) = inst {
-            println!("{} - {:?} => name = {:?}", index, inst, &codeobj.names[*n]);
-        }
-        else if let Instruction::StoreAttr(n) = inst {
-            println!("{} - {:?} => name = {:?}", index, inst, &codeobj.names[*n]);
-        }
-        else {
-            println!("{} - {:?}", index, inst);
-        }
-    }
+pub fn execute_program(vm: &mut VM, program: Program) -> Result<(), RuntimeError> {
+    execute_program_in_module(vm, program, MAIN_MODULE)
 }
 
-pub fn execute_program(vm: &mut VM, program: Program) {
-    //print_codeobj(&program.code_objects[0], None);
-
+//Runs the program in the current frame, with the globals of the given module
+pub fn execute_program_in_module(vm: &mut VM, program: Program, module: &str) -> Result<(), RuntimeError> {
     let main_code = program.code_objects.iter().find(|x| x.main).unwrap();
-    let main_codeobj_ctx = register_codeobj_consts(vm, main_code);
+    let module = vm.find_module(module).unwrap();
+    let main_codeobj_ctx = register_codeobj_consts(vm, main_code, module);
      
     execute_code_object(vm, &main_codeobj_ctx);
-}
\ No newline at end of file
+
+    //an exception that reached the main frame was not handled by anyone
+    if let Some(exception) = vm.get_current_exception() {
+        vm.clear_exception();
+        return Err(vm.exception_to_error(exception));
+    }
+    return Ok(());
+}
+//Runs the code of an imported module in a frame of its own. Returns the exception that escaped from it, if any.
+pub fn execute_module(vm: &VM, program: Program, module: MemoryAddress) -> Result<(), MemoryAddress> {
+    let module_code = program.code_objects.iter().find(|x| x.main).unwrap();
+    let module_codeobj_ctx = register_codeobj_consts(vm, module_code, module);
+    vm.new_stack_frame(&vm.get_module_name(module));
+    execute_code_object(vm, &module_codeobj_ctx);
+    match vm.pop_stack_frame().exception {
+        Some(exception) => Err(exception),
+        None => Ok(()),
+    }
+}
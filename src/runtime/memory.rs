@@ -1,13 +1,18 @@
 use crate::runtime::datamodel::*;
-use std::cell::RefCell;
+use crate::runtime::vm::StackFrame;
+use elsa::FrozenVec;
+use std::cell::{Cell, Ref, RefCell, RefMut};
 use std::collections::BTreeMap;
+use std::fmt;
+use std::mem::size_of;
 
-
+//Objects are borrowed through guards, like a RefCell: reading an object while it's being
+//changed (or deallocating it while it's borrowed) panics instead of aliasing.
 pub trait Memory {
 
-    fn get(&self, address: MemoryAddress) -> &PyObject;
+    fn get(&self, address: MemoryAddress) -> Ref<'_, PyObject>;
 
-    fn get_mut(&self, address: MemoryAddress) -> &mut PyObject;
+    fn get_mut(&self, address: MemoryAddress) -> RefMut<'_, PyObject>;
 
     fn make_const(&self, address: MemoryAddress);
 
@@ -18,289 +23,583 @@ pub trait Memory {
     fn allocate_and_write_builtin(&self, type_addr: MemoryAddress, data: BuiltInTypeData) -> MemoryAddress;
 
     fn null_ptr(&self) -> MemoryAddress;
+
+    fn live_objects(&self) -> usize;
+
+    //Roughly how much memory the live objects use, as of the last time they were measured
+    fn heap_bytes(&self) -> usize;
+
+    fn heap_stats(&self) -> HeapStats;
+}
+
+//What kind of object it is: the variant of its builtin data, or its structure for functions, types and modules
+const KINDS: [&str; 20] = [
+    "int", "float", "str", "list", "tuple", "dict", "set", "instance", "code", "traceback", "super", "cell",
+    "generator", "function", "builtin_function", "method", "type", "module", "NoneType", "NotImplementedType",
+];
+
+fn kind_of(object: &PyObject) -> usize {
+    match &object.structure {
+        PyObjectStructure::Object { raw_data, .. } => match raw_data {
+            BuiltInTypeData::Int(_) => 0,
+            BuiltInTypeData::Float(_) => 1,
+            BuiltInTypeData::String(_) => 2,
+            BuiltInTypeData::List(_) => 3,
+            BuiltInTypeData::Tuple(_) => 4,
+            BuiltInTypeData::Dict(_) => 5,
+            BuiltInTypeData::Set(_) => 6,
+            BuiltInTypeData::ClassInstance => 7,
+            BuiltInTypeData::CodeObject(_) => 8,
+            BuiltInTypeData::Traceback(_) => 9,
+            BuiltInTypeData::Super { .. } => 10,
+            BuiltInTypeData::Cell(_) => 11,
+            BuiltInTypeData::Generator(_) => 12,
+        },
+        PyObjectStructure::UserDefinedFunction { .. } => 13,
+        PyObjectStructure::NativeCallable { .. } => 14,
+        PyObjectStructure::BoundMethod { .. } => 15,
+        PyObjectStructure::Type { .. } => 16,
+        PyObjectStructure::Module { .. } => 17,
+        PyObjectStructure::None => 18,
+        PyObjectStructure::NotImplemented => 19,
+    }
 }
 
+fn frame_size(frame: &StackFrame) -> usize {
+    let addresses = frame.local_namespace.capacity() + frame.stack.capacity() + frame.cells.capacity();
+    size_of::<StackFrame>() + addresses * size_of::<MemoryAddress>()
+}
 
-/*
-pub type MemoryAddress = usize;
-pub struct MemoryCell {
-    pub data: Option<PyObject>,
-    pub valid: bool,
-    pub is_const: bool,
+//The object, plus what its strings, vectors and maps take on the heap. Code is shared by every
+//function made from it, so it's not counted.
+fn approximate_size(object: &PyObject) -> usize {
+    let address = size_of::<MemoryAddress>();
+    let entry = size_of::<String>() + address;
+    let properties: usize = object.properties.keys().map(|name| name.len() + entry).sum();
+    let data = match &object.structure {
+        PyObjectStructure::Object { raw_data, .. } => match raw_data {
+            BuiltInTypeData::String(s) => s.capacity(),
+            BuiltInTypeData::List(elements) | BuiltInTypeData::Tuple(elements) => elements.capacity() * address,
+            //the entries, and the index by hash
+            BuiltInTypeData::Dict(dict) | BuiltInTypeData::Set(dict) => {
+                dict.entries.capacity() * size_of::<DictEntry>() + dict.len() * (size_of::<i128>() + size_of::<Vec<usize>>())
+            }
+            BuiltInTypeData::Traceback(entries) => entries
+                .iter()
+                .map(|entry| size_of::<TracebackEntry>() + entry.filename.len() + entry.function_name.len())
+                .sum(),
+            BuiltInTypeData::Generator(data) => data.frame.as_deref().map_or(0, frame_size),
+            _ => 0,
+        },
+        PyObjectStructure::UserDefinedFunction { qualname, defaults, kwonly_defaults, closure, .. } => {
+            qualname.len() + (defaults.len() + closure.len()) * address + kwonly_defaults.len() * entry
+        }
+        PyObjectStructure::Type { name, functions, mro, .. } => {
+            name.len() + functions.keys().map(|name| name.len() + entry).sum::<usize>() + mro.len() * address
+        }
+        PyObjectStructure::Module { name, global_namespace } => {
+            name.len() + global_namespace.keys().map(|name| name.len() + entry).sum::<usize>()
+        }
+        _ => 0,
+    };
+    size_of::<Slot>() + properties + data
 }
 
-pub struct VecRefCellMemory {
-    pub memory: RefCell<Vec<MemoryCell>>,
-    //gc graph stores: Key = memory addresses, Values = Other adresses that point to that memory addr
-    //Every memory address has an entry here
-    //pub gc_graph: HashMap<AddressType, Vec<AddressType>>,
-    pub recently_deallocated_indexes: RefCell<Vec<usize>>,
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KindStats {
+    pub kind: &'static str,
+    //since the VM started, including the objects freed since
+    pub allocated: usize,
+    pub live: usize,
+    pub bytes: usize,
 }
 
-impl VecRefCellMemory {
-    pub fn new() -> Self {
-        Self {
-            memory: RefCell::new(vec![]),
-            recently_deallocated_indexes: RefCell::new(vec![])
+//The objects alive right now and roughly how much memory they use, by kind
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeapStats {
+    pub live_objects: usize,
+    pub bytes: usize,
+    //only the kinds that were ever allocated
+    pub kinds: Vec<KindStats>,
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<20}{:>12}{:>12}{:>12}", "kind", "allocated", "live", "bytes")?;
+        for kind in self.kinds.iter() {
+            writeln!(f, "{:<20}{:>12}{:>12}{:>12}", kind.kind, kind.allocated, kind.live, kind.bytes)?;
         }
+        let allocated: usize = self.kinds.iter().map(|kind| kind.allocated).sum();
+        writeln!(f, "{:<20}{:>12}{:>12}{:>12}", "total", allocated, self.live_objects, self.bytes)
     }
 }
 
-impl Memory for VecRefCellMemory {
+//A handle to an object: the slot of the arena it's in, and which of the objects that lived
+//in that slot it is. Slots are reused after a deallocation, with the next generation, so a
+//handle kept around after its object is gone no longer matches and is caught in O(1).
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MemoryAddress {
+    index: u32,
+    generation: u32,
+}
 
-    fn get(&self, address: MemoryAddress) -> &PyObject {
-        let mem_borrow = self.memory.borrow_mut();
-        let cell = &mem_borrow[address];
-        if cell.valid {
-            return unsafe { 
-                let const_ptr = cell.data.as_ref().unwrap() as *const PyObject;
-                let mut_ptr = const_ptr as *mut PyObject;
-                &*mut_ptr
-            };
-        } else {
-            panic!("Attempt to read from non-valid memory address {}", address);
+const NULL_INDEX: u32 = u32::MAX;
+
+impl MemoryAddress {
+    //Unique among the objects alive at the same time, used for identity hashes and reprs
+    pub fn id(self) -> u64 {
+        (self.generation as u64) << 32 | self.index as u64
+    }
+
+    pub fn is_null(self) -> bool {
+        self.index == NULL_INDEX
+    }
+}
+
+impl fmt::Debug for MemoryAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_null() {
+            return write!(f, "null");
         }
+        write!(f, "{:#x}", self.id())
     }
+}
 
-    fn get_mut(&self, address:  MemoryAddress) -> &mut PyObject {
-        let mut mem_borrow = self.memory.borrow_mut();
-        let cell = &mut mem_borrow[address];
-        if cell.valid {
-            return unsafe { 
-                let const_ptr = cell.data.as_ref().unwrap() as *const PyObject;
-                let mut_ptr = const_ptr as *mut PyObject;
-                &mut *mut_ptr
-            };
-        } else {
-            panic!("Attempt to read from non-valid memory address {}", address);
+impl fmt::Pointer for MemoryAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+struct Slot {
+    generation: Cell<u32>,
+    //None while the slot is free
+    object: RefCell<Option<PyObject>>,
+    //approximate_size of the object when it was last measured
+    size: Cell<usize>,
+}
+
+//Objects allocated (and not deallocated) since the last collection that start the next one, at least
+const COLLECTION_THRESHOLD: usize = 700;
+
+pub struct ArenaMemory {
+    //boxed, so the slots stay where they are while the arena grows and their objects are borrowed
+    slots: FrozenVec<Box<Slot>>,
+    free_slots: RefCell<Vec<u32>>,
+    //objects in the arena right now, retired slots aren't free but don't count either
+    live: Cell<usize>,
+    //objects allocated and not deallocated since the last collection
+    allocations: Cell<usize>,
+    //grows with the heap, so a big heap isn't traced over and over
+    threshold: Cell<usize>,
+    //gc.disable() stops the automatic collections, gc.collect() still works
+    collection_enabled: Cell<bool>,
+    //the sizes of the slots, added up
+    bytes: Cell<usize>,
+    //objects allocated of each kind, by index in KINDS
+    allocated_by_kind: [Cell<usize>; KINDS.len()],
+}
+
+impl ArenaMemory {
+    pub fn new() -> Self {
+        Self {
+            slots: FrozenVec::new(),
+            free_slots: RefCell::new(vec![]),
+            live: Cell::new(0),
+            allocations: Cell::new(0),
+            threshold: Cell::new(COLLECTION_THRESHOLD),
+            collection_enabled: Cell::new(true),
+            bytes: Cell::new(0),
+            allocated_by_kind: Default::default(),
         }
     }
 
-    fn make_const(&self, address: MemoryAddress) {
-        let mut mem_borrow = self.memory.borrow_mut();
-        let cell = &mut mem_borrow[address];
-        cell.is_const = true;
+    #[inline]
+    fn slot(&self, address: MemoryAddress) -> &Slot {
+        match self.slots.get(address.index as usize) {
+            Some(slot) if slot.generation.get() == address.generation => slot,
+            _ => self.invalid_address(address),
+        }
     }
 
-    fn deallocate(&self, address:  MemoryAddress) {
-        let mut mem_borrow = self.memory.borrow_mut();
-        let cell = &mut mem_borrow[address];
-        if cell.is_const {
-            return;
-        };
-        if cell.valid {
-            cell.valid = false;
-        } else {
-            panic!("Attempt to dealloate already invalid memory at address in non-valid memory address {}", address)
+    //kept out of `slot`, which runs on every object access
+    #[cold]
+    #[inline(never)]
+    fn invalid_address(&self, address: MemoryAddress) -> ! {
+        match self.slots.get(address.index as usize) {
+            Some(slot) => panic!("Trying to get deallocated memory {:?}, the slot now has generation {}", address, slot.generation.get()),
+            None => panic!("Attempt to read from non-valid memory address {:?}", address),
+        }
+    }
+
+    fn free(&self, index: u32, slot: &Slot) {
+        let object = slot.object.borrow_mut().take();
+        self.bytes.set(self.bytes.get() - slot.size.get());
+        slot.size.set(0);
+        let next_generation = slot.generation.get().wrapping_add(1);
+        slot.generation.set(next_generation);
+        //a slot that went through every generation is retired, so old handles can never match it again
+        if next_generation != 0 {
+            self.free_slots.borrow_mut().push(index);
         }
+        self.live.set(self.live.get() - 1);
+        self.allocations.set(self.allocations.get().saturating_sub(1));
+        //the handles it has are just numbers, dropping it doesn't touch other objects
+        drop(object);
+    }
+
+    //The builtin data of an object, in one borrow: this is on the path of every int and str operation
+    #[inline]
+    pub fn get_raw_data(&self, address: MemoryAddress) -> Ref<'_, BuiltInTypeData> {
+        Ref::map(self.slot(address).object.borrow(), |object| match object {
+            Some(PyObject { structure: PyObjectStructure::Object { raw_data, .. }, .. }) => raw_data,
+            object => panic!("get_raw_data cannot be called on {:?} {:p}", object.as_ref().map(|o| &o.structure), address),
+        })
+    }
+
+    #[inline]
+    pub fn get_raw_data_mut(&self, address: MemoryAddress) -> RefMut<'_, BuiltInTypeData> {
+        RefMut::map(self.slot(address).object.borrow_mut(), |object| match object {
+            Some(PyObject { structure: PyObjectStructure::Object { raw_data, .. }, .. }) => raw_data,
+            _ => panic!("get_raw_data_mut cannot be called on a non-object"),
+        })
+    }
+
+    pub fn allocation_count(&self) -> usize {
+        self.allocations.get()
+    }
+
+    pub fn collection_enabled(&self) -> bool {
+        self.collection_enabled.get()
+    }
+
+    pub fn set_collection_enabled(&self, enabled: bool) {
+        self.collection_enabled.set(enabled);
+    }
+
+    pub fn collection_due(&self) -> bool {
+        self.collection_enabled.get() && self.allocations.get() >= self.threshold.get()
+    }
 
-        self.recently_deallocated_indexes.borrow_mut().push(address);
-    }
-
-    fn allocate_and_write(&self, data: PyObject) ->  MemoryAddress {
-        let mut mem_borrow = self.memory.borrow_mut();
-        let dealloc = self.recently_deallocated_indexes.borrow_mut().pop();
-        match dealloc {
-            Some(address) => {
-                let mut cell = &mut mem_borrow[address];
-                debug_assert!(!cell.is_const);
-                if cell.valid {
-                    panic!(
-                        "Attempt to allocate onto already occupied address {}",
-                        address
-                    )
-                } else {
-                    if let PyObjectStructure::Object{raw_data, refcount: _} = &mut cell.data.as_mut().unwrap().structure {
-                        if let PyObjectStructure::Object{raw_data: new_data, refcount: _} = data.structure {
-                            *raw_data = new_data;
-                        } else {
-                            cell.data = Some(data);
-                        }
-                    } else {
-                        cell.data = Some(data);
-                    }
-                    cell.valid = true;
+    //Frees every object that can't be reached from the roots, like objects referring to each other
+    //in a cycle, whose refcounts never drop to 0. Returns how many were freed.
+    //Constants, types, modules and native functions are never freed (refcounting doesn't free them either),
+    //so they are roots too.
+    pub fn collect(&self, roots: Vec<MemoryAddress>) -> usize {
+        let mut reached = vec![false; self.slots.len()];
+        let mut pending = roots;
+        for (index, slot) in self.slots.iter().enumerate() {
+            if let Some(object) = &*slot.object.borrow() {
+                if is_permanent(object) {
+                    pending.push(MemoryAddress { index: index as u32, generation: slot.generation.get() });
                 }
-                return address;
             }
-            None => {
-                mem_borrow.push(MemoryCell {
-                    data: Some(data),
-                    valid: true,
-                    is_const: false,
-                });
-                return mem_borrow.len() - 1;
+        }
+        while let Some(address) = pending.pop() {
+            let slot = match self.slots.get(address.index as usize) {
+                Some(slot) if slot.generation.get() == address.generation => slot,
+                //null, like an unbound local
+                _ => continue,
+            };
+            if reached[address.index as usize] {
+                continue;
             }
-        };
+            reached[address.index as usize] = true;
+            if let Some(object) = &*slot.object.borrow() {
+                references(object, &mut pending);
+            }
+        }
+        let mut freed = 0;
+        for (index, slot) in self.slots.iter().enumerate() {
+            if !reached[index] && slot.object.borrow().is_some() {
+                self.free(index as u32, slot);
+                freed += 1;
+            }
+        }
+        self.allocations.set(0);
+        self.threshold.set(COLLECTION_THRESHOLD.max(self.live_objects()));
+        self.measure();
+        return freed;
     }
 
-    fn allocate_and_write_builtin(&self, type_addr:  MemoryAddress, data: BuiltInTypeData) ->  MemoryAddress {
-        let mut mem_borrow = self.memory.borrow_mut();
-        let dealloc = self.recently_deallocated_indexes.borrow_mut().pop();
-        match dealloc {
-            Some(address) => {
-                let mut cell = &mut mem_borrow[address];
-                debug_assert!(!cell.is_const);
-                if cell.valid {
-                    panic!(
-                        "Attempt to allocate onto already occupied address {}",
-                        address
-                    )
-                } else {
-                    if let PyObjectStructure::Object{raw_data, refcount: _} = &mut cell.data.as_mut().unwrap().structure {
-                        *raw_data = data;
-                    } else {
-                        cell.data = Some(PyObject {
-                            type_addr, 
-                            structure: PyObjectStructure::Object {
-                                raw_data: data,
-                                refcount: 1
-                            },
-                            is_const: false,
-                            properties: HashMap::new()
-                        })
-                    }
-                    cell.valid = true;
-                }
-                return address;
-            }
-            None => {
-                mem_borrow.push(MemoryCell {
-                    data: Some(PyObject {
-                        type_addr, 
-                        structure: PyObjectStructure::Object {
-                            raw_data: data,
-                            refcount: 1
-                        },
-                        is_const: false,
-                        properties: HashMap::new()
-                    }),
-                    valid: true,
-                    is_const: false,
-                });
-                return mem_borrow.len() - 1;
+    //Measures every object again: lists, dicts and strings grow without allocating new objects
+    pub fn measure(&self) {
+        let mut bytes = 0;
+        for slot in self.slots.iter() {
+            if let Some(object) = &*slot.object.borrow() {
+                slot.size.set(approximate_size(object));
+                bytes += slot.size.get();
             }
-        };
+        }
+        self.bytes.set(bytes);
     }
-    
-    fn null_ptr(&self) ->  MemoryAddress { 0 }
 }
-*/
 
-pub type MemoryAddress = *mut PyObject;
-pub struct UnsafeMemory {
-    pub recently_deallocated_addr: RefCell<Vec<*mut PyObject>>,
+fn is_permanent(object: &PyObject) -> bool {
+    object.is_const
+        || matches!(
+            object.structure,
+            PyObjectStructure::Type { .. }
+                | PyObjectStructure::Module { .. }
+                | PyObjectStructure::NativeCallable { .. }
+                | PyObjectStructure::None
+                | PyObjectStructure::NotImplemented
+        )
 }
 
-impl UnsafeMemory {
-    pub fn new() -> Self {
-        Self {
-            recently_deallocated_addr: RefCell::new(vec![])
+//The objects an object keeps alive
+fn references(object: &PyObject, found: &mut Vec<MemoryAddress>) {
+    found.push(object.type_addr);
+    found.extend(object.properties.values());
+    match &object.structure {
+        PyObjectStructure::Object { raw_data, .. } => match raw_data {
+            BuiltInTypeData::List(elements) | BuiltInTypeData::Tuple(elements) => found.extend(elements),
+            BuiltInTypeData::Dict(dict) | BuiltInTypeData::Set(dict) => {
+                for entry in dict.entries.iter() {
+                    found.push(entry.key);
+                    found.push(entry.value);
+                }
+            }
+            BuiltInTypeData::CodeObject(code) => code_references(code, found),
+            BuiltInTypeData::Super { this_class, instance } => found.extend([*this_class, *instance]),
+            BuiltInTypeData::Cell(contents) => found.extend(contents),
+            BuiltInTypeData::Generator(data) => {
+                code_references(&data.code, found);
+                //while the generator runs its frame is on the VM stack instead
+                if let Some(frame) = &data.frame {
+                    frame.references(found);
+                }
+            }
+            _ => {}
+        },
+        PyObjectStructure::UserDefinedFunction { code, defaults, kwonly_defaults, closure, .. } => {
+            code_references(code, found);
+            found.extend(defaults);
+            found.extend(kwonly_defaults.iter().map(|(_, value)| *value));
+            found.extend(closure);
         }
-    }
-
-    pub fn check_mem(&self, address: MemoryAddress) {
-        if self.recently_deallocated_addr.borrow().contains(&address) {
-            panic!("Trying to get recently deallocated memory {:p} {:?}", address, unsafe{ &*address})
+        PyObjectStructure::BoundMethod { function_address, bound_address } => {
+            found.extend([*function_address, *bound_address]);
         }
+        PyObjectStructure::Type { functions, supertype, mro, .. } => {
+            found.extend(functions.values());
+            found.extend(supertype);
+            found.extend(mro);
+        }
+        PyObjectStructure::Module { global_namespace, .. } => found.extend(global_namespace.values()),
+        _ => {}
     }
+}
 
+fn code_references(code: &CodeObjectContext, found: &mut Vec<MemoryAddress>) {
+    found.extend(code.consts.iter());
+    found.push(code.module);
 }
 
-impl Memory for UnsafeMemory {
+impl Memory for ArenaMemory {
 
-  
-    fn get(&self, address: MemoryAddress) -> &PyObject {
-        self.check_mem(address);
-        if address.is_null() {
-            panic!("Attempt to read from non-valid memory address null");
-        }
-        return unsafe { &*address };
+    #[inline]
+    fn get(&self, address: MemoryAddress) -> Ref<'_, PyObject> {
+        //a slot with the handle's generation always has its object
+        Ref::map(self.slot(address).object.borrow(), |object| object.as_ref().unwrap())
     }
 
-    fn get_mut(&self, address: MemoryAddress) -> &mut PyObject {
-        self.check_mem(address);
-        if address.is_null() {
-            panic!("Attempt to read from non-valid memory address null");
-        }
-        return unsafe { &mut *address };
+    #[inline]
+    fn get_mut(&self, address: MemoryAddress) -> RefMut<'_, PyObject> {
+        RefMut::map(self.slot(address).object.borrow_mut(), |object| object.as_mut().unwrap())
     }
 
     fn make_const(&self, address: MemoryAddress) {
-        self.check_mem(address);
-        unsafe { (*address).is_const = true };
+        self.get_mut(address).is_const = true;
     }
 
     fn deallocate(&self, address: MemoryAddress) {
-        self.check_mem(address);
-        if address.is_null() {
-            panic!("Null pointer!");
-        }
-        unsafe {
-            if (*address).is_const {
-                return;
-            }
+        let slot = self.slot(address);
+        if self.get(address).is_const {
+            return;
         }
-        //do not deallocate yet! Could make a new box from raw and let it go out of scope
-        self.recently_deallocated_addr.borrow_mut().push(address);
+        self.free(address.index, slot);
     }
 
     fn allocate_and_write(&self, data: PyObject) -> MemoryAddress {
-        let dealloc = self.recently_deallocated_addr.borrow_mut().pop();
-        match dealloc {
-            Some(address) => {
-                unsafe {
-                    //println!("allocate_and_write: Writing at {:p}, data = {:?}", address, data);
-                    *address = data;
-                };
-                return address;
+        self.live.set(self.live.get() + 1);
+        self.allocations.set(self.allocations.get() + 1);
+        let allocated = &self.allocated_by_kind[kind_of(&data)];
+        allocated.set(allocated.get() + 1);
+        let size = approximate_size(&data);
+        self.bytes.set(self.bytes.get() + size);
+        let free_slot = self.free_slots.borrow_mut().pop();
+        match free_slot {
+            Some(index) => {
+                let slot = self.slots.get(index as usize).unwrap();
+                *slot.object.borrow_mut() = Some(data);
+                slot.size.set(size);
+                MemoryAddress { index, generation: slot.generation.get() }
             }
             None => {
-                let boxed = Box::new(data);
-                let mutref = Box::leak(boxed); //hehe
-                return mutref as *mut PyObject;
+                let index = self.slots.len() as u32;
+                debug_assert!(index != NULL_INDEX);
+                self.slots.push(Box::new(Slot {
+                    generation: Cell::new(0),
+                    object: RefCell::new(Some(data)),
+                    size: Cell::new(size),
+                }));
+                MemoryAddress { index, generation: 0 }
             }
         }
     }
 
-  
     fn allocate_and_write_builtin(
         &self,
         type_addr: MemoryAddress,
         data: BuiltInTypeData,
     ) -> MemoryAddress {
-        let dealloc = self.recently_deallocated_addr.borrow_mut().pop();
-        match dealloc {
-            Some(address) => {
-                //println!("Writing builtin at {:p}, data = {:?}", address, data);
-                let py_obj = unsafe { &mut *address };
-                debug_assert!(!py_obj.is_const);
-                
-                *py_obj = PyObject {
-                    type_addr,
-                    structure: PyObjectStructure::Object {
-                        raw_data: data,
-                        refcount: 0,
-                    },
-                    properties: BTreeMap::new(),
-                    is_const: false,
-                };
-                
-                return address;
+        self.allocate_and_write(PyObject {
+            type_addr,
+            structure: PyObjectStructure::Object {
+                raw_data: data,
+                refcount: 0,
+            },
+            properties: BTreeMap::new(),
+            is_const: false,
+        })
+    }
+
+    fn null_ptr(&self) -> MemoryAddress {
+        MemoryAddress { index: NULL_INDEX, generation: 0 }
+    }
+
+    fn live_objects(&self) -> usize {
+        self.live.get()
+    }
+
+    fn heap_bytes(&self) -> usize {
+        self.bytes.get()
+    }
+
+    fn heap_stats(&self) -> HeapStats {
+        let mut live = [0; KINDS.len()];
+        let mut bytes = [0; KINDS.len()];
+        for slot in self.slots.iter() {
+            if let Some(object) = &*slot.object.borrow() {
+                let kind = kind_of(object);
+                live[kind] += 1;
+                bytes[kind] += approximate_size(object);
             }
-            None => self.allocate_and_write(PyObject {
-                type_addr,
-                structure: PyObjectStructure::Object {
-                    raw_data: data,
-                    refcount: 0,
-                },
-                properties: BTreeMap::new(),
-                is_const: false,
-            }),
         }
+        let kinds = KINDS
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.allocated_by_kind[*index].get() > 0)
+            .map(|(index, kind)| KindStats {
+                kind,
+                allocated: self.allocated_by_kind[index].get(),
+                live: live[index],
+                bytes: bytes[index],
+            })
+            .collect();
+        HeapStats { live_objects: self.live_objects(), bytes: bytes.iter().sum(), kinds }
     }
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int_object(memory: &ArenaMemory, value: i128) -> MemoryAddress {
+        memory.allocate_and_write_builtin(memory.null_ptr(), BuiltInTypeData::Int(value))
+    }
 
-    fn null_ptr(&self) -> MemoryAddress {std::ptr::null_mut()}
+    fn int_value(memory: &ArenaMemory, address: MemoryAddress) -> i128 {
+        memory.get(address).try_get_builtin().unwrap().take_int()
+    }
+
+    #[test]
+    fn reuses_slots_with_a_new_generation() {
+        let memory = ArenaMemory::new();
+        let first = int_object(&memory, 1);
+        memory.deallocate(first);
+        let second = int_object(&memory, 2);
+        assert_eq!(first.index, second.index);
+        assert_ne!(first, second);
+        assert_eq!(int_value(&memory, second), 2);
+        assert_eq!(memory.live_objects(), 1);
+    }
 
-}
\ No newline at end of file
+    #[test]
+    fn retired_slots_are_not_live() {
+        let memory = ArenaMemory::new();
+        let first = int_object(&memory, 1);
+        memory.slots.get(first.index as usize).unwrap().generation.set(u32::MAX);
+        memory.deallocate(MemoryAddress { index: first.index, generation: u32::MAX });
+        assert_eq!(memory.live_objects(), 0);
+        let second = int_object(&memory, 2);
+        assert_ne!(first.index, second.index);
+        assert_eq!(memory.live_objects(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Trying to get deallocated memory")]
+    fn stale_handles_are_caught() {
+        let memory = ArenaMemory::new();
+        let first = int_object(&memory, 1);
+        memory.deallocate(first);
+        int_object(&memory, 2);
+        memory.get(first);
+    }
+
+    #[test]
+    fn const_objects_are_never_deallocated() {
+        let memory = ArenaMemory::new();
+        let constant = int_object(&memory, 1);
+        memory.make_const(constant);
+        memory.deallocate(constant);
+        assert_eq!(int_value(&memory, constant), 1);
+    }
+
+    #[test]
+    fn collects_unreachable_cycles() {
+        let memory = ArenaMemory::new();
+        let linked = |memory: &ArenaMemory| {
+            let first = int_object(memory, 1);
+            let second = int_object(memory, 2);
+            memory.get_mut(first).properties.insert("next".to_string(), second);
+            memory.get_mut(second).properties.insert("next".to_string(), first);
+            first
+        };
+        let kept = linked(&memory);
+        let garbage = linked(&memory);
+        assert_eq!(memory.collect(vec![kept]), 2);
+        assert_eq!(memory.live_objects(), 2);
+        assert_eq!(int_value(&memory, kept), 1);
+        assert!(memory.slots.get(garbage.index as usize).unwrap().object.borrow().is_none());
+        assert_eq!(memory.allocation_count(), 0);
+    }
+
+    #[test]
+    fn counts_objects_and_bytes_by_kind() {
+        let memory = ArenaMemory::new();
+        let first = int_object(&memory, 1);
+        int_object(&memory, 2);
+        let list = memory.allocate_and_write_builtin(memory.null_ptr(), BuiltInTypeData::List(vec![first; 100]));
+        memory.deallocate(first);
+        let stats = memory.heap_stats();
+        assert_eq!(stats.live_objects, 2);
+        assert_eq!(stats.bytes, memory.heap_bytes());
+        let int = stats.kinds.iter().find(|kind| kind.kind == "int").unwrap();
+        assert_eq!((int.allocated, int.live), (2, 1));
+        let list_bytes = stats.kinds.iter().find(|kind| kind.kind == "list").unwrap().bytes;
+        assert!(list_bytes > 100 * size_of::<MemoryAddress>());
+        memory.deallocate(list);
+        assert_eq!(memory.heap_bytes(), int.bytes);
+    }
+
+    #[test]
+    #[should_panic(expected = "already mutably borrowed")]
+    fn reading_an_object_being_changed_panics() {
+        let memory = ArenaMemory::new();
+        let address = int_object(&memory, 1);
+        let _changing = memory.get_mut(address);
+        memory.get(address);
+    }
+}
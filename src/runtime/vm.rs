@@ -1,13 +1,23 @@
 use crate::bytecode::program::*;
 use crate::runtime::datamodel::*;
 use crate::runtime::memory::*;
-use std::cell::Cell;
+use crate::commons::errors::RuntimeError;
+use crate::runtime::arguments::bind_arguments;
+use std::cell::{Cell, Ref, RefMut};
+use std::rc::Rc;
 use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 /* this is done by somewhat following the python data model in https://docs.python.org/3/reference/datamodel.html */
 
+//Shared, so a call can hold on to the code without keeping the function object borrowed
+pub type NativeCode = Rc<dyn Fn(&VM, CallParams) -> MemoryAddress>;
+
 pub struct PyCallable {
-    pub code: Box<dyn Fn(&VM, CallParams) -> MemoryAddress>,
+    pub code: NativeCode,
 }
 
 impl std::fmt::Debug for PyCallable {
@@ -16,9 +26,11 @@ impl std::fmt::Debug for PyCallable {
     }
 }
 
+//The arguments of a call. Despite the name, keyword arguments are also here, in call order.
 #[derive(Clone)]
 pub struct PositionalParameters {
     pub params: Vec<MemoryAddress>,
+    pub kwargs: Vec<(String, MemoryAddress)>,
 }
 
 impl<'a> Into<PositionalParameters> for &'a[MemoryAddress] {
@@ -33,13 +45,15 @@ impl PositionalParameters {
         let mut reversed = params.to_vec();
         reversed.reverse();
         PositionalParameters {
-            params: reversed
+            params: reversed,
+            kwargs: vec![],
         }
     }
 
     pub fn empty() -> PositionalParameters {
         PositionalParameters {
-            params: vec![]
+            params: vec![],
+            kwargs: vec![],
         }
     }
 
@@ -49,12 +63,15 @@ impl PositionalParameters {
 
     pub fn single(addr: MemoryAddress) -> PositionalParameters {
         PositionalParameters {
-            params: vec![addr]
+            params: vec![addr],
+            kwargs: vec![],
         }
     }
 }
 
 pub struct CallParams<'a> {
+    //no native function needs its own address yet
+    #[allow(dead_code)]
     pub func_address: MemoryAddress,
     pub func_name: Option<&'a str>,
     pub params: PositionalParameters,
@@ -62,12 +79,16 @@ pub struct CallParams<'a> {
 
 pub struct FunctionCallParams {
     pub params: Vec<MemoryAddress>,
+    pub kwargs: Vec<(String, MemoryAddress)>,
 }
 
 
 pub struct MethodCallParams {
     pub bound_pyobj: MemoryAddress,
     pub params: Vec<MemoryAddress>,
+    //no builtin method takes keyword arguments yet
+    #[allow(dead_code)]
+    pub kwargs: Vec<(String, MemoryAddress)>,
 }
 
 impl<'a> CallParams<'a> {
@@ -76,14 +97,16 @@ impl<'a> CallParams<'a> {
         let rest: Vec<MemoryAddress> = self.params.params.iter().skip(1).map(|x| *x).collect();
         MethodCallParams {
             bound_pyobj: bound,
-            params: rest
+            params: rest,
+            kwargs: self.params.kwargs.clone(),
         }
     }
 
     pub fn as_function(&self) -> FunctionCallParams {
         let rest: Vec<MemoryAddress> = self.params.params.iter().map(|x| *x).collect();
         FunctionCallParams {
-            params: rest
+            params: rest,
+            kwargs: self.params.kwargs.clone(),
         }
     }
 }
@@ -91,6 +114,15 @@ impl<'a> CallParams<'a> {
 use std::cell::RefCell;
 
 
+//What a suspended generator gets when it resumes
+#[derive(Debug, Clone, Copy)]
+pub enum GeneratorInput {
+    //the value of the yield expression, None for __next__
+    Send(MemoryAddress),
+    //raised where the generator is suspended
+    Throw(MemoryAddress),
+}
+
 #[derive(PartialEq, Eq, Hash, Ord, PartialOrd)]
 pub enum SpecialValue {
     Type,
@@ -109,57 +141,204 @@ pub struct BuiltinTypeAddresses {
     pub float: MemoryAddress,
     pub boolean: MemoryAddress,
     pub string: MemoryAddress,
+    pub object: MemoryAddress,
     pub list: MemoryAddress,
+    pub tuple: MemoryAddress,
+    pub dict: MemoryAddress,
+    pub set: MemoryAddress,
+    pub generator: MemoryAddress,
+    pub exception: MemoryAddress,
     pub index_err: MemoryAddress,
     pub code_object: MemoryAddress,
+    pub traceback: MemoryAddress,
+    pub cell: MemoryAddress,
     pub true_val: MemoryAddress,
     pub false_val: MemoryAddress,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockType {
+    Except,
+    Finally,
+}
+
+//Pushed by SetupExcept/SetupFinally. When an exception is raised, we unwind to the innermost block.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Block {
+    pub block_type: BlockType,
+    pub handler: usize,
+    pub stack_level: usize,
+    pub finally_level: usize,
+}
+
+//Why the finally body is running. EndFinally uses it to resume what was going on before.
+#[derive(Debug, PartialEq, Eq)]
+pub enum FinallyReason {
+    Normal,
+    Exception(MemoryAddress),
+    Return(MemoryAddress),
+    Break { blocks: usize, destination: usize },
+}
+
+#[derive(Debug, PartialEq, Eq)]
 pub struct StackFrame {
     pub function_name: String,
     pub local_namespace: Vec<MemoryAddress>, //the compiler knows which index will be loaded at compile time, so no need for a HashMap here.
     pub stack: Vec<MemoryAddress>,
     pub exception: Option<MemoryAddress>,
     pub prog_counter: Cell<usize>,
+    pub blocks: Vec<Block>,
+    pub finally_reasons: Vec<FinallyReason>,
+    //None for native functions
+    pub code: Option<Rc<CodeObject>>,
+    //the user defined function running in this frame, super() uses it to find the class
+    pub function: Option<MemoryAddress>,
+    //cells for code.cellvars followed by the closure cells for code.freevars
+    pub cells: Vec<MemoryAddress>,
 }
 
+impl StackFrame {
+    pub fn new(function_name: &str) -> StackFrame {
+        StackFrame {
+            function_name: function_name.to_owned(),
+            local_namespace: vec![],
+            stack: vec![],
+            exception: None,
+            prog_counter: Cell::new(0),
+            blocks: vec![],
+            finally_reasons: vec![],
+            code: None,
+            function: None,
+            cells: vec![],
+        }
+    }
+
+    //Source line of the instruction being executed, if this frame runs Python code
+    pub fn current_line(&self) -> Option<usize> {
+        self.code
+            .as_ref()
+            .and_then(|code| code.line_for(self.prog_counter.get()))
+    }
+
+    //The objects this frame keeps alive
+    pub fn references(&self, found: &mut Vec<MemoryAddress>) {
+        found.extend(self.local_namespace.iter());
+        found.extend(self.stack.iter());
+        found.extend(self.exception);
+        found.extend(self.function);
+        found.extend(self.cells.iter());
+        for reason in self.finally_reasons.iter() {
+            if let FinallyReason::Exception(addr) | FinallyReason::Return(addr) = reason {
+                found.push(*addr);
+            }
+        }
+    }
+}
+
+//What run_function needs from a function object
+enum Callee {
+    Native {
+        code: NativeCode,
+        name: Option<String>,
+        is_bound: bool,
+    },
+    Function {
+        code: CodeObjectContext,
+        defaults: Vec<MemoryAddress>,
+        kwonly_defaults: Vec<(String, MemoryAddress)>,
+        closure: Vec<MemoryAddress>,
+    },
+    BoundMethod {
+        function_address: MemoryAddress,
+        bound_address: MemoryAddress,
+    },
+}
+
+//How many instructions run between two measurements of the heap, when it has a limit
+const HEAP_CHECK_INTERVAL: usize = 1 << 16;
+//How many instructions run between two looks at the clock and the interrupt flag
+const INTERRUPT_CHECK_INTERVAL: usize = 1 << 10;
+//How many more instructions the handlers of a TimeoutError get, to clean up
+const TIMEOUT_GRACE_INSTRUCTIONS: u64 = 10_000;
+//Python functions nested deeper than this raise RecursionError instead of overflowing the native stack
+const MAX_FRAME_DEPTH: usize = 500;
+
 pub struct VM {
     pub stack: RefCell<Vec<StackFrame>>,
-    pub memory: UnsafeMemory,
+    pub memory: ArenaMemory,
     pub builtin_type_addrs: BuiltinTypeAddresses,
     pub special_values: BTreeMap<SpecialValue, MemoryAddress>,
-    pub modules: BTreeMap<String, MemoryAddress>,
+    //every module imported so far, by dotted name. Imports add to it while code runs.
+    pub modules: RefCell<BTreeMap<String, MemoryAddress>>,
+    //directories where import looks for modules and packages, in order
+    pub search_path: Vec<PathBuf>,
+    //Native code waiting for Python code it called. It may hold objects the collector can't see,
+    //so garbage is only collected while there's none.
+    native_calls: Cell<usize>,
+    //objects the interpreter keeps while a call runs, like its arguments
+    pinned: RefCell<Vec<MemoryAddress>>,
+    //containers whose repr is being made, see repr_enter
+    repr_running: RefCell<Vec<MemoryAddress>>,
+    //approximate bytes the heap can take before MemoryError is raised. None for no limit.
+    pub heap_limit: Option<usize>,
+    //instructions left before the heap is measured again, while there's a limit
+    heap_check_countdown: Cell<usize>,
+    //how many instructions and how much time each run gets before TimeoutError is raised. None for no limit.
+    instruction_budget: Cell<Option<u64>>,
+    timeout: Cell<Option<Duration>>,
+    //instructions left and when the time is up for the current run
+    instructions_left: Cell<Option<u64>>,
+    deadline: Cell<Option<Instant>>,
+    //the message of the TimeoutError once a limit ran out in the current run
+    limit_exceeded: Cell<Option<&'static str>>,
+    //set by the host, from another thread or a signal handler, to raise KeyboardInterrupt
+    interrupt: Arc<AtomicBool>,
+    //instructions left before the deadline and the interrupt are checked again
+    interrupt_check_countdown: Cell<usize>,
     //pub builtin_names: Vec<MemoryAddress>,
 }
 
 impl VM {
     pub fn new() -> VM{
-        let memory = UnsafeMemory::new();
+        let memory = ArenaMemory::new();
         let nullptr = memory.null_ptr();
         let mut interpreter = VM {
-            stack: RefCell::new(vec![StackFrame {
-                function_name: "__main__".to_owned(),
-                local_namespace: vec![],
-                stack: vec![],
-                exception: None,
-                prog_counter: Cell::new(0),
-            }]),
+            stack: RefCell::new(vec![StackFrame::new("__main__")]),
             memory: memory,
             special_values: BTreeMap::new(),
-            modules: BTreeMap::new(),
+            modules: RefCell::new(BTreeMap::new()),
+            search_path: vec![],
+            native_calls: Cell::new(0),
+            pinned: RefCell::new(vec![]),
+            repr_running: RefCell::new(vec![]),
+            heap_limit: None,
+            heap_check_countdown: Cell::new(0),
+            instruction_budget: Cell::new(None),
+            timeout: Cell::new(None),
+            instructions_left: Cell::new(None),
+            deadline: Cell::new(None),
+            limit_exceeded: Cell::new(None),
+            interrupt: Arc::new(AtomicBool::new(false)),
+            interrupt_check_countdown: Cell::new(0),
             //builtin_names: vec![],
             builtin_type_addrs: BuiltinTypeAddresses {
                 int: nullptr,
                 float: nullptr,
                 boolean: nullptr,
                 string: nullptr,
+                object: nullptr,
                 list: nullptr,
+                tuple: nullptr,
+                dict: nullptr,
+                set: nullptr,
+                generator: nullptr,
                 true_val: nullptr,
                 false_val: nullptr,
+                exception: nullptr,
                 index_err: nullptr,
                 code_object: nullptr,
+                traceback: nullptr,
+                cell: nullptr,
             },
         };
         let type_type = interpreter.allocate_and_write(PyObject {
@@ -169,6 +348,7 @@ impl VM {
                 name: String::from("type"),
                 functions: BTreeMap::new(),
                 supertype: None,
+                mro: vec![],
             },
             is_const: false,
         });
@@ -183,41 +363,20 @@ impl VM {
                 name: String::from("module"),
                 functions: BTreeMap::new(),
                 supertype: None,
+                mro: vec![],
             },
             is_const: false,
         });
 
-        let builtin_module_obj = interpreter.allocate_and_write(PyObject {
-            type_addr: module_type,
-            properties: BTreeMap::new(),
-            structure: PyObjectStructure::Module {
-                name: BUILTIN_MODULE.to_string(),
-                global_namespace: BTreeMap::new(),
-            },
-            is_const: false,
-        });
-
-        let main_module_obj = interpreter.allocate_and_write(PyObject {
-            type_addr: module_type,
-            properties: BTreeMap::new(),
-            structure: PyObjectStructure::Module {
-                name: MAIN_MODULE.to_string(),
-                global_namespace: BTreeMap::new(),
-            },
-            is_const: false,
-        });
-
-       
         interpreter
-            .modules
-            .insert(BUILTIN_MODULE.to_owned(), builtin_module_obj);
+            .special_values
+            .insert(SpecialValue::ModuleType, module_type);
 
-        interpreter
-            .modules
-            .insert(MAIN_MODULE.to_owned(), main_module_obj);
+        interpreter.create_module(BUILTIN_MODULE);
+        interpreter.create_module(MAIN_MODULE);
 
         
-        let none_type = interpreter.create_type(BUILTIN_MODULE, "None", None);
+        let none_type = interpreter.create_type(BUILTIN_MODULE, "NoneType", None);
 
 
         let none_value = interpreter.allocate_and_write(PyObject {
@@ -227,7 +386,7 @@ impl VM {
             is_const: false,
         });
 
-        let not_implemented_type = interpreter.create_type(BUILTIN_MODULE, "NotImplemented", None);
+        let not_implemented_type = interpreter.create_type(BUILTIN_MODULE, "NotImplementedType", None);
 
         let not_implemented_value = interpreter.allocate_and_write(PyObject {
             type_addr: not_implemented_type,
@@ -235,8 +394,10 @@ impl VM {
             structure: PyObjectStructure::NotImplemented,
             is_const: false,
         });
+        interpreter.add_to_module(BUILTIN_MODULE, "NotImplemented", not_implemented_value);
 
-        let stop_iteration_type = interpreter.create_type(BUILTIN_MODULE, "StopIteration", Some(type_type));
+        //the supertype is set to Exception once the exception types are registered
+        let stop_iteration_type = interpreter.create_type(BUILTIN_MODULE, "StopIteration", None);
 
         let stop_iteration_value = interpreter.allocate_and_write(PyObject {
             type_addr: stop_iteration_type,
@@ -252,6 +413,7 @@ impl VM {
                 name: String::from("function"),
                 functions: BTreeMap::new(),
                 supertype: None,
+                mro: vec![],
             },
             is_const: false,
         });
@@ -265,7 +427,6 @@ impl VM {
         interpreter.make_const(stop_iteration_value);
         interpreter.make_const(callable_type);
         interpreter.make_const(module_type);
-        interpreter.make_const(main_module_obj);
 
         interpreter
             .special_values
@@ -288,24 +449,174 @@ impl VM {
         interpreter
             .special_values
             .insert(SpecialValue::CallableType, callable_type);
-        interpreter
-            .special_values
-            .insert(SpecialValue::ModuleType, module_type);
-
         return interpreter;
     }
 
+    //Drops every frame and starts over with an empty __main__ one, used between REPL inputs.
+    //The values left on the stack are not released: after an exception they are whatever the
+    //expression had pushed so far, like globals, which were never counted for the stack.
     pub fn clear_stacks(&mut self) {
-        {
-            let mut stack_borrow = self.stack.borrow_mut();
-            while stack_borrow.len() > 0 {
-                let popped = stack_borrow.pop().unwrap();
-                for stack_val in popped.stack.iter() {
-                    self.decrease_refcount(*stack_val);
-                }
+        self.stack.borrow_mut().clear();
+        self.pinned.borrow_mut().clear();
+        self.repr_running.borrow_mut().clear();
+        self.native_calls.set(0);
+        self.new_stack_frame("__main__");
+    }
+
+    //What the program can still use: the stack frames, modules, special values and builtin types,
+    //and the pinned objects
+    fn gc_roots(&self) -> Vec<MemoryAddress> {
+        let mut roots = vec![];
+        for frame in self.stack.borrow().iter() {
+            frame.references(&mut roots);
+        }
+        roots.extend(self.modules.borrow().values());
+        roots.extend(self.special_values.values());
+        roots.extend(self.pinned.borrow().iter());
+        let types = &self.builtin_type_addrs;
+        roots.extend([
+            types.int, types.float, types.boolean, types.string, types.object, types.list, types.tuple,
+            types.dict, types.set, types.generator, types.exception, types.index_err, types.code_object,
+            types.traceback, types.cell, types.true_val, types.false_val,
+        ]);
+        return roots;
+    }
+
+    //Frees the objects the program can't reach anymore, returns how many
+    pub fn collect_garbage(&self) -> usize {
+        self.memory.collect(self.gc_roots())
+    }
+
+    //Runs between instructions: collects once enough objects were allocated, unless native code is waiting
+    pub fn collect_garbage_if_due(&self) {
+        if self.native_calls.get() == 0 && self.memory.collection_due() {
+            self.collect_garbage();
+        }
+    }
+
+    pub fn heap_stats(&self) -> HeapStats {
+        self.memory.heap_stats()
+    }
+
+    //Runs between instructions when there's a heap limit. Objects are measured when they are allocated,
+    //and all of them again every HEAP_CHECK_INTERVAL instructions, since containers grow without allocating.
+    //Over the limit, the garbage is collected first (unless native code is waiting) before giving up.
+    pub fn heap_limit_exceeded(&self) -> bool {
+        let limit = match self.heap_limit {
+            Some(limit) => limit,
+            None => return false,
+        };
+        let countdown = self.heap_check_countdown.get();
+        if countdown > 0 && self.memory.heap_bytes() <= limit {
+            self.heap_check_countdown.set(countdown - 1);
+            return false;
+        }
+        self.heap_check_countdown.set(HEAP_CHECK_INTERVAL);
+        if self.native_calls.get() == 0 {
+            self.collect_garbage();
+        } else {
+            self.memory.measure();
+        }
+        self.memory.heap_bytes() > limit
+    }
+
+    //How many instructions a run can take before TimeoutError is raised, None to run without a budget
+    pub fn set_instruction_budget(&self, budget: Option<u64>) {
+        self.instruction_budget.set(budget);
+        self.start_limits();
+    }
+
+    //How long a run can take before TimeoutError is raised, None to run without a deadline
+    pub fn set_timeout(&self, timeout: Option<Duration>) {
+        self.timeout.set(timeout);
+        self.start_limits();
+    }
+
+    //Gives the code about to run a whole new instruction budget and deadline
+    pub fn start_limits(&self) {
+        self.instructions_left.set(self.instruction_budget.get());
+        self.deadline.set(self.timeout.get().map(|timeout| Instant::now() + timeout));
+        self.limit_exceeded.set(None);
+        self.interrupt_check_countdown.set(0);
+    }
+
+    //Storing true in it stops the running program with KeyboardInterrupt
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        self.interrupt.clone()
+    }
+
+    //Runs before each instruction. Returns the type and message of the exception to raise when the program
+    //ran out of instructions or time, or was interrupted.
+    pub fn check_interrupts(&self) -> Option<(&'static str, &'static str)> {
+        match self.instructions_left.get() {
+            Some(0) => return Some(self.limit_reached("instruction budget exhausted")),
+            Some(left) => self.instructions_left.set(Some(left - 1)),
+            None => {}
+        }
+        let countdown = self.interrupt_check_countdown.get();
+        if countdown > 0 {
+            self.interrupt_check_countdown.set(countdown - 1);
+            return None;
+        }
+        if self.interrupt.swap(false, Ordering::Relaxed) {
+            self.interrupt_check_countdown.set(INTERRUPT_CHECK_INTERVAL);
+            return Some(("KeyboardInterrupt", ""));
+        }
+        match self.deadline.get() {
+            Some(deadline) if Instant::now() >= deadline => Some(self.limit_reached("deadline exceeded")),
+            _ => {
+                self.interrupt_check_countdown.set(INTERRUPT_CHECK_INTERVAL);
+                None
             }
         }
-        self.new_stack_frame("__main__");
+    }
+
+    //The first time a limit runs out, `except TimeoutError:` and `finally` get TIMEOUT_GRACE_INSTRUCTIONS
+    //more to run. Once those are used up too, every instruction raises again, so the run can't go on.
+    fn limit_reached(&self, message: &'static str) -> (&'static str, &'static str) {
+        match self.limit_exceeded.get() {
+            Some(first_message) => ("TimeoutError", first_message),
+            None => {
+                self.limit_exceeded.set(Some(message));
+                self.instructions_left.set(Some(TIMEOUT_GRACE_INSTRUCTIONS));
+                self.deadline.set(None);
+                self.interrupt_check_countdown.set(INTERRUPT_CHECK_INTERVAL);
+                ("TimeoutError", message)
+            }
+        }
+    }
+
+    pub fn native_calls(&self) -> usize {
+        self.native_calls.get()
+    }
+
+    //Keeps objects alive while Python code runs, until they are unpinned (the last ones pinned first)
+    pub fn pin(&self, addrs: &[MemoryAddress]) {
+        self.pinned.borrow_mut().extend_from_slice(addrs);
+    }
+
+    pub fn unpin(&self, count: usize) {
+        let mut pinned = self.pinned.borrow_mut();
+        let remaining = pinned.len() - count;
+        pinned.truncate(remaining);
+    }
+
+    //Like CPython's Py_ReprEnter: false if the repr of this container is already being made further up,
+    //so one that contains itself shows as `[...]` instead of recursing until the stack overflows
+    pub fn repr_enter(&self, addr: MemoryAddress) -> bool {
+        let mut running = self.repr_running.borrow_mut();
+        if running.contains(&addr) {
+            return false;
+        }
+        running.push(addr);
+        true
+    }
+
+    pub fn repr_leave(&self, addr: MemoryAddress) {
+        let mut running = self.repr_running.borrow_mut();
+        if let Some(position) = running.iter().rposition(|running_addr| *running_addr == addr) {
+            running.remove(position);
+        }
     }
 
     pub fn create_type(
@@ -314,19 +625,9 @@ impl VM {
         name: &str,
         supertype: Option<MemoryAddress>,
     ) -> MemoryAddress {
-        let created_type = PyObject {
-            properties: BTreeMap::new(),
-            type_addr: self.special_values[&SpecialValue::Type],
-            structure: PyObjectStructure::Type {
-                name: name.to_string(),
-                functions: BTreeMap::new(),
-                supertype,
-            },
-            is_const: false,
-        };
-        let type_address = self.allocate_and_write(created_type);
-        let module_addr = *self.modules.get(module).unwrap();
-        let pyobj = self.get_pyobj_byaddr_mut(module_addr);
+        let type_address = self.allocate_type(name, supertype);
+        let module_addr = self.find_module(module).unwrap();
+        let mut pyobj = self.get_pyobj_byaddr_mut(module_addr);
         match &mut pyobj.structure {
             PyObjectStructure::Module {
                 global_namespace, ..
@@ -348,9 +649,30 @@ impl VM {
         }
     }
 
+    //A type that isn't added to any module, like the classes created by class statements:
+    //the code that runs the class statement stores it wherever the class name goes
+    pub fn allocate_type(&self, name: &str, supertype: Option<MemoryAddress>) -> MemoryAddress {
+        let mro = match supertype {
+            Some(supertype_addr) => self.get_mro(supertype_addr),
+            None => vec![],
+        };
+        let created_type = PyObject {
+            properties: BTreeMap::new(),
+            type_addr: self.special_values[&SpecialValue::Type],
+            structure: PyObjectStructure::Type {
+                name: name.to_string(),
+                functions: BTreeMap::new(),
+                supertype,
+                mro,
+            },
+            is_const: false,
+        };
+        self.allocate_and_write(created_type)
+    }
+
     pub fn add_to_module(&self, module: &str, name: &str, pyobject_addr: MemoryAddress) {
-        let module_addr = *self.modules.get(module).unwrap();
-        let pyobj = self.get_pyobj_byaddr_mut(module_addr);
+        let module_addr = self.find_module(module).unwrap();
+        let mut pyobj = self.get_pyobj_byaddr_mut(module_addr);
         match &mut pyobj.structure {
             PyObjectStructure::Module {
                 global_namespace, ..
@@ -371,8 +693,56 @@ impl VM {
         }
     }
 
+    //Like add_to_module, but replaces the previous value. Used for assignments to global variables.
+    pub fn set_in_module(&self, module: &str, name: &str, pyobject_addr: MemoryAddress) {
+        self.set_in_module_addr(self.find_module(module).unwrap(), name, pyobject_addr);
+    }
+
+    pub fn set_in_module_addr(&self, module_addr: MemoryAddress, name: &str, pyobject_addr: MemoryAddress) {
+        let mut pyobj = self.get_pyobj_byaddr_mut(module_addr);
+        match &mut pyobj.structure {
+            PyObjectStructure::Module {
+                global_namespace, ..
+            } => {
+                if let Some(previous) = global_namespace.insert(name.to_string(), pyobject_addr) {
+                    self.decrease_refcount(previous);
+                }
+                self.increase_refcount(pyobject_addr);
+            }
+            _ => panic!("Tried to set name {:?} in module, but Object is not module: {:?}", name, pyobj.structure),
+        }
+    }
+
     pub fn find_module(&self, module: &str) -> Option<MemoryAddress> {
-        return self.modules.get(module).map(|addr: &MemoryAddress| *addr);
+        return self.modules.borrow().get(module).copied();
+    }
+
+    //Creates an empty module and adds it to the modules, so importing it again finds this one
+    pub fn create_module(&self, name: &str) -> MemoryAddress {
+        let module = self.allocate_and_write(PyObject {
+            type_addr: self.special_values[&SpecialValue::ModuleType],
+            properties: BTreeMap::new(),
+            structure: PyObjectStructure::Module {
+                name: name.to_string(),
+                global_namespace: BTreeMap::new(),
+            },
+            is_const: false,
+        });
+        self.make_const(module);
+        self.modules.borrow_mut().insert(name.to_string(), module);
+        return module;
+    }
+
+    //Forgets a module whose code failed to run, so the next import tries again
+    pub fn remove_module(&self, name: &str) {
+        self.modules.borrow_mut().remove(name);
+    }
+
+    pub fn get_module_name(&self, module_addr: MemoryAddress) -> String {
+        match &self.get_pyobj_byaddr(module_addr).structure {
+            PyObjectStructure::Module { name, .. } => name.clone(),
+            _ => panic!("Not a module at addr: {:?}", module_addr),
+        }
     }
 
     pub fn find_in_module(&self, module: &str, name: &str) -> Option<MemoryAddress> {
@@ -399,47 +769,51 @@ impl VM {
         return pyobj.properties.get(attr_name).map(|x| *x);
     }
 
-    pub fn get_pyobj_byaddr(&self, addr: MemoryAddress) -> &PyObject {
+    #[inline]
+    pub fn get_pyobj_byaddr(&self, addr: MemoryAddress) -> Ref<'_, PyObject> {
         return self.memory.get(addr);
     }
 
-    pub fn get_pyobj_byaddr_mut(&self, addr: MemoryAddress) -> &mut PyObject {
+    #[inline]
+    pub fn get_pyobj_byaddr_mut(&self, addr: MemoryAddress) -> RefMut<'_, PyObject> {
         return self.memory.get_mut(addr);
     }
 
     pub fn get_refcount(&self, addr: MemoryAddress) -> i32 {
-        let pyobj = self.get_pyobj_byaddr_mut(addr);
-        if let PyObjectStructure::Object { refcount, .. } = &mut pyobj.structure {
+        let pyobj = self.get_pyobj_byaddr(addr);
+        if let PyObjectStructure::Object { refcount, .. } = &pyobj.structure {
             return (*refcount) as i32;
         } else {
             return -1;
         }
     }
 
-    pub fn get_function_name(&self, addr: MemoryAddress) -> &str {
-        let pyobj = self.get_pyobj_byaddr_mut(addr);
+    pub fn get_function_name(&self, addr: MemoryAddress) -> String {
+        let pyobj = self.get_pyobj_byaddr(addr);
         if let PyObjectStructure::NativeCallable { name, .. } = &pyobj.structure {
             if let Some(n) = name {
-                return n;
+                return n.clone();
             } else {
-                return "unknown";
+                return "unknown".to_string();
             }
         } else if let PyObjectStructure::UserDefinedFunction { qualname, .. } = &pyobj.structure {
-            return qualname;
+            return qualname.clone();
         } else if let PyObjectStructure::Type {name, ..} = &pyobj.structure {
-            return name;
+            return name.clone();
+        } else if let PyObjectStructure::BoundMethod { function_address, .. } = &pyobj.structure {
+            return self.get_function_name(*function_address);
         } else {
-            return "unknown"
+            return "unknown".to_string()
         }
     }
 
     pub fn set_attribute(&self, obj: MemoryAddress, attr: &str, value: MemoryAddress) {
-        let pyobj = self.get_pyobj_byaddr_mut(obj);
+        let mut pyobj = self.get_pyobj_byaddr_mut(obj);
         pyobj.properties.insert(attr.to_owned(), value);
     }
 
     pub fn increase_refcount(&self, addr: MemoryAddress) {
-        let pyobj = self.get_pyobj_byaddr_mut(addr);
+        let mut pyobj = self.get_pyobj_byaddr_mut(addr);
         if let PyObjectStructure::Object {
             raw_data: _,
             refcount,
@@ -450,13 +824,15 @@ impl VM {
     }
 
     pub fn decrease_refcount(&self, addr: MemoryAddress) {
-        let pyobj = self.get_pyobj_byaddr_mut(addr);
+        let mut pyobj = self.get_pyobj_byaddr_mut(addr);
         if let PyObjectStructure::Object { refcount, .. } = &mut pyobj.structure {
             if *refcount > 0 { //to prevent overflow, maybe this method shouldnt be called when intending to delete object
                 *refcount = *refcount - 1;
             }
 
             if *refcount <= 0 {
+                //the object can't be borrowed while it's deallocated
+                drop(pyobj);
                 self.memory.deallocate(addr);
             }
         }
@@ -470,25 +846,82 @@ impl VM {
         let pyobj = self.get_pyobj_byaddr(type_addr);
         match &pyobj.structure {
             PyObjectStructure::Type {
-                functions, supertype, ..
+                functions, mro, ..
             } => match functions.get(method_name) {
                 Some(addr) => Some(*addr),
-                None => match supertype {
-                    Some(supertype_addr) => {
-                        self.get_method_addr_byname(*supertype_addr, method_name)
-                    }
-                    None => None,
-                },
+                None => mro
+                    .iter()
+                    .find_map(|base| self.get_own_method_addr(*base, method_name)),
             },
             _ => None,
         }
     }
 
-    pub fn get_type_name(&self, addr: MemoryAddress) -> &str {
+    //Only looks at the functions defined in the type itself, not in its bases
+    pub fn get_own_method_addr(&self, type_addr: MemoryAddress, method_name: &str) -> Option<MemoryAddress> {
+        match &self.get_pyobj_byaddr(type_addr).structure {
+            PyObjectStructure::Type { functions, .. } => functions.get(method_name).copied(),
+            _ => None,
+        }
+    }
+
+    //The type followed by its method resolution order, like __mro__
+    pub fn get_mro(&self, type_addr: MemoryAddress) -> Vec<MemoryAddress> {
+        let mut result = vec![type_addr];
+        if let PyObjectStructure::Type { mro, .. } = &self.get_pyobj_byaddr(type_addr).structure {
+            result.extend(mro.iter().copied());
+        }
+        return result;
+    }
+
+    pub fn set_bases(&self, type_addr: MemoryAddress, bases: &[MemoryAddress], new_mro: Vec<MemoryAddress>) {
+        if let PyObjectStructure::Type { supertype, mro, .. } = &mut self.get_pyobj_byaddr_mut(type_addr).structure {
+            *supertype = bases.first().copied();
+            *mro = new_mro;
+        }
+    }
+
+    //C3 linearization of the bases of a new class, without the class itself.
+    //Returns None when there is no consistent order, like in `class C(A, B)` where B is a subclass of A.
+    pub fn compute_mro(&self, bases: &[MemoryAddress]) -> Option<Vec<MemoryAddress>> {
+        let mut sequences: Vec<Vec<MemoryAddress>> = bases.iter().map(|base| self.get_mro(*base)).collect();
+        sequences.push(bases.to_vec());
+        let mut result = vec![];
+        loop {
+            sequences.retain(|sequence| !sequence.is_empty());
+            if sequences.is_empty() {
+                return Some(result);
+            }
+            //the first head that doesn't appear in the tail of any sequence
+            let candidate = sequences
+                .iter()
+                .map(|sequence| sequence[0])
+                .find(|head| !sequences.iter().any(|sequence| sequence[1..].contains(head)))?;
+            result.push(candidate);
+            for sequence in sequences.iter_mut() {
+                if sequence[0] == candidate {
+                    sequence.remove(0);
+                }
+            }
+        }
+    }
+
+    //Whether type_addr is other_type or has it in its MRO. Every type is a subtype of object.
+    pub fn is_subtype(&self, type_addr: MemoryAddress, other_type: MemoryAddress) -> bool {
+        if type_addr == other_type || other_type == self.builtin_type_addrs.object {
+            return true;
+        }
+        match &self.get_pyobj_byaddr(type_addr).structure {
+            PyObjectStructure::Type { mro, .. } => mro.contains(&other_type),
+            _ => false,
+        }
+    }
+
+    pub fn get_type_name(&self, addr: MemoryAddress) -> String {
         let pyobj = self.get_pyobj_byaddr(addr);
         match &pyobj.structure {
             PyObjectStructure::Type { name, .. } => {
-                return &name;
+                return name.clone();
             }
             _ => {
                 panic!(
@@ -504,7 +937,7 @@ impl VM {
         return pyobj.type_addr;
     }
 
-    pub fn get_pyobj_type_name(&self, addr: MemoryAddress) -> &str {
+    pub fn get_pyobj_type_name(&self, addr: MemoryAddress) -> String {
         let type_addr = self.get_pyobj_type_addr(addr);
         return self.get_type_name(type_addr);
     }
@@ -517,12 +950,14 @@ impl VM {
         &self,
         code: CodeObjectContext,
         qualname: String,
-        defaults: Vec<MemoryAddress>
+        defaults: Vec<MemoryAddress>,
+        kwonly_defaults: Vec<(String, MemoryAddress)>,
+        closure: Vec<MemoryAddress>,
     ) -> MemoryAddress {
         let obj = PyObject {
             properties: BTreeMap::new(),
             type_addr: self.builtin_type_addrs.code_object,
-            structure: PyObjectStructure::UserDefinedFunction { code, qualname, defaults },
+            structure: PyObjectStructure::UserDefinedFunction { code, qualname, defaults, kwonly_defaults, closure },
             is_const: true,
         };
         return self.allocate_and_write(obj);
@@ -598,10 +1033,10 @@ impl VM {
         F: Fn(&VM, CallParams) -> MemoryAddress + 'static,
     {
         let pycallable = PyCallable {
-            code: Box::new(callable),
+            code: Rc::new(callable),
         };
         let func_addr = self.create_unbounded_callable_pyobj(pycallable, Some(name.to_string()));
-        let pyobj_type = self.get_pyobj_byaddr_mut(type_addr);
+        let mut pyobj_type = self.get_pyobj_byaddr_mut(type_addr);
         if let PyObjectStructure::Type { functions, .. } = &mut pyobj_type.structure {
             functions.insert(name.to_string(), func_addr);
         } else {
@@ -631,7 +1066,7 @@ impl VM {
         F: Fn(&VM, CallParams) -> MemoryAddress + 'static,
     {
         let pycallable = PyCallable {
-            code: Box::new(callable),
+            code: Rc::new(callable),
         };
         let func_addr = self.create_bounded_callable_pyobj(pycallable, Some(name.to_string()));
         self.register_method_addr_on_type(type_addr, name, func_addr)
@@ -643,7 +1078,7 @@ impl VM {
         name: &str,
         callable_addr: MemoryAddress,
     ) {
-        let pyobj_type = self.get_pyobj_byaddr_mut(type_addr);
+        let mut pyobj_type = self.get_pyobj_byaddr_mut(type_addr);
         if let PyObjectStructure::Type { functions, .. } = &mut pyobj_type.structure {
             functions.insert(name.to_string(), callable_addr);
         } else {
@@ -652,41 +1087,18 @@ impl VM {
     }
 
 
-    pub fn get_raw_data_of_pyobj(&self, addr: MemoryAddress) -> &BuiltInTypeData {
-        let pyobj = self.get_pyobj_byaddr(addr);
-        if let PyObjectStructure::Object { raw_data, .. } = &pyobj.structure {
-            return &raw_data;
-        } else {
-            panic!(
-                "get_raw_data_of_pyobj cannot be called on {:?} {:p}",
-                pyobj.structure, addr
-            )
-        }
+    #[inline]
+    pub fn get_raw_data_of_pyobj(&self, addr: MemoryAddress) -> Ref<'_, BuiltInTypeData> {
+        self.memory.get_raw_data(addr)
     }
 
-    pub fn get_function_bytecode(&self, addr: MemoryAddress) -> &[Instruction] {
-        let pyobj = self.get_pyobj_byaddr(addr);
-        if let PyObjectStructure::UserDefinedFunction { code, .. } = &pyobj.structure {
-            return &code.code.instructions;
-        } else {
-            panic!(
-                "get_raw_data_of_pyobj cannot be called on {:?}",
-                pyobj.structure
-            )
-        }
+    #[inline]
+    pub fn get_raw_data_of_pyobj_mut(&self, addr: MemoryAddress) -> RefMut<'_, BuiltInTypeData> {
+        self.memory.get_raw_data_mut(addr)
     }
 
-    pub fn get_raw_data_of_pyobj_mut(&self, addr: MemoryAddress) -> &mut BuiltInTypeData {
-        let pyobj = self.get_pyobj_byaddr_mut(addr);
-        if let PyObjectStructure::Object { raw_data, .. } = &mut pyobj.structure {
-            return raw_data;
-        } else {
-            panic!("get_raw_data_of_pyobj_mut cannot be called non-object")
-        }
-    }
 
-
-    pub fn try_load_function(&self, addr: MemoryAddress) -> &PyObject {
+    pub fn try_load_function(&self, addr: MemoryAddress) -> Ref<'_, PyObject> {
         return self.get_pyobj_byaddr(self.try_load_function_addr(addr));
     }
 
@@ -702,19 +1114,37 @@ impl VM {
                     .expect(format!("Type {} has no __new__ function", name).as_str());
                 return *new;
             }
-            _ => panic!("not callable: {:?} {:?}", unsafe {&*addr}, self.stack.borrow()),
+            _ => panic!("not callable: {:?} {:?}", obj, self.stack.borrow()),
         }
     }
     
 
     pub fn run_function(&self, mut positional_params: PositionalParameters, 
         function_addr: MemoryAddress, bound_addr: Option<MemoryAddress>) -> (MemoryAddress, StackFrame) {
+        if let Some(message) = self.check_callable(function_addr) {
+            return self.fail_call("TypeError", message);
+        }
         let func_name = self.get_function_name(function_addr);
-        let pyobj_func = self.try_load_function(function_addr);
-        //println!("Calling function {:?}", func_name);
-        match &pyobj_func.structure {
+        //copied out of the function object, which must not stay borrowed while the function runs
+        let callee = match &self.try_load_function(function_addr).structure {
             PyObjectStructure::NativeCallable { code, name, is_bound } => {
-                if *is_bound {
+                Callee::Native { code: code.code.clone(), name: name.clone(), is_bound: *is_bound }
+            }
+            PyObjectStructure::UserDefinedFunction { code, defaults, kwonly_defaults, closure, .. } => Callee::Function {
+                code: code.clone(),
+                defaults: defaults.clone(),
+                kwonly_defaults: kwonly_defaults.clone(),
+                closure: closure.clone(),
+            },
+            PyObjectStructure::BoundMethod { function_address, bound_address } => {
+                Callee::BoundMethod { function_address: *function_address, bound_address: *bound_address }
+            }
+            _ => panic!("Not a function at addr: {:?}", function_addr),
+        };
+        //println!("Calling function {:?}", func_name);
+        match callee {
+            Callee::Native { code, name, is_bound } => {
+                if is_bound {
                     let bounded = match bound_addr {
                         Some(x) => x,
                         None => self.pop_stack()
@@ -723,52 +1153,62 @@ impl VM {
                     positional_params.params.insert(0, bounded);
                 }
                 
-                self.new_stack_frame(func_name);
+                self.new_stack_frame(&func_name);
                 let call_params = CallParams {
                     func_address: function_addr,
                     func_name: name.as_deref(),//.map(|x| x.as_str()),
                     params: positional_params,
                 };
                 
-                let result = (code.code)(self, call_params);
+                self.native_calls.set(self.native_calls.get() + 1);
+                let result = code(self, call_params);
+                self.native_calls.set(self.native_calls.get() - 1);
                 self.increase_refcount(result);
                 let popped_stacked_frame = self.pop_stack_frame();
                 (result, popped_stacked_frame)
             }
-            PyObjectStructure::UserDefinedFunction {code, qualname, defaults} => {
-                let mut expected_number_args = code.code.params.len();
-                if let Some(_) = bound_addr {
-                    expected_number_args -= 1; //because self is already being passed
+            Callee::Function { code, defaults, kwonly_defaults, closure } => {
+                if let Some(a) = bound_addr {
+                    positional_params.params.insert(0, a);
                 }
-                
-                if defaults.len() == 0 {
-                    if expected_number_args != positional_params.params.len() {
-                        panic!("Function {} expects {} parameters, but {} were provided", qualname, code.code.params.len(), positional_params.params.len());
-                    }
-                } else {
-                    let non_default_params = expected_number_args - defaults.len();
-                    if non_default_params > positional_params.params.len() {
-                        panic!("Function {} expects {} non-default parameters, but {} were provided", qualname, code.code.params.len(), positional_params.params.len());
-                    }
-
-                    //pass the last N parameters that are necessary
-                    let to_pass = defaults.iter().skip(positional_params.params.len());
-
-                    for mem_addr in to_pass {
-                        positional_params.params.insert(0, *mem_addr);
-                    }
+                if self.stack.borrow().len() >= MAX_FRAME_DEPTH {
+                    return self.fail_call("RecursionError", String::from("maximum recursion depth exceeded"));
                 }
+                let arguments = match bind_arguments(self, &code.code, &func_name, &defaults, &kwonly_defaults, positional_params) {
+                    Ok(arguments) => arguments,
+                    Err(message) => return self.fail_call("TypeError", message),
+                };
     
-                self.new_stack_frame(func_name);
-                if let Some(a) = bound_addr {
-                    self.bind_local(0, a); 
-                    for (number, addr) in positional_params.params.iter().enumerate() {
-                        self.bind_local(number + 1, *addr);
-                    }
-                } else {
-                    for (number, addr) in positional_params.params.iter().enumerate() {
-                        self.bind_local(number, *addr);
+                self.new_stack_frame(&func_name);
+                self.stack.borrow_mut().last_mut().unwrap().function = Some(function_addr);
+                //parameters used by nested functions start in their cell
+                let cells = code.code.cellvars.iter().map(|name| {
+                    let argument = code.code.params.iter().position(|param| param == name).map(|index| arguments[index]);
+                    self.new_cell(argument)
+                });
+                let cells: Vec<MemoryAddress> = cells.chain(closure.iter().copied()).collect();
+                for (number, addr) in arguments.into_iter().enumerate() {
+                    self.bind_local(number, addr);
+                }
+                self.set_cells(cells);
+
+                if code.code.generator {
+                    //the body only starts running on the first __next__, the frame waits in the generator
+                    let frame = self.stack.borrow_mut().pop().unwrap();
+                    //the caller releases the arguments once the call returns, but the frame lives on
+                    for local in frame.local_namespace.iter() {
+                        self.increase_refcount(*local);
                     }
+                    let generator = self.allocate_type_byaddr_raw(
+                        self.builtin_type_addrs.generator,
+                        BuiltInTypeData::Generator(GeneratorData {
+                            code: code.clone(),
+                            frame: Some(Box::new(frame)),
+                            running: false,
+                        }),
+                    );
+                    self.increase_refcount(generator);
+                    return (generator, StackFrame::new(&func_name));
                 }
                 
                 //what a mess
@@ -779,33 +1219,240 @@ impl VM {
                 let popped_stacked_frame = self.pop_stack_frame();
                 (result_addr, popped_stacked_frame)
             }
-            PyObjectStructure::BoundMethod {function_address, bound_address} => {
-                self.run_function(positional_params, *function_address, Some(*bound_address))
+            Callee::BoundMethod { function_address, bound_address } => {
+                self.run_function(positional_params, function_address, Some(bound_address))
             }
-            _ => {
-                panic!("Not a function at addr: {:?}", function_addr);
+        }
+    }
+
+    //Runs a generator until it yields again. Ok is the yielded value, Err the exception for the caller:
+    //StopIteration with the return value when the body returns, or whatever the body raised.
+    pub fn resume_generator(&self, generator: MemoryAddress, input: GeneratorInput) -> Result<MemoryAddress, MemoryAddress> {
+        let none = self.special_values[&SpecialValue::NoneValue];
+        let (mut frame, code) = match &mut *self.get_raw_data_of_pyobj_mut(generator) {
+            BuiltInTypeData::Generator(data) if data.running => {
+                return Err(self.create_exception("ValueError", String::from("generator already executing")));
+            }
+            BuiltInTypeData::Generator(data) => match data.frame.take() {
+                Some(frame) => (frame, data.code.clone()),
+                None => {
+                    return Err(match input {
+                        GeneratorInput::Send(_) => self.create_exception("StopIteration", String::new()),
+                        GeneratorInput::Throw(exception) => exception,
+                    })
+                }
+            },
+            _ => panic!("Not a generator at addr: {:?}", generator),
+        };
+        //a generator is never suspended at its first instruction, there is always a value to yield before
+        let started = frame.prog_counter.get() > 0;
+        if let GeneratorInput::Send(value) = input {
+            if started {
+                //the result of the yield expression
+                self.increase_refcount(value);
+                frame.stack.push(value);
+            } else if value != none {
+                self.set_generator_frame(generator, Some(frame));
+                return Err(self.create_exception(
+                    "TypeError",
+                    String::from("can't send non-None value to a just-started generator"),
+                ));
+            }
+        }
+
+        if let BuiltInTypeData::Generator(data) = &mut *self.get_raw_data_of_pyobj_mut(generator) {
+            data.running = true;
+        }
+        self.stack.borrow_mut().push(*frame);
+        let keep_running = match input {
+            GeneratorInput::Send(_) => true,
+            GeneratorInput::Throw(exception) => crate::runtime::interpreter::throw_into_frame(self, &code, exception),
+        };
+        if keep_running {
+            crate::runtime::interpreter::execute_code_object(self, &code);
+        }
+        if let BuiltInTypeData::Generator(data) = &mut *self.get_raw_data_of_pyobj_mut(generator) {
+            data.running = false;
+        }
+
+        if self.get_pc() < code.code.instructions.len() {
+            let mut frame = self.stack.borrow_mut().pop().unwrap();
+            let yielded = frame.stack.pop().unwrap();
+            self.set_generator_frame(generator, Some(Box::new(frame)));
+            return Ok(yielded);
+        }
+        let result = self.top_stack();
+        self.increase_refcount(result);
+        let popped_frame = self.pop_stack_frame();
+        match popped_frame.exception {
+            Some(exception) if self.exception_matches(exception, self.special_values[&SpecialValue::StopIterationType]) => {
+                Err(self.create_exception("RuntimeError", String::from("generator raised StopIteration")))
+            }
+            Some(exception) => Err(exception),
+            None => {
+                let message = if result == none {
+                    String::new()
+                } else {
+                    match self.call_method(result, "__str__", PositionalParameters::empty()) {
+                        Some((str_addr, _)) => self.get_raw_data_of_pyobj(str_addr).take_string().clone(),
+                        None => String::new(),
+                    }
+                };
+                let stop_iteration = self.create_exception("StopIteration", message);
+                self.set_attribute(stop_iteration, "value", result);
+                Err(stop_iteration)
             }
         }
     }
 
+    fn set_generator_frame(&self, generator: MemoryAddress, frame: Option<Box<StackFrame>>) {
+        if let BuiltInTypeData::Generator(data) = &mut *self.get_raw_data_of_pyobj_mut(generator) {
+            data.frame = frame;
+        }
+    }
+
+    //Returns the TypeError message if the object at addr cannot be called
+    fn check_callable(&self, addr: MemoryAddress) -> Option<String> {
+        let obj = self.get_pyobj_byaddr(addr);
+        match &obj.structure {
+            PyObjectStructure::NativeCallable { .. }
+            | PyObjectStructure::UserDefinedFunction { .. }
+            | PyObjectStructure::BoundMethod { .. } => None,
+            PyObjectStructure::Type { name, functions, .. } => {
+                if functions.contains_key("__new__") {
+                    None
+                } else {
+                    Some(format!("cannot create '{}' instances", name))
+                }
+            }
+            _ => Some(format!("'{}' object is not callable", self.get_type_name(obj.type_addr))),
+        }
+    }
+
+    //Result of a call that failed before running any code: no return value, just the exception
+    fn fail_call(&self, exception_type: &str, message: String) -> (MemoryAddress, StackFrame) {
+        let none = self.special_values[&SpecialValue::NoneValue];
+        self.increase_refcount(none);
+        let mut frame = StackFrame::new("<call>");
+        frame.exception = Some(self.create_exception(exception_type, message));
+        (none, frame)
+    }
+
+    //Creates an instance of one of the builtin exception types, like TypeError
+    pub fn create_exception(&self, exception_type: &str, message: String) -> MemoryAddress {
+        let type_addr = self
+            .find_in_module(BUILTIN_MODULE, exception_type)
+            .expect("Builtin exception type not registered");
+        let exception = self.allocate_type_byaddr_raw(type_addr, BuiltInTypeData::String(message));
+        self.increase_refcount(exception);
+        self.set_attribute(exception, "__traceback__", self.special_values[&SpecialValue::NoneValue]);
+        return exception;
+    }
+
+    //Renders an exception value to be reported outside of the VM
+    pub fn exception_to_error(&self, exception: MemoryAddress) -> RuntimeError {
+        let pyobj = self.get_pyobj_byaddr(exception);
+        if let PyObjectStructure::Type { name, .. } = &pyobj.structure {
+            return RuntimeError {
+                exception_type: name.clone(),
+                message: String::new(),
+                exception,
+            };
+        }
+        let exception_type = self.get_type_name(pyobj.type_addr);
+        drop(pyobj);
+        let message = match self.call_method(exception, "__str__", PositionalParameters::empty()) {
+            Some((str_addr, _)) => match &*self.get_raw_data_of_pyobj(str_addr) {
+                BuiltInTypeData::String(s) => s.clone(),
+                _ => String::new(),
+            },
+            None => String::new(),
+        };
+        RuntimeError {
+            exception_type,
+            message,
+            exception,
+        }
+    }
+
     pub fn call_method(
         &self,
         bound_addr: MemoryAddress,
         method_name: &str,
         params: PositionalParameters,
     ) -> Option<(MemoryAddress, StackFrame)> {
-        let pyobj = self.get_pyobj_byaddr(bound_addr);
-        self.get_method_addr_byname(pyobj.type_addr, method_name)
+        let type_addr = self.get_pyobj_byaddr(bound_addr).type_addr;
+        //whoever calls a method from Rust may keep objects the collector can't see
+        self.get_method_addr_byname(type_addr, method_name)
             .map(move |method_addr| {
-                self.run_function(params, method_addr, Some(bound_addr))
+                self.native_calls.set(self.native_calls.get() + 1);
+                let result = self.run_function(params, method_addr, Some(bound_addr));
+                self.native_calls.set(self.native_calls.get() - 1);
+                result
             })
     }
 
+    //Raises (or propagates from a callee) an exception in the current frame, adding the frame to its traceback
     pub fn raise_exception(&self, exception_value_addr: MemoryAddress) {
+        self.record_traceback(exception_value_addr);
+        self.reraise_exception(exception_value_addr);
+    }
+
+    //Raises an exception again in the frame that already raised it, like at the end of a finally block
+    pub fn reraise_exception(&self, exception_value_addr: MemoryAddress) {
         let mut stack = self.stack.borrow_mut();
         let top_stack_frame = stack.last_mut().unwrap();
         top_stack_frame.exception = Some(exception_value_addr)
     }
+
+    //Prepends the current frame to the exception's __traceback__, so the entries end up
+    //ordered from the outermost call to the place where it was raised.
+    //Native frames have no source, and types raised directly by natives have no attributes.
+    fn record_traceback(&self, exception: MemoryAddress) {
+        let entry = {
+            let stack = self.stack.borrow();
+            let frame = stack.last().unwrap();
+            match &frame.code {
+                Some(code) => TracebackEntry {
+                    filename: code.filename.clone(),
+                    line: frame.current_line(),
+                    //methods are reported by their own name, not the qualified one
+                    function_name: if code.main {
+                        "<module>".to_string()
+                    } else {
+                        frame.function_name.rsplit('.').next().unwrap().to_string()
+                    },
+                },
+                None => return,
+            }
+        };
+        if let PyObjectStructure::Type { .. } = &self.get_pyobj_byaddr(exception).structure {
+            return;
+        }
+        if let Some(tb) = self.get_obj_property(exception, "__traceback__") {
+            if let PyObjectStructure::Object { raw_data: BuiltInTypeData::Traceback(entries), .. } =
+                &mut self.get_pyobj_byaddr_mut(tb).structure
+            {
+                entries.insert(0, entry);
+                return;
+            }
+        }
+        let tb = self.allocate_type_byaddr_raw(self.builtin_type_addrs.traceback, BuiltInTypeData::Traceback(vec![entry]));
+        self.increase_refcount(tb);
+        self.set_attribute(exception, "__traceback__", tb);
+    }
+
+    //Frames the exception went through, outermost first. Empty if it was never raised.
+    pub fn get_traceback(&self, exception: MemoryAddress) -> Vec<TracebackEntry> {
+        if let Some(tb) = self.get_obj_property(exception, "__traceback__") {
+            if let PyObjectStructure::Object { raw_data: BuiltInTypeData::Traceback(entries), .. } =
+                &self.get_pyobj_byaddr(tb).structure
+            {
+                return entries.clone();
+            }
+        }
+        return vec![];
+    }
     
     pub fn get_current_exception(&self) -> Option<MemoryAddress> {
         let stack = self.stack.borrow();
@@ -814,13 +1461,177 @@ impl VM {
     }
     
     pub fn new_stack_frame(&self, function_name: &str) {
-        self.stack.borrow_mut().push(StackFrame {
-            function_name: function_name.to_owned(),
-            local_namespace: vec![],
-            stack: vec![],
-            exception: None,
-            prog_counter: Cell::new(0),
-        })
+        self.stack.borrow_mut().push(StackFrame::new(function_name))
+    }
+
+    pub fn clear_exception(&self) {
+        let mut stack = self.stack.borrow_mut();
+        stack.last_mut().unwrap().exception = None;
+    }
+
+    pub fn setup_block(&self, block_type: BlockType, handler: usize) {
+        let mut stack = self.stack.borrow_mut();
+        let frame = stack.last_mut().unwrap();
+        let block = Block {
+            block_type,
+            handler,
+            stack_level: frame.stack.len(),
+            finally_level: frame.finally_reasons.len(),
+        };
+        frame.blocks.push(block);
+    }
+
+    pub fn pop_block(&self) -> Option<Block> {
+        self.stack.borrow_mut().last_mut().unwrap().blocks.pop()
+    }
+
+    pub fn push_finally_reason(&self, reason: FinallyReason) {
+        self.stack.borrow_mut().last_mut().unwrap().finally_reasons.push(reason);
+    }
+
+    pub fn pop_finally_reason(&self) -> FinallyReason {
+        match self.stack.borrow_mut().last_mut().unwrap().finally_reasons.pop() {
+            Some(reason) => reason,
+            None => panic!("EndFinally executed outside of a finally block"),
+        }
+    }
+
+    //Removes everything pushed onto the value stack after the block was set up
+    fn restore_block_state(&self, block: &Block) {
+        let mut stack = self.stack.borrow_mut();
+        let frame = stack.last_mut().unwrap();
+        frame.stack.truncate(block.stack_level);
+        frame.finally_reasons.truncate(block.finally_level);
+    }
+
+    //Tries to find a handler for the exception raised in the current frame.
+    //If one is found, the exception is cleared and the program counter is set to the handler.
+    //Otherwise returns false and the exception will propagate to the caller frame.
+    pub fn unwind_exception(&self) -> bool {
+        let exception = match self.get_current_exception() {
+            Some(addr) => addr,
+            None => return false,
+        };
+        match self.pop_block() {
+            Some(block) => {
+                self.restore_block_state(&block);
+                self.clear_exception();
+                match block.block_type {
+                    BlockType::Except => self.push_onto_stack(exception),
+                    BlockType::Finally => self.push_finally_reason(FinallyReason::Exception(exception)),
+                }
+                self.set_pc(block.handler);
+                return true;
+            }
+            None => return false,
+        }
+    }
+
+    //Pops blocks until a finally block is found, so that its body runs before returning.
+    //Returns false if there is no finally block, in which case the function can return right away.
+    pub fn return_through_finally(&self, return_value: MemoryAddress) -> bool {
+        while let Some(block) = self.pop_block() {
+            if block.block_type == BlockType::Finally {
+                self.restore_block_state(&block);
+                self.push_finally_reason(FinallyReason::Return(return_value));
+                self.set_pc(block.handler);
+                return true;
+            }
+        }
+        return false;
+    }
+
+    //Pops the given number of blocks and jumps to the destination.
+    //If a finally block is found along the way, its body runs first and the break resumes on EndFinally.
+    pub fn break_through_blocks(&self, blocks: usize, destination: usize) {
+        for popped in 0..blocks {
+            let block = self.pop_block().unwrap();
+            if block.block_type == BlockType::Finally {
+                self.restore_block_state(&block);
+                self.push_finally_reason(FinallyReason::Break {
+                    blocks: blocks - popped - 1,
+                    destination,
+                });
+                self.set_pc(block.handler);
+                return;
+            }
+        }
+        self.set_pc(destination);
+    }
+
+    //Comparisons don't always return the shared True/False objects (float ones allocate a new bool),
+    //so results have to be checked by value. Objects without __bool__ are truthy unless their __len__ is 0.
+    pub fn is_truthy(&self, addr: MemoryAddress) -> bool {
+        if let PyObjectStructure::Object { raw_data: BuiltInTypeData::Int(x), .. } = &self.get_pyobj_byaddr(addr).structure {
+            return *x != 0;
+        }
+        if let Some((as_boolean, _)) = self.call_method(addr, "__bool__", PositionalParameters::empty()) {
+            return self.get_raw_data_of_pyobj(as_boolean).take_int() != 0;
+        }
+        match self.call_method(addr, "__len__", PositionalParameters::empty()) {
+            Some((length, _)) => self.get_raw_data_of_pyobj(length).take_int() != 0,
+            None => true,
+        }
+    }
+
+    //Elements of a list or tuple, or everything an iterable produces until StopIteration.
+    //Returns the exception to be raised in the Err case.
+    pub fn collect_iterable(&self, addr: MemoryAddress) -> Result<Vec<MemoryAddress>, MemoryAddress> {
+        match &self.get_pyobj_byaddr(addr).structure {
+            PyObjectStructure::Object { raw_data: BuiltInTypeData::List(elements), .. }
+            | PyObjectStructure::Object { raw_data: BuiltInTypeData::Tuple(elements), .. } => {
+                return Ok(elements.clone());
+            }
+            _ => {}
+        }
+        let iterator = match self.call_method(addr, "__iter__", PositionalParameters::empty()) {
+            Some((iterator, _)) => iterator,
+            None => {
+                return Err(self.create_exception(
+                    "TypeError",
+                    format!("'{}' object is not iterable", self.get_pyobj_type_name(addr)),
+                ))
+            }
+        };
+        let mut elements = vec![];
+        loop {
+            let (item, frame) = match self.call_method(iterator, "__next__", PositionalParameters::empty()) {
+                Some(result) => result,
+                None => {
+                    return Err(self.create_exception(
+                        "TypeError",
+                        format!("iter() returned non-iterator of type '{}'", self.get_pyobj_type_name(iterator)),
+                    ))
+                }
+            };
+            if let Some(exception) = frame.exception {
+                if self.exception_matches(exception, self.special_values[&SpecialValue::StopIterationType]) {
+                    return Ok(elements);
+                }
+                return Err(exception);
+            }
+            elements.push(item);
+        }
+    }
+
+    //Walks the type hierarchy of the exception to check whether it is an instance (or subtype) of exception_type.
+    //Raising a type directly (like `raise StopIteration`) is also supported.
+    pub fn exception_matches(&self, exception: MemoryAddress, exception_type: MemoryAddress) -> bool {
+        let exception_pyobj = self.get_pyobj_byaddr(exception);
+        let raised_type = match &exception_pyobj.structure {
+            PyObjectStructure::Type { .. } => exception,
+            _ => exception_pyobj.type_addr,
+        };
+        return self.is_subtype(raised_type, exception_type);
+    }
+
+    //What an except clause names: a class, or a tuple of them (which may hold more tuples)
+    pub fn exception_matches_handler(&self, exception: MemoryAddress, handler: MemoryAddress) -> bool {
+        let classes = match &self.get_pyobj_byaddr(handler).structure {
+            PyObjectStructure::Object { raw_data: BuiltInTypeData::Tuple(classes), .. } => classes.clone(),
+            _ => return self.exception_matches(exception, handler),
+        };
+        classes.into_iter().any(|class| self.exception_matches_handler(exception, class))
     }
 
     pub fn pop_stack(&self) -> MemoryAddress {
@@ -830,13 +1641,39 @@ impl VM {
         }
     }
 
-    pub fn print_traceback(&self) {
-        println!("Traceback: ");
-        for val in self.stack.borrow().iter().rev() {
-            println!("\tat {} +{}", val.function_name, val.prog_counter.get())
+    //An uncaught exception the way CPython prints it, using the frames recorded in __traceback__
+    pub fn format_traceback(&self, exception: MemoryAddress) -> String {
+        let mut text = String::new();
+        let traceback = self.get_traceback(exception);
+        if !traceback.is_empty() {
+            text.push_str("Traceback (most recent call last):\n");
+            for entry in traceback.iter() {
+                match entry.line {
+                    Some(line) => text.push_str(&format!("  File \"{}\", line {}, in {}\n", entry.filename, line, entry.function_name)),
+                    None => text.push_str(&format!("  File \"{}\", in {}\n", entry.filename, entry.function_name)),
+                }
+            }
+        }
+        text.push_str(&format!("{}\n", self.exception_to_error(exception)));
+        return text;
+    }
+
+    //Prints the frames currently being executed, outermost first
+    pub fn print_call_stack(&self) {
+        for frame in self.stack.borrow().iter() {
+            if let Some(code) = &frame.code {
+                match frame.current_line() {
+                    Some(line) => println!("  File \"{}\", line {}, in {}", code.filename, line, frame.function_name),
+                    None => println!("  File \"{}\", in {}", code.filename, frame.function_name),
+                }
+            }
         }
     }
 
+    pub fn set_current_code(&self, code: Rc<CodeObject>) {
+        self.stack.borrow_mut().last_mut().unwrap().code = Some(code);
+    }
+
     pub fn print_stack(&self) {
         print!("Stack: [");
         for val in self.stack.borrow().last().unwrap().stack.iter().rev() {
@@ -876,8 +1713,11 @@ impl VM {
         let mut stack = self.stack.borrow_mut();
         match stack.pop() {
             Some(stack_frame) => {
-                for addr in stack_frame.stack.iter() {
-                    self.decrease_refcount(*addr)
+                //an exception stops an expression halfway, and what it had pushed (like globals) was never counted
+                if stack_frame.exception.is_none() {
+                    for addr in stack_frame.stack.iter() {
+                        self.decrease_refcount(*addr)
+                    }
                 }
                 return stack_frame;
             }
@@ -903,6 +1743,25 @@ impl VM {
         }
     }
 
+    pub fn new_cell(&self, contents: Option<MemoryAddress>) -> MemoryAddress {
+        if let Some(addr) = contents {
+            self.increase_refcount(addr);
+        }
+        let cell = self.allocate_type_byaddr_raw(self.builtin_type_addrs.cell, BuiltInTypeData::Cell(contents));
+        self.increase_refcount(cell);
+        cell
+    }
+
+    pub fn set_cells(&self, cells: Vec<MemoryAddress>) {
+        let mut stack = self.stack.borrow_mut();
+        stack.last_mut().unwrap().cells = cells;
+    }
+
+    pub fn get_cell(&self, index: usize) -> MemoryAddress {
+        let stack = self.stack.borrow();
+        stack.last().unwrap().cells[index]
+    }
+
     pub fn get_local(&self, name: usize) -> Option<MemoryAddress> {
         let stack = self.stack.borrow();
         let current_frame = stack.last().unwrap();
@@ -0,0 +1,53 @@
+use crate::ast::lexer::{self, Token, KEYWORDS};
+use crate::commons::errors::LexError;
+
+//What a token is, as much as tools reading the source (like the REPL coloring it) need to know
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Keyword,
+    Name,
+    String,
+    Number,
+    OpenBracket,
+    CloseBracket,
+    Colon,
+    NewLine,
+    //operators, commas, dots and indentation
+    Other,
+}
+
+//A token of the source. Both line and column start at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceToken {
+    pub kind: TokenKind,
+    pub line: usize,
+    pub column: usize,
+}
+
+//Splits the source into tokens with the horse lexer
+pub fn tokenize(source: &str) -> Result<Vec<SourceToken>, LexError> {
+    let tokens = lexer::tokenize(source)?;
+    Ok(tokens
+        .into_iter()
+        .map(|spanned| SourceToken { kind: kind_of(&spanned.token), line: spanned.span.line, column: spanned.span.column })
+        .collect())
+}
+
+//Words that are not identifiers, like `def` or `None`
+pub fn keywords() -> impl Iterator<Item = &'static str> {
+    KEYWORDS.iter().map(|(keyword, _)| *keyword)
+}
+
+fn kind_of(token: &Token) -> TokenKind {
+    match token {
+        _ if KEYWORDS.iter().any(|(_, keyword)| keyword == token) => TokenKind::Keyword,
+        Token::Identifier(_) => TokenKind::Name,
+        Token::LiteralString(_) => TokenKind::String,
+        Token::LiteralInteger(_) | Token::LiteralFloat(_) => TokenKind::Number,
+        Token::OpenParen | Token::OpenArrayBracket | Token::OpenBrace => TokenKind::OpenBracket,
+        Token::CloseParen | Token::CloseArrayBracket | Token::CloseBrace => TokenKind::CloseBracket,
+        Token::Colon => TokenKind::Colon,
+        Token::NewLine => TokenKind::NewLine,
+        _ => TokenKind::Other,
+    }
+}
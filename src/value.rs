@@ -0,0 +1,224 @@
+use crate::commons::errors::ConversionError;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::hash::Hash;
+
+//A Python value copied out of the interpreter, or to be copied into it.
+//Dicts keep their entries in order, and their keys can be any value, so they are not a HashMap.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    None,
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    Str(String),
+    List(Vec<Value>),
+    Tuple(Vec<Value>),
+    Dict(Vec<(Value, Value)>),
+}
+
+impl Value {
+    //The name of the Python type, for error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::None => "NoneType",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Str(_) => "str",
+            Value::List(_) => "list",
+            Value::Tuple(_) => "tuple",
+            Value::Dict(_) => "dict",
+        }
+    }
+
+    fn mismatch(&self, expected: &'static str) -> ConversionError {
+        ConversionError { expected, found: self.type_name() }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<i128> for Value {
+    fn from(value: i128) -> Self {
+        Value::Int(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Int(value as i128)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::Int(value as i128)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Str(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Str(value.to_string())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::None, Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(value: Vec<T>) -> Self {
+        Value::List(value.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<K: Into<Value>, V: Into<Value>> From<HashMap<K, V>> for Value {
+    fn from(value: HashMap<K, V>) -> Self {
+        Value::Dict(value.into_iter().map(|(key, value)| (key.into(), value.into())).collect())
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Bool(value) => Ok(value),
+            other => Err(other.mismatch("bool")),
+        }
+    }
+}
+
+impl TryFrom<Value> for i128 {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Int(value) => Ok(value),
+            other => Err(other.mismatch("int")),
+        }
+    }
+}
+
+impl TryFrom<Value> for i64 {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Int(value) if i64::try_from(value).is_ok() => Ok(value as i64),
+            other => Err(other.mismatch("int that fits in an i64")),
+        }
+    }
+}
+
+//ints are accepted too, like Python does where a float is expected
+impl TryFrom<Value> for f64 {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Float(value) => Ok(value),
+            Value::Int(value) => Ok(value as f64),
+            other => Err(other.mismatch("float")),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Str(value) => Ok(value),
+            other => Err(other.mismatch("str")),
+        }
+    }
+}
+
+//None is converted to None, anything else to the type inside
+impl<T: TryFrom<Value, Error = ConversionError>> TryFrom<Value> for Option<T> {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::None => Ok(None),
+            other => T::try_from(other).map(Some),
+        }
+    }
+}
+
+//from a list or a tuple
+impl<T: TryFrom<Value, Error = ConversionError>> TryFrom<Value> for Vec<T> {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::List(elements) | Value::Tuple(elements) => elements.into_iter().map(T::try_from).collect(),
+            other => Err(other.mismatch("list")),
+        }
+    }
+}
+
+impl<K, V> TryFrom<Value> for HashMap<K, V>
+where
+    K: TryFrom<Value, Error = ConversionError> + Eq + Hash,
+    V: TryFrom<Value, Error = ConversionError>,
+{
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Dict(entries) => entries
+                .into_iter()
+                .map(|(key, value)| Ok((K::try_from(key)?, V::try_from(value)?)))
+                .collect(),
+            other => Err(other.mismatch("dict")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    #[test]
+    fn converts_rust_values() {
+        let numbers: Vec<i128> = Value::from(vec![1i128, 2, 3]).try_into().unwrap();
+        assert_eq!(numbers, vec![1, 2, 3]);
+        let mut ages = HashMap::new();
+        ages.insert("ada".to_string(), 36i128);
+        let dict = Value::from(ages.clone());
+        assert_eq!(dict, Value::Dict(vec![(Value::Str("ada".into()), Value::Int(36))]));
+        assert_eq!(HashMap::<String, i128>::try_from(dict).unwrap(), ages);
+        assert_eq!(f64::try_from(Value::Int(2)), Ok(2.0));
+        assert_eq!(Option::<String>::try_from(Value::None), Ok(None));
+    }
+
+    #[test]
+    fn reports_the_type_found() {
+        let error = String::try_from(Value::List(vec![])).unwrap_err();
+        assert_eq!(error, ConversionError { expected: "str", found: "list" });
+        let error = Vec::<i128>::try_from(Value::Tuple(vec![Value::Int(1), Value::Float(1.5)])).unwrap_err();
+        assert_eq!(error.to_string(), "ConversionError: expected int, found float");
+    }
+}